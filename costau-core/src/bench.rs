@@ -0,0 +1,192 @@
+//! A harness that times the phases of a calculation separately on a corpus
+//! of expressions, so that performance changes can be measured the same way
+//! by everyone.
+//!
+//! The summary has a header line with the field names `phase`, `runs`,
+//! `failures`, `total_ns` and `mean_ns`, then one line per phase, with the
+//! fields separated by tabs.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::lexer::{Lexer, Token};
+use crate::node::Node;
+use crate::parser::Parser;
+
+/// The calculations of the regression tests, which cover most of the
+/// features.
+pub const DEFAULT_CORPUS: &str = include_str!("../../test-calculations.txt");
+
+/// A phase of a calculation.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Phase {
+    Lex,
+    Parse,
+    /// Simplifying the parsed node
+    Reduce,
+    /// Approximating the parsed node
+    Eval,
+    /// Formatting the simplified node
+    Format,
+}
+
+impl Phase {
+    /// All the phases, in the order in which they run.
+    pub const ALL: [Phase; 5] = [
+        Phase::Lex,
+        Phase::Parse,
+        Phase::Reduce,
+        Phase::Eval,
+        Phase::Format,
+    ];
+
+    /// The name of the phase in the summary.
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Lex => "lex",
+            Phase::Parse => "parse",
+            Phase::Reduce => "reduce",
+            Phase::Eval => "eval",
+            Phase::Format => "format",
+        }
+    }
+}
+
+/// The time spent in a phase over the whole corpus.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct PhaseTiming {
+    pub phase: Phase,
+    /// The number of times the phase ran
+    pub runs: u32,
+    /// The number of runs that returned an error
+    pub failures: u32,
+    pub total: Duration,
+}
+
+impl PhaseTiming {
+    /// The mean time of a run, or zero if the phase never ran.
+    pub fn mean(&self) -> Duration {
+        if self.runs == 0 {
+            Duration::from_secs(0)
+        } else {
+            self.total / self.runs
+        }
+    }
+}
+
+/// The result of a benchmark run.
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// The number of expressions in the corpus
+    pub exprs: usize,
+    /// The number of times each expression was calculated
+    pub iterations: u32,
+    /// The timings in the order of `Phase::ALL`
+    pub phases: Vec<PhaseTiming>,
+}
+
+impl Report {
+    /// Returns the timing of a phase.
+    pub fn phase(&self, phase: Phase) -> &PhaseTiming {
+        self.phases.iter().find(|x| x.phase == phase).unwrap()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "phase\truns\tfailures\ttotal_ns\tmean_ns")?;
+        for timing in &self.phases {
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{}\t{}",
+                timing.phase.name(),
+                timing.runs,
+                timing.failures,
+                timing.total.as_nanos(),
+                timing.mean().as_nanos()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Times `f` and records it in the timing of its phase.
+fn timed<T, E>(timing: &mut PhaseTiming, f: impl FnOnce() -> Result<T, E>) -> Option<T> {
+    let start = Instant::now();
+    let res = f();
+    timing.total += start.elapsed();
+    timing.runs += 1;
+    if res.is_err() {
+        timing.failures += 1;
+    }
+    res.ok()
+}
+
+/// Calculates each line of the corpus `iterations` times and times the
+/// phases separately. Empty lines and lines starting with `#` are skipped.
+/// A phase that fails stops the calculation of the expression, except that
+/// the parsed node is still formatted if it cannot be simplified.
+pub fn run(corpus: &str, iterations: u32) -> Report {
+    let exprs: Vec<&str> = corpus
+        .lines()
+        .map(str::trim)
+        .filter(|x| !x.is_empty() && !x.starts_with('#'))
+        .collect();
+    let mut phases: Vec<PhaseTiming> = Phase::ALL
+        .iter()
+        .map(|&phase| PhaseTiming {
+            phase,
+            runs: 0,
+            failures: 0,
+            total: Duration::from_secs(0),
+        })
+        .collect();
+
+    for _ in 0..iterations {
+        for expr in &exprs {
+            let tokens = match timed(&mut phases[0], || {
+                Lexer::new(expr).collect::<Result<Vec<Token>, _>>()
+            }) {
+                Some(x) => x,
+                None => continue,
+            };
+            let node = match timed(&mut phases[1], || Parser::new(&tokens).parse()) {
+                Some(x) => x,
+                None => continue,
+            };
+            let copy = node.clone();
+            let simplified = timed(&mut phases[2], || copy.simplify());
+            timed(&mut phases[3], || node.eval());
+            let result: Node = simplified.map_or(node, |x| x.result);
+            timed(&mut phases[4], || Ok::<_, ()>(result.to_string()));
+        }
+    }
+
+    Report {
+        exprs: exprs.len(),
+        iterations,
+        phases,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_times_every_phase() {
+        let report = run("1 + 2\n# a comment\n\nsqrt(8)\n2 +\n?", 3);
+        assert_eq!(report.exprs, 4);
+        let lex = report.phase(Phase::Lex);
+        assert_eq!((lex.runs, lex.failures), (12, 3));
+        let parse = report.phase(Phase::Parse);
+        assert_eq!((parse.runs, parse.failures), (9, 3));
+        assert_eq!(report.phase(Phase::Format).runs, 6);
+
+        let summary = report.to_string();
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[1].starts_with("lex\t12\t3\t"));
+        assert!(lines[5].starts_with("format\t6\t0\t"));
+    }
+}
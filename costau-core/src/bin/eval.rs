@@ -1,10 +1,7 @@
 extern crate costau_core;
-extern crate num_traits;
 
 use costau_core::lexer::Lexer;
-use costau_core::node::Node;
 use costau_core::parser::Parser;
-use num_traits::One;
 use std::env;
 
 fn main() {
@@ -17,27 +14,25 @@ fn main() {
     let parser = Parser::new(&tokens);
     let root_node = parser.parse().unwrap();
 
-    let simplified = match root_node.simplify() {
+    let calculation = match root_node.calculate() {
         Ok(x) => x,
         Err(err) => {
             println!("= (error: {:?})", err);
             return;
         }
     };
-    if simplified.did_something {
-        println!("= {}", simplified.result);
+    if calculation.did_simplify {
+        let pretty = calculation.exact.to_pretty_string();
+        if pretty.contains('\n') {
+            println!("=\n{}", pretty);
+        } else {
+            println!("= {}", pretty);
+        }
     }
 
-    let needs_approx = if let Node::Num { val, .. } = &simplified.result {
-        // if integer, already simplified to the maximum
-        !val.denom().is_one() && *val.denom() != (-1).into()
-    } else {
-        true
-    };
-    if needs_approx {
-        match simplified.result.eval() {
-            Ok(eval) => println!("≈ {}", eval),
-            Err(err) => println!("≈ (error: {:?})", err),
-        }
+    match calculation.approx {
+        Some(Ok(eval)) => println!("≈ {}", eval),
+        Some(Err(err)) => println!("≈ (error: {:?})", err),
+        None => {}
     }
 }
@@ -0,0 +1,27 @@
+use crate::node::{Node, Solutions};
+
+/// The list of the solutions of the equation `lhs = rhs` for the variable
+/// `var`. Returns `None` if the equation cannot be solved or holds for every
+/// value.
+pub fn solve(lhs: Node, rhs: Node, var: Node) -> Option<Node> {
+    let name = match var {
+        Node::Var(name) => name,
+        _ => return None,
+    };
+    match lhs.solve(&rhs, &name).ok()? {
+        Solutions::Finite(solutions) => Some(Node::Vector(solutions)),
+        Solutions::All => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::simplify_str;
+
+    #[test]
+    fn it_lists_the_solutions() {
+        assert_eq!(simplify_str("solve(2 * x^2, 8, x)"), "(-2, 2)");
+        assert_eq!(simplify_str("solve(5y - 1, 9, y)"), "(2)");
+        assert_eq!(simplify_str("solve(x^2, -1, x)"), "()");
+    }
+}
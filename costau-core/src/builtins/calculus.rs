@@ -0,0 +1,63 @@
+use num_traits::ToPrimitive;
+
+use crate::node::Node;
+
+/// The highest order of a series, because the derivatives grow quickly.
+pub const MAX_SERIES_ORDER: u32 = 20;
+
+/// The elasticity of `expr` with respect to the variable `var` at the value
+/// `at`, which is `df/dx * x/f`. It tells by how many percents the result
+/// changes when the variable changes by one percent.
+/// Returns `None` if `var` is not a variable.
+pub fn sensitivity(expr: Node, var: Node, at: Node) -> Option<Node> {
+    let name = match var {
+        Node::Var(name) => name,
+        _ => return None,
+    };
+    let x = Node::Var(name.clone());
+    let elasticity = expr.derivative(&name) * x / expr;
+    Some(elasticity.substitute(&name, &at))
+}
+
+/// The Taylor polynomial of `expr` in the variable `var` around `around` up
+/// to the power `order`. `order` must be a small non-negative integer.
+pub fn series_args(args: Vec<Node>) -> Option<Node> {
+    if args.len() != 4 {
+        return None;
+    }
+    let mut iter = args.into_iter();
+    let (expr, var, around, order) = (iter.next()?, iter.next()?, iter.next()?, iter.next()?);
+    let name = match var {
+        Node::Var(name) => name,
+        _ => return None,
+    };
+    let order = match order {
+        Node::Num { val, .. } if val.is_integer() => val.to_integer().to_u32()?,
+        _ => return None,
+    };
+    if order > MAX_SERIES_ORDER {
+        return None;
+    }
+    expr.series(&name, &around, order)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::simplify_str;
+
+    #[test]
+    fn it_computes_the_elasticity() {
+        assert_eq!(simplify_str("sensitivity(x^2, x, 3)"), "2");
+        assert_eq!(simplify_str("sensitivity(3x + 1, x, 1)"), "3/4");
+        assert_eq!(simplify_str("sensitivity(5 * y^3, y, 2)"), "3");
+    }
+
+    #[test]
+    fn it_expands_series() {
+        assert_eq!(
+            simplify_str("series(cos(x), x, 0, 4)"),
+            "1 + -1/2 * x^2 + 1/24 * x^4"
+        );
+        assert_eq!(simplify_str("series(x^3, x, 1, 1)"), "1 + 3 * (-1 + x)");
+    }
+}
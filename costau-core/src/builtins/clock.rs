@@ -0,0 +1,225 @@
+//! Clock times, such as `time_in("UTC+9") + 36 h` for the time in Tokyo in
+//! 36 hours. The library never reads the system clock itself: the
+//! application gives a `Clock` in the `EvalOptions`, so that the calculations
+//! stay reproducible. The times are read when the calculation is done, not
+//! when it is parsed, and are quantities in seconds since 1970-01-01 00:00
+//! UTC, shifted by the offset of their time zone, so that the differences
+//! between them are durations.
+
+use num_traits::ToPrimitive;
+
+use crate::node::{EvalOptions, Node, Unit};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Gives the current time to `now()` and `time_in`.
+pub trait Clock {
+    /// The number of seconds since 1970-01-01 00:00 UTC.
+    fn now(&self) -> i64;
+}
+
+/// Returns the offset from UTC in seconds of a time zone such as `UTC`,
+/// `UTC+9` or `GMT-3:30`.
+pub fn parse_zone(zone: &str) -> Option<i64> {
+    let upper = zone.trim().to_ascii_uppercase();
+    if !upper.starts_with("UTC") && !upper.starts_with("GMT") {
+        return None;
+    }
+    let offset = &upper[3..];
+    if offset.is_empty() {
+        return Some(0);
+    }
+    let sign = match offset.as_bytes()[0] {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let mut parts = offset[1..].splitn(2, ':');
+    let parse_part = |part: &str| {
+        if part.is_empty() || part.len() > 2 || !part.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        part.parse::<i64>().ok()
+    };
+    let hours = parse_part(parts.next()?)?;
+    let minutes = match parts.next() {
+        Some(part) => parse_part(part)?,
+        None => 0,
+    };
+    // the zones go from UTC-12 to UTC+14
+    if hours > 14 || minutes >= 60 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Writes the offset of a time zone as `parse_zone` reads it, such as
+/// `UTC+9` or `UTC-3:30`.
+pub fn format_zone(offset: i64) -> String {
+    if offset == 0 {
+        return "UTC".to_owned();
+    }
+    let sign = if offset < 0 { '-' } else { '+' };
+    let minutes = offset.abs() / 60;
+    match minutes % 60 {
+        0 => format!("UTC{}{}", sign, minutes / 60),
+        m => format!("UTC{}{}:{:02}", sign, minutes / 60, m),
+    }
+}
+
+/// Returns the year, the month and the day of a number of days since
+/// 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // the months start in March
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Writes a time such as the result of `time_in("UTC+9") + 36 h` as a date
+/// and a time of the day, such as `2026-10-18 05:30:00`, with the clock of the
+/// options. Returns `None` if the node is not a time.
+pub fn format_time(node: &Node, options: &EvalOptions) -> Option<String> {
+    let converted = Node::Quantity(Box::new(node.clone()), Unit::Second)
+        .simplify_with_options(options)
+        .ok()?
+        .result;
+    let total = match converted {
+        Node::Quantity(inner, Unit::Second) => match *inner {
+            Node::Num { val, .. } => val.floor().to_integer().to_i64()?,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let (year, month, day) = civil_from_days(total.div_euclid(SECONDS_PER_DAY));
+    let time = total.rem_euclid(SECONDS_PER_DAY);
+    Some(format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::{ApproxEq, F64Margin};
+
+    use super::*;
+    use crate::node::{EvalError, SimplifyError};
+    use crate::parser::ParseError;
+    use crate::test_util::{parse, try_parse};
+    use std::sync::Arc;
+
+    // A clock that is stopped at a time.
+    struct Stopped(i64);
+
+    impl Clock for Stopped {
+        fn now(&self) -> i64 {
+            self.0
+        }
+    }
+
+    fn at(time: i64) -> EvalOptions {
+        EvalOptions {
+            clock: Some(Arc::new(Stopped(time))),
+            ..EvalOptions::default()
+        }
+    }
+
+    // 2026-10-16 14:30:00 UTC
+    const NOW: i64 = 1_792_161_000;
+
+    #[test]
+    fn it_parses_time_zones() {
+        assert_eq!(parse_zone("UTC"), Some(0));
+        assert_eq!(parse_zone("UTC+9"), Some(9 * 3600));
+        assert_eq!(parse_zone("gmt-3:30"), Some(-(3 * 3600 + 30 * 60)));
+        assert_eq!(parse_zone("UTC+05:45"), Some(5 * 3600 + 45 * 60));
+        assert_eq!(parse_zone("UTC+15"), None);
+        assert_eq!(parse_zone("UTC9"), None);
+        assert_eq!(parse_zone("Tokyo"), None);
+
+        for &zone in &["UTC", "UTC+9", "UTC-3:30", "UTC+5:45"] {
+            assert_eq!(format_zone(parse_zone(zone).unwrap()), zone);
+        }
+    }
+
+    #[test]
+    fn it_does_clock_arithmetic() {
+        let format_str = |expr: &str| format_time(&parse(expr), &at(NOW));
+        assert_eq!(format_str("now()"), Some("2026-10-16 14:30:00".to_owned()));
+        assert_eq!(
+            format_str("time_in(\"UTC+9\") + 36 h"),
+            Some("2026-10-18 11:30:00".to_owned())
+        );
+        assert_eq!(
+            format_str("time_in(\"UTC-3:30\")"),
+            Some("2026-10-16 11:00:00".to_owned())
+        );
+        assert_eq!(
+            format_str("now() - 20600 days"),
+            Some("1970-05-23 14:30:00".to_owned())
+        );
+        assert_eq!(format_str("2 kg"), None);
+
+        // the differences are durations
+        let difference = parse("time_in(\"UTC+9\") - now() in h");
+        assert_eq!(
+            difference
+                .simplify_with_options(&at(NOW))
+                .unwrap()
+                .result
+                .to_string(),
+            "9 h"
+        );
+
+        assert_eq!(
+            try_parse("time_in(\"Mars\")"),
+            Err(ParseError::InvalidArguments { index: 0 })
+        );
+        // the parentheses are required
+        assert_eq!(try_parse("now("), Err(ParseError::EarlyEof));
+        assert_eq!(
+            try_parse("time_in \"UTC\""),
+            Err(ParseError::UnexpectedToken { index: 8 })
+        );
+    }
+
+    #[test]
+    fn it_reads_the_clock_when_calculating() {
+        // the same node gives the time of each calculation
+        let node = parse("time_in(\"UTC+9\") + 1 h");
+        assert_eq!(node.to_string(), "time_in(\"UTC+9\") + 1 h");
+        assert_eq!(
+            format_time(&node, &at(NOW)),
+            Some("2026-10-17 00:30:00".to_owned())
+        );
+        assert_eq!(
+            format_time(&node, &at(NOW + 60)),
+            Some("2026-10-17 00:31:00".to_owned())
+        );
+        let eval = node.eval_with_options(&at(NOW)).unwrap();
+        assert!(eval
+            .val
+            .approx_eq((NOW + 10 * 3600) as f64, F64Margin::default()));
+
+        // the times are only known with a clock
+        assert_eq!(node.eval(), Err(EvalError::NoClock));
+        assert_eq!(
+            node.simplify().map(|x| x.result),
+            Err(SimplifyError::NoClock)
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use num_rational::BigRational;
+
+use crate::node::util::common;
+use crate::node::Node;
+
+/// The tolerance of `a =~ b`
+pub fn default_tolerance() -> Node {
+    Node::Num {
+        val: BigRational::new(1.into(), 1_000_000_000.into()),
+        input_base: None,
+    }
+}
+
+/// `1` if `a` and `b` are equal up to the tolerance, `0` otherwise.
+/// The tolerance is absolute for numbers smaller than `1` and relative for
+/// bigger numbers: `|a - b| <= tol * max(1, |a|, |b|)`.
+pub fn approx_equal(a: Node, b: Node, tol: Node) -> Node {
+    let scale = Node::Max(vec![common::one(), a.clone().abs(), b.clone().abs()]);
+    let excess = (a - b).abs() - tol * scale;
+    // the sign of the excess is 1 only if the numbers are too far apart
+    common::one() - Node::Max(vec![common::zero(), excess.sign()])
+}
+
+/// `approx_equal` where the tolerance is optional.
+pub fn approx_equal_args(args: Vec<Node>) -> Option<Node> {
+    let mut iter = args.into_iter();
+    let (a, b) = (iter.next()?, iter.next()?);
+    let tol = iter.next().unwrap_or_else(default_tolerance);
+    if iter.next().is_some() {
+        return None;
+    }
+    Some(approx_equal(a, b, tol))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::simplify_str;
+
+    #[test]
+    fn it_compares_with_a_tolerance() {
+        assert_eq!(simplify_str("approx_equal(9.81, 9.8, 0.01)"), "1");
+        assert_eq!(simplify_str("approx_equal(9.82, 9.8, 0.001)"), "0");
+        // the tolerance is relative for big numbers
+        assert_eq!(simplify_str("approx_equal(1000, 1001, 0.001)"), "1");
+        assert_eq!(simplify_str("approx_equal(0.001, 0.002, 0.001)"), "1");
+    }
+
+    #[test]
+    fn it_compares_with_the_operator() {
+        assert_eq!(simplify_str("0.1 + 0.2 =~ 0.3"), "1");
+        assert_eq!(simplify_str("sqrt(2)^2 =~ 2"), "1");
+        assert_eq!(simplify_str("pi =~ 355/113"), "0");
+        assert_eq!(simplify_str("1 + 1 =~ 2 + 10^-12"), "1");
+    }
+}
@@ -0,0 +1,163 @@
+use num_bigint::{BigInt, BigUint};
+use num_rational::BigRational;
+use num_traits::{ToPrimitive, Zero};
+
+use crate::node::util::common::num;
+use crate::node::Node;
+
+/// The maximum number of dice for which we compute a distribution, to prevent
+/// the calculator from hanging.
+const MAX_COUNT: u32 = 100;
+
+/// The maximum number of sides of a die for which we compute a distribution.
+const MAX_SIDES: u32 = 1000;
+
+fn as_dice(node: &Node) -> Option<(u32, u32)> {
+    match node {
+        Node::Dice { count, sides } if *count > 0 && *sides > 0 => Some((*count, *sides)),
+        _ => None,
+    }
+}
+
+fn as_ratio(node: Node) -> Option<BigRational> {
+    match node.simplify().ok()?.result {
+        Node::Num { val, .. } => Some(val),
+        _ => None,
+    }
+}
+
+/// Returns the number of ways to get each sum, starting from the smallest sum
+/// which is `count`.
+fn distribution(count: u32, sides: u32) -> Option<Vec<BigUint>> {
+    if count > MAX_COUNT || sides > MAX_SIDES {
+        return None;
+    }
+    let sides = sides as usize;
+    let mut ways: Vec<BigUint> = vec![1u32.into()];
+    for _ in 0..count {
+        // With one more die, the number of ways to get `s` is the sum of
+        // the number of ways to get `s - 1`, ..., `s - sides` without it.
+        let mut next = Vec::with_capacity(ways.len() + sides - 1);
+        let mut window: BigUint = Zero::zero();
+        for s in 0..ways.len() + sides - 1 {
+            if s < ways.len() {
+                window += &ways[s];
+            }
+            if s >= sides {
+                window -= &ways[s - sides];
+            }
+            next.push(window.clone());
+        }
+        ways = next;
+    }
+    Some(ways)
+}
+
+/// Builds dice from their count and number of sides, as `dice(3, 6)` is the
+/// same as `3d6`.
+pub fn dice(count: Node, sides: Node) -> Option<Node> {
+    let count = as_ratio(count)?;
+    let sides = as_ratio(sides)?;
+    if !count.is_integer() || !sides.is_integer() {
+        return None;
+    }
+    let dice = Node::Dice {
+        count: count.to_integer().to_u32()?,
+        sides: sides.to_integer().to_u32()?,
+    };
+    // no dice or dice without sides, which the lexer rejects in `3d0`
+    as_dice(&dice)?;
+    Some(dice)
+}
+
+/// The expected value of the sum of the dice: `n(m+1)/2`
+pub fn mean(dice: Node) -> Option<Node> {
+    let (count, sides) = as_dice(&dice)?;
+    Some(num(BigRational::new(
+        BigInt::from(count) * (BigInt::from(sides) + 1),
+        2.into(),
+    )))
+}
+
+/// The variance of the sum of the dice: `n(m^2-1)/12`
+pub fn variance(dice: Node) -> Option<Node> {
+    let (count, sides) = as_dice(&dice)?;
+    let sides = BigInt::from(sides);
+    Some(num(BigRational::new(
+        BigInt::from(count) * (&sides * &sides - 1),
+        12.into(),
+    )))
+}
+
+fn prob_where<F: Fn(&BigRational) -> bool>(dice: Node, f: F) -> Option<Node> {
+    let (count, sides) = as_dice(&dice)?;
+    let ways = distribution(count, sides)?;
+    let mut favorable: BigUint = Zero::zero();
+    for (i, w) in ways.iter().enumerate() {
+        let sum = BigRational::from_integer((u64::from(count) + i as u64).into());
+        if f(&sum) {
+            favorable += w;
+        }
+    }
+    let total = BigUint::from(sides).pow(count);
+    Some(num(BigRational::new(favorable.into(), total.into())))
+}
+
+/// The probability that the sum of the dice is `value`.
+pub fn prob(dice: Node, value: Node) -> Option<Node> {
+    let value = as_ratio(value)?;
+    prob_where(dice, |sum| *sum == value)
+}
+
+/// The probability that the sum of the dice is greater than or equal to
+/// `threshold`.
+pub fn prob_at_least(dice: Node, threshold: Node) -> Option<Node> {
+    let threshold = as_ratio(threshold)?;
+    prob_where(dice, |sum| *sum >= threshold)
+}
+
+/// The probability that the sum of the dice is less than or equal to
+/// `threshold`.
+pub fn prob_at_most(dice: Node, threshold: Node) -> Option<Node> {
+    let threshold = as_ratio(threshold)?;
+    prob_where(dice, |sum| *sum <= threshold)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ParseError;
+    use crate::test_util::{simplify_str, try_parse};
+
+    #[test]
+    fn it_computes_the_distribution_of_dice() {
+        let ways: Vec<u32> = distribution(2, 6)
+            .unwrap()
+            .iter()
+            .map(|x| x.to_u32().unwrap())
+            .collect();
+        assert_eq!(ways, vec![1, 2, 3, 4, 5, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(distribution(MAX_COUNT + 1, 6), None);
+    }
+
+    #[test]
+    fn it_computes_exact_statistics() {
+        assert_eq!(simplify_str("dice_mean(3d6)"), "21/2");
+        assert_eq!(simplify_str("dice_variance(3d6)"), "35/4");
+        assert_eq!(simplify_str("dice_prob(2d6, 7)"), "1/6");
+        assert_eq!(simplify_str("dice_prob_at_least(3d6, 3*6)"), "1/216");
+        assert_eq!(simplify_str("dice_prob_at_most(dice(2, 4), 100)"), "1");
+    }
+
+    #[test]
+    fn it_rejects_dice_without_sides() {
+        assert_eq!(
+            try_parse("dice(3, 0)"),
+            Err(ParseError::InvalidArguments { index: 0 })
+        );
+        assert_eq!(
+            try_parse("dice(0, 6)"),
+            Err(ParseError::InvalidArguments { index: 0 })
+        );
+    }
+}
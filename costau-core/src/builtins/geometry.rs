@@ -0,0 +1,156 @@
+use num_rational::BigRational;
+use num_traits::{Signed, Zero};
+
+use crate::node::util::common;
+use crate::node::{ConstKind, Node};
+
+fn pi() -> Node {
+    Node::Const(ConstKind::Pi)
+}
+
+/// The area of a disk: `pi*r^2`
+pub fn area_circle(r: Node) -> Node {
+    pi() * r.sqr()
+}
+
+/// The surface area of a sphere: `4pi*r^2`
+pub fn area_sphere(r: Node) -> Node {
+    common::num(BigRational::from_integer(4.into())) * pi() * r.sqr()
+}
+
+/// The area of a triangle: `b*h/2`
+pub fn area_triangle(base: Node, height: Node) -> Node {
+    base * height / common::two()
+}
+
+/// The area of a rectangle: `w*h`
+pub fn area_rectangle(width: Node, height: Node) -> Node {
+    width * height
+}
+
+/// The perimeter of a circle: `2pi*r`
+pub fn circumference(r: Node) -> Node {
+    common::two() * pi() * r
+}
+
+/// The volume of a ball: `4/3*pi*r^3`
+pub fn volume_sphere(r: Node) -> Node {
+    common::num(BigRational::new(4.into(), 3.into()))
+        * pi()
+        * Node::Exp(Box::new(r), Box::new(common::three()))
+}
+
+/// The volume of a cylinder: `pi*r^2*h`
+pub fn volume_cylinder(r: Node, height: Node) -> Node {
+    pi() * r.sqr() * height
+}
+
+/// The volume of a cone: `pi*r^2*h/3`
+pub fn volume_cone(r: Node, height: Node) -> Node {
+    common::num(BigRational::new(1.into(), 3.into())) * pi() * r.sqr() * height
+}
+
+/// The area of a simple polygon given by the list of its vertices, using the
+/// shoelace formula.
+/// If every coordinate is a number, then the area is computed directly.
+/// Returns `None` if there are less than three vertices or if a vertex is not
+/// a point in the plane.
+pub fn area_polygon(vertices: Vec<Node>) -> Option<Node> {
+    // accept both `area_polygon((0, 0), (1, 0), (0, 1))` and
+    // `area_polygon(((0, 0), (1, 0), (0, 1)))`
+    let vertices = match vertices.as_slice() {
+        [Node::Vector(items)] if items.iter().all(|i| matches!(i, Node::Vector(_))) => {
+            vertices.into_iter().next().unwrap().into_items()
+        }
+        _ => vertices,
+    };
+    if vertices.len() < 3 {
+        return None;
+    }
+    let mut points = Vec::with_capacity(vertices.len());
+    for vertex in vertices {
+        match vertex {
+            Node::Vector(coords) if coords.len() == 2 => {
+                let mut iter = coords.into_iter();
+                points.push((iter.next().unwrap(), iter.next().unwrap()));
+            }
+            _ => return None,
+        }
+    }
+
+    if let Some(area) = exact_area_polygon(&points) {
+        return Some(Node::Num {
+            val: area,
+            input_base: None,
+        });
+    }
+
+    let mut terms = Vec::with_capacity(points.len());
+    for (i, (x1, y1)) in points.iter().enumerate() {
+        let (x2, y2) = &points[(i + 1) % points.len()];
+        terms.push(x1.clone() * y2.clone() - x2.clone() * y1.clone());
+    }
+    Some(Node::Sum(terms).abs() / common::two())
+}
+
+fn exact_area_polygon(points: &[(Node, Node)]) -> Option<BigRational> {
+    let mut twice_area: BigRational = Zero::zero();
+    for (i, (x1, y1)) in points.iter().enumerate() {
+        let (x2, y2) = &points[(i + 1) % points.len()];
+        match (x1, y1, x2, y2) {
+            (
+                Node::Num { val: x1, .. },
+                Node::Num { val: y1, .. },
+                Node::Num { val: x2, .. },
+                Node::Num { val: y2, .. },
+            ) => twice_area += x1 * y2 - x2 * y1,
+            _ => return None,
+        }
+    }
+    Some(twice_area.abs() / BigRational::from_integer(2.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::simplify_str;
+
+    #[test]
+    fn it_keeps_pi_in_results() {
+        assert_eq!(simplify_str("area_circle(2)"), "4pi");
+        assert_eq!(simplify_str("volume_sphere(3)"), "36pi");
+        assert_eq!(simplify_str("circumference(1/2)"), "pi");
+    }
+
+    #[test]
+    fn it_computes_the_area_of_polygons() {
+        // a square with a side of 2, clockwise
+        assert_eq!(
+            simplify_str("area_polygon((0, 0), (0, 2), (2, 2), (2, 0))"),
+            "4"
+        );
+        // a triangle, given as a single list
+        assert_eq!(
+            simplify_str("area_polygon(((0, 0), (1, 0), (0, 1)))"),
+            "1/2"
+        );
+        // a rectangle with a symbolic width
+        assert_eq!(
+            simplify_str("area_polygon((0, 0), (0, 2), (w, 2), (w, 0))"),
+            "2 * abs w"
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_polygons() {
+        let two = || common::two();
+        assert_eq!(
+            area_polygon(vec![
+                Node::Vector(vec![two(), two()]),
+                Node::Vector(vec![two(), two()])
+            ]),
+            None
+        );
+        assert_eq!(area_polygon(vec![two(), two(), two()]), None);
+    }
+}
@@ -0,0 +1,47 @@
+use crate::node::util::common;
+use crate::node::Node;
+
+/// The weighted average of `values`: `sum(v*w)/sum(w)`
+/// Returns `None` if `values` and `weights` are not lists of the same length.
+pub fn wavg(values: Node, weights: Node) -> Option<Node> {
+    let values = values.into_items();
+    let weights = weights.into_items();
+    if values.len() != weights.len() {
+        return None;
+    }
+    let total_weight = Node::Sum(weights.clone());
+    let total = Node::Sum(
+        values
+            .into_iter()
+            .zip(weights)
+            .map(|(v, w)| v * w)
+            .collect(),
+    );
+    Some(total / total_weight)
+}
+
+/// The score needed on the remaining part of a course to end with the
+/// `target` grade, given the `current` grade and the `weight` of the remaining
+/// part between 0 and 1.
+pub fn needed_score(current: Node, weight: Node, target: Node) -> Node {
+    // current*(1 - weight) + x*weight = target
+    (target - current * (common::one() - weight.clone())) / weight
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::simplify_str;
+
+    #[test]
+    fn it_computes_weighted_averages_exactly() {
+        assert_eq!(simplify_str("wavg((12, 15, 10), (1, 2, 3))"), "12");
+        assert_eq!(simplify_str("wavg((1, 2), (1, 2))"), "5/3");
+    }
+
+    #[test]
+    fn it_computes_the_needed_score() {
+        // 12 on 60% of the course, and we want 14 in the end
+        assert_eq!(simplify_str("needed_score(12, 0.4, 14)"), "17");
+        assert_eq!(simplify_str("needed_score(10, 1/3, 11)"), "13");
+    }
+}
@@ -0,0 +1,100 @@
+//! Functions that the user can call but which are not nodes by themselves.
+//! Instead, they are defined in terms of other nodes, so that the result can
+//! be simplified exactly.
+
+pub mod algebra;
+pub mod calculus;
+pub mod clock;
+pub mod compare;
+pub mod dice;
+pub mod geometry;
+pub mod grades;
+pub mod ratio;
+pub mod roman;
+pub mod rounding;
+pub mod vector;
+
+use crate::lexer::IdentKind;
+use crate::node::Node;
+
+/// Builds the node for a call to the builtin function `kind` with the given
+/// arguments.
+/// Returns `None` if the function does not accept the arguments.
+pub(crate) fn call(kind: &IdentKind, args: Vec<Node>) -> Option<Node> {
+    match kind {
+        IdentKind::AreaCircle => unary(args, geometry::area_circle),
+        IdentKind::AreaSphere => unary(args, geometry::area_sphere),
+        IdentKind::AreaTriangle => binary(args, geometry::area_triangle),
+        IdentKind::AreaRectangle => binary(args, geometry::area_rectangle),
+        IdentKind::AreaPolygon => geometry::area_polygon(args),
+        IdentKind::Circumference => unary(args, geometry::circumference),
+        IdentKind::VolumeSphere => unary(args, geometry::volume_sphere),
+        IdentKind::VolumeCylinder => binary(args, geometry::volume_cylinder),
+        IdentKind::VolumeCone => binary(args, geometry::volume_cone),
+        IdentKind::Rotate => binary(args, vector::rotate),
+        IdentKind::AngleBetween => binary(args, vector::angle_between),
+        IdentKind::Proj => binary(args, vector::proj),
+        IdentKind::Orthogonalize => vector::orthogonalize(args),
+        IdentKind::Dice => binary(args, dice::dice),
+        IdentKind::DiceMean => unary(args, dice::mean),
+        IdentKind::DiceVariance => unary(args, dice::variance),
+        IdentKind::DiceProb => binary(args, dice::prob),
+        IdentKind::DiceProbAtLeast => binary(args, dice::prob_at_least),
+        IdentKind::DiceProbAtMost => binary(args, dice::prob_at_most),
+        IdentKind::Wavg => binary(args, grades::wavg),
+        IdentKind::NeededScore => ternary(args, grades::needed_score),
+        IdentKind::RatioSplit => binary(args, ratio::split),
+        IdentKind::RatioScale => binary(args, ratio::scale),
+        IdentKind::ApproxEqual => compare::approx_equal_args(args),
+        IdentKind::Sensitivity => ternary(args, calculus::sensitivity),
+        IdentKind::Series => calculus::series_args(args),
+        IdentKind::Solve => ternary(args, algebra::solve),
+        IdentKind::RoundTo => binary(args, rounding::round_to),
+        IdentKind::Roman => unary(args, roman::roman),
+        IdentKind::ToRoman => unary(args, roman::to_roman),
+        _ => None,
+    }
+}
+
+// The functions below accept builtins that return either a `Node` or an
+// `Option<Node>`.
+
+fn unary<F, R>(args: Vec<Node>, f: F) -> Option<Node>
+where
+    F: Fn(Node) -> R,
+    R: Into<Option<Node>>,
+{
+    if args.len() != 1 {
+        return None;
+    }
+    f(args.into_iter().next().unwrap()).into()
+}
+
+fn binary<F, R>(args: Vec<Node>, f: F) -> Option<Node>
+where
+    F: Fn(Node, Node) -> R,
+    R: Into<Option<Node>>,
+{
+    if args.len() != 2 {
+        return None;
+    }
+    let mut iter = args.into_iter();
+    let a = iter.next().unwrap();
+    let b = iter.next().unwrap();
+    f(a, b).into()
+}
+
+fn ternary<F, R>(args: Vec<Node>, f: F) -> Option<Node>
+where
+    F: Fn(Node, Node, Node) -> R,
+    R: Into<Option<Node>>,
+{
+    if args.len() != 3 {
+        return None;
+    }
+    let mut iter = args.into_iter();
+    let a = iter.next().unwrap();
+    let b = iter.next().unwrap();
+    let c = iter.next().unwrap();
+    f(a, b, c).into()
+}
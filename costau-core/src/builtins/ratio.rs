@@ -0,0 +1,51 @@
+use crate::node::Node;
+
+fn as_terms(ratio: Node) -> Option<Vec<Node>> {
+    match ratio {
+        Node::Ratio(terms) => Some(terms),
+        _ => None,
+    }
+}
+
+/// Splits `total` into parts following the ratio, for example
+/// `ratio_split(3:2, 500) = (300, 200)`.
+pub fn split(ratio: Node, total: Node) -> Option<Node> {
+    let terms = as_terms(ratio)?;
+    let sum = Node::Sum(terms.clone());
+    Some(Node::Vector(
+        terms
+            .into_iter()
+            .map(|t| t * total.clone() / sum.clone())
+            .collect(),
+    ))
+}
+
+/// Scales the ratio so that its first term becomes `first`, for example
+/// `ratio_scale(3:2, 150) = (150, 100)`.
+pub fn scale(ratio: Node, first: Node) -> Option<Node> {
+    let terms = as_terms(ratio)?;
+    let reference = terms[0].clone();
+    Some(Node::Vector(
+        terms
+            .into_iter()
+            .map(|t| t * first.clone() / reference.clone())
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::simplify_str;
+
+    #[test]
+    fn it_reduces_ratios() {
+        assert_eq!(simplify_str("6:4"), "3:2");
+        assert_eq!(simplify_str("0.5:1:1.5"), "1:2:3");
+    }
+
+    #[test]
+    fn it_scales_ratios() {
+        assert_eq!(simplify_str("ratio_split(6:4, 500)"), "(300, 200)");
+        assert_eq!(simplify_str("ratio_scale(3:2, 100)"), "(100, 200/3)");
+    }
+}
@@ -0,0 +1,142 @@
+use num_rational::BigRational;
+use num_traits::ToPrimitive;
+
+use crate::node::Node;
+
+/// The largest number that can be written without a bar over the numerals
+pub const MAX_ROMAN: u32 = 3999;
+
+const NUMERALS: [(u32, &str); 13] = [
+    (1000, "M"),
+    (900, "CM"),
+    (500, "D"),
+    (400, "CD"),
+    (100, "C"),
+    (90, "XC"),
+    (50, "L"),
+    (40, "XL"),
+    (10, "X"),
+    (9, "IX"),
+    (5, "V"),
+    (4, "IV"),
+    (1, "I"),
+];
+
+/// Writes a number between 1 and `MAX_ROMAN` in Roman numerals, such as
+/// `MMXXIV` for 2024.
+pub fn write_roman(mut n: u32) -> Option<String> {
+    if n == 0 || n > MAX_ROMAN {
+        return None;
+    }
+    let mut out = String::new();
+    for &(val, numeral) in NUMERALS.iter() {
+        while n >= val {
+            out.push_str(numeral);
+            n -= val;
+        }
+    }
+    Some(out)
+}
+
+/// Reads a number in Roman numerals in any case. Only the standard form is
+/// accepted, so `IIII` and `IC` are not valid.
+pub fn parse_roman(s: &str) -> Option<u32> {
+    let s = s.to_ascii_uppercase();
+    let mut rest = s.as_str();
+    let mut n = 0;
+    for &(val, numeral) in NUMERALS.iter() {
+        while rest.starts_with(numeral) {
+            n += val;
+            rest = &rest[numeral.len()..];
+        }
+    }
+    // reading greedily accepts some invalid numerals, which are not written
+    // back the same way
+    if !rest.is_empty() || write_roman(n).as_ref() != Some(&s) {
+        return None;
+    }
+    Some(n)
+}
+
+/// The number written in Roman numerals in a string, such as in
+/// `roman("MMXXIV")`.
+pub fn from_roman(numeral: &str) -> Option<Node> {
+    Some(Node::Num {
+        val: BigRational::from_integer(parse_roman(numeral)?.into()),
+        input_base: None,
+    })
+}
+
+/// The number written in Roman numerals as the name of a variable, such as
+/// the result of `to_roman`.
+pub fn roman(numeral: Node) -> Option<Node> {
+    match numeral {
+        Node::Var(name) => from_roman(&name),
+        _ => None,
+    }
+}
+
+/// Writes an integer in Roman numerals, such as `to_roman(2024)`. The
+/// numerals are the name of a variable, which `roman` reads back.
+pub fn to_roman(x: Node) -> Option<Node> {
+    let val = match x.simplify().ok()?.result {
+        Node::Num { val, .. } if val.is_integer() => val,
+        _ => return None,
+    };
+    Some(Node::Var(write_roman(val.to_integer().to_u32()?)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ParseError;
+    use crate::test_util::{simplify_str, try_parse};
+
+    #[test]
+    fn it_converts_roman_numerals() {
+        assert_eq!(write_roman(2024), Some("MMXXIV".to_owned()));
+        assert_eq!(write_roman(3999), Some("MMMCMXCIX".to_owned()));
+        assert_eq!(write_roman(0), None);
+        assert_eq!(write_roman(4000), None);
+        for n in 1..=MAX_ROMAN {
+            assert_eq!(parse_roman(&write_roman(n).unwrap()), Some(n));
+        }
+    }
+
+    #[test]
+    fn it_validates_roman_numerals() {
+        assert_eq!(parse_roman("mcmxc"), Some(1990));
+        assert_eq!(parse_roman("IIII"), None);
+        assert_eq!(parse_roman("IC"), None);
+        assert_eq!(parse_roman("VX"), None);
+        assert_eq!(parse_roman(""), None);
+    }
+
+    #[test]
+    fn it_reads_roman_numerals_in_expressions() {
+        assert_eq!(simplify_str("roman(\"MMXXIV\") - roman(\"xxiv\")"), "2000");
+        assert_eq!(simplify_str("roman(MMXXIV) - roman(XXIV)"), "2000");
+        assert_eq!(
+            try_parse("roman(\"IIII\")"),
+            Err(ParseError::InvalidArguments { index: 0 })
+        );
+        assert_eq!(try_parse("roman(\"X\""), Err(ParseError::EarlyEof));
+    }
+
+    #[test]
+    fn it_writes_roman_numerals_in_expressions() {
+        assert_eq!(simplify_str("to_roman(2000 + 24)"), "MMXXIV");
+        assert_eq!(simplify_str("roman(to_roman(1990))"), "1990");
+        for expr in &[
+            "to_roman(0)",
+            "to_roman(4000)",
+            "to_roman(1/2)",
+            "to_roman(x)",
+        ] {
+            assert_eq!(
+                try_parse(expr),
+                Err(ParseError::InvalidArguments { index: 0 })
+            );
+        }
+    }
+}
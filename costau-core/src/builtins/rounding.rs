@@ -0,0 +1,20 @@
+use crate::node::Node;
+
+/// Rounds `x` to the nearest multiple of `resolution`, such as the nearest
+/// 1/16 inch. This is exact for rational numbers.
+pub fn round_to(x: Node, resolution: Node) -> Node {
+    (x / resolution.clone()).round() * resolution
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::simplify_str;
+
+    #[test]
+    fn it_rounds_to_a_resolution() {
+        assert_eq!(simplify_str("round_to(5.53, 1/16)"), "11/2");
+        assert_eq!(simplify_str("round_to(pi, 1/16)"), "25/8");
+        assert_eq!(simplify_str("round_to(12.26, 0.5)"), "25/2");
+        assert_eq!(simplify_str("round_to(-7.4, 5)"), "-5");
+    }
+}
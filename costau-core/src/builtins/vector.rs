@@ -0,0 +1,175 @@
+use num_rational::BigRational;
+use num_traits::Zero;
+
+use crate::node::Node;
+
+fn as_ratio(node: Node) -> Option<BigRational> {
+    match node.simplify().ok()?.result {
+        Node::Num { val, .. } => Some(val),
+        _ => None,
+    }
+}
+
+/// Rotates the point `(x, y)` counterclockwise around the origin.
+/// Returns `None` if `point` is not a point in the plane.
+pub fn rotate(point: Node, angle: Node) -> Option<Node> {
+    let (x, y) = match point {
+        Node::Vector(coords) if coords.len() == 2 => {
+            let mut iter = coords.into_iter();
+            (iter.next().unwrap(), iter.next().unwrap())
+        }
+        _ => return None,
+    };
+    let cos = angle.clone().cos();
+    let sin = angle.sin();
+    Some(Node::Vector(vec![
+        x.clone() * cos.clone() - y.clone() * sin.clone(),
+        x * sin + y * cos,
+    ]))
+}
+
+/// The dot product of two vectors.
+/// Returns `None` if they are not vectors with the same dimension.
+pub fn dot(a: Node, b: Node) -> Option<Node> {
+    match (a, b) {
+        (Node::Vector(a), Node::Vector(b)) if a.len() == b.len() && !a.is_empty() => Some(
+            Node::Sum(a.into_iter().zip(b).map(|(a, b)| a * b).collect()),
+        ),
+        _ => None,
+    }
+}
+
+/// The length of a vector.
+/// Returns `None` if `v` is not a vector.
+pub fn norm(v: Node) -> Option<Node> {
+    dot(v.clone(), v).map(Node::sqrt)
+}
+
+/// The unsigned angle between two vectors, in [0; pi].
+/// Returns `None` if they are not vectors with the same dimension.
+pub fn angle_between(a: Node, b: Node) -> Option<Node> {
+    let cos = dot(a.clone(), b.clone())? / (norm(a)? * norm(b)?);
+    Some(cos.acos())
+}
+
+/// The projection of `u` onto `v`.
+/// Returns `None` if they are not vectors with the same dimension.
+pub fn proj(u: Node, v: Node) -> Option<Node> {
+    let factor = dot(u, v.clone())? / dot(v.clone(), v.clone())?;
+    Some(Node::Vector(
+        v.into_items()
+            .into_iter()
+            .map(|x| x * factor.clone())
+            .collect(),
+    ))
+}
+
+fn rational_dot(a: &[BigRational], b: &[BigRational]) -> BigRational {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Orthogonal vectors that span the same space as the given ones, with the
+/// Gram-Schmidt process. The vectors that depend on the previous ones are
+/// dropped. The arithmetic is exact, so the vectors are not normalized.
+/// Returns `None` if they are not vectors with the same dimension and
+/// rational coordinates.
+pub fn orthogonalize(vectors: Vec<Node>) -> Option<Node> {
+    // accept both `orthogonalize((1, 0), (1, 1))` and
+    // `orthogonalize(((1, 0), (1, 1)))`
+    let vectors = match vectors.as_slice() {
+        [Node::Vector(items)] if items.iter().all(|i| matches!(i, Node::Vector(_))) => {
+            vectors.into_iter().next().unwrap().into_items()
+        }
+        _ => vectors,
+    };
+    let mut basis: Vec<Vec<BigRational>> = Vec::new();
+    let mut dimension = None;
+    for vector in vectors {
+        let coords = match vector {
+            Node::Vector(coords) if !coords.is_empty() => coords,
+            _ => return None,
+        };
+        if *dimension.get_or_insert(coords.len()) != coords.len() {
+            return None;
+        }
+        let mut w = coords
+            .into_iter()
+            .map(as_ratio)
+            .collect::<Option<Vec<_>>>()?;
+        for b in &basis {
+            let factor = rational_dot(&w, b) / rational_dot(b, b);
+            for (x, y) in w.iter_mut().zip(b) {
+                *x -= &factor * y;
+            }
+        }
+        if w.iter().any(|x| !x.is_zero()) {
+            basis.push(w);
+        }
+    }
+    // there must be at least one vector
+    dimension?;
+    Some(Node::Vector(
+        basis
+            .into_iter()
+            .map(|b| {
+                Node::Vector(
+                    b.into_iter()
+                        .map(|val| Node::Num {
+                            val,
+                            input_base: None,
+                        })
+                        .collect(),
+                )
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{simplify, simplify_str, try_parse};
+
+    #[test]
+    fn it_rotates_exactly_with_common_angles() {
+        assert_eq!(simplify_str("rotate((2, 1), pi/2)"), "(-1, 2)");
+        assert_eq!(simplify_str("rotate((1, 0), 3pi)"), "(-1, 0)");
+    }
+
+    #[test]
+    fn it_finds_common_angles_between_vectors() {
+        assert_eq!(simplify_str("angle_between((1, 0), (0, 3))"), "1/2 * pi");
+        assert_eq!(simplify_str("angle_between((1, 0), (-2, 0))"), "pi");
+        assert_eq!(simplify_str("angle_between((1, 0), (1, 1))"), "1/4 * pi");
+        assert_eq!(simplify_str("angle_between((2, 2), (2, 2))"), "0");
+    }
+
+    #[test]
+    fn it_approximates_other_angles_between_vectors() {
+        let angle = simplify("angle_between((1, 0), (3, 4))");
+        assert!((angle.eval().unwrap().val - 0.6f64.acos()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_projects_vectors() {
+        assert_eq!(simplify_str("proj((2, 3), (1, 0))"), "(2, 0)");
+        assert_eq!(simplify_str("proj((1, 2), (3, 1))"), "(3/2, 1/2)");
+        assert!(try_parse("proj((1, 2), (3, 1, 0))").is_err());
+    }
+
+    #[test]
+    fn it_orthogonalizes_vectors_exactly() {
+        assert_eq!(
+            simplify_str("orthogonalize((1, 1, 0), (1, 0, 1), (0, 1, 1))"),
+            "((1, 1, 0), (1/2, -1/2, 1), (-2/3, 2/3, 2/3))"
+        );
+        // dependent vectors are dropped
+        assert_eq!(
+            simplify_str("orthogonalize(((1, 2), (2, 4), (0, 1)))"),
+            "((1, 2), (-2/5, 1/5))"
+        );
+        assert_eq!(
+            simplify_str("orthogonalize((1/2, 0), (1, 1))"),
+            "((1/2, 0), (0, 1))"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Physical constants, with their uncertainty and where their value comes
+//! from, so that results computed from them can tell how precise they are.
+
+/// The source of most of the values below.
+pub const CODATA_2018: &str = "CODATA 2018";
+
+/// A physical constant.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct PhysicalConstant {
+    /// The variable name of the constant in expressions
+    pub symbol: &'static str,
+
+    pub name: &'static str,
+
+    pub value: f64,
+
+    /// The standard uncertainty of the value, which is zero for the
+    /// constants that define the SI units
+    pub uncertainty: f64,
+
+    /// The SI unit of the value
+    pub unit: &'static str,
+
+    /// Where the value and the uncertainty come from
+    pub source: &'static str,
+}
+
+impl PhysicalConstant {
+    /// Returns whether the value is exact by definition.
+    pub fn is_exact(&self) -> bool {
+        self.uncertainty == 0.0
+    }
+}
+
+macro_rules! constant {
+    ($symbol:expr, $name:expr, $value:expr, $uncertainty:expr, $unit:expr) => {
+        PhysicalConstant {
+            symbol: $symbol,
+            name: $name,
+            value: $value,
+            uncertainty: $uncertainty,
+            unit: $unit,
+            source: CODATA_2018,
+        }
+    };
+}
+
+/// All the known physical constants.
+pub const CONSTANTS: &[PhysicalConstant] = &[
+    constant!(
+        "c",
+        "speed of light in vacuum",
+        299_792_458.0,
+        0.0,
+        "m s^-1"
+    ),
+    constant!("h", "Planck constant", 6.626_070_15e-34, 0.0, "J Hz^-1"),
+    constant!(
+        "hbar",
+        "reduced Planck constant",
+        1.054_571_817e-34,
+        0.0,
+        "J s"
+    ),
+    constant!("q_e", "elementary charge", 1.602_176_634e-19, 0.0, "C"),
+    constant!("k_B", "Boltzmann constant", 1.380_649e-23, 0.0, "J K^-1"),
+    constant!("N_A", "Avogadro constant", 6.022_140_76e23, 0.0, "mol^-1"),
+    constant!(
+        "R",
+        "molar gas constant",
+        8.314_462_618,
+        0.0,
+        "J mol^-1 K^-1"
+    ),
+    constant!(
+        "G",
+        "Newtonian constant of gravitation",
+        6.674_30e-11,
+        0.000_15e-11,
+        "m^3 kg^-1 s^-2"
+    ),
+    constant!(
+        "m_e",
+        "electron mass",
+        9.109_383_701_5e-31,
+        0.000_000_002_8e-31,
+        "kg"
+    ),
+    constant!(
+        "m_p",
+        "proton mass",
+        1.672_621_923_69e-27,
+        0.000_000_000_51e-27,
+        "kg"
+    ),
+    constant!(
+        "alpha",
+        "fine-structure constant",
+        7.297_352_569_3e-3,
+        0.000_000_001_1e-3,
+        ""
+    ),
+    constant!(
+        "eps_0",
+        "vacuum electric permittivity",
+        8.854_187_812_8e-12,
+        0.000_000_001_3e-12,
+        "F m^-1"
+    ),
+    constant!(
+        "mu_0",
+        "vacuum magnetic permeability",
+        1.256_637_062_12e-6,
+        0.000_000_000_19e-6,
+        "N A^-2"
+    ),
+];
+
+/// Returns the physical constant with the given symbol, if there is one.
+pub fn find(symbol: &str) -> Option<&'static PhysicalConstant> {
+    CONSTANTS.iter().find(|x| x.symbol == symbol)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_finds_constants() {
+        let g = find("G").unwrap();
+        assert_eq!(g.name, "Newtonian constant of gravitation");
+        assert!(!g.is_exact());
+        assert!(find("c").unwrap().is_exact());
+        assert_eq!(find("g"), None);
+    }
+}
@@ -0,0 +1,199 @@
+//! Differential testing of the simplifier: the value of an expression is
+//! approximated directly from the parsed tree, which is the oracle, and
+//! after simplifying it. Both must agree, so that a wrong simplification
+//! rule is caught even if nobody wrote a test for its result.
+
+use crate::lexer::{Lexer, Token};
+use crate::node::{EvalError, EvalSuccess, Node, SimplifyError};
+use crate::parser::Parser;
+
+/// How far apart the two values can be. They agree if either the absolute
+/// or the relative difference is small enough.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Tolerance {
+    pub absolute: f64,
+    pub relative: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Tolerance {
+        Tolerance {
+            absolute: 1e-9,
+            relative: 1e-9,
+        }
+    }
+}
+
+impl Tolerance {
+    // equal values are compared exactly first because the difference of two
+    // infinities of the same sign is not a number
+    #[allow(clippy::float_cmp)]
+    fn accepts(&self, a: f64, b: f64) -> bool {
+        if a == b {
+            return true;
+        }
+        let diff = (a - b).abs();
+        diff <= self.absolute || diff <= self.relative * a.abs().max(b.abs())
+    }
+}
+
+/// A difference between the direct and the symbolic results.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Divergence {
+    /// The node could not be simplified although it can be approximated
+    Simplify(SimplifyError),
+    /// Only one of the paths gives a value
+    Error {
+        simplified: Node,
+        direct: Result<EvalSuccess, EvalError>,
+        symbolic: Result<EvalSuccess, EvalError>,
+    },
+    /// The values are too far apart
+    Value {
+        simplified: Node,
+        direct: EvalSuccess,
+        symbolic: EvalSuccess,
+    },
+}
+
+/// Approximates the node before and after simplifying it, and returns how
+/// the results differ if they do. Nodes that cannot be approximated, such as
+/// nodes with variables, agree if they still cannot be after simplifying
+/// them.
+pub fn compare(node: &Node, tolerance: &Tolerance) -> Option<Divergence> {
+    let direct = node.eval();
+    let simplified = match node.clone().simplify() {
+        Ok(x) => x.result,
+        Err(err) if direct.is_ok() => return Some(Divergence::Simplify(err)),
+        Err(_) => return None,
+    };
+    let symbolic = simplified.eval();
+    match (direct, symbolic) {
+        (Ok(direct), Ok(symbolic)) => {
+            let agree = tolerance.accepts(direct.val, symbolic.val)
+                && tolerance.accepts(direct.imag, symbolic.imag);
+            if agree {
+                None
+            } else {
+                Some(Divergence::Value {
+                    simplified,
+                    direct,
+                    symbolic,
+                })
+            }
+        }
+        (Err(_), Err(_)) => None,
+        (direct, symbolic) => Some(Divergence::Error {
+            simplified,
+            direct,
+            symbolic,
+        }),
+    }
+}
+
+/// A line of a corpus whose results differ.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Mismatch {
+    /// The number of the line, starting at 1
+    pub line: usize,
+    pub expr: String,
+    pub divergence: Divergence,
+}
+
+/// Compares the results of each line of the corpus. The lines that cannot
+/// be parsed are skipped, since they are not about the simplifier.
+pub fn compare_corpus(corpus: &str, tolerance: &Tolerance) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    for (i, expr) in corpus.lines().enumerate() {
+        let tokens: Vec<Token> = match Lexer::new(expr).collect() {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        let node = match Parser::new(&tokens).parse() {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        if let Some(divergence) = compare(&node, tolerance) {
+            mismatches.push(Mismatch {
+                line: i + 1,
+                expr: expr.to_owned(),
+                divergence,
+            });
+        }
+    }
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use num_rational::BigRational;
+
+    use super::*;
+
+    #[test]
+    fn it_finds_no_divergence_in_the_regression_tests() {
+        let corpus = include_str!("../../test-calculations.txt");
+        let mismatches = compare_corpus(corpus, &Tolerance::default());
+        assert_eq!(mismatches, vec![]);
+    }
+
+    #[test]
+    fn it_finds_no_divergence_in_roots_of_powers_of_negative_numbers() {
+        let corpus = "sqrt((-3)^3)\n((-2)^2)^(1/2)\n((-8)^2)^(1/3)\n(4^3)^(1/2)\n((-3)^2)^(3/2)";
+        let mismatches = compare_corpus(corpus, &Tolerance::default());
+        assert_eq!(mismatches, vec![]);
+    }
+
+    #[test]
+    fn it_finds_no_divergence_in_rounding_near_integers() {
+        let corpus = "floor(sqrt(3)^2)\nceil(sqrt(2)^2)\nfloor(1/3 - sqrt(3)^-2)\nround(sqrt(5)^2 / 2)\nfloor(pi)";
+        let mismatches = compare_corpus(corpus, &Tolerance::default());
+        assert_eq!(mismatches, vec![]);
+    }
+
+    #[test]
+    fn it_reports_divergences() {
+        let num = |n: i64| Node::Num {
+            val: BigRational::from_integer(n.into()),
+            input_base: Some(10),
+        };
+        let node = num(1) + num(2);
+        assert_eq!(compare(&node, &Tolerance::default()), None);
+
+        // a simplification that would give the wrong value
+        let tolerance = Tolerance {
+            absolute: 0.0,
+            relative: 0.0,
+        };
+        assert!(!tolerance.accepts(1.0, 1.0 + 1e-15));
+        assert!(Tolerance::default().accepts(1e12, 1e12 + 1e-3));
+        assert!(!Tolerance::default().accepts(1.0, 1.1));
+
+        let mismatches = compare_corpus("1 + 2\n2 +\nx / x", &tolerance);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].line, 3);
+        match &mismatches[0].divergence {
+            Divergence::Error { direct, .. } => assert_eq!(*direct, Err(EvalError::UnboundVar)),
+            x => panic!("unexpected divergence {:?}", x),
+        }
+
+        // the direct approximation loses the 1, but the exact simplification
+        // does not
+        let mismatches = compare_corpus("1 + 2\n(1e16 + 1) - 1e16", &Tolerance::default());
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].line, 2);
+        assert_eq!(mismatches[0].expr, "(1e16 + 1) - 1e16");
+        match &mismatches[0].divergence {
+            Divergence::Value {
+                simplified,
+                direct,
+                symbolic,
+            } => {
+                assert_eq!(simplified.to_string(), "1");
+                assert_eq!((direct.val, direct.imag), (0.0, 0.0));
+                assert_eq!((symbolic.val, symbolic.imag), (1.0, 0.0));
+            }
+            x => panic!("unexpected divergence {:?}", x),
+        }
+    }
+}
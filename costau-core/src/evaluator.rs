@@ -0,0 +1,391 @@
+//! An evaluator runs calculations on background threads so that frontends
+//! can stay responsive, for example when showing results as the user types.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::atomic::{self, AtomicBool, AtomicU64};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::functions::{FunctionRegistry, Reader, SharedRegistry};
+use crate::node::EvalOptions;
+use crate::session::{evaluate, Entry, SessionError};
+
+/// How urgent a calculation is. Calculations with a higher priority are
+/// started first.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+/// A description of the error of a queued calculation.
+#[derive(Debug, PartialEq)]
+pub enum QueueError {
+    /// The calculation was cancelled or superseded before it finished
+    Cancelled,
+    Failed(SessionError),
+}
+
+struct Job {
+    expr: String,
+    priority: Priority,
+    /// Used to run jobs with the same priority in the order they were
+    /// submitted
+    seq: u64,
+    /// If set, the job is cancelled when a newer job of the same kind is
+    /// submitted
+    generation: Option<u64>,
+    /// The options of the evaluator when the job was submitted
+    options: EvalOptions,
+    cancelled: Arc<AtomicBool>,
+    sender: Sender<Result<Entry, QueueError>>,
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Job) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Job {}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Job) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Job {
+    fn cmp(&self, other: &Job) -> Ordering {
+        // the heap pops the greatest job first
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: BinaryHeap<Job>,
+    next_seq: u64,
+    shutting_down: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    available: Condvar,
+    latest_generation: AtomicU64,
+    functions: SharedRegistry,
+}
+
+impl Shared {
+    fn is_superseded(&self, job: &Job) -> bool {
+        match job.generation {
+            Some(g) => g < self.latest_generation.load(atomic::Ordering::SeqCst),
+            None => false,
+        }
+    }
+}
+
+/// A handle to a queued calculation.
+pub struct Ticket {
+    receiver: Receiver<Result<Entry, QueueError>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Ticket {
+    /// Blocks until the calculation is done.
+    pub fn wait(self) -> Result<Entry, QueueError> {
+        // the sender is only dropped without sending if the evaluator is
+        // dropped
+        self.receiver.recv().unwrap_or(Err(QueueError::Cancelled))
+    }
+
+    /// Returns the result of the calculation if it is done.
+    pub fn try_get(&self) -> Option<Result<Entry, QueueError>> {
+        match self.receiver.try_recv() {
+            Ok(r) => Some(r),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some(Err(QueueError::Cancelled)),
+        }
+    }
+
+    /// Cancels the calculation if it has not finished yet.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, atomic::Ordering::SeqCst);
+    }
+}
+
+/// Runs calculations on a pool of threads.
+pub struct Evaluator {
+    shared: Arc<Shared>,
+    options: Mutex<EvalOptions>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Evaluator {
+    /// Creates an evaluator with `threads` worker threads.
+    pub fn new(threads: usize) -> Evaluator {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            available: Condvar::new(),
+            latest_generation: AtomicU64::new(0),
+            functions: SharedRegistry::new(),
+        });
+        let workers = (0..threads)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || work(&shared))
+            })
+            .collect();
+        Evaluator {
+            shared,
+            options: Mutex::new(EvalOptions::default()),
+            workers,
+        }
+    }
+
+    /// Queues a calculation.
+    pub fn submit(&self, expr: &str, priority: Priority) -> Ticket {
+        self.push(expr, priority, None)
+    }
+
+    /// Queues a calculation and cancels the calculations that were previously
+    /// queued with this function. This is meant for showing the result while
+    /// the user is typing, where only the last expression matters.
+    pub fn submit_latest(&self, expr: &str, priority: Priority) -> Ticket {
+        let generation = self
+            .shared
+            .latest_generation
+            .fetch_add(1, atomic::Ordering::SeqCst)
+            + 1;
+        self.push(expr, priority, Some(generation))
+    }
+
+    /// Changes the functions that the next calculations can call, such as
+    /// `evaluator.update_functions(|x| x.define(f))`. The calculations that
+    /// already started keep the functions they started with.
+    pub fn update_functions<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut FunctionRegistry) -> R,
+    {
+        self.shared.functions.update(f)
+    }
+
+    /// Changes the options of the next calculations, such as the exchange
+    /// rates that convert the currencies. The calculations that are already
+    /// queued keep the options they were submitted with.
+    pub fn set_options(&self, options: EvalOptions) {
+        *self.options.lock().unwrap() = options;
+    }
+
+    fn push(&self, expr: &str, priority: Priority, generation: Option<u64>) -> Ticket {
+        let options = self.options.lock().unwrap().clone();
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let mut queue = self.shared.queue.lock().unwrap();
+        let seq = queue.next_seq;
+        queue.next_seq += 1;
+        queue.jobs.push(Job {
+            expr: expr.to_owned(),
+            priority,
+            seq,
+            generation,
+            options,
+            cancelled: cancelled.clone(),
+            sender,
+        });
+        self.shared.available.notify_one();
+        Ticket {
+            receiver,
+            cancelled,
+        }
+    }
+}
+
+impl Drop for Evaluator {
+    fn drop(&mut self) {
+        {
+            let mut queue = self.shared.queue.lock().unwrap();
+            queue.shutting_down = true;
+            // the tickets of the remaining jobs will report a cancellation
+            queue.jobs.clear();
+        }
+        self.shared.available.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn work(shared: &Shared) {
+    let no_vars = HashMap::new();
+    let mut functions = Reader::new();
+    loop {
+        let job = {
+            let mut queue = shared.queue.lock().unwrap();
+            loop {
+                if queue.shutting_down {
+                    return;
+                }
+                if let Some(job) = queue.jobs.pop() {
+                    break job;
+                }
+                queue = shared.available.wait(queue).unwrap();
+            }
+        };
+
+        let is_cancelled =
+            |job: &Job| job.cancelled.load(atomic::Ordering::SeqCst) || shared.is_superseded(job);
+        let result = if is_cancelled(&job) {
+            Err(QueueError::Cancelled)
+        } else {
+            let functions = functions.current(&shared.functions);
+            let result = evaluate(&job.expr, &no_vars, &no_vars, &[], functions, &job.options)
+                .map_err(QueueError::Failed);
+            // the calculation cannot be interrupted, but its result is not
+            // wanted anymore
+            if is_cancelled(&job) {
+                Err(QueueError::Cancelled)
+            } else {
+                result
+            }
+        };
+        // the ticket may have been dropped
+        let _ = job.sender.send(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::functions::UserFunction;
+    use crate::node::{Node, RateProvider, SimplifyError, Unit};
+    use num_rational::BigRational;
+
+    #[test]
+    fn it_runs_calculations_in_the_background() {
+        let evaluator = Evaluator::new(2);
+        let tickets: Vec<_> = (0..10)
+            .map(|i| evaluator.submit(&format!("{} * 2", i), Priority::Normal))
+            .collect();
+        for (i, ticket) in tickets.into_iter().enumerate() {
+            let entry = ticket.wait().unwrap();
+            assert_eq!(entry.result.to_string(), (i * 2).to_string());
+        }
+
+        let ticket = evaluator.submit("1 +", Priority::High);
+        assert!(matches!(ticket.wait(), Err(QueueError::Failed(_))));
+    }
+
+    #[test]
+    fn it_cancels_superseded_calculations() {
+        // without workers, nothing runs until the evaluator is dropped
+        let evaluator = Evaluator::new(0);
+        let old = evaluator.submit_latest("1", Priority::Normal);
+        let cancelled = evaluator.submit("2", Priority::Normal);
+        cancelled.cancel();
+        assert_eq!(old.try_get(), None);
+        drop(evaluator);
+        assert_eq!(old.wait(), Err(QueueError::Cancelled));
+        assert_eq!(cancelled.wait(), Err(QueueError::Cancelled));
+
+        let evaluator = Evaluator::new(1);
+        let tickets: Vec<_> = (0..5)
+            .map(|i| evaluator.submit_latest(&i.to_string(), Priority::Normal))
+            .collect();
+        let mut results: Vec<_> = tickets.into_iter().map(Ticket::wait).collect();
+        assert_eq!(results.pop().unwrap().unwrap().result.to_string(), "4");
+    }
+
+    #[test]
+    fn it_calls_functions_defined_while_running() {
+        let evaluator = Evaluator::new(2);
+        assert!(evaluator.submit("f(2)", Priority::Normal).wait().is_ok());
+        let f = UserFunction {
+            name: "f".to_owned(),
+            params: vec!["x".to_owned()],
+            body: Node::Var("x".to_owned()) + Node::Var("x".to_owned()),
+        };
+        evaluator.update_functions(|x| x.define(f)).unwrap();
+        let ticket = evaluator.submit("f(2)", Priority::Normal);
+        assert_eq!(ticket.wait().unwrap().result.to_string(), "4");
+        let ticket = evaluator.submit("f(1, 2)", Priority::Normal);
+        assert!(matches!(ticket.wait(), Err(QueueError::Failed(_))));
+    }
+
+    #[test]
+    fn it_converts_currencies_with_the_rates_of_the_options() {
+        struct Fixed;
+
+        impl RateProvider for Fixed {
+            fn rate(&self, from: Unit, to: Unit) -> Option<BigRational> {
+                match (from, to) {
+                    (Unit::Euro, Unit::UsDollar) => Some(BigRational::new(11.into(), 10.into())),
+                    _ => None,
+                }
+            }
+        }
+
+        let evaluator = Evaluator::new(2);
+        let ticket = evaluator.submit("10 EUR in USD", Priority::Normal);
+        assert!(matches!(ticket.wait(), Err(QueueError::Failed(_))));
+        evaluator.set_options(EvalOptions {
+            rates: Some(Arc::new(Fixed)),
+            ..EvalOptions::default()
+        });
+        let ticket = evaluator.submit("10 EUR in USD", Priority::Normal);
+        assert_eq!(ticket.wait().unwrap().result.to_string(), "11 USD");
+    }
+
+    #[test]
+    fn it_runs_calculations_within_the_memory_budget() {
+        let evaluator = Evaluator::new(2);
+        evaluator.set_options(EvalOptions {
+            memory_budget: Some(64),
+            ..EvalOptions::default()
+        });
+        let big = "9".repeat(90);
+        let ticket = evaluator.submit(&format!("{} * {}", big, big), Priority::Normal);
+        assert_eq!(
+            ticket.wait(),
+            Err(QueueError::Failed(SessionError::Simplify(
+                SimplifyError::OverBudget
+            )))
+        );
+    }
+
+    #[test]
+    fn it_orders_jobs_by_priority() {
+        let mut heap = BinaryHeap::new();
+        for (seq, &priority) in [
+            Priority::Low,
+            Priority::High,
+            Priority::Normal,
+            Priority::High,
+        ]
+        .iter()
+        .enumerate()
+        {
+            let (sender, _) = mpsc::channel();
+            heap.push(Job {
+                expr: String::new(),
+                priority,
+                seq: seq as u64,
+                generation: None,
+                options: EvalOptions::default(),
+                cancelled: Arc::new(AtomicBool::new(false)),
+                sender,
+            });
+        }
+        let order: Vec<_> = iter_heap(heap).map(|j| j.seq).collect();
+        assert_eq!(order, vec![1, 3, 2, 0]);
+    }
+
+    fn iter_heap(mut heap: BinaryHeap<Job>) -> impl Iterator<Item = Job> {
+        std::iter::from_fn(move || heap.pop())
+    }
+}
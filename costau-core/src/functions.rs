@@ -0,0 +1,256 @@
+//! Functions defined by the user, such as `f` where `f(x, y)` is `x^2 + y`.
+//!
+//! A registry is never changed once it is shared: defining a function
+//! creates a new version that shares the other functions with the previous
+//! one. Calculations keep the version they started with, so that frontends
+//! can add functions while calculations run on other threads, and the
+//! threads only take a lock to copy a pointer when a new version is
+//! published.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::lexer::{Lexer, TokenKind};
+use crate::node::Node;
+
+/// A function defined by the user.
+#[derive(Debug, PartialEq, Clone)]
+pub struct UserFunction {
+    pub name: String,
+    /// The names of the arguments in the body
+    pub params: Vec<String>,
+    /// The value of the function in terms of its arguments
+    pub body: Node,
+}
+
+impl UserFunction {
+    /// Returns the body with the parameters replaced with the arguments, or
+    /// `None` if the number of arguments is wrong.
+    pub fn call(&self, args: Vec<Node>) -> Option<Node> {
+        if args.len() != self.params.len() {
+            return None;
+        }
+        // the parameters are renamed to names that cannot be typed so that
+        // they cannot be confused with the variables in the arguments
+        let mut body = self.body.clone();
+        for (i, param) in self.params.iter().enumerate() {
+            body = body.substitute(param, &Node::Var(format!("${}", i)));
+        }
+        for (i, arg) in args.iter().enumerate() {
+            body = body.substitute(&format!("${}", i), arg);
+        }
+        Some(body)
+    }
+}
+
+/// A description of why a function cannot be defined.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum FunctionError {
+    /// The name is not read as a single identifier
+    InvalidName,
+    /// The name is the one of a builtin function or constant
+    Builtin { name: String },
+}
+
+/// A version of the functions defined by the user.
+#[derive(Debug, Default, Clone)]
+pub struct FunctionRegistry {
+    version: u64,
+    functions: Arc<HashMap<String, Arc<UserFunction>>>,
+}
+
+impl FunctionRegistry {
+    pub fn new() -> FunctionRegistry {
+        FunctionRegistry::default()
+    }
+
+    /// The number of changes since the registry was created.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn get(&self, name: &str) -> Option<&UserFunction> {
+        self.functions.get(name).map(|x| &**x)
+    }
+
+    /// Returns the names of the functions, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.functions.keys().map(|x| &**x)
+    }
+
+    /// Defines a function, or replaces the one with the same name.
+    pub fn define(&mut self, function: UserFunction) -> Result<(), FunctionError> {
+        let mut tokens = Lexer::new(&function.name);
+        match (tokens.next(), tokens.next()) {
+            (Some(Ok(token)), None) => match token.kind {
+                TokenKind::UnknownIdent(ref name) if *name == function.name => {}
+                TokenKind::Ident(_) => {
+                    return Err(FunctionError::Builtin {
+                        name: function.name,
+                    })
+                }
+                _ => return Err(FunctionError::InvalidName),
+            },
+            _ => return Err(FunctionError::InvalidName),
+        }
+        Arc::make_mut(&mut self.functions).insert(function.name.clone(), Arc::new(function));
+        self.version += 1;
+        Ok(())
+    }
+
+    /// Removes the definition of a function.
+    pub fn undefine(&mut self, name: &str) {
+        if self.functions.contains_key(name) {
+            Arc::make_mut(&mut self.functions).remove(name);
+            self.version += 1;
+        }
+    }
+}
+
+/// A registry that is shared by threads. The changes are published as new
+/// versions, which the threads pick up with `Reader`.
+#[derive(Debug, Default)]
+pub struct SharedRegistry {
+    version: AtomicU64,
+    /// Only held to copy or to replace the pointer to the current version
+    current: Mutex<Arc<FunctionRegistry>>,
+    /// Held while a change is made, so that two changes cannot both start
+    /// from the same version and lose one of them
+    writer: Mutex<()>,
+}
+
+/// Locks a mutex even if a thread panicked while holding it. The data behind
+/// the locks of `SharedRegistry` is always valid because the changes are
+/// made on copies.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+impl SharedRegistry {
+    pub fn new() -> SharedRegistry {
+        SharedRegistry::default()
+    }
+
+    /// Returns the current version.
+    pub fn snapshot(&self) -> Arc<FunctionRegistry> {
+        lock(&self.current).clone()
+    }
+
+    /// Changes a copy of the current version and publishes it. The
+    /// calculations that already started are not affected, and the threads
+    /// keep reading the current version while `f` runs. Nothing is published
+    /// if `f` panics.
+    pub fn update<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut FunctionRegistry) -> R,
+    {
+        let _writer = lock(&self.writer);
+        let mut registry = (*self.snapshot()).clone();
+        let res = f(&mut registry);
+        let version = registry.version;
+        *lock(&self.current) = Arc::new(registry);
+        self.version.store(version, Ordering::SeqCst);
+        res
+    }
+}
+
+/// A thread's copy of the current version of a shared registry, which is
+/// only replaced when a new version is published.
+#[derive(Debug, Default)]
+pub struct Reader {
+    cached: Arc<FunctionRegistry>,
+}
+
+impl Reader {
+    pub fn new() -> Reader {
+        Reader::default()
+    }
+
+    /// Returns the current version of the shared registry. This only reads
+    /// an atomic integer unless the version changed since the last call.
+    pub fn current(&mut self, shared: &SharedRegistry) -> &Arc<FunctionRegistry> {
+        if shared.version.load(Ordering::SeqCst) != self.cached.version {
+            self.cached = shared.snapshot();
+        }
+        &self.cached
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+
+    fn square() -> UserFunction {
+        UserFunction {
+            name: "sq".to_owned(),
+            params: vec!["x".to_owned()],
+            body: Node::Var("x".to_owned()) * Node::Var("x".to_owned()),
+        }
+    }
+
+    #[test]
+    fn it_checks_the_names_of_functions() {
+        let mut registry = FunctionRegistry::new();
+        assert_eq!(registry.define(square()), Ok(()));
+        assert_eq!(registry.version(), 1);
+        let with_name = |name: &str| UserFunction {
+            name: name.to_owned(),
+            ..square()
+        };
+        assert_eq!(
+            registry.define(with_name("sin")),
+            Err(FunctionError::Builtin {
+                name: "sin".to_owned()
+            })
+        );
+        assert_eq!(
+            registry.define(with_name("f g")),
+            Err(FunctionError::InvalidName)
+        );
+        assert_eq!(
+            registry.define(with_name("2")),
+            Err(FunctionError::InvalidName)
+        );
+        registry.undefine("sq");
+        registry.undefine("sq");
+        assert_eq!(registry.version(), 2);
+        assert_eq!(registry.names().count(), 0);
+    }
+
+    #[test]
+    fn it_keeps_old_versions_for_running_calculations() {
+        let shared = SharedRegistry::new();
+        let mut reader = Reader::new();
+        let old = reader.current(&shared).clone();
+        shared.update(|x| x.define(square())).unwrap();
+        assert!(old.get("sq").is_none());
+
+        let new = reader.current(&shared).clone();
+        let call = new.get("sq").unwrap().call(vec![Node::Var("y".to_owned())]);
+        assert_eq!(call.unwrap().to_string(), "y * y");
+        // the versions that are in use are not changed
+        shared.update(|x| x.undefine("sq"));
+        assert!(new.get("sq").is_some());
+        assert!(reader.current(&shared).get("sq").is_none());
+        assert_eq!(new.get("sq").unwrap().call(vec![]), None);
+    }
+
+    #[test]
+    fn it_keeps_working_after_a_change_panics() {
+        let shared = SharedRegistry::new();
+        let mut reader = Reader::new();
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            shared.update(|x| {
+                x.define(square()).unwrap();
+                panic!("the change failed");
+            })
+        }));
+        assert!(res.is_err());
+        // the change was not published
+        assert!(reader.current(&shared).get("sq").is_none());
+        shared.update(|x| x.define(square())).unwrap();
+        assert!(reader.current(&shared).get("sq").is_some());
+    }
+}
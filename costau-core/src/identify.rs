@@ -0,0 +1,294 @@
+//! Finds exact expressions that a floating-point number could be the value
+//! of, such as `pi/4` for `0.7853981633974483`, which is sometimes called an
+//! inverse symbolic calculator.
+
+use num_rational::BigRational;
+
+use crate::node::util::common::{self, num};
+use crate::node::{ConstKind, Node};
+
+/// The largest denominator of the rational coefficients.
+const MAX_DENOM: i64 = 1000;
+
+/// The largest numerator and denominator of the coefficient of the constant
+/// in `a + b * c` forms, which are searched by brute force.
+const MAX_SECOND_COEF: i64 = 12;
+
+/// The relative difference below which a candidate matches.
+const TOLERANCE: f64 = 1e-12;
+
+/// An exact expression that has the value of the number.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Candidate {
+    pub node: Node,
+
+    /// The absolute difference between the value of the expression and the
+    /// number
+    pub error: f64,
+
+    /// How complicated the expression is, the lower the more plausible
+    pub complexity: usize,
+}
+
+/// A known constant with how complicated it is to write.
+struct Constant {
+    node: fn() -> Node,
+    val: f64,
+    complexity: usize,
+}
+
+fn sqrt(n: i64) -> Node {
+    Node::Exp(
+        Box::new(num(BigRational::from_integer(n.into()))),
+        Box::new(num(BigRational::new(1.into(), 2.into()))),
+    )
+}
+
+const CONSTANTS: [Constant; 9] = [
+    Constant {
+        node: || Node::Const(ConstKind::Pi),
+        val: std::f64::consts::PI,
+        complexity: 1,
+    },
+    Constant {
+        node: || Node::Const(ConstKind::E),
+        val: std::f64::consts::E,
+        complexity: 1,
+    },
+    Constant {
+        node: || sqrt(2),
+        val: std::f64::consts::SQRT_2,
+        complexity: 2,
+    },
+    Constant {
+        node: || sqrt(3),
+        val: 1.732_050_807_568_877_2,
+        complexity: 2,
+    },
+    Constant {
+        node: || sqrt(5),
+        val: 2.236_067_977_499_79,
+        complexity: 2,
+    },
+    Constant {
+        node: || Node::Ln(Box::new(common::two())),
+        val: std::f64::consts::LN_2,
+        complexity: 2,
+    },
+    Constant {
+        node: || {
+            Node::Exp(
+                Box::new(Node::Const(ConstKind::Pi)),
+                Box::new(common::two()),
+            )
+        },
+        val: std::f64::consts::PI * std::f64::consts::PI,
+        complexity: 3,
+    },
+    Constant {
+        node: || {
+            Node::Exp(
+                Box::new(Node::Const(ConstKind::Pi)),
+                Box::new(common::minus_one()),
+            )
+        },
+        val: std::f64::consts::FRAC_1_PI,
+        complexity: 2,
+    },
+    Constant {
+        node: || {
+            Node::Exp(
+                Box::new(Node::Const(ConstKind::Pi)),
+                Box::new(num(BigRational::new(1.into(), 2.into()))),
+            )
+        },
+        val: 1.772_453_850_905_516,
+        complexity: 3,
+    },
+];
+
+/// Returns the fraction with a denominator of at most `max_denom` that is
+/// the closest to `x`, using its continued fraction.
+fn approximate(x: f64, max_denom: i64) -> Option<(i64, i64)> {
+    if !x.is_finite() || x.abs() > 1e15 {
+        return None;
+    }
+    // the convergents h/k
+    let (mut h0, mut h1) = (0i64, 1i64);
+    let (mut k0, mut k1) = (1i64, 0i64);
+    let mut rest = x;
+    loop {
+        let a = rest.floor();
+        let a_int = a as i64;
+        let h2 = a_int.checked_mul(h1)?.checked_add(h0)?;
+        let k2 = a_int.checked_mul(k1)?.checked_add(k0)?;
+        if k2 > max_denom {
+            break;
+        }
+        h0 = h1;
+        h1 = h2;
+        k0 = k1;
+        k1 = k2;
+        let frac = rest - a;
+        if frac < 1e-15 {
+            break;
+        }
+        rest = 1.0 / frac;
+    }
+    if k1 == 0 {
+        None
+    } else {
+        Some((h1, k1))
+    }
+}
+
+/// The number of digits of the numerator and the denominator.
+fn fraction_complexity(numer: i64, denom: i64) -> usize {
+    let digits = |n: i64| n.abs().to_string().len();
+    if denom == 1 {
+        digits(numer)
+    } else {
+        digits(numer) + digits(denom)
+    }
+}
+
+fn matches(val: f64, x: f64) -> bool {
+    (val - x).abs() <= TOLERANCE * x.abs().max(1.0)
+}
+
+/// Searches small integer combinations of known constants, such as `p/q`,
+/// `p/q * pi` or `a + b * sqrt(2)` with rational `a` and `b`, whose value is
+/// `x`. The candidates are sorted from the most plausible, which is the
+/// simplest one.
+pub fn identify(x: f64) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = Vec::new();
+    if !x.is_finite() {
+        return candidates;
+    }
+    let mut push = |node: Node, val: f64, complexity: usize| {
+        let node = match node.simplify() {
+            Ok(x) => x.result,
+            Err(_) => return,
+        };
+        match candidates.iter_mut().find(|c| c.node == node) {
+            Some(c) if c.complexity <= complexity => {}
+            Some(c) => c.complexity = complexity,
+            None => candidates.push(Candidate {
+                node,
+                error: (val - x).abs(),
+                complexity,
+            }),
+        }
+    };
+
+    // p/q and sqrt(p/q)
+    if let Some((p, q)) = approximate(x, MAX_DENOM) {
+        let val = p as f64 / q as f64;
+        if matches(val, x) {
+            push(
+                num(BigRational::new(p.into(), q.into())),
+                val,
+                fraction_complexity(p, q),
+            );
+        }
+    }
+    if let Some((p, q)) = approximate(x * x, MAX_DENOM) {
+        let val = (p as f64 / q as f64).sqrt().copysign(x);
+        let product = p.checked_mul(q);
+        if let (true, Some(product)) = (p > 0 && matches(val, x), product) {
+            // written as sqrt(pq)/q without a root in the denominator
+            let sign = if x < 0.0 { -1 } else { 1 };
+            let node = num(BigRational::new(sign.into(), q.into())) * sqrt(product);
+            push(node, val, fraction_complexity(p, q) + 1);
+        }
+    }
+
+    for constant in &CONSTANTS {
+        // p/q * c
+        if let Some((p, q)) = approximate(x / constant.val, MAX_DENOM) {
+            let val = p as f64 / q as f64 * constant.val;
+            if p != 0 && matches(val, x) {
+                let complexity = fraction_complexity(p, q) + constant.complexity;
+                push(
+                    num(BigRational::new(p.into(), q.into())) * (constant.node)(),
+                    val,
+                    complexity,
+                );
+            }
+        }
+        // a + b * c
+        for s in 1..=MAX_SECOND_COEF {
+            for r in -MAX_SECOND_COEF..=MAX_SECOND_COEF {
+                let b = r as f64 / s as f64;
+                if r == 0 || approximate(b, s) != Some((r, s)) {
+                    // zero or not in lowest terms
+                    continue;
+                }
+                let rest = x - b * constant.val;
+                if let Some((p, q)) = approximate(rest, MAX_DENOM) {
+                    let val = p as f64 / q as f64 + b * constant.val;
+                    if p != 0 && matches(val, x) {
+                        let complexity = fraction_complexity(p, q)
+                            + fraction_complexity(r, s)
+                            + constant.complexity;
+                        push(
+                            num(BigRational::new(p.into(), q.into()))
+                                + num(BigRational::new(r.into(), s.into())) * (constant.node)(),
+                            val,
+                            complexity,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    candidates.sort_by(|a, b| {
+        a.complexity
+            .cmp(&b.complexity)
+            .then(a.error.partial_cmp(&b.error).unwrap())
+    });
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn best(x: f64) -> Option<String> {
+        identify(x).first().map(|c| c.node.to_string())
+    }
+
+    #[test]
+    fn it_finds_fractions_and_roots() {
+        assert_eq!(best(0.5), Some("1/2".to_owned()));
+        assert_eq!(best(-0.75), Some("-3/4".to_owned()));
+        assert_eq!(best(2f64.sqrt() / 2.0), Some("1/2 * 2^(1/2)".to_owned()));
+    }
+
+    #[test]
+    fn it_finds_multiples_of_constants() {
+        assert_eq!(
+            best(std::f64::consts::PI / 4.0),
+            Some("1/4 * pi".to_owned())
+        );
+        assert_eq!(best(3.0 * std::f64::consts::E), Some("3e".to_owned()));
+        assert_eq!(best(1.0 + 2f64.sqrt()), Some("1 + 2^(1/2)".to_owned()));
+        assert_eq!(best(2f64.ln() / 3.0), Some("1/3 * ln 2".to_owned()));
+    }
+
+    #[test]
+    fn it_ranks_simpler_candidates_first() {
+        let candidates = identify(std::f64::consts::PI);
+        assert_eq!(candidates[0].node.to_string(), "pi");
+        assert!(candidates
+            .windows(2)
+            .all(|x| x[0].complexity <= x[1].complexity));
+    }
+
+    #[test]
+    fn it_finds_nothing_for_random_numbers() {
+        assert!(identify(0.123_456_789_012_345_6).is_empty());
+        assert!(identify(f64::NAN).is_empty());
+    }
+}
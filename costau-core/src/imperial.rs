@@ -0,0 +1,194 @@
+//! Lengths in feet and inches with fractions of an inch, such as `5' 3 1/2"`,
+//! which are common in construction work.
+
+use num_bigint::{BigInt, BigUint};
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{Signed, Zero};
+
+use crate::lexer::BigUrational;
+
+const INCHES_PER_FOOT: u32 = 12;
+
+struct Scanner<'a> {
+    input: &'a [u8],
+    index: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn skip_spaces(&mut self) {
+        while self.input.get(self.index) == Some(&b' ') {
+            self.index += 1;
+        }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        let found = self.input.get(self.index) == Some(&c);
+        if found {
+            self.index += 1;
+        }
+        found
+    }
+
+    fn peek_digit(&self) -> bool {
+        matches!(self.input.get(self.index), Some(c) if c.is_ascii_digit())
+    }
+
+    fn int(&mut self) -> Option<BigUint> {
+        let start = self.index;
+        while self.peek_digit() {
+            self.index += 1;
+        }
+        if start == self.index {
+            return None;
+        }
+        let digits = std::str::from_utf8(&self.input[start..self.index]).unwrap();
+        digits.parse().ok()
+    }
+
+    /// Consumes the `/ 4` part of `3/4`.
+    fn denom(&mut self) -> Option<BigUint> {
+        let start = self.index;
+        self.skip_spaces();
+        if self.eat(b'/') {
+            self.skip_spaces();
+            if let Some(denom) = self.int().filter(|x| !x.is_zero()) {
+                return Some(denom);
+            }
+        }
+        self.index = start;
+        None
+    }
+
+    /// Consumes a whole number, a fraction such as `3/4` or a mixed number
+    /// such as `3 1/2`.
+    fn inches(&mut self) -> Option<BigUrational> {
+        let whole = self.int()?;
+        if let Some(denom) = self.denom() {
+            return Some(BigUrational::new(whole, denom));
+        }
+        let start = self.index;
+        self.skip_spaces();
+        if let Some(numer) = self.int() {
+            if let Some(denom) = self.denom() {
+                return Some(BigUrational::from(whole) + BigUrational::new(numer, denom));
+            }
+        }
+        self.index = start;
+        Some(BigUrational::from(whole))
+    }
+}
+
+/// Parses a length such as `5' 3 1/2"`, `5'` or `3/4"` at the start of the
+/// input. Returns the length in inches and the number of bytes that were
+/// read, or `None` if the input does not start with such a length. Only the
+/// bytes of the length are read.
+pub fn parse_feet_inches(input: &[u8]) -> Option<(BigUrational, usize)> {
+    let mut scanner = Scanner { input, index: 0 };
+    let feet = scanner.int()?;
+    if scanner.eat(b'\'') {
+        let feet = BigUrational::from(feet * INCHES_PER_FOOT);
+        let end_of_feet = scanner.index;
+        scanner.skip_spaces();
+        match scanner.input.get(scanner.index) {
+            Some(c) if c.is_ascii_digit() => {}
+            // such as `0'h 9F`, where the apostrophe is a separator
+            Some(c) if c.is_ascii_alphabetic() => return None,
+            _ => return Some((feet, end_of_feet)),
+        }
+        let inches = scanner.inches()?;
+        scanner.skip_spaces();
+        // without the inch mark, the apostrophe is a thousands separator
+        if !scanner.eat(b'"') {
+            return None;
+        }
+        return Some((feet + inches, scanner.index));
+    }
+
+    scanner.index = 0;
+    let inches = scanner.inches()?;
+    scanner.skip_spaces();
+    if !scanner.eat(b'"') {
+        return None;
+    }
+    Some((inches, scanner.index))
+}
+
+/// Formats a length in inches as feet and inches, rounded to the nearest
+/// fraction of an inch with the denominator `smallest_denom`, such as `16`
+/// for sixteenths of an inch.
+pub fn format_feet_inches(inches: &BigRational, smallest_denom: u32) -> String {
+    let denom = BigInt::from(smallest_denom);
+    let rounded = BigRational::new((inches * &denom).round().to_integer(), denom);
+    let sign = if rounded.is_negative() { "-" } else { "" };
+    let rounded = rounded.abs();
+
+    let (feet, whole) = rounded.to_integer().div_rem(&BigInt::from(INCHES_PER_FOOT));
+    let fraction = rounded.fract();
+    let mut inches = whole.to_string();
+    if !fraction.is_zero() {
+        if whole.is_zero() {
+            inches = fraction.to_string();
+        } else {
+            inches = format!("{} {}", inches, fraction);
+        }
+    }
+    if feet.is_zero() {
+        format!("{}{}\"", sign, inches)
+    } else {
+        format!("{}{}' {}\"", sign, feet, inches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::FormatOptions;
+    use crate::test_util::{simplify, simplify_str};
+
+    fn inches(numer: u32, denom: u32) -> BigUrational {
+        BigUrational::new(numer.into(), denom.into())
+    }
+
+    #[test]
+    fn it_parses_feet_and_inches() {
+        assert_eq!(parse_feet_inches(b"5' 3 1/2\""), Some((inches(127, 2), 9)));
+        assert_eq!(parse_feet_inches(b"5'3\" + 1"), Some((inches(63, 1), 4)));
+        assert_eq!(parse_feet_inches(b"3/4\""), Some((inches(3, 4), 4)));
+        assert_eq!(parse_feet_inches(b"2' + 1"), Some((inches(24, 1), 2)));
+        // thousands separators
+        assert_eq!(parse_feet_inches(b"1'000"), None);
+        assert_eq!(parse_feet_inches(b"0'h 9F"), None);
+        assert_eq!(parse_feet_inches(b"12"), None);
+    }
+
+    #[test]
+    fn it_formats_feet_and_inches() {
+        let format = |numer: i64, denom: i64, smallest_denom: u32| {
+            let inches = BigRational::new(numer.into(), denom.into());
+            format_feet_inches(&inches, smallest_denom)
+        };
+        assert_eq!(format(127, 2, 16), "5' 3 1/2\"");
+        assert_eq!(format(60, 1, 16), "5' 0\"");
+        assert_eq!(format(3, 8, 4), "1/2\"");
+        assert_eq!(format(-100, 7, 8), "-1' 2 1/4\"");
+        assert_eq!(format(1000, 1001, 2), "1\"");
+    }
+
+    #[test]
+    fn it_calculates_with_feet_and_inches() {
+        assert_eq!(simplify_str("2 * 5'"), "120 inch");
+        assert_eq!(simplify_str("5' 3\" in ft"), "(21/4) ft");
+        assert_eq!(simplify_str("5' + 1 m"), simplify_str("60 inch + 1 m"));
+
+        let options = FormatOptions {
+            feet_inches: Some(16),
+            ..FormatOptions::default()
+        };
+        let format = |expr: &str| simplify(expr).format(&options);
+        assert_eq!(format("5' 3 1/2\" + 1/3\""), "5' 3 13/16\"");
+        assert_eq!(format("5' 3\" in ft"), "5' 3\"");
+        assert_eq!(format("2 * 5'"), "10' 0\"");
+        assert_eq!(format("3 m"), "3 m");
+    }
+}
@@ -4,7 +4,9 @@ use std::iter::FusedIterator;
 use std::str::FromStr;
 
 use num_bigint::BigUint;
-use num_traits::{One, Zero};
+use num_traits::{One, ToPrimitive, Zero};
+
+use crate::imperial::parse_feet_inches;
 
 pub use self::token::*;
 
@@ -12,8 +14,19 @@ pub use self::token::*;
 #[derive(Debug, PartialEq, Eq)]
 pub enum LexerErrorKind {
     UnknownToken,
+    /// The power of ten of a number in scientific notation is larger than
+    /// `MAX_EXPONENT`
+    ExponentTooLarge,
+    /// A string such as `"UTC+9` has no closing quote
+    UnterminatedString,
+    /// The dice have no sides, such as in `2d0`
+    NoDiceSides,
 }
 
+/// The largest power of ten of a number in scientific notation, such as `23`
+/// in `6.02e23`, so that the numbers stay small enough to compute with.
+pub const MAX_EXPONENT: u32 = 10_000;
+
 /// When the expression is malformed, the lexer will return this error.
 #[derive(Debug, PartialEq, Eq)]
 pub struct LexerError {
@@ -32,15 +45,23 @@ pub struct Lexer<'a> {
     expr: &'a [u8],
     index: usize,
     has_failed: bool,
+    symbols: &'a [String],
 }
 
 impl<'a> Lexer<'a> {
     /// Create a new lexer from an expression.
     pub fn new(expr: &str) -> Lexer {
+        Lexer::with_symbols(expr, &[])
+    }
+
+    /// Create a lexer that also reads the symbols of the operators defined by
+    /// the user.
+    pub fn with_symbols(expr: &'a str, symbols: &'a [String]) -> Lexer<'a> {
         Lexer {
             expr: expr.as_bytes(),
             index: 0,
             has_failed: false,
+            symbols,
         }
     }
 
@@ -74,6 +95,34 @@ impl<'a> Lexer<'a> {
         None
     }
 
+    fn try_consume_symbol(&mut self) -> Option<Token> {
+        // the longest symbol wins, so that `//` is not read as `/`
+        let rest = &self.expr[self.index..];
+        let symbol = self
+            .symbols
+            .iter()
+            .filter(|x| !x.is_empty() && rest.starts_with(x.as_bytes()))
+            .max_by_key(|x| x.len())?;
+        let original_index = self.index;
+        self.index += symbol.len();
+        Some(Token {
+            kind: TokenKind::Symbol(symbol.clone()),
+            index: original_index,
+        })
+    }
+
+    fn try_consume_multi_char_token(&mut self) -> Option<Token> {
+        if self.expr[self.index..].starts_with(b"=~") {
+            let original_index = self.index;
+            self.index += 2;
+            return Some(Token {
+                kind: TokenKind::ApproxEq,
+                index: original_index,
+            });
+        }
+        None
+    }
+
     fn try_consume_ident(&mut self) -> Option<Result<Token, LexerError>> {
         let original_index = self.index;
         let mut ident = String::new();
@@ -81,8 +130,8 @@ impl<'a> Lexer<'a> {
         while self.index < self.expr.len() {
             let c = self.expr[self.index] as char;
 
-            // every letter in an identifier is alphabetic
-            if !c.is_ascii_alphabetic() {
+            // every character in an identifier is alphabetic or an underscore
+            if !c.is_ascii_alphabetic() && c != '_' {
                 break;
             }
 
@@ -95,6 +144,17 @@ impl<'a> Lexer<'a> {
             return None;
         }
 
+        // `log2`, `log10` and `atan2` are the only identifiers with digits
+        if ident.eq_ignore_ascii_case("log") || ident.eq_ignore_ascii_case("atan") {
+            for suffix in &["10", "2"] {
+                if self.expr[self.index..].starts_with(suffix.as_bytes()) {
+                    ident.push_str(suffix);
+                    self.index += suffix.len();
+                    break;
+                }
+            }
+        }
+
         let token_kind = IdentKind::from_str(&ident)
             .map(TokenKind::Ident)
             .unwrap_or_else(|_| TokenKind::UnknownIdent(ident));
@@ -114,16 +174,161 @@ impl<'a> Lexer<'a> {
         })
     }
 
-    fn try_consume_num(&mut self) -> Option<Token> {
+    /// Returns the base of an uppercase prefix, such as the `X` of `0XFF`.
+    /// It must come right after a zero and before a digit of the base, so
+    /// that `0B` alone stays zero bytes.
+    fn get_base_from_upper_prefix(&self, c: char) -> Option<u32> {
+        let base = match c {
+            'B' => 2,
+            'O' => 8,
+            'X' => 16,
+            _ => return None,
+        };
+        let next = *self.expr.get(self.index + 1)? as char;
+        if self.expr[self.index - 1] == b'0' && next.is_digit(base) {
+            Some(base)
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether a digit of the base comes after the prefix of the base
+    /// at `index`, skipping the whitespace and the apostrophes.
+    fn is_digit_after_prefix(&self, base: u32) -> bool {
+        let next = self.expr[self.index + 1..]
+            .iter()
+            .find(|&&c| c != b' ' && c != b'\'');
+        matches!(next, Some(&c) if (c as char).is_digit(base))
+    }
+
+    /// Returns the base of a number such as `36#z1` when its `#` is the next
+    /// character. The base must be from 2 to 36 and a digit of the base must
+    /// come after the `#`.
+    fn get_base_before_hash(&self, numer: &BigUint) -> Option<u32> {
+        let base = numer.to_u32().filter(|x| (2..=36).contains(x))?;
+        let next = *self.expr.get(self.index + 1)? as char;
+        if next.is_digit(base) {
+            Some(base)
+        } else {
+            None
+        }
+    }
+
+    /// Consumes the `d6` part of `3d6` at `index`, which is right after the
+    /// digits, so that `3 d6` is a product with the variable `d6`.
+    fn try_consume_dice_sides(&mut self, index: usize) -> Option<Result<u32, LexerError>> {
+        let rest = &self.expr[index..];
+        if rest.first() != Some(&b'd') {
+            return None;
+        }
+        let digits = rest[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+
+        let mut sides: u32 = 0;
+        for c in &rest[1..1 + digits] {
+            // too many sides
+            sides = sides.checked_mul(10)?.checked_add((c - b'0') as u32)?;
+        }
+        self.index = index + 1 + digits;
+        if sides == 0 {
+            return Some(Err(LexerError {
+                kind: LexerErrorKind::NoDiceSides,
+                index,
+            }));
+        }
+        Some(Ok(sides))
+    }
+
+    /// Consumes a length in feet and inches such as `5' 3 1/2"`.
+    fn try_consume_feet_inches(&mut self) -> Option<Token> {
+        let (inches, len) = parse_feet_inches(&self.expr[self.index..])?;
+        let original_index = self.index;
+        self.index += len;
+        Some(Token {
+            kind: TokenKind::FeetInches(inches),
+            index: original_index,
+        })
+    }
+
+    /// Consumes the `e-3` part of `2e-3` at `index`, which is right after the
+    /// digits, and returns whether the power of ten is negative and its
+    /// absolute value.
+    fn try_consume_exponent(&mut self, index: usize) -> Option<Result<(bool, u32), LexerError>> {
+        let rest = &self.expr[index..];
+        if rest.first() != Some(&b'e') && rest.first() != Some(&b'E') {
+            return None;
+        }
+        let (negative, start) = match rest.get(1) {
+            Some(b'+') => (false, 2),
+            Some(b'-') => (true, 2),
+            _ => (false, 1),
+        };
+        // `2e` and `2e-x` are products with the constant
+        let digits = rest[start..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if digits == 0 {
+            return None;
+        }
+        let mut expon: u32 = 0;
+        for c in &rest[start..start + digits] {
+            expon = expon.saturating_mul(10).saturating_add((c - b'0') as u32);
+        }
+        self.index = index + start + digits;
+        if expon > MAX_EXPONENT {
+            return Some(Err(LexerError {
+                kind: LexerErrorKind::ExponentTooLarge,
+                index,
+            }));
+        }
+        Some(Ok((negative, expon)))
+    }
+
+    /// Consumes a string between double quotes, such as the time zone in
+    /// `time_in("UTC+9")`.
+    fn try_consume_string(&mut self) -> Option<Result<Token, LexerError>> {
+        if self.expr.get(self.index) != Some(&b'"') {
+            return None;
+        }
+        let original_index = self.index;
+        let len = match self.expr[self.index + 1..].iter().position(|&c| c == b'"') {
+            Some(len) => len,
+            None => {
+                return Some(Err(LexerError {
+                    kind: LexerErrorKind::UnterminatedString,
+                    index: original_index,
+                }))
+            }
+        };
+        let contents = &self.expr[self.index + 1..self.index + 1 + len];
+        self.index += len + 2;
+        Some(Ok(Token {
+            kind: TokenKind::Str(String::from_utf8_lossy(contents).into_owned()),
+            index: original_index,
+        }))
+    }
+
+    fn try_consume_num(&mut self) -> Option<Result<Token, LexerError>> {
+        if let Some(token) = self.try_consume_feet_inches() {
+            return Some(Ok(token));
+        }
         let original_index = self.index;
         let mut numer: BigUint = Zero::zero();
         let mut denom: BigUint = One::one();
         let mut has_dot = false;
         let mut has_digit = false;
         let mut base: u32 = 10;
+        let mut has_hash = false;
+        // the end of the number before the whitespace
+        let mut end = self.index;
 
         while self.index < self.expr.len() {
             let c = self.expr[self.index] as char;
+            // whether there is no whitespace before the character
+            let is_joined = self.index == end;
 
             if let Some(digit) = c.to_digit(base) {
                 numer *= base;
@@ -153,16 +358,40 @@ impl<'a> Lexer<'a> {
 
                     // If no number was specified yet, the user can specify an
                     // input base. This allows them to write numbers like
-                    // 0xCAFE or 00b110.
-                    (_, Some(new_base)) if has_digit && numer.is_zero() => {
+                    // 0xCAFE or 00b110. After whitespace, the prefix must
+                    // come before a digit of the base, so that `0 h` is zero
+                    // hours.
+                    (_, Some(new_base))
+                        if has_digit
+                            && numer.is_zero()
+                            && !has_hash
+                            && (is_joined || self.is_digit_after_prefix(new_base)) =>
+                    {
                         base = new_base;
                     }
+                    (_, None) if has_digit && numer.is_zero() && !has_hash => {
+                        match self.get_base_from_upper_prefix(c) {
+                            Some(new_base) => base = new_base,
+                            None => break,
+                        }
+                    }
+
+                    // any base from 2 to 36, for example `36#z1`
+                    ('#', _) if base == 10 && !has_dot => match self.get_base_before_hash(&numer) {
+                        Some(new_base) => {
+                            base = new_base;
+                            has_hash = true;
+                            numer = Zero::zero();
+                        }
+                        None => break,
+                    },
 
                     _ => break,
                 }
             }
 
             self.index += 1;
+            end = self.index;
 
             // ignore whitespace between digits
             self.consume_whitespace();
@@ -173,13 +402,52 @@ impl<'a> Lexer<'a> {
             return None;
         }
 
-        Some(Token {
+        // scientific notation, for example `1.5e10`
+        if base == 10 {
+            match self.try_consume_exponent(end) {
+                Some(Ok((negative, expon))) => {
+                    let power = num_traits::pow(BigUint::from(10u32), expon as usize);
+                    if negative {
+                        denom *= power;
+                    } else {
+                        numer *= power;
+                    }
+                    return Some(Ok(Token {
+                        kind: TokenKind::Num {
+                            val: BigUrational::new(numer, denom),
+                            input_base: base,
+                        },
+                        index: original_index,
+                    }));
+                }
+                Some(Err(err)) => return Some(Err(err)),
+                None => {}
+            }
+        }
+
+        // dice notation, for example `3d6`
+        if base == 10 && !has_dot {
+            if let Some(count) = numer.to_u32() {
+                match self.try_consume_dice_sides(end) {
+                    Some(Ok(sides)) => {
+                        return Some(Ok(Token {
+                            kind: TokenKind::Dice { count, sides },
+                            index: original_index,
+                        }))
+                    }
+                    Some(Err(err)) => return Some(Err(err)),
+                    None => {}
+                }
+            }
+        }
+
+        Some(Ok(Token {
             kind: TokenKind::Num {
                 val: BigUrational::new(numer, denom),
                 input_base: base,
             },
             index: original_index,
-        })
+        }))
     }
 }
 
@@ -204,22 +472,23 @@ impl<'a> Iterator for Lexer<'a> {
 
         let original_index = self.index;
         let maybe_token = self
-            .try_consume_single_char_token()
-            .or_else(|| self.try_consume_num());
-
-        // if we couldn't get a token yet, try to parse an identifier
+            .try_consume_symbol()
+            .or_else(|| self.try_consume_multi_char_token())
+            .or_else(|| self.try_consume_single_char_token())
+            .map(Ok)
+            .or_else(|| self.try_consume_string())
+            .or_else(|| self.try_consume_num())
+            // if we couldn't get a token yet, try to parse an identifier
+            .or_else(|| self.try_consume_ident());
         let maybe_token = match maybe_token {
-            Some(val) => Some(val),
-            None => match self.try_consume_ident() {
-                Some(Ok(val)) => Some(val),
-                Some(Err(err)) => {
-                    self.has_failed = true;
-
-                    // do not try another token if this didn't work
-                    return Some(Err(err));
-                }
-                None => None,
-            },
+            Some(Ok(val)) => Some(val),
+            Some(Err(err)) => {
+                self.has_failed = true;
+
+                // do not try another token if this didn't work
+                return Some(Err(err));
+            }
+            None => None,
         };
 
         Some(maybe_token.ok_or_else(|| {
@@ -237,6 +506,7 @@ impl<'a> Iterator for Lexer<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::{parse, simplify_str};
 
     #[test]
     fn it_handles_empty_string() {
@@ -259,7 +529,7 @@ mod tests {
 
     #[test]
     fn it_handles_single_char_tokens() {
-        const EXPECTED: [TokenKind; 7] = [
+        const EXPECTED: [TokenKind; 9] = [
             TokenKind::Plus,
             TokenKind::Minus,
             TokenKind::Times,
@@ -267,6 +537,8 @@ mod tests {
             TokenKind::Hat,
             TokenKind::OpenParen,
             TokenKind::CloseParen,
+            TokenKind::Comma,
+            TokenKind::Colon,
         ];
 
         let expected_tokens: Vec<Token> = EXPECTED
@@ -276,7 +548,7 @@ mod tests {
             .map(|(i, kind)| Token { kind, index: i })
             .collect();
 
-        let actual_tokens: Vec<Token> = Lexer::new("+-*/^()").map(|r| r.unwrap()).collect();
+        let actual_tokens: Vec<Token> = Lexer::new("+-*/^(),:").map(|r| r.unwrap()).collect();
 
         assert_eq!(actual_tokens, expected_tokens);
     }
@@ -549,6 +821,266 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_handles_dice() {
+        let mut lexer = Lexer::new("3d6+d6+0d6");
+        assert_eq!(
+            lexer.next(),
+            Some(Ok(Token {
+                kind: TokenKind::Dice { count: 3, sides: 6 },
+                index: 0
+            }))
+        );
+        assert_eq!(
+            lexer.next(),
+            Some(Ok(Token {
+                kind: TokenKind::Plus,
+                index: 3
+            }))
+        );
+        assert_eq!(
+            lexer.next(),
+            Some(Ok(Token {
+                kind: TokenKind::UnknownIdent("d".to_string()),
+                index: 4
+            }))
+        );
+        assert_eq!(
+            lexer.next(),
+            Some(Ok(Token {
+                kind: TokenKind::Num {
+                    val: BigUrational::from(BigUint::from(6u32)),
+                    input_base: 10
+                },
+                index: 5
+            }))
+        );
+        assert_eq!(
+            lexer.next(),
+            Some(Ok(Token {
+                kind: TokenKind::Plus,
+                index: 6
+            }))
+        );
+        // this is the number 6 written in base 10
+        assert_eq!(
+            lexer.next(),
+            Some(Ok(Token {
+                kind: TokenKind::Num {
+                    val: BigUrational::from(BigUint::from(6u32)),
+                    input_base: 10
+                },
+                index: 7
+            }))
+        );
+        assert_eq!(lexer.next(), None);
+
+        // the sides must be right after the number of dice
+        let kinds: Vec<TokenKind> = Lexer::new("3 d6").map(|x| x.unwrap().kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Num {
+                    val: BigUrational::from(BigUint::from(3u32)),
+                    input_base: 10
+                },
+                TokenKind::UnknownIdent("d".to_string()),
+                TokenKind::Num {
+                    val: BigUrational::from(BigUint::from(6u32)),
+                    input_base: 10
+                },
+            ]
+        );
+
+        // the dice must have sides
+        let mut lexer = Lexer::new("2 * 2d0");
+        assert_eq!(
+            lexer.nth(2),
+            Some(Err(LexerError {
+                kind: LexerErrorKind::NoDiceSides,
+                index: 5
+            }))
+        );
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn it_handles_feet_and_inches() {
+        let mut lexer = Lexer::new("5' 3 1/2\" - 1'000");
+        assert_eq!(
+            lexer.next(),
+            Some(Ok(Token {
+                kind: TokenKind::FeetInches(BigUrational::new(
+                    BigUint::from(127u32),
+                    BigUint::from(2u32)
+                )),
+                index: 0
+            }))
+        );
+        assert_eq!(
+            lexer.next(),
+            Some(Ok(Token {
+                kind: TokenKind::Minus,
+                index: 10
+            }))
+        );
+        assert_eq!(
+            lexer.next(),
+            Some(Ok(Token {
+                kind: TokenKind::Num {
+                    val: BigUrational::from(BigUint::from(1000u32)),
+                    input_base: 10
+                },
+                index: 12
+            }))
+        );
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn it_handles_base_prefixes() {
+        let num = |val: u64, input_base: u32| TokenKind::Num {
+            val: BigUrational::from(BigUint::from(val)),
+            input_base,
+        };
+        let kinds = |expr: &str| {
+            Lexer::new(expr)
+                .map(|x| x.unwrap().kind)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kinds("0xFF"), vec![num(0xFF, 16)]);
+        assert_eq!(kinds("0b1011"), vec![num(0b1011, 2)]);
+        assert_eq!(kinds("0o17"), vec![num(0o17, 8)]);
+        assert_eq!(kinds("0XFF"), vec![num(0xFF, 16)]);
+        assert_eq!(kinds("0B1011"), vec![num(0b1011, 2)]);
+        assert_eq!(kinds("0O17"), vec![num(0o17, 8)]);
+        // zero bytes
+        assert_eq!(
+            kinds("0B"),
+            vec![num(0, 10), TokenKind::UnknownIdent("B".to_owned())]
+        );
+
+        // the base of the result is the base of the numbers
+        assert_eq!(simplify_str("0XFF + 0b1"), "0b100000000");
+    }
+
+    #[test]
+    fn it_handles_any_base() {
+        let num = |val: u64, input_base: u32| TokenKind::Num {
+            val: BigUrational::from(BigUint::from(val)),
+            input_base,
+        };
+        let kinds = |expr: &str| {
+            Lexer::new(expr)
+                .map(|x| x.unwrap().kind)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kinds("36#z1"), vec![num(35 * 36 + 1, 36)]);
+        assert_eq!(kinds("7#123"), vec![num(66, 7)]);
+        assert_eq!(
+            kinds("3#12.1"),
+            vec![TokenKind::Num {
+                val: BigUrational::new(BigUint::from(16u32), BigUint::from(3u32)),
+                input_base: 3,
+            }]
+        );
+        // the base prefixes are digits after the `#`
+        assert_eq!(kinds("16#0b1"), vec![num(0xB1, 16)]);
+        assert_eq!(kinds("8#0x1")[0], num(0, 8));
+
+        // the base is out of range or there is no digit of the base
+        for expr in &["37#1", "1#0", "7#8", "7#"] {
+            let mut lexer = Lexer::new(expr);
+            assert!(lexer.next().unwrap().is_ok());
+            assert_eq!(
+                lexer.next().unwrap().unwrap_err().kind,
+                LexerErrorKind::UnknownToken
+            );
+        }
+
+        // the result is written in the same base
+        let node = parse("36#z1 + 1");
+        assert_eq!(node.clone().simplify().unwrap().result.to_string(), "36#Z2");
+        assert_eq!(format!("{:#}", node.eval().unwrap()), "36#Z2");
+    }
+
+    #[test]
+    fn it_handles_scientific_notation() {
+        let num = |numer: u64, denom: u64| TokenKind::Num {
+            val: BigUrational::new(numer.into(), denom.into()),
+            input_base: 10,
+        };
+        let kinds = |expr: &str| {
+            Lexer::new(expr)
+                .map(|x| x.unwrap().kind)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kinds("1.5e10"), vec![num(15_000_000_000, 1)]);
+        assert_eq!(kinds("2E-3"), vec![num(2, 1000)]);
+        assert_eq!(kinds("3.2e+6"), vec![num(3_200_000, 1)]);
+        assert_eq!(kinds("1 000e3"), vec![num(1_000_000, 1)]);
+
+        // the constant when no digits follow
+        let e = || TokenKind::Ident(IdentKind::E);
+        assert_eq!(kinds("2e"), vec![num(2, 1), e()]);
+        assert_eq!(kinds("2e-x")[..2], [num(2, 1), e()]);
+        assert_eq!(kinds("2 e3"), vec![num(2, 1), e(), num(3, 1)]);
+
+        let mut lexer = Lexer::new("1 + 1e99999");
+        assert_eq!(
+            lexer.nth(2),
+            Some(Err(LexerError {
+                kind: LexerErrorKind::ExponentTooLarge,
+                index: 5
+            }))
+        );
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn it_handles_amounts_in_dollars() {
+        let kinds: Vec<TokenKind> = Lexer::new("$4.50 per kg")
+            .map(|r| r.unwrap().kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Dollar,
+                TokenKind::Num {
+                    val: BigUrational::new(BigUint::from(9u32), BigUint::from(2u32)),
+                    input_base: 10
+                },
+                TokenKind::UnknownIdent("per".to_string()),
+                TokenKind::UnknownIdent("kg".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_handles_strings() {
+        let kinds: Vec<TokenKind> = Lexer::new("time_in(\"UTC+9\")")
+            .map(|r| r.unwrap().kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Ident(IdentKind::TimeIn),
+                TokenKind::OpenParen,
+                TokenKind::Str("UTC+9".to_owned()),
+                TokenKind::CloseParen,
+            ]
+        );
+
+        let mut lexer = Lexer::new("2 + \"UTC");
+        assert_eq!(
+            lexer.nth(2),
+            Some(Err(LexerError {
+                kind: LexerErrorKind::UnterminatedString,
+                index: 4
+            }))
+        );
+    }
+
     #[test]
     fn it_handles_constants() {
         let mut lexer = Lexer::new("pi*tau+zzz");
@@ -13,12 +13,74 @@ pub enum IdentKind {
     Pi,
     Tau,
     E,
+    I,
     // functions
     Sin,
     Cos,
     Tan,
+    Asin,
+    Acos,
+    Atan,
+    Atan2,
+    Sinh,
+    Cosh,
+    Tanh,
+    Abs,
+    Floor,
+    Ceil,
+    Round,
+    Sign,
+    Min,
+    Max,
+    Ln,
+    Log,
+    Log2,
+    Log10,
     Sqrt,
     Cbrt,
+    // geometry
+    AreaCircle,
+    AreaSphere,
+    AreaTriangle,
+    AreaRectangle,
+    AreaPolygon,
+    Circumference,
+    VolumeSphere,
+    VolumeCylinder,
+    VolumeCone,
+    // vectors
+    Rotate,
+    AngleBetween,
+    Proj,
+    Orthogonalize,
+    // dice
+    Dice,
+    DiceMean,
+    DiceVariance,
+    DiceProb,
+    DiceProbAtLeast,
+    DiceProbAtMost,
+    // grades
+    Wavg,
+    NeededScore,
+    // ratios
+    RatioSplit,
+    RatioScale,
+    // comparisons
+    ApproxEqual,
+    // calculus
+    Sensitivity,
+    Series,
+    // algebra
+    Solve,
+    // rounding
+    RoundTo,
+    // roman numerals
+    Roman,
+    ToRoman,
+    // clock
+    Now,
+    TimeIn,
 }
 
 impl FromStr for IdentKind {
@@ -29,11 +91,62 @@ impl FromStr for IdentKind {
             "pi" => IdentKind::Pi,
             "tau" => IdentKind::Tau,
             "e" => IdentKind::E,
+            "i" => IdentKind::I,
             "sin" | "sine" => IdentKind::Sin,
             "cos" | "cosine" => IdentKind::Cos,
             "tan" | "tangent" => IdentKind::Tan,
+            "asin" | "arcsin" => IdentKind::Asin,
+            "acos" | "arccos" => IdentKind::Acos,
+            "atan" | "arctan" => IdentKind::Atan,
+            "sinh" => IdentKind::Sinh,
+            "cosh" => IdentKind::Cosh,
+            "tanh" => IdentKind::Tanh,
+            "abs" => IdentKind::Abs,
+            "floor" => IdentKind::Floor,
+            "ceil" => IdentKind::Ceil,
+            "round" => IdentKind::Round,
+            "sign" | "sgn" => IdentKind::Sign,
+            "min" => IdentKind::Min,
+            "max" => IdentKind::Max,
+            "ln" => IdentKind::Ln,
+            "log" => IdentKind::Log,
+            "log2" => IdentKind::Log2,
+            "log10" => IdentKind::Log10,
+            "atan2" => IdentKind::Atan2,
             "sqrt" => IdentKind::Sqrt,
             "cbrt" => IdentKind::Cbrt,
+            "area_circle" => IdentKind::AreaCircle,
+            "area_sphere" => IdentKind::AreaSphere,
+            "area_triangle" => IdentKind::AreaTriangle,
+            "area_rectangle" | "area_rect" => IdentKind::AreaRectangle,
+            "area_polygon" => IdentKind::AreaPolygon,
+            "circumference" => IdentKind::Circumference,
+            "volume_sphere" => IdentKind::VolumeSphere,
+            "volume_cylinder" => IdentKind::VolumeCylinder,
+            "volume_cone" => IdentKind::VolumeCone,
+            "rotate" => IdentKind::Rotate,
+            "angle_between" => IdentKind::AngleBetween,
+            "proj" => IdentKind::Proj,
+            "orthogonalize" => IdentKind::Orthogonalize,
+            "dice" => IdentKind::Dice,
+            "dice_mean" => IdentKind::DiceMean,
+            "dice_variance" | "dice_var" => IdentKind::DiceVariance,
+            "dice_prob" => IdentKind::DiceProb,
+            "dice_prob_at_least" => IdentKind::DiceProbAtLeast,
+            "dice_prob_at_most" => IdentKind::DiceProbAtMost,
+            "wavg" => IdentKind::Wavg,
+            "needed_score" => IdentKind::NeededScore,
+            "ratio_split" => IdentKind::RatioSplit,
+            "ratio_scale" => IdentKind::RatioScale,
+            "approx_equal" | "approx_eq" => IdentKind::ApproxEqual,
+            "sensitivity" | "elasticity" => IdentKind::Sensitivity,
+            "series" | "taylor" => IdentKind::Series,
+            "solve" => IdentKind::Solve,
+            "round_to" => IdentKind::RoundTo,
+            "roman" => IdentKind::Roman,
+            "to_roman" => IdentKind::ToRoman,
+            "now" => IdentKind::Now,
+            "time_in" => IdentKind::TimeIn,
             _ => return Err(()),
         })
     }
@@ -42,7 +155,17 @@ impl FromStr for IdentKind {
 /// Tokens are simple things like numbers, operators, parentheses, and so on.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TokenKind {
-    Num { val: BigUrational, input_base: u32 },
+    Num {
+        val: BigUrational,
+        input_base: u32,
+    },
+    /// A length in feet and inches such as `5' 3 1/2"`, in inches
+    FeetInches(BigUrational),
+    /// A number of dice with the same number of sides, for example `3d6`
+    Dice {
+        count: u32,
+        sides: u32,
+    },
     Ident(IdentKind),
     UnknownIdent(String),
     Plus,
@@ -52,6 +175,18 @@ pub enum TokenKind {
     Hat,
     OpenParen,
     CloseParen,
+    Comma,
+    Colon,
+    /// `$`, which is the prefix of amounts in US dollars
+    Dollar,
+    /// `%`, which divides the number before it by 100
+    Percent,
+    /// `=~`, which compares with a tolerance
+    ApproxEq,
+    /// The symbol of an operator defined by the user, such as `//`
+    Symbol(String),
+    /// A string between double quotes, such as `"UTC+9"`
+    Str(String),
 }
 
 impl TokenKind {
@@ -64,6 +199,10 @@ impl TokenKind {
             '^' => TokenKind::Hat,
             '(' => TokenKind::OpenParen,
             ')' => TokenKind::CloseParen,
+            ',' => TokenKind::Comma,
+            ':' => TokenKind::Colon,
+            '$' => TokenKind::Dollar,
+            '%' => TokenKind::Percent,
             _ => return None,
         })
     }
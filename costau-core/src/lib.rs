@@ -5,12 +5,33 @@ extern crate num_bigint;
 extern crate num_integer;
 extern crate num_rational;
 extern crate num_traits;
+extern crate rand;
 extern crate ryu;
+#[cfg(feature = "with-serde")]
+extern crate serde;
+#[cfg(feature = "with-serde")]
+extern crate serde_json;
 
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod builtins;
+pub mod constants;
+pub mod differential;
+pub mod evaluator;
+pub mod functions;
+pub mod identify;
+pub mod imperial;
 pub mod lexer;
+pub mod measurement;
+pub mod montecarlo;
+pub mod natural;
 pub mod node;
 pub mod parser;
 mod ratio2flt;
+pub mod session;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod words;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,115 @@
+//! Measurements are values with an uncertainty. The uncertainty of the
+//! variables of an expression is propagated to its result, so that it can be
+//! displayed with honest error bars.
+
+use std::fmt;
+
+use crate::constants;
+use crate::node::{EvalError, FormatOptions, Node};
+
+/// A value and its standard uncertainty.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Measurement {
+    pub val: f64,
+    pub uncertainty: f64,
+}
+
+impl Measurement {
+    pub fn new(val: f64, uncertainty: f64) -> Measurement {
+        Measurement { val, uncertainty }
+    }
+
+    /// The uncertainty relative to the value.
+    pub fn relative_uncertainty(&self) -> f64 {
+        (self.uncertainty / self.val).abs()
+    }
+
+    /// Writes the value and its uncertainty with the words of the locale of
+    /// the options.
+    pub fn format(&self, options: &FormatOptions) -> String {
+        format!(
+            "{} {} {}",
+            self.val, options.locale.plus_minus, self.uncertainty
+        )
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format(&FormatOptions::default()))
+    }
+}
+
+/// Evaluates the node where the variables are measurements. The variables
+/// that are not in `inputs` can be physical constants.
+/// The uncertainties are propagated to the first order, assuming that they
+/// are independent.
+pub fn eval_measurement(
+    node: &Node,
+    inputs: &[(&str, Measurement)],
+) -> Result<Measurement, EvalError> {
+    let names = node.vars();
+    let mut values = Vec::with_capacity(names.len());
+    for name in &names {
+        let input = inputs.iter().find(|x| x.0 == name).map(|x| x.1);
+        let input = input
+            .or_else(|| constants::find(name).map(|x| Measurement::new(x.value, x.uncertainty)));
+        values.push(input.ok_or(EvalError::UnboundVar)?);
+    }
+
+    let vars: Vec<&str> = names.iter().map(|x| x.as_str()).collect();
+    let vals: Vec<f64> = values.iter().map(|x| x.val).collect();
+    let val = node.compile(&vars)?.eval(&vals)?;
+    if !val.is_finite() {
+        return Err(EvalError::NotReal);
+    }
+
+    let mut variance = 0.0;
+    for (name, input) in names.iter().zip(&values) {
+        if input.uncertainty == 0.0 {
+            continue;
+        }
+        let slope = node.derivative(name).compile(&vars)?.eval(&vals)?;
+        variance += (slope * input.uncertainty) * (slope * input.uncertainty);
+    }
+    Ok(Measurement::new(val, variance.sqrt()))
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::{ApproxEq, F64Margin};
+
+    use super::*;
+    use crate::test_util::parse;
+
+    fn eval_str(expr: &str, inputs: &[(&str, Measurement)]) -> Result<Measurement, EvalError> {
+        let node = parse(expr);
+        eval_measurement(&node, inputs)
+    }
+
+    #[test]
+    fn it_propagates_uncertainties() {
+        let x = Measurement::new(3.0, 0.3);
+        let y = Measurement::new(4.0, 0.4);
+        let sum = eval_str("x + y", &[("x", x), ("y", y)]).unwrap();
+        assert!(sum.val.approx_eq(7.0, F64Margin::default()));
+        assert!((sum.uncertainty - 0.5).abs() < 1e-12);
+        // relative uncertainties add up in quadrature for products
+        let product = eval_str("x * y", &[("x", x), ("y", y)]).unwrap();
+        assert!((product.relative_uncertainty() - 0.1f64.hypot(0.1)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn it_uses_the_uncertainty_of_constants() {
+        let m = Measurement::new(5.972e24, 0.0);
+        let r = Measurement::new(6.371e6, 0.0);
+        let g = eval_str("G * m / r^2", &[("m", m), ("r", r)]).unwrap();
+        assert!((g.val - 9.82).abs() < 0.01);
+        let relative = 0.000_15 / 6.674_30;
+        assert!((g.relative_uncertainty() - relative).abs() < 1e-12);
+
+        let c = eval_str("c * 2", &[]).unwrap();
+        assert!(c.uncertainty.approx_eq(0.0, F64Margin::default()));
+        assert_eq!(eval_str("z * 2", &[]), Err(EvalError::UnboundVar));
+    }
+}
@@ -0,0 +1,232 @@
+//! Monte Carlo simulations estimate how the result of an expression is
+//! spread when its variables are uncertain, by evaluating it for many random
+//! values of the variables.
+
+use rand::prelude::*;
+use std::f64::consts::PI;
+
+use crate::node::{EvalError, EvalOptions, Node};
+
+/// The possible values of a variable and how likely they are.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Distribution {
+    /// All the values between `min` and `max` are equally likely
+    Uniform { min: f64, max: f64 },
+    /// The bell curve
+    Normal { mean: f64, stddev: f64 },
+    /// The likelihood grows linearly from `min` to `mode` and then decreases
+    /// linearly to `max`, which is handy for "best case, likely, worst case"
+    /// estimates
+    Triangular { min: f64, mode: f64, max: f64 },
+}
+
+impl Distribution {
+    /// Picks a random value.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> f64 {
+        match *self {
+            Distribution::Uniform { min, max } => min + (max - min) * rng.gen::<f64>(),
+            Distribution::Normal { mean, stddev } => {
+                // Box-Muller transform, where `u` is not zero to avoid `ln(0)`
+                let u = 1.0 - rng.gen::<f64>();
+                let v = rng.gen::<f64>();
+                mean + stddev * (-2.0 * u.ln()).sqrt() * (2.0 * PI * v).cos()
+            }
+            Distribution::Triangular { min, mode, max } => {
+                let u = rng.gen::<f64>();
+                let width = max - min;
+                if u * width < mode - min {
+                    min + (u * width * (mode - min)).sqrt()
+                } else {
+                    max - ((1.0 - u) * width * (max - mode)).sqrt()
+                }
+            }
+        }
+    }
+}
+
+/// A description of the error of a simulation.
+#[derive(Debug, PartialEq)]
+pub enum MonteCarloError {
+    /// The expression cannot be compiled
+    Eval(EvalError),
+    /// None of the samples gave a real number
+    NoSamples,
+}
+
+/// The spread of the results of a simulation.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Summary {
+    pub mean: f64,
+
+    /// The standard deviation of the results
+    pub stddev: f64,
+
+    /// The number of samples that did not give a real number, for example
+    /// because a square root of a negative number was taken
+    pub failed: usize,
+
+    // The results in increasing order.
+    sorted: Vec<f64>,
+}
+
+impl Summary {
+    /// Returns the value below which the fraction `q` of the results are,
+    /// interpolating between the results. `q` must be between `0` and `1`.
+    pub fn quantile(&self, q: f64) -> f64 {
+        let pos = q * (self.sorted.len() - 1) as f64;
+        let i = pos.floor() as usize;
+        match self.sorted.get(i + 1) {
+            Some(next) => self.sorted[i] + (next - self.sorted[i]) * (pos - i as f64),
+            None => self.sorted[i],
+        }
+    }
+
+    pub fn median(&self) -> f64 {
+        self.quantile(0.5)
+    }
+
+    pub fn min(&self) -> f64 {
+        self.sorted[0]
+    }
+
+    pub fn max(&self) -> f64 {
+        self.sorted[self.sorted.len() - 1]
+    }
+
+    /// The number of samples that gave a real number
+    pub fn count(&self) -> usize {
+        self.sorted.len()
+    }
+}
+
+/// Evaluates `node` for `n` random values of the variables in `inputs`.
+pub fn montecarlo(
+    node: &Node,
+    inputs: &[(&str, Distribution)],
+    n: usize,
+) -> Result<Summary, MonteCarloError> {
+    montecarlo_with_options(node, inputs, n, &EvalOptions::default())
+}
+
+/// Same as `montecarlo` but with the random number generator of the options,
+/// so that simulations with a seed can be reproduced.
+pub fn montecarlo_with_options(
+    node: &Node,
+    inputs: &[(&str, Distribution)],
+    n: usize,
+    options: &EvalOptions,
+) -> Result<Summary, MonteCarloError> {
+    montecarlo_with_rng(node, inputs, n, &mut options.rng())
+}
+
+/// Same as `montecarlo` but with the given random number generator.
+pub fn montecarlo_with_rng<R: Rng>(
+    node: &Node,
+    inputs: &[(&str, Distribution)],
+    n: usize,
+    rng: &mut R,
+) -> Result<Summary, MonteCarloError> {
+    let vars: Vec<&str> = inputs.iter().map(|&(name, _)| name).collect();
+    let compiled = node.compile(&vars).map_err(MonteCarloError::Eval)?;
+
+    let mut values = vec![0.0; inputs.len()];
+    let mut sorted = Vec::with_capacity(n);
+    for _ in 0..n {
+        for (value, input) in values.iter_mut().zip(inputs.iter()) {
+            *value = input.1.sample(rng);
+        }
+        let result = compiled.eval(&values).map_err(MonteCarloError::Eval)?;
+        if result.is_finite() {
+            sorted.push(result);
+        }
+    }
+    if sorted.is_empty() {
+        return Err(MonteCarloError::NoSamples);
+    }
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let count = sorted.len() as f64;
+    let mean = sorted.iter().sum::<f64>() / count;
+    let variance = if sorted.len() > 1 {
+        sorted.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (count - 1.0)
+    } else {
+        0.0
+    };
+    Ok(Summary {
+        mean,
+        stddev: variance.sqrt(),
+        failed: n - sorted.len(),
+        sorted,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{montecarlo_with_rng, Distribution, MonteCarloError, Summary};
+    use crate::node::EvalError;
+    use crate::test_util::parse;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn run(expr: &str, inputs: &[(&str, Distribution)]) -> Result<Summary, MonteCarloError> {
+        let node = parse(expr);
+        let mut rng = StdRng::seed_from_u64(42);
+        montecarlo_with_rng(&node, inputs, 20_000, &mut rng)
+    }
+
+    #[test]
+    fn it_estimates_the_spread() {
+        let summary = run(
+            "x + y",
+            &[
+                ("x", Distribution::Uniform { min: 0.0, max: 1.0 }),
+                (
+                    "y",
+                    Distribution::Normal {
+                        mean: 10.0,
+                        stddev: 2.0,
+                    },
+                ),
+            ],
+        )
+        .unwrap();
+        assert!((summary.mean - 10.5).abs() < 0.05);
+        assert!((summary.stddev - (4.0f64 + 1.0 / 12.0).sqrt()).abs() < 0.05);
+        assert!((summary.median() - 10.5).abs() < 0.1);
+        assert!(summary.quantile(0.05) < summary.quantile(0.95));
+        assert_eq!(summary.failed, 0);
+    }
+
+    #[test]
+    fn it_samples_triangular_distributions() {
+        let summary = run(
+            "x",
+            &[(
+                "x",
+                Distribution::Triangular {
+                    min: 1.0,
+                    mode: 2.0,
+                    max: 6.0,
+                },
+            )],
+        )
+        .unwrap();
+        assert!((summary.mean - 3.0).abs() < 0.05);
+        assert!(summary.min() >= 1.0 && summary.max() <= 6.0);
+    }
+
+    #[test]
+    fn it_skips_non_real_results() {
+        let normal = Distribution::Normal {
+            mean: 0.0,
+            stddev: 1.0,
+        };
+        let summary = run("sqrt(x)", &[("x", normal)]).unwrap();
+        assert!(summary.failed > 9_000 && summary.failed < 11_000);
+        assert_eq!(summary.count() + summary.failed, 20_000);
+        assert_eq!(
+            run("x + y", &[("x", normal)]).unwrap_err(),
+            MonteCarloError::Eval(EvalError::UnboundVar)
+        );
+    }
+}
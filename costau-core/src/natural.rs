@@ -0,0 +1,246 @@
+//! Expressions written in words, such as "two plus the square root of nine",
+//! which is what voice assistants hear.
+
+use num_bigint::BigUint;
+use num_traits::{Pow, Zero};
+
+use crate::lexer::{BigUrational, IdentKind, Lexer, Token, TokenKind};
+use crate::node::Node;
+use crate::parser::{ParseError, Parser};
+use crate::words::{ENGLISH_TENS, ENGLISH_UNITS};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum NaturalError {
+    /// A word that is not a number, an operation, a function or a single
+    /// letter variable
+    UnknownWord {
+        index: usize,
+    },
+    Parser(ParseError),
+}
+
+fn int(n: u32) -> TokenKind {
+    TokenKind::Num {
+        val: BigUrational::from(BigUint::from(n)),
+        input_base: 10,
+    }
+}
+
+/// Returns the value of a word such as `seven` or `seventy`.
+fn number_word(word: &str) -> Option<u32> {
+    if let Some(n) = ENGLISH_UNITS.iter().position(|x| *x == word) {
+        return Some(n as u32);
+    }
+    ENGLISH_TENS
+        .iter()
+        .position(|x| !x.is_empty() && *x == word)
+        .map(|n| n as u32 * 10)
+}
+
+fn scale_word(word: &str) -> Option<u32> {
+    match word {
+        "thousand" => Some(3),
+        "million" => Some(6),
+        "billion" => Some(9),
+        "trillion" => Some(12),
+        _ => None,
+    }
+}
+
+/// Reads a number such as `one thousand two hundred and five point two` at
+/// the start of the words, and returns it with the number of words read.
+fn parse_number(words: &[&str]) -> Option<(BigUrational, usize)> {
+    let mut total = BigUint::zero();
+    let mut group = 0u32;
+    let mut i = 0;
+    let mut found = false;
+    while i < words.len() {
+        let word = words[i];
+        if let Some(n) = number_word(word) {
+            group += n;
+        } else if word == "hundred" && found {
+            group *= 100;
+        } else if let Some(scale) = scale_word(word).filter(|_| found) {
+            total += BigUint::from(group) * BigUint::from(10u32).pow(scale);
+            group = 0;
+        } else if word == "and"
+            && found
+            && matches!(words.get(i + 1), Some(w) if number_word(w).is_some())
+        {
+            // such as `one hundred and five`
+        } else {
+            break;
+        }
+        found = true;
+        i += 1;
+    }
+    if !found {
+        return None;
+    }
+    let mut val = BigUrational::from(total + BigUint::from(group));
+
+    // the digits after the decimal point are read one by one
+    if words.get(i) == Some(&"point") {
+        let mut digits = 0;
+        let mut fraction = BigUint::zero();
+        while let Some(d) = words.get(i + 1 + digits).and_then(|w| number_word(w)) {
+            if d > 9 {
+                break;
+            }
+            fraction = fraction * 10u32 + d;
+            digits += 1;
+        }
+        if digits > 0 {
+            let denom = BigUint::from(10u32).pow(digits as u32);
+            val += BigUrational::new(fraction, denom);
+            i += 1 + digits;
+        }
+    }
+    Some((val, i))
+}
+
+/// Returns the tokens of the words at the start of `words` and the number of
+/// words that they take.
+fn parse_phrase(words: &[&str]) -> Option<(Vec<TokenKind>, usize)> {
+    use self::IdentKind::*;
+    use self::TokenKind::*;
+
+    Some(match words {
+        // words that do not change the meaning
+        ["the", ..] | ["of", ..] | ["and", ..] => (vec![], 1),
+
+        // operations
+        ["plus", ..] => (vec![Plus], 1),
+        ["minus", ..] | ["negative", ..] => (vec![Minus], 1),
+        ["times", ..] => (vec![Times], 1),
+        ["multiplied", "by", ..] => (vec![Times], 2),
+        ["divided", "by", ..] => (vec![Slash], 2),
+        ["over", ..] => (vec![Slash], 1),
+        ["raised", "to", "the", "power", "of", ..] => (vec![Hat], 5),
+        ["to", "the", "power", "of", ..] => (vec![Hat], 4),
+        ["squared", ..] => (vec![Hat, int(2)], 1),
+        ["cubed", ..] => (vec![Hat, int(3)], 1),
+        ["open", "parenthesis", ..] => (vec![OpenParen], 2),
+        ["close", "parenthesis", ..] => (vec![CloseParen], 2),
+
+        // functions
+        ["square", "root", ..] => (vec![Ident(Sqrt)], 2),
+        ["cube", "root", ..] => (vec![Ident(Cbrt)], 2),
+        ["absolute", "value", ..] => (vec![Ident(Abs)], 2),
+        ["natural", "log", ..] | ["natural", "logarithm", ..] => (vec![Ident(Ln)], 2),
+        ["log", ..] | ["logarithm", ..] => (vec![Ident(Log)], 1),
+        ["sine", ..] => (vec![Ident(Sin)], 1),
+        ["cosine", ..] => (vec![Ident(Cos)], 1),
+        ["tangent", ..] => (vec![Ident(Tan)], 1),
+
+        // constants
+        ["pi", ..] => (vec![Ident(Pi)], 1),
+        _ => return None,
+    })
+}
+
+/// Parses an expression written in words, such as
+/// `two plus the square root of nine`. Digits, symbols and single letter
+/// variables can be mixed with the words.
+pub fn parse_natural(input: &str) -> Result<Node, NaturalError> {
+    let lower = input.to_lowercase();
+    let mut words = Vec::new();
+    let mut indices = Vec::new();
+    let mut start = None;
+    // `thirty-four` is two words
+    for (i, c) in lower
+        .char_indices()
+        .chain(std::iter::once((lower.len(), ' ')))
+    {
+        let is_separator = c.is_whitespace() || c == '-' && start.is_some();
+        match (start, is_separator) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                words.push(&lower[s..i]);
+                indices.push(s);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        let index = indices[i];
+        if let Some((val, count)) = parse_number(&words[i..]) {
+            tokens.push(Token {
+                kind: TokenKind::Num {
+                    val,
+                    input_base: 10,
+                },
+                index,
+            });
+            i += count;
+            continue;
+        }
+        if let Some((kinds, count)) = parse_phrase(&words[i..]) {
+            tokens.extend(kinds.into_iter().map(|kind| Token { kind, index }));
+            i += count;
+            continue;
+        }
+
+        // the other words are read as typed expressions, except for the
+        // English words
+        let word = words[i];
+        if word.len() > 1 && word.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(NaturalError::UnknownWord { index });
+        }
+        for token in Lexer::new(word) {
+            let token = token.map_err(|_| NaturalError::UnknownWord { index })?;
+            tokens.push(Token {
+                kind: token.kind,
+                index: index + token.index,
+            });
+        }
+        i += 1;
+    }
+
+    Parser::new(&tokens).parse().map_err(NaturalError::Parser)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(input: &str) -> Result<String, NaturalError> {
+        parse_natural(input).map(|x| x.to_string())
+    }
+
+    #[test]
+    fn it_parses_numbers_in_words() {
+        assert_eq!(
+            parse_str("one thousand two hundred thirty-four point five"),
+            Ok("2469/2".to_owned())
+        );
+        assert_eq!(
+            parse_str("three hundred and five million"),
+            Ok("305000000".to_owned())
+        );
+    }
+
+    #[test]
+    fn it_parses_operations_and_functions() {
+        assert_eq!(
+            parse_str("two plus the square root of nine"),
+            Ok("2 + 9^(1/2)".to_owned())
+        );
+        assert_eq!(
+            parse_str("x squared minus three times x"),
+            Ok("x^2 - 3x".to_owned())
+        );
+        assert_eq!(
+            parse_str("the sine of 2 divided by y"),
+            Ok("sin 2 / y".to_owned())
+        );
+        assert_eq!(
+            parse_str("two plus banana"),
+            Err(NaturalError::UnknownWord { index: 9 })
+        );
+    }
+}
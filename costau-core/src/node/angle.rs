@@ -0,0 +1,92 @@
+use num_rational::BigRational;
+
+use super::util::common::num;
+use super::{ConstKind, Node};
+
+/// The unit of the angles given to and returned by trigonometric functions.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum AngleUnit {
+    Radians,
+    /// A full turn is 360 degrees
+    Degrees,
+    /// A full turn is 400 gradians
+    Gradians,
+}
+
+impl AngleUnit {
+    /// Returns the number of units in a half turn, or `None` for radians.
+    fn half_turn(self) -> Option<i64> {
+        match self {
+            AngleUnit::Radians => None,
+            AngleUnit::Degrees => Some(180),
+            AngleUnit::Gradians => Some(200),
+        }
+    }
+}
+
+/// Returns the node where the angles in the given unit are converted to
+/// radians, so that the rest of the code only has to know about radians.
+/// Because the conversion uses `pi`, the simplification of multiples of `pi`
+/// still gives exact values such as `sin(90) = 1` for degrees.
+pub fn to_radians(node: Node, unit: AngleUnit) -> Node {
+    let half_turn = match unit.half_turn() {
+        Some(x) => x,
+        None => return node,
+    };
+    let node = node.map_children(|child| to_radians(child, unit));
+    let from_unit = |angle: Node| {
+        Node::Product(vec![
+            angle,
+            Node::Const(ConstKind::Pi),
+            num(BigRational::new(1.into(), half_turn.into())),
+        ])
+    };
+    let to_unit = |angle: Node| {
+        Node::Product(vec![
+            angle,
+            num(BigRational::from_integer(half_turn.into())),
+            Node::Const(ConstKind::Pi).inverse(),
+        ])
+    };
+    match node {
+        Node::Sin(inner) => from_unit(*inner).sin(),
+        Node::Cos(inner) => from_unit(*inner).cos(),
+        Node::Tan(inner) => from_unit(*inner).tan(),
+        node @ Node::Asin(_) | node @ Node::Acos(_) | node @ Node::Atan(_) => to_unit(node),
+        node @ Node::Atan2(_, _) => to_unit(node),
+        node => node,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    fn simplify_in(expr: &str, unit: AngleUnit) -> String {
+        parse(expr).simplify_in(unit).unwrap().result.to_string()
+    }
+
+    #[test]
+    fn it_simplifies_angles_in_degrees_exactly() {
+        assert_eq!(simplify_in("sin(90)", AngleUnit::Degrees), "1");
+        assert_eq!(simplify_in("cos(60)", AngleUnit::Degrees), "1/2");
+        assert_eq!(simplify_in("tan(50)", AngleUnit::Gradians), "1");
+        assert_eq!(simplify_in("asin(1)", AngleUnit::Degrees), "90");
+        assert_eq!(simplify_in("sin(90)", AngleUnit::Radians), "sin 90");
+        assert_eq!(simplify_in("sin(90) * 2 m", AngleUnit::Degrees), "2 m");
+    }
+
+    #[test]
+    fn it_evaluates_angles_in_degrees() {
+        let result = parse("sin(30)").eval_in(AngleUnit::Degrees).unwrap();
+        assert!((result.val - 0.5).abs() < 1e-15);
+        let result = parse("atan2(1, 1)").eval_in(AngleUnit::Gradians).unwrap();
+        assert!((result.val - 50.0).abs() < 1e-12);
+        // the other functions are not affected
+        let result = parse("sqrt(4) + sinh(0)")
+            .eval_in(AngleUnit::Degrees)
+            .unwrap();
+        assert!((result.val - 2.0).abs() < 1e-15);
+    }
+}
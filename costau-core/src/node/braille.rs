@@ -0,0 +1,446 @@
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed};
+
+use super::display::{get_node_priority, NodePriority};
+use super::units::has_units;
+use super::util::is_minus_one;
+use super::{ConstKind, FormatOptions, Node};
+use crate::builtins::clock::format_zone;
+
+/// The braille codes for mathematics.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum BrailleCode {
+    /// The Nemeth code, which is used in North America
+    Nemeth,
+    /// The technical material of Unified English Braille
+    Ueb,
+}
+
+/// The braille space, which is used instead of a normal space so that the
+/// output only contains braille cells.
+const SPACE: &str = "⠀";
+
+const LETTERS: [&str; 26] = [
+    "⠁", "⠃", "⠉", "⠙", "⠑", "⠋", "⠛", "⠓", "⠊", "⠚", "⠅", "⠇", "⠍", "⠝", "⠕", "⠏", "⠟", "⠗", "⠎",
+    "⠞", "⠥", "⠧", "⠺", "⠭", "⠽", "⠵",
+];
+
+/// The digits of the Nemeth code are in the lower part of the cell.
+const NEMETH_DIGITS: [&str; 10] = ["⠴", "⠂", "⠆", "⠒", "⠲", "⠢", "⠖", "⠶", "⠦", "⠔"];
+
+/// The digits of UEB are the letters from `j` to `i`.
+const UEB_DIGITS: [&str; 10] = ["⠚", "⠁", "⠃", "⠉", "⠙", "⠑", "⠋", "⠛", "⠓", "⠊"];
+
+/// The symbols that differ between the codes.
+struct Symbols {
+    digits: [&'static str; 10],
+    decimal_point: &'static str,
+    plus: &'static str,
+    minus: &'static str,
+    times: &'static str,
+    slash: &'static str,
+    ratio: &'static str,
+    comma: &'static str,
+    open: &'static str,
+    close: &'static str,
+    subscript: &'static str,
+}
+
+const NEMETH: Symbols = Symbols {
+    digits: NEMETH_DIGITS,
+    decimal_point: "⠨",
+    plus: "⠬",
+    minus: "⠤",
+    times: "⠡",
+    slash: "⠸⠌",
+    ratio: "⠐⠂",
+    comma: "⠠⠀",
+    open: "⠷",
+    close: "⠾",
+    subscript: "⠰",
+};
+
+const UEB: Symbols = Symbols {
+    digits: UEB_DIGITS,
+    decimal_point: "⠲",
+    plus: "⠐⠖",
+    minus: "⠐⠤",
+    times: "⠐⠦",
+    slash: "⠸⠌",
+    ratio: "⠒",
+    comma: "⠂",
+    open: "⠐⠣",
+    close: "⠐⠜",
+    subscript: "⠢",
+};
+
+struct Writer {
+    code: BrailleCode,
+    symbols: &'static Symbols,
+    out: String,
+    /// The number of superscripts that the writer is in
+    level: usize,
+    /// An indicator that is written before the next symbol, such as the
+    /// return to the baseline after a superscript
+    pending: Option<String>,
+    /// Whether a number written now needs the numeric indicator in the
+    /// Nemeth code, which is the case at the start and after a space
+    nemeth_numeric: bool,
+    /// Whether the last symbol is a digit, after which the letters from `a`
+    /// to `j` need the grade 1 indicator in UEB
+    after_digit: bool,
+}
+
+impl Writer {
+    fn push(&mut self, s: &str) {
+        if let Some(pending) = self.pending.take() {
+            self.out.push_str(&pending);
+        }
+        self.out.push_str(s);
+        self.nemeth_numeric = false;
+        self.after_digit = false;
+    }
+
+    fn push_digits(&mut self, digits: &str) {
+        let indicator = match self.code {
+            BrailleCode::Nemeth => self.nemeth_numeric,
+            BrailleCode::Ueb => !self.after_digit,
+        };
+        if indicator {
+            self.push("⠼");
+        }
+        for c in digits.chars() {
+            match c.to_digit(10) {
+                Some(d) => self.push(self.symbols.digits[d as usize]),
+                None => self.push(self.symbols.decimal_point),
+            }
+            self.after_digit = true;
+        }
+    }
+
+    fn push_letters(&mut self, letters: &str) {
+        for c in letters.chars() {
+            let lower = c.to_ascii_lowercase();
+            match lower {
+                'a'..='z' => {
+                    let needs_grade_1 =
+                        self.code == BrailleCode::Ueb && self.after_digit && lower <= 'j';
+                    if needs_grade_1 {
+                        self.push("⠰");
+                    }
+                    if c.is_ascii_uppercase() {
+                        self.push("⠠");
+                    }
+                    self.push(LETTERS[(lower as u8 - b'a') as usize]);
+                }
+                '0'..='9' => self.push_digits(&c.to_string()),
+                '_' => self.push(self.symbols.subscript),
+                c => self.push(&c.to_string()),
+            }
+        }
+        if letters.contains('_') && self.code == BrailleCode::Nemeth {
+            self.pending = Some(self.baseline());
+        }
+    }
+
+    /// The indicator of the current level, which is written when going back
+    /// to it.
+    fn baseline(&self) -> String {
+        match self.level {
+            0 => "⠐".to_owned(),
+            level => "⠘".repeat(level),
+        }
+    }
+
+    fn write_number(&mut self, val: &BigRational) {
+        if val.is_negative() {
+            // the minus sign does not end the start of the number
+            let numeric = self.nemeth_numeric;
+            self.push(self.symbols.minus);
+            self.nemeth_numeric = numeric;
+        }
+        let val = val.abs();
+        if val.is_integer() {
+            self.push_digits(&val.numer().to_string());
+            return;
+        }
+        match self.code {
+            BrailleCode::Nemeth => {
+                // the digits inside a fraction do not need the indicator
+                self.push("⠹");
+                self.push_digits(&val.numer().to_string());
+                self.push("⠌");
+                self.push_digits(&val.denom().to_string());
+                self.push("⠼");
+            }
+            BrailleCode::Ueb => {
+                self.push_digits(&val.numer().to_string());
+                self.push("⠌");
+                self.after_digit = true;
+                self.push_digits(&val.denom().to_string());
+            }
+        }
+    }
+
+    fn write_with_paren(&mut self, node: &Node, curr_prio: NodePriority, left_assoc: bool) {
+        let prio = get_node_priority(node, &FormatOptions::default());
+        let needs_paren = if left_assoc {
+            prio < curr_prio
+        } else {
+            prio <= curr_prio
+        };
+        if needs_paren {
+            self.push(self.symbols.open);
+        }
+        self.write_node(node);
+        if needs_paren {
+            self.push(self.symbols.close);
+        }
+    }
+
+    fn write_list(&mut self, items: &[&Node]) {
+        self.push(self.symbols.open);
+        for (i, item) in items.iter().enumerate() {
+            if i != 0 {
+                self.push(self.symbols.comma);
+            }
+            self.write_node(item);
+        }
+        self.push(self.symbols.close);
+    }
+
+    fn write_func(&mut self, name: &str, inner: &Node) {
+        self.push_letters(name);
+        self.push(SPACE);
+        self.write_with_paren(inner, NodePriority::Exp, true);
+    }
+
+    fn write_superscript(&mut self, expon: &Node) {
+        match self.code {
+            BrailleCode::Nemeth => {
+                // the level indicators tell where the superscript ends
+                self.level += 1;
+                self.push(&"⠘".repeat(self.level));
+                self.write_node(expon);
+                self.level -= 1;
+                self.pending = Some(self.baseline());
+            }
+            BrailleCode::Ueb => {
+                self.push("⠔");
+                let is_item = match expon {
+                    Node::Var(name) => name.chars().count() == 1,
+                    Node::Num { val, .. } => val.is_integer() && !val.is_negative(),
+                    Node::Const(_) => true,
+                    _ => false,
+                };
+                if is_item {
+                    self.write_node(expon);
+                } else {
+                    self.push("⠣");
+                    self.write_node(expon);
+                    self.push("⠜");
+                }
+            }
+        }
+    }
+
+    fn write_node(&mut self, node: &Node) {
+        match node {
+            Node::Const(kind) => match kind {
+                ConstKind::Pi => self.push("⠨⠏"),
+                ConstKind::Tau => self.push("⠨⠞"),
+                ConstKind::E => self.push_letters("e"),
+                ConstKind::I => self.push_letters("i"),
+            },
+            Node::Var(name) => self.push_letters(name),
+            Node::Num { val, .. } => self.write_number(val),
+            Node::Sum(children) => {
+                for (i, child) in children.iter().enumerate() {
+                    if i != 0 {
+                        // subtractions are written with a minus sign like
+                        // in the text output
+                        let negated = match child {
+                            Node::Product(factors) if factors.len() == 2 => {
+                                if is_minus_one(&factors[0]) {
+                                    Some(factors[1].clone())
+                                } else if is_minus_one(&factors[1]) {
+                                    Some(factors[0].clone())
+                                } else {
+                                    None
+                                }
+                            }
+                            Node::Num { val, .. } if val.is_negative() => Some(Node::Num {
+                                val: -val,
+                                input_base: None,
+                            }),
+                            _ => None,
+                        };
+                        if let Some(negated) = negated {
+                            self.push(self.symbols.minus);
+                            self.write_with_paren(&negated, NodePriority::AddOrSub, false);
+                            continue;
+                        }
+                        self.push(self.symbols.plus);
+                    }
+                    self.write_with_paren(child, NodePriority::AddOrSub, true);
+                }
+            }
+            Node::Product(children) => {
+                if let [minus_one, value] = &children[..] {
+                    if is_minus_one(minus_one) && matches!(value, Node::Const(_) | Node::Var(_)) {
+                        self.push(self.symbols.minus);
+                        return self.write_node(value);
+                    }
+                }
+                let mut previous_was_int = false;
+                for (i, child) in children.iter().enumerate() {
+                    if i != 0 {
+                        if let Node::Exp(a, b) = child {
+                            if is_minus_one(b) {
+                                self.push(self.symbols.slash);
+                                self.write_with_paren(a, NodePriority::MulOrDiv, false);
+                                continue;
+                            }
+                        }
+                        let implicit_mul =
+                            previous_was_int && matches!(child, Node::Const(_) | Node::Var(_));
+                        if !implicit_mul {
+                            self.push(self.symbols.times);
+                        }
+                    }
+                    self.write_with_paren(child, NodePriority::MulOrDiv, true);
+                    previous_was_int = matches!(child, Node::Num { val, .. } if val.is_integer());
+                }
+            }
+            Node::Exp(a, b) => {
+                if is_minus_one(b) {
+                    self.write_number(&BigRational::one());
+                    self.push(self.symbols.slash);
+                    self.write_with_paren(a, NodePriority::MulOrDiv, false);
+                } else {
+                    self.write_with_paren(a, NodePriority::Exp, false);
+                    self.write_superscript(b);
+                }
+            }
+            Node::Vector(items) => self.write_list(&items.iter().collect::<Vec<_>>()),
+            Node::Ratio(terms) => {
+                for (i, term) in terms.iter().enumerate() {
+                    if i != 0 {
+                        self.push(self.symbols.ratio);
+                    }
+                    self.write_with_paren(term, NodePriority::Ratio, false);
+                }
+            }
+            Node::Dice { count, sides } => {
+                self.write_number(&BigRational::from_integer(BigInt::from(*count)));
+                self.push_letters("d");
+                self.write_number(&BigRational::from_integer(BigInt::from(*sides)));
+            }
+            Node::Time { offset: 0 } => {
+                self.push_letters("now");
+                self.push(self.symbols.open);
+                self.push(self.symbols.close);
+            }
+            Node::Time { offset } => {
+                self.push_letters("time_in");
+                self.push(self.symbols.open);
+                self.push_letters(&format_zone(*offset));
+                self.push(self.symbols.close);
+            }
+            Node::Quantity(inner, unit) => {
+                let prio = if has_units(inner) {
+                    NodePriority::Convert
+                } else {
+                    NodePriority::Exp
+                };
+                self.write_with_paren(inner, prio, false);
+                self.push(SPACE);
+                if has_units(inner) {
+                    self.push_letters("in");
+                    self.push(SPACE);
+                }
+                self.push_letters(unit.symbol());
+            }
+            // functions
+            Node::Sin(inner) => self.write_func("sin", inner),
+            Node::Cos(inner) => self.write_func("cos", inner),
+            Node::Tan(inner) => self.write_func("tan", inner),
+            Node::Asin(inner) => self.write_func("asin", inner),
+            Node::Acos(inner) => self.write_func("acos", inner),
+            Node::Atan(inner) => self.write_func("atan", inner),
+            Node::Atan2(y, x) => {
+                self.push_letters("atan2");
+                self.write_list(&[y, x]);
+            }
+            Node::Sinh(inner) => self.write_func("sinh", inner),
+            Node::Cosh(inner) => self.write_func("cosh", inner),
+            Node::Tanh(inner) => self.write_func("tanh", inner),
+            Node::Abs(inner) => self.write_func("abs", inner),
+            Node::Floor(inner) => self.write_func("floor", inner),
+            Node::Ceil(inner) => self.write_func("ceil", inner),
+            Node::Round(inner) => self.write_func("round", inner),
+            Node::Sign(inner) => self.write_func("sign", inner),
+            Node::Min(children) => {
+                self.push_letters("min");
+                self.write_list(&children.iter().collect::<Vec<_>>());
+            }
+            Node::Max(children) => {
+                self.push_letters("max");
+                self.write_list(&children.iter().collect::<Vec<_>>());
+            }
+            Node::Ln(inner) => self.write_func("ln", inner),
+            Node::Log(base, inner) => {
+                self.push_letters("log");
+                self.write_list(&[base, inner]);
+            }
+        }
+    }
+}
+
+/// Transcribes the node to braille in the given code, with the characters of
+/// the Unicode braille patterns.
+pub fn to_braille(node: &Node, code: BrailleCode) -> String {
+    let mut writer = Writer {
+        code,
+        symbols: match code {
+            BrailleCode::Nemeth => &NEMETH,
+            BrailleCode::Ueb => &UEB,
+        },
+        out: String::new(),
+        level: 0,
+        pending: None,
+        nemeth_numeric: true,
+        after_digit: false,
+    };
+    writer.write_node(node);
+    writer.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    fn braille_str(expr: &str, code: BrailleCode) -> String {
+        let node = parse(expr);
+        to_braille(&node.simplify().unwrap().result, code)
+    }
+
+    #[test]
+    fn it_writes_nemeth() {
+        assert_eq!(braille_str("x^2 + 1", BrailleCode::Nemeth), "⠼⠂⠬⠭⠘⠆");
+        assert_eq!(braille_str("x^2 * y", BrailleCode::Nemeth), "⠭⠘⠆⠐⠡⠽");
+        assert_eq!(braille_str("5/2", BrailleCode::Nemeth), "⠹⠢⠌⠆⠼");
+        assert_eq!(braille_str("-3", BrailleCode::Nemeth), "⠤⠼⠒");
+        assert_eq!(braille_str("sin(2 * x)", BrailleCode::Nemeth), "⠎⠊⠝⠀⠷⠆⠭⠾");
+    }
+
+    #[test]
+    fn it_writes_ueb() {
+        assert_eq!(braille_str("x^2 + 1", BrailleCode::Ueb), "⠼⠁⠐⠖⠭⠔⠼⠃");
+        assert_eq!(braille_str("3/4", BrailleCode::Ueb), "⠼⠉⠌⠙");
+        assert_eq!(braille_str("2 * a", BrailleCode::Ueb), "⠼⠃⠰⠁");
+        assert_eq!(braille_str("x^(y + 1)", BrailleCode::Ueb), "⠭⠔⠣⠼⠁⠐⠖⠽⠜");
+    }
+}
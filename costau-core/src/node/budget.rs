@@ -0,0 +1,95 @@
+//! A budget of memory for the calculations, so that the builds for web pages
+//! and phones cannot run out of memory on an expression such as `7^7^7`.
+//! It is the `memory_budget` of the `EvalOptions`. The powers of numbers that
+//! would go over it are kept unevaluated, so that they are approximated, and
+//! the other rules that build big numbers, the expansions and the exact
+//! evaluations fail with an `OverBudget` error. The estimates are rough: they
+//! count the bytes of the digits of the exact numbers and of the nodes that
+//! the expansions create.
+
+use num_rational::BigRational;
+use std::mem;
+
+use super::Node;
+
+fn fits(bytes: f64, budget: Option<usize>) -> bool {
+    match budget {
+        Some(budget) => bytes <= budget as f64,
+        None => true,
+    }
+}
+
+/// Returns the number of bits of an exact number.
+pub(super) fn bits(val: &BigRational) -> f64 {
+    (val.numer().bits() + val.denom().bits()) as f64
+}
+
+/// Returns whether an exact number of this many bits fits in the budget of
+/// the `EvalOptions`, in bytes. There is no limit if it is `None`.
+pub(super) fn fits_bits(bits: f64, budget: Option<usize>) -> bool {
+    fits(bits / 8.0, budget)
+}
+
+/// Returns whether the sum or the product of two exact numbers fits in the
+/// budget. Both have at most as many bits as the two numbers and one more.
+pub(super) fn fits_sum_or_product(a: &BigRational, b: &BigRational, budget: Option<usize>) -> bool {
+    fits_bits(bits(a) + bits(b) + 1.0, budget)
+}
+
+/// Returns whether this many new nodes fit in the budget.
+pub(super) fn fits_nodes(count: usize, budget: Option<usize>) -> bool {
+    fits(count as f64 * mem::size_of::<Node>() as f64, budget)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{EvalError, EvalOptions, SimplifyError};
+    use crate::test_util::{calculate_str, parse};
+
+    #[test]
+    fn it_keeps_calculations_within_the_budget() {
+        let options = EvalOptions {
+            memory_budget: Some(64),
+            ..EvalOptions::default()
+        };
+        let within = |expr: &str| {
+            parse(expr)
+                .calculate_with_options(&options)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(within("2^10 + 1"), "1025");
+        // 2^256 takes about 96 bytes, so it is approximated
+        assert_eq!(within("2^256 + 1"), "1 + 2^256 ≈ 1.157920892373162e77");
+        assert_eq!(
+            parse("3^1000").eval_exact_with_options(&options),
+            Err(EvalError::OverBudget)
+        );
+
+        // the sums and the products of numbers are checked too
+        // a number of 90 digits takes about 38 bytes
+        let big = "9".repeat(90);
+        let product = format!("{} * {}", big, big);
+        assert_eq!(
+            parse(&product).simplify_with_options(&options),
+            Err(SimplifyError::OverBudget)
+        );
+        assert_eq!(
+            parse(&product).eval_exact_with_options(&options),
+            Err(EvalError::OverBudget)
+        );
+
+        let options = EvalOptions {
+            memory_budget: Some(4096),
+            ..EvalOptions::default()
+        };
+        let expand = |expr: &str| parse(expr).expand_with_options(&options);
+        assert_eq!(expand("2 * (x + 1)"), Ok(parse("2 + 2x")));
+        assert_eq!(expand("(a + b + c + d)^8"), Err(SimplifyError::OverBudget));
+
+        // there is no limit without a budget
+        assert!(calculate_str("2^256 + 1").starts_with("115792089237316195"));
+        assert!(parse(&product).simplify().is_ok());
+        assert!(parse("(a + b + c + d)^8").expand().is_ok());
+    }
+}
@@ -0,0 +1,299 @@
+//! A compact binary format for compiled expressions, so that frontends can
+//! cache the expressions that they evaluate often, such as the functions of
+//! a graph, without parsing and simplifying them again.
+//!
+//! A blob starts with the bytes `CSTC` and the version of the format, then a
+//! byte that is 1 if the polynomials are evaluated with compensation. The
+//! names of the variables and the instructions follow, each list with its
+//! length first. The integers are written as 32-bit little-endian numbers
+//! and the numbers as 64-bit little-endian floats.
+//!
+//! Blobs of another version are rejected, and the instructions are checked
+//! when they are read, so that a corrupted blob cannot make the evaluation
+//! panic.
+
+use std::convert::TryInto;
+
+use super::compile::{Compiled, Func, Op};
+
+/// The version of the format that is written, and the only one that can be
+/// read.
+pub const BYTECODE_VERSION: u8 = 1;
+
+const MAGIC: &[u8; 4] = b"CSTC";
+
+/// A description of why a blob cannot be read as a compiled expression.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum BytecodeError {
+    /// The blob does not start with the bytes of the format
+    BadMagic,
+    /// The blob was written by another version of the format
+    UnsupportedVersion(u8),
+    /// The blob ends in the middle of the expression
+    UnexpectedEnd,
+    /// The blob has an unknown instruction, an instruction with invalid
+    /// operands, or something after the expression
+    Invalid,
+}
+
+fn write_u32(out: &mut Vec<u8>, n: usize) {
+    out.extend_from_slice(&(n as u32).to_le_bytes());
+}
+
+fn write_f64(out: &mut Vec<u8>, x: f64) {
+    out.extend_from_slice(&x.to_le_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    index: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], BytecodeError> {
+        if self.bytes.len() - self.index < len {
+            return Err(BytecodeError::UnexpectedEnd);
+        }
+        let bytes = &self.bytes[self.index..self.index + len];
+        self.index += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, BytecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn f64(&mut self) -> Result<f64, BytecodeError> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Reads a length, which cannot be larger than the rest of the blob
+    /// since each item takes at least a byte.
+    fn len(&mut self) -> Result<usize, BytecodeError> {
+        let len = self.u32()?;
+        if len > self.bytes.len() - self.index {
+            return Err(BytecodeError::UnexpectedEnd);
+        }
+        Ok(len)
+    }
+}
+
+fn write_op(out: &mut Vec<u8>, op: &Op) {
+    match op {
+        Op::Push(x) => {
+            out.push(0);
+            write_f64(out, *x);
+        }
+        Op::Var(i) => {
+            out.push(1);
+            write_u32(out, *i);
+        }
+        Op::Add(n) => {
+            out.push(2);
+            write_u32(out, *n);
+        }
+        Op::Mul(n) => {
+            out.push(3);
+            write_u32(out, *n);
+        }
+        Op::Min(n) => {
+            out.push(4);
+            write_u32(out, *n);
+        }
+        Op::Max(n) => {
+            out.push(5);
+            write_u32(out, *n);
+        }
+        Op::Pow => out.push(6),
+        Op::Atan2 => out.push(7),
+        Op::Log => out.push(8),
+        Op::Func(f) => {
+            out.push(9);
+            out.push(Func::ALL.iter().position(|x| x == f).unwrap() as u8);
+        }
+        Op::Horner(i, coefs) => {
+            out.push(10);
+            write_u32(out, *i);
+            write_u32(out, coefs.len());
+            for &coef in coefs {
+                write_f64(out, coef);
+            }
+        }
+    }
+}
+
+fn read_op(reader: &mut Reader) -> Result<Op, BytecodeError> {
+    Ok(match reader.u8()? {
+        0 => Op::Push(reader.f64()?),
+        1 => Op::Var(reader.u32()?),
+        2 => Op::Add(reader.u32()?),
+        3 => Op::Mul(reader.u32()?),
+        4 => Op::Min(reader.u32()?),
+        5 => Op::Max(reader.u32()?),
+        6 => Op::Pow,
+        7 => Op::Atan2,
+        8 => Op::Log,
+        9 => match Func::ALL.get(reader.u8()? as usize) {
+            Some(&f) => Op::Func(f),
+            None => return Err(BytecodeError::Invalid),
+        },
+        10 => {
+            let i = reader.u32()?;
+            let len = reader.len()?;
+            let coefs = (0..len)
+                .map(|_| reader.f64())
+                .collect::<Result<Vec<_>, _>>()?;
+            Op::Horner(i, coefs)
+        }
+        _ => return Err(BytecodeError::Invalid),
+    })
+}
+
+/// Returns the largest number of numbers on the stack while the
+/// instructions run, or `None` if they do not leave exactly one number or
+/// use a variable that does not exist.
+fn max_stack(ops: &[Op], vars: usize) -> Option<usize> {
+    let mut depth: usize = 0;
+    let mut max = 0;
+    for op in ops {
+        let popped = match op {
+            Op::Var(i) | Op::Horner(i, _) if *i >= vars => return None,
+            Op::Horner(_, coefs) if coefs.is_empty() => return None,
+            Op::Push(_) | Op::Var(_) | Op::Horner(..) => 0,
+            Op::Add(n) | Op::Mul(n) | Op::Min(n) | Op::Max(n) => *n,
+            Op::Pow | Op::Atan2 | Op::Log => 2,
+            Op::Func(_) => 1,
+        };
+        depth = depth.checked_sub(popped)? + 1;
+        max = max.max(depth);
+    }
+    if depth == 1 {
+        Some(max)
+    } else {
+        None
+    }
+}
+
+impl Compiled {
+    /// Writes the compiled expression as a blob that `from_bytes` can read.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(BYTECODE_VERSION);
+        out.push(self.compensated as u8);
+        write_u32(&mut out, self.vars.len());
+        for var in &self.vars {
+            write_u32(&mut out, var.len());
+            out.extend_from_slice(var.as_bytes());
+        }
+        write_u32(&mut out, self.ops.len());
+        for op in &self.ops {
+            write_op(&mut out, op);
+        }
+        out
+    }
+
+    /// Reads a compiled expression written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Compiled, BytecodeError> {
+        let mut reader = Reader { bytes, index: 0 };
+        if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(BytecodeError::BadMagic);
+        }
+        let version = reader.u8()?;
+        if version != BYTECODE_VERSION {
+            return Err(BytecodeError::UnsupportedVersion(version));
+        }
+        let compensated = match reader.u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(BytecodeError::Invalid),
+        };
+        let vars = (0..reader.len()?)
+            .map(|_| {
+                let len = reader.len()?;
+                String::from_utf8(reader.take(len)?.to_vec()).map_err(|_| BytecodeError::Invalid)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let ops = (0..reader.len()?)
+            .map(|_| read_op(&mut reader))
+            .collect::<Result<Vec<_>, _>>()?;
+        if reader.index != bytes.len() {
+            return Err(BytecodeError::Invalid);
+        }
+        let max_stack = max_stack(&ops, vars.len()).ok_or(BytecodeError::Invalid)?;
+        Ok(Compiled {
+            ops,
+            vars,
+            max_stack,
+            compensated,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::EvalOptions;
+    use super::*;
+    use crate::test_util::parse;
+
+    #[test]
+    fn it_reloads_compiled_expressions() {
+        let options = EvalOptions {
+            compensated: true,
+            ..EvalOptions::default()
+        };
+        let node = parse("sin(x) * max(y, 2) + log(2, y) + x^3 - 2x");
+        for compiled in &[
+            node.compile(&["x", "y"]).unwrap(),
+            parse("x^3 - 2x + 1")
+                .compile_with_options(&["x"], &options)
+                .unwrap(),
+        ] {
+            let reloaded = Compiled::from_bytes(&compiled.to_bytes()).unwrap();
+            assert_eq!(&reloaded, compiled);
+            assert_eq!(reloaded.eval(&[0.5, 3.0]), compiled.eval(&[0.5, 3.0]));
+        }
+    }
+
+    #[test]
+    fn it_rejects_invalid_blobs() {
+        let bytes = parse("x + 1").compile(&["x"]).unwrap().to_bytes();
+        assert_eq!(Compiled::from_bytes(b"JUNK"), Err(BytecodeError::BadMagic));
+
+        let mut newer = bytes.clone();
+        newer[4] = BYTECODE_VERSION + 1;
+        assert_eq!(
+            Compiled::from_bytes(&newer),
+            Err(BytecodeError::UnsupportedVersion(BYTECODE_VERSION + 1))
+        );
+
+        assert_eq!(
+            Compiled::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(BytecodeError::UnexpectedEnd)
+        );
+
+        // the sum takes more numbers than there are on the stack
+        let mut unbalanced = bytes;
+        let len = unbalanced.len();
+        unbalanced[len - 4] = 3;
+        assert_eq!(
+            Compiled::from_bytes(&unbalanced),
+            Err(BytecodeError::Invalid)
+        );
+
+        // a variable that does not exist
+        let compiled = Compiled {
+            ops: vec![Op::Var(1)],
+            vars: vec!["x".to_owned()],
+            max_stack: 1,
+            compensated: false,
+        };
+        assert_eq!(
+            Compiled::from_bytes(&compiled.to_bytes()),
+            Err(BytecodeError::Invalid)
+        );
+    }
+}
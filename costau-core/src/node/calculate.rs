@@ -0,0 +1,132 @@
+use num_traits::One;
+use std::fmt;
+
+use super::simplify::simplify_passes;
+use super::telemetry::{run_pass, Pass, Telemetry};
+use super::units::resolve;
+use super::{
+    EvalError, EvalOptions, EvalSuccess, FormatOptions, Node, SimplifyError, SimplifyOptions,
+};
+
+/// The exact and the approximate results of a calculation, so that
+/// frontends can show `1/3 ≈ 0.3333333333333333`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Calculation {
+    /// The simplified node
+    pub exact: Node,
+    /// Whether the simplification changed the node
+    pub did_simplify: bool,
+    /// The approximation of the exact result. It is `None` if the exact
+    /// result is an integer, which cannot be written more simply.
+    pub approx: Option<Result<EvalSuccess, EvalError>>,
+}
+
+impl Calculation {
+    /// Formats the exact result and its approximation with the options, such
+    /// as `1/3 ≈ 0.3333` with 4 decimal places. The approximation is left out
+    /// if there is none.
+    pub fn format(&self, options: &FormatOptions) -> String {
+        let exact = self.exact.format(options);
+        match &self.approx {
+            Some(Ok(approx)) => format!(
+                "{} {} {}",
+                exact,
+                options.locale.approx,
+                approx.format(options)
+            ),
+            _ => exact,
+        }
+    }
+}
+
+impl fmt::Display for Calculation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.format(&FormatOptions::default()))
+    }
+}
+
+/// Simplifies the node and approximates the result.
+pub fn calculate(node: Node) -> Result<Calculation, SimplifyError> {
+    calculate_with_telemetry(node, &mut ())
+}
+
+/// Like `calculate`, but reports the rules that fired and the time of each
+/// pass.
+pub fn calculate_with_telemetry(
+    node: Node,
+    telemetry: &mut dyn Telemetry,
+) -> Result<Calculation, SimplifyError> {
+    calculate_with_options(node, &EvalOptions::default(), telemetry)
+}
+
+/// Like `calculate_with_telemetry`, but with the options of the calculation.
+pub fn calculate_with_options(
+    node: Node,
+    options: &EvalOptions,
+    telemetry: &mut dyn Telemetry,
+) -> Result<Calculation, SimplifyError> {
+    let simplified = simplify_passes(node, &SimplifyOptions::default(), options, telemetry)?;
+    // the value in the unit of the result
+    let is_integer = match resolve(&simplified.result, options).map(|x| x.0) {
+        Ok(Node::Num { val, .. }) => val.denom().is_one(),
+        _ => false,
+    };
+    let approx = if is_integer {
+        None
+    } else {
+        Some(run_pass(telemetry, Pass::Eval, |_| {
+            simplified.result.eval_with_options(options)
+        }))
+    };
+    Ok(Calculation {
+        exact: simplified.result,
+        did_simplify: simplified.did_something,
+        approx,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Precision;
+    use super::*;
+    use crate::test_util::parse;
+
+    fn calculate_str(expr: &str) -> Calculation {
+        parse(expr).calculate().unwrap()
+    }
+
+    #[test]
+    fn it_gives_the_exact_and_approximate_results() {
+        let calculation = calculate_str("1/6 + 1/6");
+        assert!(calculation.did_simplify);
+        assert_eq!(calculation.to_string(), "1/3 ≈ 0.3333333333333333");
+        let options = FormatOptions {
+            precision: Some(Precision::Decimals(4)),
+            ..FormatOptions::default()
+        };
+        assert_eq!(calculation.format(&options), "1/3 ≈ 0.3333");
+
+        // integers are not approximated
+        let calculation = calculate_str("2 * 3");
+        assert_eq!(calculation.approx, None);
+        assert_eq!(calculation.to_string(), "6");
+
+        let calculation = calculate_str("x + x");
+        assert_eq!(calculation.approx, Some(Err(EvalError::UnboundVar)));
+        assert_eq!(calculation.to_string(), "2x");
+
+        assert_eq!(calculate_str("3 kg + 2 kg").to_string(), "5 kg");
+        assert_eq!(
+            calculate_str("1 mi in km").to_string(),
+            "(25146/15625) km ≈ 1.609344 km"
+        );
+        assert_eq!(
+            calculate_str("60 km per h * 90 minutes").to_string(),
+            "90 km"
+        );
+        assert_eq!(
+            calculate_str("1 km / 8 minutes").to_string(),
+            "(1/8) km per minute ≈ 0.125 km per minute"
+        );
+    }
+}
@@ -0,0 +1,564 @@
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Zero};
+
+use super::compile::Compiled;
+use super::units::{attach, resolve};
+use super::util::{common, is_minus_one};
+use super::{EvalOptions, Node};
+
+/// The maximum number of times the rule of L'Hôpital is applied.
+const MAX_LHOPITAL_STEPS: usize = 5;
+
+/// The side from which a limit is approached.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum LimitSide {
+    /// Both sides must have the same limit
+    Both,
+    /// From values smaller than the point
+    Left,
+    /// From values bigger than the point
+    Right,
+}
+
+/// Returns the derivative of the node with respect to the variable `var`.
+/// The result is not simplified.
+pub fn derivative(node: &Node, var: &str) -> Node {
+    if !node.contains_var(var) {
+        return common::zero();
+    }
+    let d = |node: &Node| derivative(node, var);
+    match node {
+        Node::Var(_) => common::one(),
+        Node::Const(_) | Node::Num { .. } | Node::Dice { .. } | Node::Time { .. } => common::zero(),
+        Node::Sum(children) => Node::Sum(children.iter().map(d).collect()),
+        Node::Product(children) => {
+            // (fgh)' = f'gh + fg'h + fgh'
+            let terms = (0..children.len())
+                .filter(|&i| children[i].contains_var(var))
+                .map(|i| {
+                    let mut factors = children.clone();
+                    factors[i] = d(&children[i]);
+                    Node::Product(factors)
+                })
+                .collect();
+            Node::Sum(terms)
+        }
+        Node::Vector(items) => Node::Vector(items.iter().map(d).collect()),
+        Node::Ratio(terms) => Node::Ratio(terms.iter().map(d).collect()),
+        // the rate of change is in the unit of the quantity
+        Node::Quantity(inner, unit) => match resolve(node, &EvalOptions::default()) {
+            Ok((plain, Some(unit))) => attach(d(&plain), unit),
+            _ => Node::Quantity(Box::new(d(inner)), *unit),
+        },
+        Node::Exp(a, b) => {
+            let (a, b) = (&**a, &**b);
+            if !b.contains_var(var) {
+                // (u^n)' = n u^(n-1) u'
+                let expon = b.clone() - common::one();
+                b.clone() * Node::Exp(Box::new(a.clone()), Box::new(expon)) * d(a)
+            } else {
+                // (u^v)' = u^v (v' ln(u) + v u' / u)
+                node.clone() * (d(b) * a.clone().ln() + b.clone() * d(a) / a.clone())
+            }
+        }
+        Node::Sin(u) => u.clone().cos() * d(u),
+        Node::Cos(u) => -(u.clone().sin()) * d(u),
+        Node::Tan(u) => u.clone().cos().sqr().inverse() * d(u),
+        Node::Asin(u) => d(u) / (common::one() - u.clone().sqr()).sqrt(),
+        Node::Acos(u) => -(d(u) / (common::one() - u.clone().sqr()).sqrt()),
+        Node::Atan(u) => d(u) / (common::one() + u.clone().sqr()),
+        Node::Atan2(y, x) => {
+            // the derivative of the angle of `(x, y)` is
+            // (x y' - y x') / (x^2 + y^2)
+            let (y, x) = (&**y, &**x);
+            (x.clone() * d(y) - y.clone() * d(x)) / (x.clone().sqr() + y.clone().sqr())
+        }
+        Node::Sinh(u) => u.clone().cosh() * d(u),
+        Node::Cosh(u) => u.clone().sinh() * d(u),
+        Node::Tanh(u) => u.clone().cosh().sqr().inverse() * d(u),
+        Node::Abs(u) => u.clone().sign() * d(u),
+        // these functions are constant where their derivative is defined
+        Node::Floor(_) | Node::Ceil(_) | Node::Round(_) | Node::Sign(_) => common::zero(),
+        Node::Min(children) | Node::Max(children) => {
+            // max(a, b) = (a + b + |a - b|) / 2 and min(a, b) = (a + b - |a - b|) / 2
+            let is_max = matches!(node, Node::Max(_));
+            let mut iter = children.iter().cloned();
+            let first = iter.next().unwrap_or_else(common::zero);
+            let folded = iter.fold(first, |a, b| {
+                let gap = (a.clone() - b.clone()).abs();
+                let gap = if is_max { gap } else { -gap };
+                (a + b + gap) / common::two()
+            });
+            d(&folded)
+        }
+        Node::Ln(u) => d(u) / *u.clone(),
+        Node::Log(base, u) => d(&(u.clone().ln() / base.clone().ln())),
+    }
+}
+
+/// Returns the coefficient `a` if the node is of the form `ax + b` where `x` is
+/// the variable `var`.
+fn get_linear_coef(node: &Node, var: &str) -> Option<Node> {
+    let coef = derivative(node, var).simplify().ok()?.result;
+    if coef.contains_var(var) || coef == common::zero() {
+        None
+    } else {
+        Some(coef)
+    }
+}
+
+/// Returns an antiderivative of the node with respect to the variable `var`,
+/// or `None` if the node is not a simple integrand. The result is not
+/// simplified.
+pub fn integrate(node: &Node, var: &str) -> Option<Node> {
+    let x = || Node::Var(var.to_owned());
+    if !node.contains_var(var) {
+        return Some(node.clone() * x());
+    }
+    Some(match node {
+        Node::Var(_) => x().sqr() / common::two(),
+        Node::Sum(children) => Node::Sum(
+            children
+                .iter()
+                .map(|child| integrate(child, var))
+                .collect::<Option<_>>()?,
+        ),
+        Node::Product(children) => {
+            // only one factor can depend on the variable
+            let (dependent, constant): (Vec<&Node>, Vec<&Node>) =
+                children.iter().partition(|child| child.contains_var(var));
+            if dependent.len() != 1 {
+                return None;
+            }
+            let mut factors: Vec<Node> = constant.into_iter().cloned().collect();
+            factors.push(integrate(dependent[0], var)?);
+            Node::Product(factors)
+        }
+        Node::Exp(a, b) if !b.contains_var(var) => {
+            // (au + b)^n
+            let coef = get_linear_coef(a, var)?;
+            if **b == common::minus_one() {
+                a.clone().abs().ln() / coef
+            } else {
+                let expon = *b.clone() + common::one();
+                Node::Exp(a.clone(), Box::new(expon.clone())) / (expon * coef)
+            }
+        }
+        Node::Exp(a, b) if !a.contains_var(var) => {
+            // c^(au + b)
+            let coef = get_linear_coef(b, var)?;
+            node.clone() / (coef * a.clone().ln())
+        }
+        Node::Sin(u) => -(u.clone().cos()) / get_linear_coef(u, var)?,
+        Node::Cos(u) => u.clone().sin() / get_linear_coef(u, var)?,
+        Node::Tan(u) => -(u.clone().cos().abs().ln()) / get_linear_coef(u, var)?,
+        Node::Sinh(u) => u.clone().cosh() / get_linear_coef(u, var)?,
+        Node::Cosh(u) => u.clone().sinh() / get_linear_coef(u, var)?,
+        Node::Ln(u) => {
+            // the antiderivative of ln(x) is x ln(x) - x
+            let coef = get_linear_coef(u, var)?;
+            (*u.clone() * u.clone().ln() - *u.clone()) / coef
+        }
+        _ => return None,
+    })
+}
+
+/// Returns whether the value of the node can jump, so that its limit is not
+/// always its value.
+fn is_discontinuous(node: &Node) -> bool {
+    match node {
+        Node::Floor(_) | Node::Ceil(_) | Node::Round(_) | Node::Sign(_) => true,
+        node => node.children().into_iter().any(is_discontinuous),
+    }
+}
+
+/// Returns the simplified value of the node when the variable is `point`, if
+/// it is defined.
+fn value_at(node: &Node, var: &str, point: &Node) -> Option<Node> {
+    let val = node.substitute(var, point).simplify().ok()?.result;
+    match val.eval() {
+        Ok(x) if x.val.is_finite() && x.imag.is_finite() => Some(val),
+        // the value can be symbolic
+        Err(super::EvalError::UnboundVar) => Some(val),
+        _ => None,
+    }
+}
+
+/// Splits the node into a numerator and a denominator, if it is a fraction.
+fn split_fraction(node: &Node) -> Option<(Node, Node)> {
+    let factors = match node {
+        Node::Product(factors) => factors.clone(),
+        node => vec![node.clone()],
+    };
+    let (denom, numer): (Vec<Node>, Vec<Node>) = factors.into_iter().partition(|x| match x {
+        Node::Exp(_, b) => is_minus_one(b),
+        _ => false,
+    });
+    if denom.is_empty() {
+        return None;
+    }
+    let denom = denom
+        .into_iter()
+        .map(|x| match x {
+            Node::Exp(a, _) => *a,
+            _ => unreachable!(),
+        })
+        .collect();
+    Some((Node::Product(numer), Node::Product(denom)))
+}
+
+/// Estimates the limit by evaluating the node closer and closer to the point.
+/// The result is rounded to the digits that do not change anymore.
+fn numeric_limit(node: &Node, var: &str, point: &Node, side: LimitSide) -> Option<f64> {
+    let approach = |sign: i64| -> Option<f64> {
+        let mut previous: Option<f64> = None;
+        for &exponent in &[4, 6, 8] {
+            let h = BigRational::new(sign.into(), num_traits::pow(BigInt::from(10), exponent));
+            let near = Node::Sum(vec![
+                point.clone(),
+                Node::Num {
+                    val: h,
+                    input_base: None,
+                },
+            ]);
+            let val = node.substitute(var, &near).eval().ok()?;
+            if !val.val.is_finite() || val.imag != 0.0 {
+                return None;
+            }
+            if let Some(previous) = previous {
+                if (val.val - previous).abs() <= 1e-6 * val.val.abs().max(1.0) {
+                    return Some(val.val);
+                }
+            }
+            previous = Some(val.val);
+        }
+        None
+    };
+    match side {
+        LimitSide::Left => approach(-1),
+        LimitSide::Right => approach(1),
+        LimitSide::Both => {
+            let (left, right) = (approach(-1)?, approach(1)?);
+            if (left - right).abs() <= 1e-6 * left.abs().max(1.0) {
+                Some((left + right) / 2.0)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Rounds a number to 6 significant digits, and returns it as an exact
+/// number.
+fn round_to_node(val: f64) -> Node {
+    let val = if val == 0.0 {
+        BigRational::zero()
+    } else {
+        let scale = 5 - val.abs().log10().floor() as i32;
+        let ten = BigRational::from_integer(10.into());
+        let scale = if scale >= 0 {
+            num_traits::pow(ten, scale as usize)
+        } else {
+            BigRational::one() / num_traits::pow(ten, -scale as usize)
+        };
+        let scaled = BigRational::from_float(val).unwrap() * &scale;
+        scaled.round() / scale
+    };
+    Node::Num {
+        val,
+        input_base: None,
+    }
+}
+
+/// Returns the limit of the node when the variable `var` approaches `point`,
+/// or `None` if it does not exist or cannot be found.
+pub fn limit(node: &Node, var: &str, point: &Node, side: LimitSide) -> Option<Node> {
+    if !is_discontinuous(node) {
+        if let Some(val) = value_at(node, var, point) {
+            return Some(val);
+        }
+        // 0/0: the rule of L'Hôpital says that the limit is the one of the
+        // quotient of the derivatives
+        let mut fraction = split_fraction(node);
+        for _ in 0..MAX_LHOPITAL_STEPS {
+            let (numer, denom) = match fraction {
+                Some(x) => x,
+                None => break,
+            };
+            let is_zero = |x: &Node| value_at(x, var, point) == Some(common::zero());
+            if !is_zero(&numer) || !is_zero(&denom) {
+                break;
+            }
+            let numer = numer.derivative(var).simplify().ok()?.result;
+            let denom = denom.derivative(var).simplify().ok()?.result;
+            if let Some(val) = value_at(&(numer.clone() / denom.clone()), var, point) {
+                return Some(val);
+            }
+            fraction = Some((numer, denom));
+        }
+    }
+    numeric_limit(node, var, point, side).map(round_to_node)
+}
+
+/// Returns the Taylor polynomial of the node around `point` with the terms
+/// up to `(var - point)^order`, or `None` if a derivative is not defined at
+/// the point.
+pub fn series(node: &Node, var: &str, point: &Node, order: u32) -> Option<Node> {
+    let x = Node::Var(var.to_owned());
+    let offset = if *point == common::zero() {
+        x
+    } else {
+        x - point.clone()
+    };
+    let mut terms = Vec::new();
+    let mut derivative = node.clone().simplify().ok()?.result;
+    let mut factorial = BigInt::one();
+    for k in 0..=order {
+        if k > 0 {
+            derivative = derivative.derivative(var).simplify().ok()?.result;
+            factorial *= k;
+        }
+        let coef = value_at(&derivative, var, point)?;
+        let power = Node::Exp(
+            Box::new(offset.clone()),
+            Box::new(Node::Num {
+                val: BigRational::from_integer(k.into()),
+                input_base: None,
+            }),
+        );
+        let inv_factorial = Node::Num {
+            val: BigRational::new(BigInt::one(), factorial.clone()),
+            input_base: None,
+        };
+        terms.push(Node::Product(vec![coef, inv_factorial, power]));
+    }
+    Some(Node::Sum(terms).simplify().ok()?.result)
+}
+
+/// Approximates the derivative of a function of one variable with a central
+/// difference.
+fn numeric_derivative(f: &Compiled, x: f64) -> Option<f64> {
+    let h = 1e-6 * x.abs().max(1.0);
+    Some((f.eval(&[x + h]).ok()? - f.eval(&[x - h]).ok()?) / (2.0 * h))
+}
+
+/// Finds a value of the variable `var` where the node is zero with Newton's
+/// method, starting from `guess`. It stops when a step is smaller than
+/// `tolerance`, or gives up after `max_iter` steps.
+pub fn find_root(
+    node: &Node,
+    var: &str,
+    guess: f64,
+    tolerance: f64,
+    max_iter: usize,
+) -> Option<f64> {
+    // the value is computed more precisely near the roots of polynomials
+    let options = EvalOptions {
+        compensated: true,
+        ..EvalOptions::default()
+    };
+    let f = node.compile_with_options(&[var], &options).ok()?;
+    // the numeric derivative is used where the symbolic one is not defined
+    let df = node.derivative(var).compile(&[var]).ok();
+    let mut x = guess;
+    for _ in 0..max_iter {
+        let y = f.eval(&[x]).ok()?;
+        if !y.is_finite() {
+            return None;
+        }
+        if y == 0.0 {
+            return Some(x);
+        }
+        let slope = match df.as_ref().and_then(|df| df.eval(&[x]).ok()) {
+            Some(slope) if slope.is_finite() && slope != 0.0 => slope,
+            _ => numeric_derivative(&f, x)?,
+        };
+        if slope == 0.0 || !slope.is_finite() {
+            return None;
+        }
+        let step = y / slope;
+        x -= step;
+        if step.abs() <= tolerance {
+            return Some(x);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::node::LimitSide;
+    use crate::test_util::parse;
+
+    fn derivative_str(expr: &str) -> String {
+        let node = parse(expr).derivative("x");
+        node.simplify().unwrap().result.to_string()
+    }
+
+    /// Compares the derivative at `x = 1/3` with the one of the expected
+    /// expression.
+    fn assert_derivative_value(expr: &str, expected: &str) {
+        let third = parse("1/3");
+        let val = parse(expr).derivative("x").substitute("x", &third);
+        let expected = parse(expected).substitute("x", &third);
+        let (val, expected) = (val.eval().unwrap().val, expected.eval().unwrap().val);
+        assert!((val - expected).abs() < 1e-12, "{} != {}", val, expected);
+    }
+
+    #[test]
+    fn it_differentiates_polynomials() {
+        assert_eq!(derivative_str("3x^2 + 2x + 1"), "2 + 6x");
+        assert_eq!(derivative_str("y * x"), "y");
+        assert_eq!(derivative_str("y^2"), "0");
+    }
+
+    #[test]
+    fn it_differentiates_functions() {
+        assert_derivative_value("x^2 * sin(x)", "2 * x * sin(x) + x^2 * cos(x)");
+        assert_derivative_value("cos(x^2)", "-2 * x * sin(x^2)");
+        assert_derivative_value("tan(x)", "1 + tan(x)^2");
+        assert_derivative_value("ln(x) + log(2, x)", "1/x + 1/(x * ln(2))");
+        assert_derivative_value("x^x", "x^x * (ln(x) + 1)");
+        assert_derivative_value("asin(x) + acos(x)", "0");
+        assert_derivative_value("atan(x)", "1 / (1 + x^2)");
+        assert_derivative_value("atan2(x, 1)", "1 / (1 + x^2)");
+        assert_derivative_value("sinh(2 * x) + abs(x - 1)", "2 * cosh(2 * x) - 1");
+        assert_derivative_value("max(x, 1 - x, 0)", "-1");
+    }
+
+    /// Checks that the derivative of the antiderivative is the integrand at
+    /// `x = 1/3`.
+    fn assert_integral(expr: &str) {
+        let third = parse("1/3");
+        let node = parse(expr);
+        let integral = node.integrate("x").unwrap();
+        let val = integral.derivative("x").substitute("x", &third);
+        let expected = node.substitute("x", &third);
+        let (val, expected) = (val.eval().unwrap().val, expected.eval().unwrap().val);
+        assert!(
+            (val - expected).abs() < 1e-12,
+            "{}: {} != {}",
+            expr,
+            val,
+            expected
+        );
+    }
+
+    #[test]
+    fn it_integrates_polynomials() {
+        let integral = parse("3x^2 + 4").integrate("x").unwrap();
+        assert_eq!(integral.simplify().unwrap().result.to_string(), "4x + x^3");
+        assert_integral("x^5 - 2x + 7");
+        assert_integral("1/x + 1/(2x + 1)");
+        assert_integral("sqrt(3x)");
+        assert_integral("x * x^2 / 4");
+    }
+
+    #[test]
+    fn it_integrates_functions() {
+        assert_integral("e^x + 2^(3x)");
+        assert_integral("sin(2x) + cos(x) * 3");
+        assert_integral("tan(x) + sinh(x) + cosh(x / 2)");
+        assert_integral("ln(2x + 1)");
+    }
+
+    #[test]
+    fn it_gives_up_on_other_integrands() {
+        assert_eq!(parse("x * sin(x)").integrate("x"), None);
+        assert_eq!(parse("sin(x^2)").integrate("x"), None);
+        assert_eq!(parse("x^x").integrate("x"), None);
+    }
+
+    fn limit_str(expr: &str, point: &str, side: LimitSide) -> Option<String> {
+        let limit = parse(expr).limit("x", &parse(point), side)?;
+        Some(limit.to_string())
+    }
+
+    #[test]
+    fn it_finds_limits_of_indeterminate_forms() {
+        let both = LimitSide::Both;
+        assert_eq!(limit_str("x^2 + 1", "2", both), Some("5".to_owned()));
+        assert_eq!(limit_str("sin(x) / x", "0", both), Some("1".to_owned()));
+        assert_eq!(
+            limit_str("(x^2 - 1) / (x - 1)", "1", both),
+            Some("2".to_owned())
+        );
+        assert_eq!(
+            limit_str("(1 - cos(x)) / x^2", "0", both),
+            Some("1/2".to_owned())
+        );
+
+        let limit = parse("(1 + x)^(1/x)")
+            .limit("x", &parse("0"), both)
+            .unwrap();
+        assert!((limit.eval().unwrap().val - std::f64::consts::E).abs() < 1e-5);
+    }
+
+    fn series_str(expr: &str, point: &str, order: u32) -> Option<String> {
+        let series = parse(expr).series("x", &parse(point), order)?;
+        Some(series.to_string())
+    }
+
+    #[test]
+    fn it_expands_series() {
+        assert_eq!(
+            series_str("e^x", "0", 3),
+            Some("1 + x + 1/2 * x^2 + 1/6 * x^3".to_owned())
+        );
+        assert_eq!(
+            series_str("sin(x)", "0", 5),
+            Some("x + -1/6 * x^3 + 1/120 * x^5".to_owned())
+        );
+        assert_eq!(
+            series_str("x^2 + 2x + 3", "0", 5),
+            Some("3 + 2x + x^2".to_owned())
+        );
+        assert_eq!(series_str("1/x", "0", 2), None);
+    }
+
+    #[test]
+    fn it_expands_series_around_other_points() {
+        let series = parse("ln(x)").series("x", &parse("1"), 4).unwrap();
+        let near = parse("1.1");
+        let val = series.substitute("x", &near).eval().unwrap().val;
+        assert!((val - 1.1f64.ln()).abs() < 1e-5);
+    }
+
+    fn assert_root(expr: &str, guess: f64, expected: f64) {
+        let root = parse(expr).find_root("x", guess, 1e-12, 50).unwrap();
+        assert!((root - expected).abs() < 1e-9, "{} != {}", root, expected);
+    }
+
+    #[test]
+    fn it_finds_roots_numerically() {
+        assert_root("cos(x) - x", 1.0, 0.739_085_133_215_160_6);
+        assert_root("x^3 - 2x - 5", 2.0, 2.094_551_481_542_326);
+        assert_root("x * e^x - 1", 0.0, 0.567_143_290_409_783_8);
+        assert_root("x^2 - 2", -1.0, -std::f64::consts::SQRT_2);
+    }
+
+    #[test]
+    fn it_gives_up_without_roots() {
+        assert_eq!(parse("x^2 + 1").find_root("x", 0.5, 1e-12, 50), None);
+        assert_eq!(parse("ln(x)").find_root("x", -1.0, 1e-12, 50), None);
+    }
+
+    #[test]
+    fn it_finds_one_sided_limits() {
+        assert_eq!(
+            limit_str("sign(x)", "0", LimitSide::Right),
+            Some("1".to_owned())
+        );
+        assert_eq!(
+            limit_str("sign(x)", "0", LimitSide::Left),
+            Some("-1".to_owned())
+        );
+        assert_eq!(limit_str("sign(x)", "0", LimitSide::Both), None);
+        assert_eq!(
+            limit_str("floor(x)", "2", LimitSide::Left),
+            Some("1".to_owned())
+        );
+        assert_eq!(limit_str("1/x", "0", LimitSide::Both), None);
+    }
+}
@@ -0,0 +1,458 @@
+use std::f64::consts::{E, PI};
+
+use num_integer::Integer;
+
+use super::eval::{EvalError, EvalOptions};
+use super::factor::get_coefs;
+use super::units::resolve;
+use super::{ConstKind, Node};
+use crate::ratio2flt::ratio_to_f64;
+
+/// A function of one number.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(super) enum Func {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Abs,
+    Floor,
+    Ceil,
+    Round,
+    Sign,
+    Ln,
+}
+
+impl Func {
+    pub(super) const ALL: [Func; 15] = [
+        Func::Sin,
+        Func::Cos,
+        Func::Tan,
+        Func::Asin,
+        Func::Acos,
+        Func::Atan,
+        Func::Sinh,
+        Func::Cosh,
+        Func::Tanh,
+        Func::Abs,
+        Func::Floor,
+        Func::Ceil,
+        Func::Round,
+        Func::Sign,
+        Func::Ln,
+    ];
+
+    fn apply(self, x: f64) -> f64 {
+        match self {
+            Func::Sin => x.sin(),
+            Func::Cos => x.cos(),
+            Func::Tan => x.tan(),
+            Func::Asin => x.asin(),
+            Func::Acos => x.acos(),
+            Func::Atan => x.atan(),
+            Func::Sinh => x.sinh(),
+            Func::Cosh => x.cosh(),
+            Func::Tanh => x.tanh(),
+            Func::Abs => x.abs(),
+            Func::Floor => x.floor(),
+            Func::Ceil => x.ceil(),
+            Func::Round => x.round(),
+            Func::Sign => sign(x),
+            Func::Ln => x.ln(),
+        }
+    }
+}
+
+/// An instruction of a compiled expression, which works on a stack of
+/// numbers.
+#[derive(Debug, PartialEq, Clone)]
+pub(super) enum Op {
+    Push(f64),
+    /// Pushes the value of the variable with this index
+    Var(usize),
+    /// Replaces the last `n` numbers with their sum
+    Add(usize),
+    /// Replaces the last `n` numbers with their product
+    Mul(usize),
+    Min(usize),
+    Max(usize),
+    Pow,
+    Atan2,
+    /// Replaces the base and the number with the logarithm
+    Log,
+    Func(Func),
+    /// Pushes the value of the polynomial in the variable with this index,
+    /// with the coefficients from the leading one to the constant term
+    Horner(usize, Vec<f64>),
+}
+
+/// An expression that was compiled to be evaluated quickly many times with
+/// different values for its variables.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Compiled {
+    pub(super) ops: Vec<Op>,
+    pub(super) vars: Vec<String>,
+    pub(super) max_stack: usize,
+    pub(super) compensated: bool,
+}
+
+/// Returns `a + b` and its rounding error.
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let z = s - a;
+    (s, (a - (s - z)) + (b - z))
+}
+
+/// Returns `a * b` and its rounding error.
+fn two_product(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    (p, a.mul_add(b, -p))
+}
+
+/// Evaluates the polynomial with the compensated Horner scheme of Graillat,
+/// Langlois and Louvet: the rounding errors of each step are accumulated
+/// separately and added back at the end.
+fn compensated_horner(coefs: &[f64], x: f64) -> f64 {
+    let mut s = coefs[0];
+    let mut c = 0.0;
+    for &coef in &coefs[1..] {
+        let (p, product_error) = two_product(s, x);
+        let (sum, sum_error) = two_sum(p, coef);
+        s = sum;
+        c = c * x + (product_error + sum_error);
+    }
+    s + c
+}
+
+fn sign(x: f64) -> f64 {
+    if x == 0.0 {
+        0.0
+    } else {
+        x.signum()
+    }
+}
+
+impl Compiled {
+    /// Compiles the node where the variables `vars` will be given when it is
+    /// evaluated.
+    pub fn new(node: &Node, vars: &[&str]) -> Result<Compiled, EvalError> {
+        Compiled::with_options(node, vars, &EvalOptions::default())
+    }
+
+    /// Like `new`, but with the options of the evaluation.
+    pub fn with_options(
+        node: &Node,
+        vars: &[&str],
+        options: &EvalOptions,
+    ) -> Result<Compiled, EvalError> {
+        let mut compiled = Compiled {
+            ops: Vec::new(),
+            vars: vars.iter().map(|&x| x.to_owned()).collect(),
+            max_stack: 0,
+            compensated: options.compensated,
+        };
+        // the value in the unit of the result
+        compiled.compile(&resolve(node, options)?.0, 0)?;
+        Ok(compiled)
+    }
+
+    /// Returns the names of the variables in the order of their values.
+    pub fn vars(&self) -> &[String] {
+        &self.vars
+    }
+
+    fn push(&mut self, op: Op, depth: usize) {
+        self.max_stack = self.max_stack.max(depth + 1);
+        self.ops.push(op);
+    }
+
+    fn compile_list(&mut self, children: &[Node], depth: usize) -> Result<usize, EvalError> {
+        for (i, child) in children.iter().enumerate() {
+            self.compile(child, depth + i)?;
+        }
+        Ok(children.len())
+    }
+
+    /// Returns the instruction that computes the node if it is a polynomial
+    /// of degree 2 or more in one of the variables.
+    fn compile_polynomial(&self, node: &Node) -> Option<Op> {
+        if !matches!(node, Node::Sum(_) | Node::Product(_) | Node::Exp(..)) {
+            return None;
+        }
+        self.vars.iter().enumerate().find_map(|(i, var)| {
+            let coefs = get_coefs(node, var).filter(|x| x.len() > 2)?;
+            Some(Op::Horner(
+                i,
+                coefs.iter().rev().map(ratio_to_f64).collect(),
+            ))
+        })
+    }
+
+    /// Adds the instructions to compute `|base|^expon`.
+    fn compile_abs_pow(&mut self, base: &Node, expon: f64, depth: usize) -> Result<(), EvalError> {
+        self.compile(base, depth)?;
+        self.push(Op::Func(Func::Abs), depth);
+        self.push(Op::Push(expon), depth + 1);
+        self.push(Op::Pow, depth);
+        Ok(())
+    }
+
+    /// Adds the instructions to compute the node, when `depth` numbers are
+    /// already on the stack.
+    fn compile(&mut self, node: &Node, depth: usize) -> Result<(), EvalError> {
+        if self.compensated {
+            if let Some(op) = self.compile_polynomial(node) {
+                self.push(op, depth);
+                return Ok(());
+            }
+        }
+        let op = match node {
+            Node::Const(kind) => Op::Push(match kind {
+                ConstKind::Pi => PI,
+                ConstKind::Tau => PI * 2.0,
+                ConstKind::E => E,
+                ConstKind::I => return Err(EvalError::NotReal),
+            }),
+            Node::Var(name) => match self.vars.iter().position(|x| x == name) {
+                Some(i) => Op::Var(i),
+                None => return Err(EvalError::UnboundVar),
+            },
+            Node::Num { val, .. } => Op::Push(ratio_to_f64(val)),
+            Node::Vector(_) | Node::Ratio(_) | Node::Dice { .. } => {
+                return Err(EvalError::NotAScalar)
+            }
+            // the times are resolved with the units
+            Node::Time { .. } => return Err(EvalError::NoClock),
+            Node::Quantity(inner, _) => return self.compile(inner, depth),
+            Node::Sum(children) => Op::Add(self.compile_list(children, depth)?),
+            Node::Product(children) => Op::Mul(self.compile_list(children, depth)?),
+            Node::Min(children) => Op::Min(self.compile_list(children, depth)?),
+            Node::Max(children) => Op::Max(self.compile_list(children, depth)?),
+            // the real root of a negative number, such as `(-8)^(1/3)`, like
+            // in `eval`, which is `sign(a)^p * |a|^(p/q)`
+            Node::Exp(a, b) => match b.eval_exact() {
+                Ok(expon) if !expon.is_integer() && expon.denom().is_odd() => {
+                    let expon_f64 = ratio_to_f64(&expon);
+                    if expon.numer().is_even() {
+                        return self.compile_abs_pow(a, expon_f64, depth);
+                    }
+                    self.compile(a, depth)?;
+                    self.push(Op::Func(Func::Sign), depth);
+                    self.compile_abs_pow(a, expon_f64, depth + 1)?;
+                    Op::Mul(2)
+                }
+                _ => {
+                    self.compile(a, depth)?;
+                    self.compile(b, depth + 1)?;
+                    Op::Pow
+                }
+            },
+            Node::Atan2(a, b) => {
+                self.compile(a, depth)?;
+                self.compile(b, depth + 1)?;
+                Op::Atan2
+            }
+            Node::Log(a, b) => {
+                self.compile(a, depth)?;
+                self.compile(b, depth + 1)?;
+                Op::Log
+            }
+            Node::Sin(inner)
+            | Node::Cos(inner)
+            | Node::Tan(inner)
+            | Node::Asin(inner)
+            | Node::Acos(inner)
+            | Node::Atan(inner)
+            | Node::Sinh(inner)
+            | Node::Cosh(inner)
+            | Node::Tanh(inner)
+            | Node::Abs(inner)
+            | Node::Floor(inner)
+            | Node::Ceil(inner)
+            | Node::Round(inner)
+            | Node::Sign(inner)
+            | Node::Ln(inner) => {
+                self.compile(inner, depth)?;
+                Op::Func(match node {
+                    Node::Sin(_) => Func::Sin,
+                    Node::Cos(_) => Func::Cos,
+                    Node::Tan(_) => Func::Tan,
+                    Node::Asin(_) => Func::Asin,
+                    Node::Acos(_) => Func::Acos,
+                    Node::Atan(_) => Func::Atan,
+                    Node::Sinh(_) => Func::Sinh,
+                    Node::Cosh(_) => Func::Cosh,
+                    Node::Tanh(_) => Func::Tanh,
+                    Node::Abs(_) => Func::Abs,
+                    Node::Floor(_) => Func::Floor,
+                    Node::Ceil(_) => Func::Ceil,
+                    Node::Round(_) => Func::Round,
+                    Node::Sign(_) => Func::Sign,
+                    _ => Func::Ln,
+                })
+            }
+        };
+        self.push(op, depth);
+        Ok(())
+    }
+
+    /// Evaluates the expression with the values of the variables, in the
+    /// order they were given to `new`. The result is NaN if it is not a real
+    /// number, and an error if a variable has no value.
+    pub fn eval(&self, values: &[f64]) -> Result<f64, EvalError> {
+        if values.len() < self.vars.len() {
+            return Err(EvalError::UnboundVar);
+        }
+        let mut stack: Vec<f64> = Vec::with_capacity(self.max_stack);
+        for op in &self.ops {
+            let result = match op {
+                Op::Push(x) => *x,
+                Op::Var(i) => values[*i],
+                Op::Add(n) => {
+                    let start = stack.len() - n;
+                    stack.drain(start..).sum()
+                }
+                Op::Mul(n) => {
+                    let start = stack.len() - n;
+                    stack.drain(start..).product()
+                }
+                Op::Min(n) => {
+                    let start = stack.len() - n;
+                    stack.drain(start..).fold(f64::INFINITY, f64::min)
+                }
+                Op::Max(n) => {
+                    let start = stack.len() - n;
+                    stack.drain(start..).fold(f64::NEG_INFINITY, f64::max)
+                }
+                Op::Pow | Op::Atan2 | Op::Log => {
+                    let b = stack.pop().unwrap();
+                    let a = stack.pop().unwrap();
+                    match op {
+                        Op::Pow => a.powf(b),
+                        Op::Atan2 => a.atan2(b),
+                        _ => b.ln() / a.ln(),
+                    }
+                }
+                Op::Func(f) => {
+                    let x = stack.pop().unwrap();
+                    f.apply(x)
+                }
+                Op::Horner(i, coefs) => compensated_horner(coefs, values[*i]),
+            };
+            stack.push(result);
+        }
+        Ok(stack.pop().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    #[test]
+    fn it_evaluates_like_the_tree() {
+        let exprs = [
+            "x^2 + 3x*y - 1",
+            "sin(x) * cos(y) / tan(x + 1)",
+            "max(x, y, 1/2) + min(abs(x - y), 3) + log(2, y)",
+            "atan2(y, x) + floor(x * 10) + sign(-y)",
+        ];
+        for expr in exprs.iter() {
+            let node = parse(expr);
+            let compiled = node.compile(&["x", "y"]).unwrap();
+            for &(x, y) in &[(0.5, 2.0), (-1.25, 3.5), (2.0, 0.125)] {
+                let expected = node
+                    .substitute("x", &parse(&x.to_string()))
+                    .substitute("y", &parse(&y.to_string()))
+                    .eval()
+                    .unwrap()
+                    .val;
+                let val = compiled.eval(&[x, y]).unwrap();
+                assert!(
+                    (val - expected).abs() < 1e-12,
+                    "{}: {} != {}",
+                    expr,
+                    val,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn it_evaluates_polynomials_precisely_near_their_roots() {
+        // (x - 1)^7 is tiny near 1, but its expanded form cancels badly
+        let node = parse("(x - 1)^7").expand().unwrap();
+        let options = EvalOptions {
+            compensated: true,
+            ..EvalOptions::default()
+        };
+        let naive = node.compile(&["x"]).unwrap();
+        let compensated = node.compile_with_options(&["x"], &options).unwrap();
+        let x = 1.01;
+        let expected = (x - 1.0f64).powi(7);
+        let error = |val: f64| ((val - expected) / expected).abs();
+        assert!(error(naive.eval(&[x]).unwrap()) > 1e-3);
+        assert!(error(compensated.eval(&[x]).unwrap()) < 1e-9);
+
+        // the other nodes are compiled as usual
+        let node = parse("sin(x) + x^2 * y");
+        let compiled = node.compile_with_options(&["x", "y"], &options).unwrap();
+        assert!((compiled.eval(&[0.5, 2.0]).unwrap() - (0.5f64.sin() + 0.5)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn it_evaluates_in_the_unit_of_the_result() {
+        let compiled = parse("x km + 300 m").compile(&["x"]).unwrap();
+        assert!((compiled.eval(&[5.0]).unwrap() - 5.3).abs() < 1e-12);
+        let compiled = parse("x km per h * 30 minutes").compile(&["x"]).unwrap();
+        assert!((compiled.eval(&[60.0]).unwrap() - 30.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn it_refuses_unknown_variables() {
+        assert_eq!(
+            parse("x + z").compile(&["x"]).unwrap_err(),
+            EvalError::UnboundVar
+        );
+        assert!(parse("sqrt(x)")
+            .compile(&["x"])
+            .unwrap()
+            .eval(&[-1.0])
+            .unwrap()
+            .is_nan());
+        // too few values
+        let compiled = parse("x + y").compile(&["x", "y"]).unwrap();
+        assert_eq!(compiled.eval(&[1.0]), Err(EvalError::UnboundVar));
+    }
+
+    #[test]
+    fn it_takes_the_real_roots_of_negative_numbers() {
+        for &(expr, x) in &[
+            ("x^(1/3)", -8.0),
+            ("x^(2/3)", -8.0),
+            ("(x - 9)^(-5/3)", 1.0),
+        ] {
+            let node = parse(expr);
+            let expected = node
+                .substitute("x", &parse(&x.to_string()))
+                .eval()
+                .unwrap()
+                .val;
+            let val = node.compile(&["x"]).unwrap().eval(&[x]).unwrap();
+            assert!(
+                (val - expected).abs() < 1e-12,
+                "{}: {} != {}",
+                expr,
+                val,
+                expected
+            );
+        }
+    }
+}
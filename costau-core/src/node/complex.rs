@@ -0,0 +1,222 @@
+use std::f64::consts::{E, PI};
+use std::ops::*;
+
+use num_rational::BigRational;
+use num_traits::One;
+
+use super::eval::{eval_real, EvalError};
+use super::{ConstKind, Node};
+use crate::ratio2flt::ratio_to_f64;
+
+/// A complex number, used to approximate nodes that are not real.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub(crate) struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    pub fn real(re: f64) -> Complex {
+        Complex { re, im: 0.0 }
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.re == 0.0 && self.im == 0.0
+    }
+
+    pub fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    pub fn inv(self) -> Complex {
+        let d = self.re * self.re + self.im * self.im;
+        Complex::new(self.re / d, -self.im / d)
+    }
+
+    pub fn exp(self) -> Complex {
+        let r = self.re.exp();
+        Complex::new(r * self.im.cos(), r * self.im.sin())
+    }
+
+    /// The principal value of the natural logarithm.
+    pub fn ln(self) -> Complex {
+        // `-0.0` comes from the rounding of real numbers, such as in the
+        // inverse of `-1`, and must not put negative numbers on the branch of
+        // `-pi`
+        let im = if self.im == 0.0 { 0.0 } else { self.im };
+        Complex::new(self.abs().ln(), im.atan2(self.re))
+    }
+
+    pub fn pow(self, expon: Complex) -> Complex {
+        if expon.im == 0.0 && expon.re.fract() == 0.0 && expon.re.abs() <= 64.0 {
+            // multiply to avoid the rounding errors of `exp` and `ln`, so
+            // that `i^2` is exactly `-1`
+            let mut result = Complex::real(1.0);
+            for _ in 0..expon.re.abs() as u32 {
+                result = result * self;
+            }
+            return if expon.re < 0.0 { result.inv() } else { result };
+        }
+        (expon * self.ln()).exp()
+    }
+
+    pub fn sin(self) -> Complex {
+        Complex::new(
+            self.re.sin() * self.im.cosh(),
+            self.re.cos() * self.im.sinh(),
+        )
+    }
+
+    pub fn cos(self) -> Complex {
+        Complex::new(
+            self.re.cos() * self.im.cosh(),
+            -self.re.sin() * self.im.sinh(),
+        )
+    }
+
+    pub fn sinh(self) -> Complex {
+        (self.exp() - (-self).exp()) * Complex::real(0.5)
+    }
+
+    pub fn cosh(self) -> Complex {
+        (self.exp() + (-self).exp()) * Complex::real(0.5)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, rhs: Complex) -> Complex {
+        let d = rhs.re * rhs.re + rhs.im * rhs.im;
+        Complex::new(
+            (self.re * rhs.re + self.im * rhs.im) / d,
+            (self.im * rhs.re - self.re * rhs.im) / d,
+        )
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+/// Approximates the node value as a complex number.
+pub(crate) fn eval_complex(node: &Node) -> Result<Complex, EvalError> {
+    let z = eval_complex_rec(node)?;
+    // remove the tiny parts that are only here because of rounding errors,
+    // for example in `e^(i*pi)`
+    let margin = z.abs() * 1e-12;
+    let clean = |x: f64| if x.abs() <= margin { 0.0 } else { x };
+    Ok(Complex::new(clean(z.re), clean(z.im)))
+}
+
+fn eval_complex_rec(node: &Node) -> Result<Complex, EvalError> {
+    Ok(match node {
+        Node::Const(kind) => match kind {
+            ConstKind::Pi => Complex::real(PI),
+            ConstKind::Tau => Complex::real(PI * 2.0),
+            ConstKind::E => Complex::real(E),
+            ConstKind::I => Complex::new(0.0, 1.0),
+        },
+        Node::Num { val, .. } => Complex::real(ratio_to_f64(val)),
+        Node::Sum(children) => {
+            let mut acc = Complex::real(0.0);
+            for child in children {
+                acc = acc + eval_complex_rec(child)?;
+            }
+            acc
+        }
+        Node::Product(children) => {
+            let mut acc = Complex::real(1.0);
+            for child in children {
+                acc = acc * eval_complex_rec(child)?;
+            }
+            acc
+        }
+        Node::Exp(base, expon) => {
+            let a = eval_complex_rec(base)?;
+            let b = eval_complex_rec(expon)?;
+            if a.is_zero() {
+                if b.re > 0.0 {
+                    return Ok(Complex::real(0.0));
+                }
+                if expon.eval_exact().ok() == Some(-BigRational::one()) {
+                    return Err(EvalError::DivisionByZero);
+                }
+                return Err(EvalError::ZeroToPowerOfNonPositive);
+            }
+            a.pow(b)
+        }
+        Node::Sin(inner) => eval_complex_rec(inner)?.sin(),
+        Node::Cos(inner) => eval_complex_rec(inner)?.cos(),
+        Node::Tan(inner) => {
+            let z = eval_complex_rec(inner)?;
+            let cos = z.cos();
+            if cos.abs() < 1e-15 {
+                return Err(EvalError::Tan90Or270);
+            }
+            z.sin() / cos
+        }
+        Node::Sinh(inner) => eval_complex_rec(inner)?.sinh(),
+        Node::Cosh(inner) => eval_complex_rec(inner)?.cosh(),
+        Node::Tanh(inner) => {
+            let z = eval_complex_rec(inner)?;
+            z.sinh() / z.cosh()
+        }
+        Node::Abs(inner) => Complex::real(eval_complex_rec(inner)?.abs()),
+        Node::Ln(inner) => {
+            let z = eval_complex_rec(inner)?;
+            if z.is_zero() {
+                return Err(EvalError::LogOfNonPositive);
+            }
+            z.ln()
+        }
+        Node::Log(base, inner) => {
+            let base = eval_complex_rec(base)?;
+            let z = eval_complex_rec(inner)?;
+            if base.is_zero() || base == Complex::real(1.0) {
+                return Err(EvalError::InvalidLogBase);
+            }
+            if z.is_zero() {
+                return Err(EvalError::LogOfNonPositive);
+            }
+            z.ln() / base.ln()
+        }
+        // the other nodes only make sense with real numbers
+        node => Complex::real(eval_real(node)?.val),
+    })
+}
@@ -0,0 +1,174 @@
+use num_bigint::BigInt;
+use num_traits::{One, Signed};
+
+use super::{ConstKind, Node};
+
+/// The maximum number of decimal places that can be requested, which caps
+/// the work of the computation.
+pub const MAX_CONSTANT_DIGITS: u32 = 50_000;
+
+/// The number of decimal places that are computed in addition to the
+/// requested ones so that the truncated digits are right.
+const GUARD_DIGITS: u32 = 10;
+
+/// The reason why the digits of a constant cannot be computed.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum DigitsError {
+    /// Only `pi`, `tau`, `e` and square roots of natural numbers are
+    /// supported
+    Unsupported,
+    /// More than `MAX_CONSTANT_DIGITS` decimal places were requested
+    TooManyDigits,
+}
+
+/// The sums of the Chudnovsky series between the terms `start` and `end`,
+/// which are split in two halves so that the big multiplications are
+/// balanced.
+fn chudnovsky(start: u64, end: u64) -> (BigInt, BigInt, BigInt) {
+    if end - start == 1 {
+        let k = start;
+        let (p, q) = if k == 0 {
+            (BigInt::one(), BigInt::one())
+        } else {
+            let p = BigInt::from(6 * k - 5) * (2 * k - 1) * (6 * k - 1);
+            // 640320^3 / 24
+            let q = BigInt::from(k) * k * k * 10_939_058_860_032_000u64;
+            (p, q)
+        };
+        let t: BigInt = &p * (BigInt::from(545_140_134) * k + 13_591_409);
+        return if k % 2 == 1 { (p, q, -t) } else { (p, q, t) };
+    }
+    let mid = (start + end) / 2;
+    let (p1, q1, t1) = chudnovsky(start, mid);
+    let (p2, q2, t2) = chudnovsky(mid, end);
+    (&p1 * p2, &q1 * &q2, t1 * q2 + p1 * t2)
+}
+
+/// Computes `pi * scale`, rounded down, with the Chudnovsky series and
+/// binary splitting. Each term of the series gives about 47 bits.
+pub(crate) fn pi_scaled(scale: &BigInt) -> BigInt {
+    let terms = scale.bits() / 47 + 2;
+    let (_, q, t) = chudnovsky(0, terms);
+    // pi = 426880 sqrt(10005) Q / T
+    let square: BigInt = scale * scale * 10005;
+    let sqrt = square.sqrt();
+    sqrt * q * 426_880 / t
+}
+
+/// Computes `e * scale`, rounded down, by summing `1/k!` with binary
+/// splitting.
+fn e_scaled(scale: &BigInt) -> BigInt {
+    // the sum of 1/((a+1)(a+2)...k) for a < k <= b as a fraction p/q
+    fn split(a: u64, b: u64) -> (BigInt, BigInt) {
+        if b - a == 1 {
+            return (BigInt::one(), BigInt::from(b));
+        }
+        let m = (a + b) / 2;
+        let (p1, q1) = split(a, m);
+        let (p2, q2) = split(m, b);
+        (p1 * &q2 + p2, q1 * q2)
+    }
+    // stop when k! is bigger than the scale
+    let target = scale.bits() as f64 * std::f64::consts::LN_2;
+    let mut terms = 1u64;
+    let mut log_factorial = 0.0;
+    while log_factorial <= target {
+        terms += 1;
+        log_factorial += (terms as f64).ln();
+    }
+    let (p, q) = split(0, terms);
+    scale + scale * p / q
+}
+
+/// Computes `value * scale` rounded down, if the digits of the node can be
+/// computed.
+fn scaled(node: &Node, scale: &BigInt) -> Option<BigInt> {
+    match node {
+        Node::Const(ConstKind::Pi) => Some(pi_scaled(scale)),
+        Node::Const(ConstKind::Tau) => Some(pi_scaled(&(scale * 2))),
+        Node::Const(ConstKind::E) => Some(e_scaled(scale)),
+        Node::Exp(base, expon) => {
+            // such as `sqrt(2)`, whose exponent is written `2^(-1)`
+            let base = base.eval_exact().ok()?;
+            let expon = expon.eval_exact().ok()?;
+            let is_sqrt = expon.numer().is_one() && *expon.denom() == BigInt::from(2);
+            if !is_sqrt || !base.is_integer() || base.is_negative() {
+                return None;
+            }
+            let square: BigInt = base.numer() * scale * scale;
+            Some(square.sqrt())
+        }
+        _ => None,
+    }
+}
+
+/// Computes the first `places` decimal places of `pi`, `tau`, `e` or the
+/// square root of a natural number, such as `3.14159` for `pi` with 5
+/// places. The digits are truncated rather than rounded, like in the digit
+/// lists of the constants.
+pub fn digits(node: &Node, places: u32) -> Result<String, DigitsError> {
+    if places > MAX_CONSTANT_DIGITS {
+        return Err(DigitsError::TooManyDigits);
+    }
+    let ten = BigInt::from(10);
+    let scale = num_traits::pow(ten.clone(), (places + GUARD_DIGITS) as usize);
+    let val = scaled(node, &scale).ok_or(DigitsError::Unsupported)?;
+    let val = val / num_traits::pow(ten, GUARD_DIGITS as usize);
+
+    let s = val.to_string();
+    let int_len = s.len().saturating_sub(places as usize);
+    if places == 0 {
+        return Ok(s);
+    }
+    if int_len == 0 {
+        let zeros = "0".repeat(places as usize - s.len());
+        return Ok(format!("0.{}{}", zeros, s));
+    }
+    Ok(format!("{}.{}", &s[..int_len], &s[int_len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    fn digits_str(expr: &str, places: u32) -> Result<String, DigitsError> {
+        let node = parse(expr);
+        node.digits(places)
+    }
+
+    #[test]
+    fn it_computes_the_digits_of_constants() {
+        assert_eq!(
+            digits_str("pi", 60),
+            Ok("3.141592653589793238462643383279502884197169399375105820974944".to_owned())
+        );
+        assert_eq!(
+            digits_str("e", 40),
+            Ok("2.7182818284590452353602874713526624977572".to_owned())
+        );
+        assert_eq!(
+            digits_str("sqrt(2)", 30),
+            Ok("1.414213562373095048801688724209".to_owned())
+        );
+        assert_eq!(digits_str("tau", 5), Ok("6.28318".to_owned()));
+        assert_eq!(digits_str("pi", 0), Ok("3".to_owned()));
+    }
+
+    #[test]
+    fn it_finds_far_digits() {
+        // the famous six nines of pi start at the 762nd decimal place
+        let s = digits_str("pi", 767).unwrap();
+        assert!(s.ends_with("999999"));
+        assert!(digits_str("pi", 1000).unwrap().ends_with("201989"));
+    }
+
+    #[test]
+    fn it_rejects_other_nodes_and_too_many_digits() {
+        assert_eq!(digits_str("ln(2)", 10), Err(DigitsError::Unsupported));
+        assert_eq!(
+            digits_str("pi", MAX_CONSTANT_DIGITS + 1),
+            Err(DigitsError::TooManyDigits)
+        );
+    }
+}
@@ -1,29 +1,509 @@
-use num_traits::{One, Signed};
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use std::cmp::Ordering;
 use std::fmt;
 use std::fmt::{Display, Write};
 
+use super::units::{get_per, has_units, split_compound, Unit};
 use super::util::is_minus_one;
 use super::{ConstKind, Node};
+use crate::builtins::clock::format_zone;
+use crate::imperial::format_feet_inches;
+use crate::lexer::{Lexer, Token};
+use crate::parser::Parser;
+
+/// The options that change how nodes are displayed.
+#[derive(Debug, PartialEq, Eq, Default, Copy, Clone)]
+pub struct FormatOptions {
+    /// Shows integer exponents as superscripts, such as `x²`
+    pub superscripts: bool,
+    /// Shows common fractions with a single character, such as `½`
+    pub vulgar_fractions: bool,
+    /// Separates the groups of digits of decimal numbers
+    pub grouping: Option<DigitGrouping>,
+    /// The number of digits of approximated values, instead of the shortest
+    /// digits that give back the same value
+    pub precision: Option<Precision>,
+    /// How approximated values are rounded to the precision
+    pub rounding: Rounding,
+    /// Removes the zeros at the end of the decimal places of approximated
+    /// values, such as `1.50` shown as `1.5`
+    pub trim_zeros: bool,
+    /// How approximated values are written
+    pub notation: Notation,
+    /// The words and symbols of the text
+    pub locale: Locale,
+    /// Shows the exact lengths in feet and inches as feet and inches, such
+    /// as `5' 3 1/2"`, rounded to the fraction of an inch with this
+    /// denominator, such as `16` for sixteenths of an inch
+    pub feet_inches: Option<u32>,
+}
+
+/// How many digits of approximated values are shown.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Precision {
+    /// A number of decimal places, such as `3.14` with 2
+    Decimals(u32),
+    /// A number of significant figures, such as `3.14` with 3. Very big and
+    /// very small values are shown in scientific notation, such as `1.5e-9`.
+    Significant(u32),
+}
+
+/// The notations of approximated values.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Notation {
+    /// Decimal notation, such as `1234.5`
+    Decimal,
+    /// Scientific notation, such as `1.2345e+3`, for the values whose decimal
+    /// exponent is less than `min_exponent` or at least `max_exponent`, and
+    /// decimal notation for the others. The precision is the one of the
+    /// mantissa.
+    Scientific {
+        min_exponent: i32,
+        max_exponent: i32,
+    },
+    /// Engineering notation, such as `12.345e+3`, where the exponent is a
+    /// multiple of 3, or `12.345k` with SI prefixes for the exponents from -24
+    /// to 24. The precision is the one of the mantissa.
+    Engineering { si_prefixes: bool },
+}
+
+impl Default for Notation {
+    fn default() -> Notation {
+        Notation::Decimal
+    }
+}
+
+/// How approximated values are rounded to the precision.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Rounding {
+    /// Ties are rounded away from zero, such as `2.5` to `3`
+    HalfAwayFromZero,
+    /// Ties are rounded to the even neighbor, such as `2.5` to `2`
+    HalfToEven,
+    /// The digits that are not shown are dropped, such as `2.9` to `2`
+    TowardZero,
+}
+
+impl Default for Rounding {
+    fn default() -> Rounding {
+        Rounding::HalfAwayFromZero
+    }
+}
+
+/// The ways of grouping digits of the different locales.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum DigitGrouping {
+    /// `1,234,567.89`
+    English,
+    /// `1 234 567,89`
+    French,
+    /// `12,34,567.89`, where the digits before the last three are grouped by
+    /// two
+    Indian,
+}
+
+/// The words and symbols in the text that the library writes, so that
+/// frontends can translate all of it.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Locale {
+    /// Between an exact result and its approximation, `≈` in English
+    pub approx: &'static str,
+    /// Between the items of a list, such as the arguments of `max`
+    pub list_separator: &'static str,
+    /// Before the unit of a rate, such as `km per h`
+    pub per: &'static str,
+    /// Before the unit of a conversion, such as `5 km in mi`
+    pub conversion: &'static str,
+    /// Between a measurement and its uncertainty
+    pub plus_minus: &'static str,
+    /// The infinite approximations, which can be `∞`
+    pub infinity: &'static str,
+    /// Between the solutions of an equation
+    pub or: &'static str,
+    /// The solutions of an equation that has none
+    pub no_solution: &'static str,
+    /// The solutions of an equation that holds for every value
+    pub any_value: &'static str,
+}
+
+impl Locale {
+    pub const ENGLISH: Locale = Locale {
+        approx: "≈",
+        list_separator: ", ",
+        per: "per",
+        conversion: "in",
+        plus_minus: "±",
+        infinity: "inf",
+        or: "or",
+        no_solution: "no solution",
+        any_value: "any value",
+    };
+}
+
+impl Default for Locale {
+    fn default() -> Locale {
+        Locale::ENGLISH
+    }
+}
+
+/// Writes the digits of an integer with the separators of the grouping.
+fn push_grouped(out: &mut String, digits: &str, grouping: DigitGrouping) {
+    let (separator, head_size) = match grouping {
+        DigitGrouping::English => (',', 3),
+        DigitGrouping::French => (' ', 3),
+        DigitGrouping::Indian => (',', 2),
+    };
+    // the last three digits are always a group
+    let split = digits.len().saturating_sub(3);
+    let (head, tail) = digits.split_at(split);
+    let mut groups: Vec<&[u8]> = head.as_bytes().rchunks(head_size).collect();
+    groups.reverse();
+    for group in groups {
+        out.push_str(std::str::from_utf8(group).unwrap());
+        out.push(separator);
+    }
+    out.push_str(tail);
+}
+
+/// Rounds a nonnegative number to an integer.
+fn round(x: &BigRational, rounding: Rounding) -> BigInt {
+    let int = x.trunc().to_integer();
+    let half = BigRational::new(BigInt::one(), BigInt::from(2));
+    let round_up = match x.fract().cmp(&half) {
+        _ if rounding == Rounding::TowardZero => false,
+        Ordering::Less => false,
+        Ordering::Greater => true,
+        Ordering::Equal => rounding == Rounding::HalfAwayFromZero || int.is_odd(),
+    };
+    if round_up {
+        int + 1
+    } else {
+        int
+    }
+}
+
+/// Returns `10^n`.
+fn pow10(n: i32) -> BigRational {
+    let ten = BigInt::from(10);
+    if n < 0 {
+        BigRational::new(BigInt::one(), num_traits::pow(ten, -n as usize))
+    } else {
+        BigRational::from_integer(num_traits::pow(ten, n as usize))
+    }
+}
+
+/// Writes the integer `digits * 10^-decimals`.
+fn push_fixed(out: &mut String, digits: &BigInt, decimals: i32, trim_zeros: bool) {
+    if decimals <= 0 {
+        out.push_str(&(digits * pow10(-decimals).to_integer()).to_string());
+        return;
+    }
+    let decimals = decimals as usize;
+    let mut s = digits.to_string();
+    if s.len() <= decimals {
+        s = "0".repeat(decimals + 1 - s.len()) + &s;
+    }
+    let (int, mut frac) = s.split_at(s.len() - decimals);
+    if trim_zeros {
+        frac = frac.trim_end_matches('0');
+    }
+    out.push_str(int);
+    if !frac.is_empty() {
+        out.push('.');
+        out.push_str(frac);
+    }
+}
+
+/// Returns the decimal exponent of a positive number, such as 2 for `123`.
+fn decimal_exponent(abs: &BigRational) -> i32 {
+    let mut exponent = abs.to_f64().unwrap().log10().floor() as i32;
+    // the approximation can be off by one
+    if pow10(exponent) > *abs {
+        exponent -= 1;
+    } else if pow10(exponent + 1) <= *abs {
+        exponent += 1;
+    }
+    exponent
+}
+
+/// Rounds a nonnegative number to `n` significant figures, and returns the
+/// digits along with the decimal exponent of the first one.
+fn round_significant(abs: &BigRational, n: i32, rounding: Rounding) -> (BigInt, i32) {
+    let mut exponent = if abs.is_zero() {
+        0
+    } else {
+        decimal_exponent(abs)
+    };
+    let mut digits = round(&(abs * pow10(n - 1 - exponent)), rounding);
+    // such as `9.99` rounded to `10.0` with 3 significant figures
+    if digits >= pow10(n).to_integer() {
+        exponent += 1;
+        digits = round(&(abs * pow10(n - 1 - exponent)), rounding);
+    }
+    (digits, exponent)
+}
+
+/// The SI prefixes of the exponents from -24 to 24 by steps of 3.
+const SI_PREFIXES: [&str; 17] = [
+    "y", "z", "a", "f", "p", "n", "µ", "m", "", "k", "M", "G", "T", "P", "E", "Z", "Y",
+];
+
+/// Writes the mantissa of a finite nonzero value with the precision of the
+/// options, and returns its exponent, which is a multiple of `step`. The
+/// mantissa is between 1 and `10^step`.
+fn push_mantissa(
+    out: &mut String,
+    val: f64,
+    abs: &BigRational,
+    options: &FormatOptions,
+    step: i32,
+) -> i32 {
+    let to_step = |exponent: i32| exponent.div_euclid(step) * step;
+    match options.precision {
+        None => {
+            // the shortest digits that give back the same value
+            let printed = format!("{:e}", val.abs());
+            let mut parts = printed.splitn(2, 'e');
+            let digits = parts.next().unwrap().replace('.', "");
+            let exponent: i32 = parts.next().unwrap().parse().unwrap();
+            let int_len = (exponent - to_step(exponent)) as usize + 1;
+            if digits.len() <= int_len {
+                out.push_str(&digits);
+                out.push_str(&"0".repeat(int_len - digits.len()));
+            } else {
+                out.push_str(&digits[..int_len]);
+                out.push('.');
+                out.push_str(&digits[int_len..]);
+            }
+            to_step(exponent)
+        }
+        Some(Precision::Decimals(n)) => {
+            let n = n as i32;
+            let mut exponent = to_step(decimal_exponent(abs));
+            let mut digits = round(&(abs * pow10(n - exponent)), options.rounding);
+            // such as `999.99` rounded to `1000.0` with 1 decimal place
+            if digits >= pow10(n + step).to_integer() {
+                exponent += step;
+                digits = round(&(abs * pow10(n - exponent)), options.rounding);
+            }
+            push_fixed(out, &digits, n, options.trim_zeros);
+            exponent
+        }
+        Some(Precision::Significant(n)) => {
+            let n = n.max(1) as i32;
+            let (digits, exponent) = round_significant(abs, n, options.rounding);
+            let decimals = n - 1 - (exponent - to_step(exponent));
+            push_fixed(out, &digits, decimals, options.trim_zeros);
+            to_step(exponent)
+        }
+    }
+}
+
+/// Writes an exponent with its sign, such as `e+12`.
+fn push_exponent(out: &mut String, exponent: i32) {
+    let sign = if exponent < 0 { '-' } else { '+' };
+    write!(out, "e{}{}", sign, exponent.abs()).unwrap();
+}
+
+/// Writes a finite value in base 10 with the notation, the precision, the
+/// rounding and the trimming of the options.
+pub(super) fn format_decimal(val: f64, options: &FormatOptions) -> String {
+    // a float is a binary fraction, so it can be rounded exactly
+    let abs = BigRational::from_float(val.abs()).unwrap();
+    if abs.is_zero() {
+        // zero is written in decimal notation in every notation
+    } else if let Notation::Scientific {
+        min_exponent,
+        max_exponent,
+    } = options.notation
+    {
+        let exponent = decimal_exponent(&abs);
+        if exponent < min_exponent || exponent >= max_exponent {
+            let mut out = if val < 0.0 {
+                "-".to_owned()
+            } else {
+                String::new()
+            };
+            let exponent = push_mantissa(&mut out, val, &abs, options, 1);
+            push_exponent(&mut out, exponent);
+            return out;
+        }
+    } else if let Notation::Engineering { si_prefixes } = options.notation {
+        let mut out = if val < 0.0 {
+            "-".to_owned()
+        } else {
+            String::new()
+        };
+        let exponent = push_mantissa(&mut out, val, &abs, options, 3);
+        match SI_PREFIXES.get((exponent / 3 + 8) as usize) {
+            Some(prefix) if si_prefixes && exponent.abs() <= 24 => out.push_str(prefix),
+            _ => push_exponent(&mut out, exponent),
+        }
+        return out;
+    }
+
+    let (digits, decimals, exponent) = match options.precision {
+        None => {
+            let mut buf = ryu::Buffer::new();
+            let printed = buf.format(val);
+            // the shortest digits only end with a zero in `1.0`
+            if options.trim_zeros && printed.ends_with(".0") {
+                return printed[..printed.len() - 2].to_owned();
+            }
+            return printed.to_owned();
+        }
+        Some(Precision::Decimals(n)) => (
+            round(&(&abs * pow10(n as i32)), options.rounding),
+            n as i32,
+            None,
+        ),
+        Some(Precision::Significant(n)) => {
+            let n = n.max(1) as i32;
+            let (digits, exponent) = round_significant(&abs, n, options.rounding);
+            if !(-6..=20).contains(&exponent) {
+                (digits, n - 1, Some(exponent))
+            } else {
+                (digits, n - 1 - exponent, None)
+            }
+        }
+    };
+
+    let mut out = String::new();
+    if val < 0.0 && !digits.is_zero() {
+        out.push('-');
+    }
+    push_fixed(&mut out, &digits, decimals, options.trim_zeros);
+    if let Some(exponent) = exponent {
+        write!(out, "e{}", exponent).unwrap();
+    }
+    out
+}
+
+/// Groups the digits of the integer parts of the decimal numbers in the text,
+/// such as `-1234.5 + 67890i`, and replaces the decimal points for the
+/// locale. The fractional parts and the exponents are kept as they are.
+pub fn group_digits(text: &str, grouping: DigitGrouping) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        if !chars[i].is_ascii_digit() {
+            let is_decimal_point = chars[i] == '.'
+                && i > 0
+                && chars[i - 1].is_ascii_digit()
+                && matches!(chars.get(i + 1), Some(c) if c.is_ascii_digit());
+            if is_decimal_point && grouping == DigitGrouping::French {
+                out.push(',');
+            } else {
+                out.push(chars[i]);
+            }
+            i += 1;
+            continue;
+        }
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+        let digits: String = chars[start..i].iter().collect();
+        let prev = |n: usize| {
+            if start >= n {
+                Some(chars[start - n])
+            } else {
+                None
+            }
+        };
+        let is_integer_part = match prev(1) {
+            Some('.') | Some('e') | Some('E') => false,
+            Some('-') | Some('+') => !matches!(prev(2), Some('e') | Some('E')),
+            _ => true,
+        };
+        if is_integer_part {
+            push_grouped(&mut out, &digits, grouping);
+        } else {
+            out.push_str(&digits);
+        }
+    }
+    out
+}
+
+/// Returns the character of a common fraction, if there is one.
+fn get_vulgar_fraction(numer: &BigInt, denom: &BigInt) -> Option<char> {
+    let (numer, denom) = (numer.to_u8()?, denom.to_u8()?);
+    Some(match (numer, denom) {
+        (1, 2) => '½',
+        (1, 3) => '⅓',
+        (2, 3) => '⅔',
+        (1, 4) => '¼',
+        (3, 4) => '¾',
+        (1, 5) => '⅕',
+        (2, 5) => '⅖',
+        (3, 5) => '⅗',
+        (4, 5) => '⅘',
+        (1, 6) => '⅙',
+        (5, 6) => '⅚',
+        (1, 7) => '⅐',
+        (1, 8) => '⅛',
+        (3, 8) => '⅜',
+        (5, 8) => '⅝',
+        (7, 8) => '⅞',
+        (1, 9) => '⅑',
+        (1, 10) => '⅒',
+        _ => return None,
+    })
+}
+
+fn write_superscript(f: &mut fmt::Formatter<'_>, val: &BigInt) -> fmt::Result {
+    for c in val.to_string().chars() {
+        f.write_char(match c {
+            '-' => '⁻',
+            '0' => '⁰',
+            '1' => '¹',
+            '2' => '²',
+            '3' => '³',
+            '4' => '⁴',
+            '5' => '⁵',
+            '6' => '⁶',
+            '7' => '⁷',
+            '8' => '⁸',
+            _ => '⁹',
+        })?;
+    }
+    Ok(())
+}
 
 #[derive(PartialEq, Eq, PartialOrd, Ord)]
-enum NodePriority {
+pub(super) enum NodePriority {
+    /// A conversion such as `x in km`
+    Convert,
+    Ratio,
     AddOrSub,
     MulOrDiv,
     Exp,
     Value,
 }
 
-fn get_node_priority(node: &Node) -> NodePriority {
+pub(super) fn get_node_priority(node: &Node, options: &FormatOptions) -> NodePriority {
     match node {
-        Node::Const(_) | Node::UnknownConst(_) => NodePriority::Value,
+        Node::Const(_) | Node::Var(_) | Node::Vector(_) | Node::Dice { .. } | Node::Time { .. } => {
+            NodePriority::Value
+        }
         Node::Num { val, .. } => {
-            if val.denom().is_one() {
+            if val.denom().is_one()
+                || (options.vulgar_fractions
+                    && get_vulgar_fraction(&val.numer().abs(), val.denom()).is_some())
+            {
                 NodePriority::Value
             } else {
                 // it will be displayed as a fraction with a division sign
                 NodePriority::MulOrDiv
             }
         }
+        Node::Ratio(_) => NodePriority::Ratio,
+        Node::Quantity(inner, _) if has_units(inner) => NodePriority::Convert,
+        Node::Quantity(..) => NodePriority::MulOrDiv,
         Node::Sum(_) => NodePriority::AddOrSub,
         Node::Product(_) => NodePriority::MulOrDiv,
         Node::Exp(_, b) => {
@@ -35,12 +515,31 @@ fn get_node_priority(node: &Node) -> NodePriority {
             }
         }
         // functions
-        Node::Sin(_) | Node::Cos(_) | Node::Tan(_) => NodePriority::Exp,
+        Node::Sin(_)
+        | Node::Cos(_)
+        | Node::Tan(_)
+        | Node::Asin(_)
+        | Node::Acos(_)
+        | Node::Atan(_)
+        | Node::Atan2(_, _)
+        | Node::Sinh(_)
+        | Node::Cosh(_)
+        | Node::Tanh(_)
+        | Node::Abs(_)
+        | Node::Floor(_)
+        | Node::Ceil(_)
+        | Node::Round(_)
+        | Node::Sign(_)
+        | Node::Min(_)
+        | Node::Max(_)
+        | Node::Ln(_)
+        | Node::Log(_, _) => NodePriority::Exp,
     }
 }
 
 fn write_with_paren(
     f: &mut fmt::Formatter<'_>,
+    options: &FormatOptions,
     node: &Node,
     curr_prio: NodePriority,
     left_assoc: bool,
@@ -48,168 +547,419 @@ fn write_with_paren(
 ) -> fmt::Result {
     let needs_paren = if left_assoc {
         // mul(1,mul(2,3)) => 1*2*3
-        get_node_priority(node) < curr_prio
+        get_node_priority(node, options) < curr_prio
     } else {
         // pow(1,pow(2,3)) => 1^(2^3)
-        get_node_priority(node) <= curr_prio
+        get_node_priority(node, options) <= curr_prio
     };
     if needs_paren {
         f.write_char('(')?;
     } else if needs_separation {
         f.write_char(' ')?;
     }
-    node.fmt(f)?;
+    write_node(f, options, node)?;
     if needs_paren {
         f.write_char(')')?;
     }
     Ok(())
 }
 
-fn write_func(f: &mut fmt::Formatter<'_>, name: &str, inner: &Node) -> fmt::Result {
+fn write_list(f: &mut fmt::Formatter<'_>, options: &FormatOptions, items: &[Node]) -> fmt::Result {
+    f.write_char('(')?;
+    for (i, item) in items.iter().enumerate() {
+        if i != 0 {
+            f.write_str(options.locale.list_separator)?;
+        }
+        write_node(f, options, item)?;
+    }
+    f.write_char(')')
+}
+
+fn write_func(
+    f: &mut fmt::Formatter<'_>,
+    options: &FormatOptions,
+    name: &str,
+    inner: &Node,
+) -> fmt::Result {
     f.write_str(name)?;
-    write_with_paren(f, inner, NodePriority::Exp, true, true)
+    write_with_paren(f, options, inner, NodePriority::Exp, true, true)
+}
+
+/// Returns the rows of the node if it is a vector of vectors that all have the
+/// same non-zero length.
+pub(super) fn get_matrix_rows(node: &Node) -> Option<Vec<&[Node]>> {
+    let rows = match node {
+        Node::Vector(rows) if !rows.is_empty() => rows,
+        _ => return None,
+    };
+    let mut res = Vec::with_capacity(rows.len());
+    for row in rows {
+        match row {
+            Node::Vector(items) if !items.is_empty() => res.push(&items[..]),
+            _ => return None,
+        }
+    }
+    if res.iter().any(|r| r.len() != res[0].len()) {
+        return None;
+    }
+    Some(res)
+}
+
+/// Formats a node over multiple lines so that matrices are shown as a grid
+/// with aligned columns. Other nodes are formatted on a single line.
+pub(crate) fn pretty(node: &Node) -> String {
+    let rows = match get_matrix_rows(node) {
+        Some(rows) => rows,
+        None => return node.to_string(),
+    };
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|r| r.iter().map(|x| x.to_string()).collect())
+        .collect();
+    let mut widths = vec![0; cells[0].len()];
+    for row in &cells {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    for (i, row) in cells.iter().enumerate() {
+        let (left, right) = if cells.len() == 1 {
+            ('[', ']')
+        } else if i == 0 {
+            ('⎡', '⎤')
+        } else if i == cells.len() - 1 {
+            ('⎣', '⎦')
+        } else {
+            ('⎢', '⎥')
+        };
+        if i != 0 {
+            out.push('\n');
+        }
+        out.push(left);
+        for (cell, w) in row.iter().zip(&widths) {
+            // right-align so that the digits line up
+            write!(out, " {:>1$}", cell, w).unwrap();
+        }
+        out.push(' ');
+        out.push(right);
+    }
+    out
 }
 
 impl Display for Node {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Node::Const(kind) => match kind {
-                ConstKind::Pi => write!(f, "pi"),
-                ConstKind::Tau => write!(f, "tau"),
-                ConstKind::E => write!(f, "e"),
-            },
-            Node::UnknownConst(s) => write!(f, "{}", s),
-            Node::Num { val, input_base } => {
-                let input_base = input_base.unwrap_or(10);
-                match input_base {
-                    2 if val.is_integer() => write!(f, "{:#b}", val.numer()),
-                    8 if val.is_integer() => write!(f, "{:#o}", val.numer()),
-                    10 => write!(f, "{}", val),
-                    16 if val.is_integer() => write!(f, "{:#X}", val.numer()),
-                    input_base => {
-                        eprintln!(
-                            "warning: cannot print BigRational in base {} yet",
-                            input_base
-                        );
-                        write!(f, "{}", val)
+        write_node(f, &FormatOptions::default(), self)
+    }
+}
+
+/// Formats a node with the given options.
+pub(crate) fn format(node: &Node, options: &FormatOptions) -> String {
+    struct Formatted<'a>(&'a Node, &'a FormatOptions);
+
+    impl<'a> Display for Formatted<'a> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write_node(f, self.1, self.0)
+        }
+    }
+
+    Formatted(node, options).to_string()
+}
+
+fn parse(text: &str) -> Option<Node> {
+    let tokens: Result<Vec<Token>, _> = Lexer::new(text).collect();
+    Parser::new(&tokens.ok()?).parse().ok()
+}
+
+/// Formats the node on a single line without the spaces that are not
+/// needed, such as `2x*(y+1)`, to store it in little space.
+pub(crate) fn compact(node: &Node) -> String {
+    let text = node.to_string();
+    let chars: Vec<char> = text.chars().collect();
+    let is_word = |c: Option<&char>| matches!(c, Some(c) if c.is_ascii_alphanumeric() || *c == '_');
+    let mut out = String::with_capacity(text.len());
+    for (i, c) in chars.iter().enumerate() {
+        // the space in `sin x` separates two words
+        if *c == ' ' && !(is_word(chars.get(i.wrapping_sub(1))) && is_word(chars.get(i + 1))) {
+            continue;
+        }
+        out.push(*c);
+    }
+    // the spaces are kept if removing them changes the meaning
+    if out != text && parse(&out) != parse(&text) {
+        return text;
+    }
+    out
+}
+
+fn write_decimal(
+    f: &mut fmt::Formatter<'_>,
+    options: &FormatOptions,
+    val: &BigRational,
+) -> fmt::Result {
+    match options.grouping {
+        Some(grouping) => f.write_str(&group_digits(&val.to_string(), grouping)),
+        None => write!(f, "{}", val),
+    }
+}
+
+fn write_node(f: &mut fmt::Formatter<'_>, options: &FormatOptions, node: &Node) -> fmt::Result {
+    match node {
+        Node::Const(kind) => match kind {
+            ConstKind::Pi => write!(f, "pi"),
+            ConstKind::Tau => write!(f, "tau"),
+            ConstKind::E => write!(f, "e"),
+            ConstKind::I => write!(f, "i"),
+        },
+        Node::Var(s) => write!(f, "{}", s),
+        Node::Num { val, input_base } => {
+            let input_base = input_base.unwrap_or(10);
+            match input_base {
+                2 if val.is_integer() => write!(f, "{:#b}", val.numer()),
+                8 if val.is_integer() => write!(f, "{:#o}", val.numer()),
+                10 if options.vulgar_fractions => {
+                    match get_vulgar_fraction(&val.numer().abs(), val.denom()) {
+                        Some(c) if val.is_negative() => write!(f, "-{}", c),
+                        Some(c) => f.write_char(c),
+                        None => write_decimal(f, options, val),
+                    }
+                }
+                10 => write_decimal(f, options, val),
+                16 if val.is_integer() => write!(f, "{:#X}", val.numer()),
+                input_base if val.is_integer() => {
+                    if val.is_negative() {
+                        f.write_char('-')?;
                     }
+                    let digits = val.numer().abs().to_str_radix(input_base);
+                    write!(f, "{}#{}", input_base, digits.to_uppercase())
+                }
+                input_base => {
+                    eprintln!(
+                        "warning: cannot print BigRational in base {} yet",
+                        input_base
+                    );
+                    write!(f, "{}", val)
                 }
             }
-            Node::Sum(children) => {
-                let mut first = true;
-                for child in children {
-                    if first {
-                        first = false;
-                    } else {
-                        // detect subtraction
-                        if let Node::Product(c_children) = child {
-                            if c_children.len() == 2 {
-                                let mut is_done = false;
-                                for i in 0..=1 {
-                                    if is_minus_one(&c_children[i]) {
-                                        // directly output "- x" instead of "+ (-1) * x"
-                                        write!(f, " - ")?;
-                                        write_with_paren(
-                                            f,
-                                            &c_children[1 - i],
-                                            NodePriority::AddOrSub,
-                                            false,
-                                            false,
-                                        )?;
-                                        is_done = true;
-                                        break;
-                                    }
-                                }
-                                if is_done {
-                                    continue;
+        }
+        Node::Sum(children) => {
+            let mut first = true;
+            for child in children {
+                if first {
+                    first = false;
+                } else {
+                    // detect subtraction
+                    if let Node::Product(c_children) = child {
+                        if c_children.len() == 2 {
+                            let mut is_done = false;
+                            for i in 0..=1 {
+                                if is_minus_one(&c_children[i]) {
+                                    // directly output "- x" instead of "+ (-1) * x"
+                                    write!(f, " - ")?;
+                                    write_with_paren(
+                                        f,
+                                        options,
+                                        &c_children[1 - i],
+                                        NodePriority::AddOrSub,
+                                        false,
+                                        false,
+                                    )?;
+                                    is_done = true;
+                                    break;
                                 }
                             }
-                        } else if let Node::Num { val, input_base } = child {
-                            if val.is_negative() {
-                                // directly output "- x" instead of "+ -x"
-                                write!(f, " - ")?;
-                                let fake_node = Node::Num {
-                                    val: -val, // remove negative sign
-                                    input_base: *input_base,
-                                };
-                                fake_node.fmt(f)?;
+                            if is_done {
                                 continue;
                             }
                         }
-                        write!(f, " + ")?;
-                    };
-                    write_with_paren(f, child, get_node_priority(self), true, false)?;
-                }
-                Ok(())
-            }
-            Node::Product(children) => {
-                let mut first = true;
-                let mut previous_was_int = false;
-                for child in children {
-                    if first {
-                        first = false;
-                    } else {
-                        // detect division
-                        if let Node::Exp(a, b) = child {
-                            if is_minus_one(b) {
-                                // directly output "/ x" instead of "* 1/x"
-                                write!(f, " / ")?;
-                                write_with_paren(f, a, NodePriority::MulOrDiv, false, false)?;
-                                continue;
-                            }
+                    } else if let Node::Num { val, input_base } = child {
+                        if val.is_negative() {
+                            // directly output "- x" instead of "+ -x"
+                            write!(f, " - ")?;
+                            let fake_node = Node::Num {
+                                val: -val, // remove negative sign
+                                input_base: *input_base,
+                            };
+                            write_node(f, options, &fake_node)?;
+                            continue;
                         }
-                        // Use implicit multiplication for integers followed by
-                        // constants.
-                        let mut implicit_mul = false;
-                        if previous_was_int {
-                            if let Node::Const(..) = &child {
-                                implicit_mul = true;
-                            } else if let Node::UnknownConst(..) = &child {
-                                implicit_mul = true;
-                            }
+                    }
+                    write!(f, " + ")?;
+                };
+                write_with_paren(
+                    f,
+                    options,
+                    child,
+                    get_node_priority(node, options),
+                    true,
+                    false,
+                )?;
+            }
+            Ok(())
+        }
+        Node::Product(children) => {
+            // such as `25 m^2`, like the units of the approximations
+            if let Some((value, unit)) = split_compound(node) {
+                write_with_paren(f, options, value, NodePriority::Exp, false, false)?;
+                return write!(f, " {}", unit.format(options.locale.per));
+            }
+            if let [minus_one, value @ Node::Const(_)] | [minus_one, value @ Node::Var(_)] =
+                &children[..]
+            {
+                if is_minus_one(minus_one) {
+                    // directly output "-i" instead of "-1i"
+                    f.write_char('-')?;
+                    return write_node(f, options, value);
+                }
+            }
+            let mut first = true;
+            let mut previous_was_int = false;
+            for child in children {
+                if first {
+                    first = false;
+                } else {
+                    if let Some(unit) = get_per(child) {
+                        write!(f, " {} {}", options.locale.per, unit)?;
+                        continue;
+                    }
+                    // detect division
+                    if let Node::Exp(a, b) = child {
+                        if is_minus_one(b) {
+                            // directly output "/ x" instead of "* 1/x"
+                            write!(f, " / ")?;
+                            write_with_paren(f, options, a, NodePriority::MulOrDiv, false, false)?;
+                            continue;
                         }
-                        if !implicit_mul {
-                            write!(f, " * ")?;
+                    }
+                    // Use implicit multiplication for integers followed by
+                    // constants.
+                    let mut implicit_mul = false;
+                    if previous_was_int {
+                        if let Node::Const(..) = &child {
+                            implicit_mul = true;
+                        } else if let Node::Var(..) = &child {
+                            implicit_mul = true;
                         }
                     }
-                    write_with_paren(f, child, get_node_priority(self), true, false)?;
+                    if !implicit_mul {
+                        write!(f, " * ")?;
+                    }
+                }
+                write_with_paren(
+                    f,
+                    options,
+                    child,
+                    get_node_priority(node, options),
+                    true,
+                    false,
+                )?;
 
-                    previous_was_int = false;
-                    if let Node::Num { val, .. } = &child {
-                        if val.is_integer() {
-                            previous_was_int = true;
-                        }
+                previous_was_int = false;
+                if let Node::Num { val, .. } = &child {
+                    if val.is_integer() {
+                        previous_was_int = true;
                     }
                 }
-                Ok(())
             }
-            Node::Exp(a, b) => {
-                if is_minus_one(b) {
-                    // a^-1 = 1/a
-                    write!(f, "1/")?;
-                    write_with_paren(f, a, NodePriority::MulOrDiv, false, false)
-                } else {
-                    write_with_paren(f, a, NodePriority::Exp, false, false)?;
-                    f.write_char('^')?;
-                    write_with_paren(f, b, NodePriority::Exp, false, false)
+            Ok(())
+        }
+        Node::Exp(a, b) => {
+            if is_minus_one(b) {
+                // a^-1 = 1/a
+                write!(f, "1/")?;
+                write_with_paren(f, options, a, NodePriority::MulOrDiv, false, false)
+            } else {
+                write_with_paren(f, options, a, NodePriority::Exp, false, false)?;
+                if let Node::Num { val, .. } = &**b {
+                    if options.superscripts && val.is_integer() {
+                        return write_superscript(f, val.numer());
+                    }
+                }
+                f.write_char('^')?;
+                write_with_paren(f, options, b, NodePriority::Exp, false, false)
+            }
+        }
+        Node::Vector(items) => write_list(f, options, items),
+        Node::Ratio(terms) => {
+            for (i, term) in terms.iter().enumerate() {
+                if i != 0 {
+                    f.write_char(':')?;
+                }
+                write_with_paren(f, options, term, NodePriority::Ratio, false, false)?;
+            }
+            Ok(())
+        }
+        Node::Dice { count, sides } => write!(f, "{}d{}", count, sides),
+        Node::Time { offset: 0 } => f.write_str("now()"),
+        Node::Time { offset } => write!(f, "time_in(\"{}\")", format_zone(*offset)),
+        Node::Quantity(inner, unit) if has_units(inner) => {
+            write_with_paren(f, options, inner, NodePriority::Convert, true, false)?;
+            write!(f, " {} {}", options.locale.conversion, unit)
+        }
+        Node::Quantity(inner, unit) => {
+            if let (Some(smallest_denom), Node::Num { val, .. }) = (options.feet_inches, &**inner) {
+                let inches = match unit {
+                    Unit::Inch => Some(val.clone()),
+                    Unit::Foot => Some(val * BigInt::from(12)),
+                    _ => None,
+                };
+                if let Some(inches) = inches {
+                    return f.write_str(&format_feet_inches(&inches, smallest_denom));
                 }
             }
-            // functions
-            Node::Sin(inner) => write_func(f, "sin", inner),
-            Node::Cos(inner) => write_func(f, "cos", inner),
-            Node::Tan(inner) => write_func(f, "tan", inner),
+            write_with_paren(f, options, inner, NodePriority::Exp, false, false)?;
+            write!(f, " {}", unit)
+        }
+        // functions
+        Node::Sin(inner) => write_func(f, options, "sin", inner),
+        Node::Cos(inner) => write_func(f, options, "cos", inner),
+        Node::Tan(inner) => write_func(f, options, "tan", inner),
+        Node::Asin(inner) => write_func(f, options, "asin", inner),
+        Node::Acos(inner) => write_func(f, options, "acos", inner),
+        Node::Atan(inner) => write_func(f, options, "atan", inner),
+        Node::Atan2(y, x) => {
+            f.write_str("atan2")?;
+            write_list(f, options, &[(**y).clone(), (**x).clone()])
+        }
+        Node::Sinh(inner) => write_func(f, options, "sinh", inner),
+        Node::Cosh(inner) => write_func(f, options, "cosh", inner),
+        Node::Tanh(inner) => write_func(f, options, "tanh", inner),
+        Node::Abs(inner) => write_func(f, options, "abs", inner),
+        Node::Floor(inner) => write_func(f, options, "floor", inner),
+        Node::Ceil(inner) => write_func(f, options, "ceil", inner),
+        Node::Round(inner) => write_func(f, options, "round", inner),
+        Node::Sign(inner) => write_func(f, options, "sign", inner),
+        Node::Min(children) => {
+            f.write_str("min")?;
+            write_list(f, options, children)
+        }
+        Node::Max(children) => {
+            f.write_str("max")?;
+            write_list(f, options, children)
+        }
+        Node::Ln(inner) => write_func(f, options, "ln", inner),
+        Node::Log(base, inner) => {
+            f.write_str("log")?;
+            write_list(f, options, &[(**base).clone(), (**inner).clone()])
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::super::{EvalSuccess, FormatOptions, Node};
+    use super::{group_digits, DigitGrouping, Locale};
     use crate::lexer::{Lexer, Token};
+    use crate::measurement::Measurement;
     use crate::parser::Parser;
+    use crate::test_util::parse;
+    use num_rational::BigRational;
 
+    #[allow(clippy::float_cmp)]
     #[test]
     fn it_formats_a_node_correctly() {
-        const CASES: [&str; 8] = [
+        const CASES: [&str; 13] = [
             // easy
             "1+2",
             "1*3+5",
@@ -222,6 +972,12 @@ mod tests {
             "1/2pi",
             // number base
             "0xFF/0b10*sin(2)",
+            // functions with multiple arguments
+            "area_triangle(3, 1/2)",
+            "log(2, 3) * ln(pi)",
+            "sinh cosh tanh 2",
+            "abs(floor(-5/2) + ceil round sign 3)",
+            "min(2, max(pi, 1/3), e) ^ max(1)",
         ];
         for c in &CASES {
             let tokens: Vec<Token> = Lexer::new(c).map(|x| x.unwrap()).collect();
@@ -244,4 +1000,122 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn it_aligns_the_columns_of_matrices() {
+        let matrix = parse("((1, 200, 3), (40, 5, 600), (7, 8, 9))");
+        assert_eq!(
+            matrix.to_pretty_string(),
+            "⎡  1 200   3 ⎤\n⎢ 40   5 600 ⎥\n⎣  7   8   9 ⎦"
+        );
+        assert_eq!(matrix.to_string(), "((1, 200, 3), (40, 5, 600), (7, 8, 9))");
+        let row = Node::Vector(vec![parse("(1, 2)")]);
+        assert_eq!(row.to_pretty_string(), "[ 1 2 ]");
+
+        // ragged lists are not matrices
+        assert_eq!(
+            parse("((1, 2), (3, 4, 5))").to_pretty_string(),
+            "((1, 2), (3, 4, 5))"
+        );
+    }
+
+    #[test]
+    fn it_formats_with_unicode_characters() {
+        let options = FormatOptions {
+            superscripts: true,
+            vulgar_fractions: true,
+            ..FormatOptions::default()
+        };
+
+        let node = parse("3/4 * x^2 - 1/2 + y^-3 + x^10")
+            .simplify()
+            .unwrap()
+            .result;
+        assert_eq!(node.format(&options), "-½ + ¾ * x² + y⁻³ + x¹⁰");
+        assert_eq!(node.format(&FormatOptions::default()), node.to_string());
+        // only some fractions have a character
+        let node = parse("(2/7)^y").simplify().unwrap().result;
+        assert_eq!(node.format(&options), "(2/7)^y");
+    }
+
+    #[test]
+    fn it_formats_compactly() {
+        let compact = |expr: &str| parse(expr).to_compact_string();
+        assert_eq!(compact("2 * x * (y + 1)"), "2x*(y+1)");
+        assert_eq!(compact("sin(x) + 1 / 2"), "sin x+1/2");
+        assert_eq!(compact("x^(1/2) - 3"), "x^(1/2)-3");
+        assert_eq!(compact("max(1, 2)"), "max(1,2)");
+    }
+
+    #[test]
+    fn it_groups_digits() {
+        let with_grouping = |grouping| FormatOptions {
+            grouping: Some(grouping),
+            ..FormatOptions::default()
+        };
+        let num = Node::Num {
+            val: BigRational::new(12_345_678.into(), 7.into()),
+            input_base: None,
+        };
+        let options = with_grouping(DigitGrouping::English);
+        assert_eq!(num.format(&options), "12,345,678/7");
+
+        let text = "-1234567.891 + 1234.5e-12345i";
+        assert_eq!(
+            group_digits(text, DigitGrouping::English),
+            "-1,234,567.891 + 1,234.5e-12345i"
+        );
+        assert_eq!(
+            group_digits(text, DigitGrouping::French),
+            "-1 234 567,891 + 1 234,5e-12345i"
+        );
+        assert_eq!(
+            group_digits("1234567.89", DigitGrouping::Indian),
+            "12,34,567.89"
+        );
+        assert_eq!(group_digits("123", DigitGrouping::Indian), "123");
+    }
+
+    #[test]
+    fn it_writes_the_words_of_the_locale() {
+        let options = FormatOptions {
+            locale: Locale {
+                approx: "~",
+                list_separator: "; ",
+                per: "par",
+                conversion: "en",
+                plus_minus: "+/-",
+                infinity: "∞",
+                or: "ou",
+                no_solution: "aucune solution",
+                any_value: "toute valeur",
+            },
+            ..FormatOptions::default()
+        };
+        assert_eq!(parse("max(1, x)").format(&options), "max(1; x)");
+        assert_eq!(parse("60 km per h").format(&options), "60 km par h");
+        assert_eq!(parse("5 km in mi").format(&options), "5 km en mi");
+        assert_eq!(
+            parse("1/3").calculate().unwrap().format(&options),
+            "1/3 ~ 0.3333333333333333"
+        );
+        let eval = EvalSuccess {
+            val: f64::NEG_INFINITY,
+            imag: 0.0,
+            display_base: None,
+            unit: None,
+        };
+        assert_eq!(eval.format(&options), "-∞");
+        assert_eq!(Measurement::new(2.0, 0.5).format(&options), "2 +/- 0.5");
+
+        let solutions = parse("x^2").solve(&parse("1"), "x").unwrap();
+        assert_eq!(solutions.format(&options), "-1 ou 1");
+        let solutions = parse("x^2").solve(&parse("-1"), "x").unwrap();
+        assert_eq!(solutions.format(&options), "aucune solution");
+        let solutions = parse("x").solve(&parse("x"), "x").unwrap();
+        assert_eq!(solutions.format(&options), "toute valeur");
+
+        // the default locale is English
+        assert_eq!(Measurement::new(2.0, 0.5).to_string(), "2 ± 0.5");
+    }
 }
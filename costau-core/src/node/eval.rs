@@ -1,54 +1,329 @@
 use float_cmp::{ApproxEq, F64Margin};
-use num_traits::Float;
+use num_bigint::BigUint;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{Float, FromPrimitive, One, Zero};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+#[cfg(feature = "with-serde")]
+use serde::{Deserialize, Serialize};
 use std::f64::consts::{E, PI};
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::ops::*;
+use std::sync::Arc;
 
+use super::complex::eval_complex;
+use super::display::{format_decimal, group_digits};
+use super::rates::RateProvider;
+use super::units::{has_units, resolve};
 use super::util::{fold_nodes, get_op_result_base};
-use super::{ConstKind, Node};
+use super::{CompoundUnit, ConstKind, FormatOptions, Node, Precision};
+use crate::builtins::clock::Clock;
 use crate::ratio2flt::ratio_to_f64;
 
 /// A struct that holds the result of a calculation.
 #[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct EvalSuccess {
-    /// The result value
+    /// The result value, or its real part if it is a complex number
     pub val: f64,
 
+    /// The imaginary part of the result value, which is zero for real numbers
+    pub imag: f64,
+
     /// The base the result value should be displayed in
     pub display_base: Option<u32>,
+
+    /// The unit of the result value, if it is a quantity
+    pub unit: Option<CompoundUnit>,
+}
+
+/// The options of the algorithms that are not deterministic.
+#[derive(Default, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct EvalOptions {
+    /// The seed of the random number generator, so that Monte Carlo
+    /// simulations and other randomized algorithms give the same results
+    /// every time. A random seed is used if it is `None`.
+    pub seed: Option<u64>,
+    /// Whether the compiled expressions compute the polynomials in one
+    /// variable with the compensated Horner scheme, which is about twice as
+    /// precise near their roots but slower.
+    pub compensated: bool,
+    /// The number of bytes that the exact numbers and the expansions of a
+    /// calculation can take, so that the hosts with little memory are not
+    /// brought down by an expression such as `7^7^7`. There is no limit if
+    /// it is `None`.
+    pub memory_budget: Option<usize>,
+    /// The exchange rates that convert the currencies, such as in
+    /// `25 EUR in USD`. The currencies cannot be converted if it is `None`.
+    /// It is shared with the threads of an `Evaluator` and is not saved.
+    #[cfg_attr(feature = "with-serde", serde(skip))]
+    pub rates: Option<Arc<dyn RateProvider + Send + Sync>>,
+    /// The clock that gives the times of `now()` and `time_in`, which are
+    /// read when the calculation is done. The times cannot be calculated if
+    /// it is `None`. It is shared like the rates and is not saved either.
+    #[cfg_attr(feature = "with-serde", serde(skip))]
+    pub clock: Option<Arc<dyn Clock + Send + Sync>>,
+}
+
+/// Whether two shared objects are the same object, whatever the vtables of
+/// the pointers.
+fn same_object<T: ?Sized>(a: &Option<Arc<T>>, b: &Option<Arc<T>>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => &**a as *const T as *const u8 == &**b as *const T as *const u8,
+        (a, b) => a.is_none() && b.is_none(),
+    }
+}
+
+impl fmt::Debug for EvalOptions {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("EvalOptions")
+            .field("seed", &self.seed)
+            .field("compensated", &self.compensated)
+            .field("memory_budget", &self.memory_budget)
+            .field("rates", &self.rates.as_ref().map(|_| ".."))
+            .field("clock", &self.clock.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+impl PartialEq for EvalOptions {
+    fn eq(&self, other: &EvalOptions) -> bool {
+        self.seed == other.seed
+            && self.compensated == other.compensated
+            && self.memory_budget == other.memory_budget
+            && same_object(&self.rates, &other.rates)
+            && same_object(&self.clock, &other.clock)
+    }
+}
+
+impl EvalOptions {
+    /// Creates the random number generator of the options.
+    pub fn rng(&self) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        }
+    }
+}
+
+/// The value of a result in the bases that programmers use.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MultiBase {
+    pub dec: String,
+    pub hex: String,
+    pub bin: String,
+    pub oct: String,
+}
+
+impl EvalSuccess {
+    /// Formats the value in bases 10, 16, 2 and 8 at once, whatever the base
+    /// it should be displayed in.
+    pub fn to_multi_base(&self) -> MultiBase {
+        let in_base = |base| {
+            EvalSuccess {
+                display_base: Some(base),
+                ..*self
+            }
+            .to_string()
+        };
+        MultiBase {
+            dec: in_base(10),
+            hex: in_base(16),
+            bin: in_base(2),
+            oct: in_base(8),
+        }
+    }
+
+    /// Formats a part of the value with the precision of the options, which
+    /// is only supported in base 10, or as decimal places in bases 2, 8 and
+    /// 16.
+    fn format_part(&self, val: f64, options: &FormatOptions) -> String {
+        let part = EvalSuccess {
+            val,
+            imag: 0.0,
+            display_base: self.display_base,
+            unit: None,
+        };
+        match (self.display_base.unwrap_or(10), options.precision) {
+            _ if val.is_infinite() => {
+                let sign = if val < 0.0 { "-" } else { "" };
+                format!("{}{}", sign, options.locale.infinity)
+            }
+            (10, _) if val.is_finite() => format_decimal(val, options),
+            (2, Some(Precision::Decimals(n)))
+            | (8, Some(Precision::Decimals(n)))
+            | (16, Some(Precision::Decimals(n))) => format!("{:.*}", n as usize, part),
+            _ => part.to_string(),
+        }
+    }
+
+    /// Formats the value with the precision, the rounding and the digit
+    /// grouping of the options.
+    pub fn format(&self, options: &FormatOptions) -> String {
+        let text = if self.imag == 0.0 {
+            self.format_part(self.val, options)
+        } else if self.val == 0.0 {
+            self.format_part(self.imag, options) + "i"
+        } else {
+            let sign = if self.imag < 0.0 { " - " } else { " + " };
+            self.format_part(self.val, options)
+                + sign
+                + &self.format_part(self.imag.abs(), options)
+                + "i"
+        };
+        let text = match options.grouping {
+            Some(grouping) if self.display_base.unwrap_or(10) == 10 => {
+                group_digits(&text, grouping)
+            }
+            _ => text,
+        };
+        match self.unit {
+            Some(unit) => format!("{} {}", text, unit.format(options.locale.per)),
+            None => text,
+        }
+    }
 }
 
 /// A description of the error of a calculation.
 #[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub enum EvalError {
+    /// A division by zero, which is a power of zero of exponent `-1`
+    DivisionByZero,
     ZeroToPowerOfNonPositive,
     ComplexRoot,
     Tan90Or270,
-    UnknownConst,
+    UnboundVar,
+    NotAScalar,
+    OutOfDomain,
+    LogOfNonPositive,
+    InvalidLogBase,
+    /// A complex number was given to a function that only accepts real numbers
+    NotReal,
+    /// The value cannot be computed without an approximation
+    NotExact,
+    /// The value is too big to be represented
+    Overflow,
+    /// The value is not a number, such as the result of `inf - inf`
+    Invalid,
+    /// Quantities of different dimensions were combined, such as meters
+    /// added to seconds
+    IncompatibleUnits,
+    /// The exchange rate between two currencies is not known
+    UnknownRate,
+    /// The calculation has a time, such as `now()`, but the options have no
+    /// clock
+    NoClock,
+    /// The calculation would take more memory than the budget of the
+    /// `EvalOptions`
+    OverBudget,
+}
+
+/// A part of a calculation whose value is not a finite number although the
+/// values of its children are, which is where the calculation went wrong.
+#[derive(Debug, PartialEq, Clone)]
+pub struct NonFinite {
+    pub node: Node,
+    /// `EvalError::Overflow` or `EvalError::Invalid`
+    pub error: EvalError,
 }
 
 /// Approximates the node value.
 pub fn eval(node: &Node) -> Result<EvalSuccess, EvalError> {
+    eval_with_options(node, &EvalOptions::default())
+}
+
+/// Like `eval`, but converts the currencies with the rates of the options.
+pub fn eval_with_options(node: &Node, options: &EvalOptions) -> Result<EvalSuccess, EvalError> {
+    if has_units(node) {
+        let (plain, unit) = resolve(node, options)?;
+        return Ok(EvalSuccess {
+            unit,
+            ..eval(&plain)?
+        });
+    }
+    let result = eval_any(node)?;
+    match non_finite_error(&result) {
+        Some(err) => Err(err),
+        None => Ok(result),
+    }
+}
+
+fn non_finite_error(result: &EvalSuccess) -> Option<EvalError> {
+    if result.val.is_nan() || result.imag.is_nan() {
+        Some(EvalError::Invalid)
+    } else if result.val.is_infinite() || result.imag.is_infinite() {
+        Some(EvalError::Overflow)
+    } else {
+        None
+    }
+}
+
+/// Finds the first part of the calculation whose value is not a finite
+/// number although the values of its children are, if there is one.
+pub fn find_non_finite(node: &Node) -> Option<NonFinite> {
+    if let Some(cause) = node.children().into_iter().find_map(find_non_finite) {
+        return Some(cause);
+    }
+    let error = non_finite_error(&eval_any(node).ok()?)?;
+    Some(NonFinite {
+        node: node.clone(),
+        error,
+    })
+}
+
+fn eval_any(node: &Node) -> Result<EvalSuccess, EvalError> {
+    match eval_real(node) {
+        // the value might still be a complex number
+        Err(EvalError::ComplexRoot) | Err(EvalError::NotReal) => {
+            let z = eval_complex(node)?;
+            Ok(EvalSuccess {
+                val: z.re,
+                imag: z.im,
+                display_base: None,
+                unit: None,
+            })
+        }
+        r => r,
+    }
+}
+
+/// Approximates the node value if it is a real number.
+pub(crate) fn eval_real(node: &Node) -> Result<EvalSuccess, EvalError> {
     Ok(match node {
+        Node::Const(ConstKind::I) => return Err(EvalError::NotReal),
         Node::Const(kind) => EvalSuccess {
             val: match kind {
                 ConstKind::Pi => PI,
                 ConstKind::Tau => PI * 2.0,
                 ConstKind::E => E,
+                ConstKind::I => unreachable!(),
             },
+            imag: 0.0,
             display_base: None,
+            unit: None,
         },
-        Node::UnknownConst(_) => return Err(EvalError::UnknownConst),
+        Node::Var(_) => return Err(EvalError::UnboundVar),
         Node::Num { val, input_base } => EvalSuccess {
             val: ratio_to_f64(&val),
+            imag: 0.0,
             display_base: *input_base,
+            unit: None,
         },
+        Node::Vector(_) | Node::Ratio(_) | Node::Dice { .. } => return Err(EvalError::NotAScalar),
+        // the times are resolved with the units
+        Node::Time { .. } => return Err(EvalError::NoClock),
+        Node::Quantity(..) => eval(node)?,
         Node::Sum(children) => fold_nodes(children.iter(), 0.0, Add::add)?,
         Node::Product(children) => fold_nodes(children.iter(), 1.0, Mul::mul)?,
-        Node::Exp(a, b) => {
-            let a = eval(a)?;
-            let b = eval(b)?;
+        Node::Min(children) => fold_nodes(children.iter(), f64::INFINITY, f64::min)?,
+        Node::Max(children) => fold_nodes(children.iter(), f64::NEG_INFINITY, f64::max)?,
+        Node::Exp(a, expon) => {
+            let a = eval_real(a)?;
+            let b = eval_real(expon)?;
             let a_is_zero = a.val.approx_eq(
                 0.0,
                 F64Margin {
@@ -71,52 +346,135 @@ pub fn eval(node: &Node) -> Result<EvalSuccess, EvalError> {
                 },
             );
             let b_is_minus_one = b.val.approx_eq(
-                0.0,
+                -1.0,
                 F64Margin {
                     epsilon: 0.0,
                     ulps: 2,
                 },
             );
+            if a_is_zero && b_is_minus_one {
+                return Err(EvalError::DivisionByZero);
+            }
             if a_is_zero && (b_is_zero || b.val < 0.0) {
                 return Err(EvalError::ZeroToPowerOfNonPositive);
             }
             if b_is_zero {
                 return Ok(EvalSuccess {
                     val: 1.0,
+                    imag: 0.0,
                     display_base: None,
+                    unit: None,
                 });
             } else if b_is_one {
                 return Ok(EvalSuccess {
                     val: a.val,
+                    imag: 0.0,
                     display_base: None,
+                    unit: None,
                 });
             } else if b_is_minus_one {
                 return Ok(EvalSuccess {
                     val: 1.0 / a.val,
+                    imag: 0.0,
                     display_base: a.display_base,
+                    unit: None,
                 });
             }
-            let result = a.val.powf(b.val);
+            let result = match expon.eval_exact() {
+                // the real root of a negative number, such as `(-8)^(1/3)`,
+                // which the other evaluators and `simplify` also give
+                Ok(expon) if a.val < 0.0 && expon.denom().is_odd() => {
+                    let abs = (-a.val).powf(b.val);
+                    if expon.numer().is_odd() {
+                        -abs
+                    } else {
+                        abs
+                    }
+                }
+                _ => a.val.powf(b.val),
+            };
             if result.is_nan() {
                 return Err(EvalError::ComplexRoot);
             }
             EvalSuccess {
                 val: result,
+                imag: 0.0,
                 display_base: get_op_result_base(a.display_base, b.display_base),
+                unit: None,
             }
         }
-        Node::Sin(inner) => eval_map(inner, f64::sin, false)?,
-        Node::Cos(inner) => eval_map(inner, f64::cos, false)?,
-        Node::Tan(inner) => {
-            let original = eval(inner)?;
-            if original.val.is_infinite() {
-                // don't even try anymore
-                return Ok(EvalSuccess {
-                    val: 0.0,
-                    display_base: None,
-                });
+        Node::Sin(inner) => eval_map(inner, f64::sin, true)?,
+        Node::Cos(inner) => eval_map(inner, f64::cos, true)?,
+        Node::Asin(inner) => eval_map(inner, |x| clamp_to_unit(x).asin(), false)?,
+        Node::Acos(inner) => eval_map(inner, |x| clamp_to_unit(x).acos(), false)?,
+        Node::Atan(inner) => eval_map(inner, f64::atan, false)?,
+        Node::Atan2(y, x) => {
+            let y = eval_finite(y)?;
+            let x = eval_finite(x)?;
+            if y.val == 0.0 && x.val == 0.0 {
+                return Err(EvalError::OutOfDomain);
             }
-            assert!(!original.val.is_nan());
+            EvalSuccess {
+                val: y.val.atan2(x.val),
+                imag: 0.0,
+                display_base: None,
+                unit: None,
+            }
+        }
+        Node::Sinh(inner) => eval_map(inner, f64::sinh, false)?,
+        Node::Cosh(inner) => eval_map(inner, f64::cosh, false)?,
+        Node::Tanh(inner) => eval_map(inner, f64::tanh, false)?,
+        Node::Abs(inner) => eval_exact(inner, f64::abs, true)?,
+        Node::Floor(inner) => eval_rounding(inner, f64::floor, BigRational::floor)?,
+        Node::Ceil(inner) => eval_rounding(inner, f64::ceil, BigRational::ceil)?,
+        Node::Round(inner) => eval_rounding(inner, f64::round, BigRational::round)?,
+        Node::Sign(inner) => eval_exact(
+            inner,
+            |x| {
+                // `f64::signum` returns 1 for 0
+                if x == 0.0 {
+                    0.0
+                } else {
+                    x.signum()
+                }
+            },
+            false,
+        )?,
+        Node::Ln(inner) => {
+            let original = eval_finite(inner)?;
+            if original.val <= 0.0 {
+                return Err(EvalError::LogOfNonPositive);
+            }
+            EvalSuccess {
+                val: original.val.ln(),
+                imag: 0.0,
+                display_base: None,
+                unit: None,
+            }
+        }
+        Node::Log(base_node, inner) => {
+            let base = eval_finite(base_node)?;
+            let original = eval_finite(inner)?;
+            // the logarithm of the base is zero only for a base of exactly 1
+            let base_is_one = match base_node.eval_exact() {
+                Ok(exact) => exact.is_one(),
+                Err(_) => base.val.ln() == 0.0,
+            };
+            if base.val <= 0.0 || base_is_one {
+                return Err(EvalError::InvalidLogBase);
+            }
+            if original.val <= 0.0 {
+                return Err(EvalError::LogOfNonPositive);
+            }
+            EvalSuccess {
+                val: original.val.ln() / base.val.ln(),
+                imag: 0.0,
+                display_base: None,
+                unit: None,
+            }
+        }
+        Node::Tan(inner) => {
+            let original = eval_finite(inner)?;
             // check if angle is k(2pi) + pi/2 or k(2pi) + 3*pi/2
             let n = (original.val / (PI * 2.0)).floor();
             let impossible1 = n * (PI * 2.0) + PI / 2.0;
@@ -143,7 +501,9 @@ pub fn eval(node: &Node) -> Result<EvalSuccess, EvalError> {
             }
             EvalSuccess {
                 val: result,
+                imag: 0.0,
                 display_base: None,
+                unit: None,
             }
         }
     })
@@ -152,32 +512,93 @@ pub fn eval(node: &Node) -> Result<EvalSuccess, EvalError> {
 fn eval_map<F: Fn(f64) -> f64>(
     node: &Node,
     f: F,
-    keep_base: bool,
+    periodic: bool,
 ) -> Result<EvalSuccess, EvalError> {
-    let original = eval(node)?;
-    if original.val.is_infinite() {
-        // don't even try anymore
-        return Ok(EvalSuccess {
-            val: 0.0,
-            display_base: None,
-        });
+    let original = eval_finite(node)?;
+    let mut result = f(original.val);
+    if result.is_nan() {
+        return Err(EvalError::OutOfDomain);
     }
-    assert!(!original.val.is_nan());
     // to prevent surprises when doing `sin(pi)*99999999`
-    let mut result = f(original.val);
-    if is_approx_zero(result) {
+    if periodic && is_approx_zero(result) {
         result = 0.0;
     }
     Ok(EvalSuccess {
         val: result,
+        imag: 0.0,
+        display_base: None,
+        unit: None,
+    })
+}
+
+/// Allows values that are just a tiny bit out of the domain of `asin` and
+/// `acos` because of rounding errors.
+fn clamp_to_unit(x: f64) -> f64 {
+    if x.abs() > 1.0 && is_approx_zero(x.abs() - 1.0) {
+        x.signum()
+    } else {
+        x
+    }
+}
+
+/// Applies a function whose result is exact for any float, so unlike
+/// `eval_map`, small results are kept as they are.
+fn eval_exact<F: Fn(f64) -> f64>(
+    node: &Node,
+    f: F,
+    keep_base: bool,
+) -> Result<EvalSuccess, EvalError> {
+    let original = eval_finite(node)?;
+    Ok(EvalSuccess {
+        val: f(original.val),
+        imag: 0.0,
         display_base: if keep_base {
             original.display_base
         } else {
             None
         },
+        unit: None,
+    })
+}
+
+/// Applies a function that rounds to an integer, on the exact value of the
+/// node if it has one. Otherwise, an approximation that cannot be told apart
+/// from an integer because of the rounding errors, such as `sqrt(3)^2`, is
+/// taken as the integer so that it does not round to its neighbor.
+fn eval_rounding<F: Fn(f64) -> f64>(
+    node: &Node,
+    f: F,
+    f_exact: fn(&BigRational) -> BigRational,
+) -> Result<EvalSuccess, EvalError> {
+    let original = eval_finite(node)?;
+    let val = match node.eval_exact() {
+        Ok(exact) => ratio_to_f64(&f_exact(&exact)),
+        Err(_) => {
+            let nearest = original.val.round();
+            match node.eval_interval() {
+                Ok(bounds) if bounds.contains(nearest) => nearest,
+                _ => f(original.val),
+            }
+        }
+    };
+    Ok(EvalSuccess {
+        val,
+        imag: 0.0,
+        display_base: original.display_base,
+        unit: None,
     })
 }
 
+/// Approximates the argument of a function, which must be a finite number
+/// because the function cannot give a meaningful result otherwise.
+fn eval_finite(node: &Node) -> Result<EvalSuccess, EvalError> {
+    let original = eval_real(node)?;
+    match non_finite_error(&original) {
+        Some(err) => Err(err),
+        None => Ok(original),
+    }
+}
+
 fn is_approx_zero(a: f64) -> bool {
     a.approx_eq(
         0.0,
@@ -190,78 +611,127 @@ fn is_approx_zero(a: f64) -> bool {
     )
 }
 
+/// The number of significant bits of a float result that are shown when it is
+/// displayed in a base that is a power of two. A `f64` has 53 bits, but the
+/// last ones are usually wrong because of rounding errors in the calculation,
+/// so showing them would make the result look more precise than it is.
+const TRUSTED_BITS: u32 = 48;
+
+/// Formats a float in base 2, 8 or 16 and returns whether it is nonnegative
+/// along with its digits. If some nonzero digits are not trustworthy, they are
+/// cut and an ellipsis is added.
+fn format_in_power_of_two_base(val: f64, base: u32, precision: Option<usize>) -> (bool, String) {
+    let bits_per_digit = match base {
+        2 => 1,
+        8 => 3,
+        16 => 4,
+        _ => unreachable!(),
+    };
+    let (mut mantissa, mut exp, sign) = val.integer_decode();
+    let is_nonnegative = sign == 1 || mantissa == 0;
+
+    // forget about the bits that are not trustworthy
+    let len = 64 - mantissa.leading_zeros();
+    let mut is_truncated = false;
+    if len > TRUSTED_BITS {
+        let dropped = len - TRUSTED_BITS;
+        is_truncated = mantissa & ((1 << dropped) - 1) != 0;
+        mantissa >>= dropped;
+        exp += dropped as i16;
+    }
+
+    let mantissa = BigUint::from(mantissa);
+    let (int_part, frac_part, frac_bits) = if exp >= 0 {
+        (mantissa << exp as usize, BigUint::zero(), 0)
+    } else {
+        let frac_bits = -exp as usize;
+        let int_part = &mantissa >> frac_bits;
+        let frac_part = mantissa - (&int_part << frac_bits);
+        (int_part, frac_part, frac_bits)
+    };
+
+    let mut result = int_part.to_str_radix(base).to_uppercase();
+    // align the fractional part on the digits of the base
+    let padding_bits = (bits_per_digit - frac_bits % bits_per_digit) % bits_per_digit;
+    let digit_count = (frac_bits + padding_bits) / bits_per_digit;
+    let frac_part = frac_part << padding_bits;
+    let mut frac_digits = if frac_part.is_zero() {
+        String::new()
+    } else {
+        let digits = frac_part.to_str_radix(base).to_uppercase();
+        let mut padded = "0".repeat(digit_count - digits.len());
+        padded += digits.trim_end_matches('0');
+        padded
+    };
+    if let Some(precision) = precision {
+        if frac_digits.len() > precision {
+            is_truncated = true;
+            frac_digits.truncate(precision);
+        }
+        while frac_digits.len() < precision {
+            frac_digits.push('0');
+        }
+    }
+    if !frac_digits.is_empty() {
+        result.push('.');
+        result += &frac_digits;
+    }
+    if is_truncated {
+        result.push('…');
+    }
+    (is_nonnegative, result)
+}
+
 impl Display for EvalSuccess {
     fn fmt(&self, out: &mut Formatter) -> fmt::Result {
-        // TODO: add tests
+        if let Some(unit) = self.unit {
+            EvalSuccess {
+                unit: None,
+                ..*self
+            }
+            .fmt(out)?;
+            return write!(out, " {}", unit);
+        }
+        if self.imag != 0.0 {
+            // show complex numbers as `a + bi`
+            let part = |val: f64| EvalSuccess {
+                val,
+                imag: 0.0,
+                display_base: self.display_base,
+                unit: None,
+            };
+            if self.val != 0.0 {
+                part(self.val).fmt(out)?;
+                out.write_str(if self.imag < 0.0 { " - " } else { " + " })?;
+                part(self.imag.abs()).fmt(out)?;
+            } else {
+                part(self.imag).fmt(out)?;
+            }
+            return out.write_str("i");
+        }
         match self.display_base.unwrap_or(10) {
-            2 => {
-                let (mantissa, exp, sign) = self.val.integer_decode();
-                let is_nonnegative = sign == 1 || mantissa == 0;
-                let mut result = String::new();
-                let mut is_printing = true;
-                let mut consecutive_zeros = 0;
-
-                if exp <= -53 {
-                    result += "0.";
-                    for _ in exp..-53 {
-                        result.push('0');
-                    }
-                }
-                for i in (0..53).rev() {
-                    let bit = mantissa & (1 << i) != 0;
-                    let bit_exp = exp + i;
-                    if bit_exp < 0 {
-                        // check how many digits we must print after the decimal separator
-                        if let Some(precision) = out.precision() {
-                            if (-bit_exp as usize) > precision {
-                                break;
-                            }
-                        }
-                    }
-                    // do not print leading zeros
-                    if bit || bit_exp < 0 {
-                        is_printing = true;
-                    }
-                    if is_printing {
-                        if bit_exp < 0 && !bit && out.precision().is_none() {
-                            // Do not print zeros after the decimal separator
-                            // unless there is a one after.
-                            // Example: 0b1.100000000 -> 0b1.1
-                            consecutive_zeros += 1;
-                        } else {
-                            if consecutive_zeros > 0 {
-                                // we forgot to print the decimal separator
-                                if bit_exp + consecutive_zeros == -1 {
-                                    result.push('.');
-                                }
-                                for _ in 0..consecutive_zeros {
-                                    result.push('0');
-                                }
-                                consecutive_zeros = 0;
-                            }
-                            if i != 52 && bit_exp == -1 {
-                                // we got our first digit after a dot!
-                                result.push('.');
-                            }
-                            result.push(if bit { '0' } else { '1' });
-                        }
-                    }
-                }
-                if let Some(precision) = out.precision() {
-                    if exp < 0 {
-                        let already_printed = -exp as usize;
-
-                        // fill with zeros at the end
-                        for _ in already_printed..precision {
-                            result.push('0');
-                        }
-                    }
-                }
-
-                out.pad_integral(is_nonnegative, "0b", &result)
+            base @ 2 | base @ 8 | base @ 16 if self.val.is_finite() => {
+                let (is_nonnegative, digits) =
+                    format_in_power_of_two_base(self.val, base, out.precision());
+                let prefix = match base {
+                    2 => "0b",
+                    8 => "0o",
+                    _ => "0x",
+                };
+                out.pad_integral(is_nonnegative, prefix, &digits)
+            }
+            base if base != 10 && self.val.is_finite() && self.val.fract() == 0.0 => {
+                let digits = BigUint::from_f64(self.val.abs())
+                    .unwrap()
+                    .to_str_radix(base);
+                out.pad_integral(
+                    self.val >= 0.0,
+                    &format!("{}#", base),
+                    &digits.to_uppercase(),
+                )
             }
             display_base => {
-                if display_base != 10 {
+                if display_base != 10 && self.val.is_finite() {
                     eprintln!("warning: cannot print float in base {} yet", display_base);
                 }
                 let mut buf = ryu::Buffer::new();
@@ -278,6 +748,8 @@ mod tests {
 
     use super::*;
     use crate::node::util::common;
+    use crate::node::{Notation, Rounding};
+    use crate::test_util::parse;
 
     #[test]
     fn it_errors_with_0_to_power_of_non_positive() {
@@ -293,11 +765,334 @@ mod tests {
     }
 
     #[test]
-    fn it_errors_with_sqrt_of_minus_one() {
+    fn it_finds_the_cause_of_an_overflow() {
+        let num = |n: i64| Node::Num {
+            val: BigRational::from_integer(n.into()),
+            input_base: Some(10),
+        };
+        // 1 + 2 * 10^400
+        let power = Node::Exp(Box::new(num(10)), Box::new(num(400)));
+        let input = num(1) + num(2) * power.clone();
+        assert_eq!(
+            find_non_finite(&input),
+            Some(NonFinite {
+                node: power.clone(),
+                error: EvalError::Overflow,
+            })
+        );
+
+        // 10^400 - 10^400
+        let input = Node::Sum(vec![power.clone(), -power.clone()]);
+        assert_eq!(eval(&input), Err(EvalError::Invalid));
+        // the first power already overflows
+        assert_eq!(find_non_finite(&input).unwrap().node, power);
+
+        assert_eq!(find_non_finite(&num(1)), None);
+
+        // sin(sinh(1000)) is not 0 even though sin is bounded
+        let hyperbolic = num(1000).sinh();
+        let input = hyperbolic.clone().sin();
+        assert_eq!(eval(&input), Err(EvalError::Overflow));
+        assert_eq!(find_non_finite(&input).unwrap().node, hyperbolic);
+        assert_eq!(eval(&power.ln().sin()), Err(EvalError::Overflow));
+    }
+
+    #[test]
+    fn it_errors_with_division_by_zero_and_overflow() {
+        // 1/(2-2)
+        let input = common::one() / (common::two() - common::two());
+        assert_eq!(eval(&input), Err(EvalError::DivisionByZero));
+
+        // 1/2 is still a division
+        let input = common::one() / common::two();
+        assert!(eval(&input)
+            .unwrap()
+            .val
+            .approx_eq(0.5, F64Margin::default()));
+
+        // 10^400
+        let input = Node::Exp(
+            Box::new(Node::Num {
+                val: BigRational::from_integer(10.into()),
+                input_base: Some(10),
+            }),
+            Box::new(Node::Num {
+                val: BigRational::from_integer(400.into()),
+                input_base: Some(10),
+            }),
+        );
+        assert_eq!(eval(&input), Err(EvalError::Overflow));
+
+        // a number that is too large for a float
+        assert_eq!(eval(&parse("1e400")), Err(EvalError::Overflow));
+        assert_eq!(eval(&parse("-1e400")), Err(EvalError::Overflow));
+        assert!(eval(&parse("1e300")).unwrap().val.is_finite());
+    }
+
+    #[test]
+    fn it_formats_in_all_bases_at_once() {
+        let result = EvalSuccess {
+            val: 255.0,
+            imag: 0.0,
+            display_base: Some(16),
+            unit: None,
+        };
+        assert_eq!(
+            result.to_multi_base(),
+            MultiBase {
+                dec: "255.0".to_owned(),
+                hex: "FF".to_owned(),
+                bin: "11111111".to_owned(),
+                oct: "377".to_owned(),
+            }
+        );
+
+        let result = EvalSuccess {
+            val: -2.5,
+            imag: 0.0,
+            display_base: None,
+            unit: None,
+        };
+        let multi_base = result.to_multi_base();
+        assert_eq!(multi_base.hex, "-2.8");
+        assert_eq!(multi_base.bin, "-10.1");
+    }
+
+    #[test]
+    fn it_formats_with_a_precision() {
+        let format = |val: f64, precision, rounding, trim_zeros| {
+            let result = EvalSuccess {
+                val,
+                imag: 0.0,
+                display_base: Some(10),
+                unit: None,
+            };
+            result.format(&FormatOptions {
+                precision: Some(precision),
+                rounding,
+                trim_zeros,
+                ..FormatOptions::default()
+            })
+        };
+        let nearest = Rounding::HalfAwayFromZero;
+        assert_eq!(format(PI, Precision::Decimals(4), nearest, false), "3.1416");
+        assert_eq!(format(1.5, Precision::Decimals(3), nearest, false), "1.500");
+        assert_eq!(format(1.5, Precision::Decimals(3), nearest, true), "1.5");
+        assert_eq!(
+            format(-0.001, Precision::Decimals(2), nearest, false),
+            "0.00"
+        );
+        // 0.125 is exact in binary, unlike 0.135
+        assert_eq!(
+            format(0.125, Precision::Decimals(2), nearest, false),
+            "0.13"
+        );
+        let even = Rounding::HalfToEven;
+        assert_eq!(format(0.125, Precision::Decimals(2), even, false), "0.12");
+        let down = Rounding::TowardZero;
+        assert_eq!(format(-2.99, Precision::Decimals(1), down, false), "-2.9");
+
+        assert_eq!(
+            format(PI, Precision::Significant(3), nearest, false),
+            "3.14"
+        );
+        assert_eq!(
+            format(9.996, Precision::Significant(3), nearest, false),
+            "10.0"
+        );
+        assert_eq!(
+            format(123_456.0, Precision::Significant(2), nearest, false),
+            "120000"
+        );
+        assert_eq!(
+            format(0.000_123_4, Precision::Significant(2), nearest, false),
+            "0.00012"
+        );
+        assert_eq!(
+            format(6.02e23, Precision::Significant(4), nearest, false),
+            "6.020e23"
+        );
+        assert_eq!(
+            format(6.02e23, Precision::Significant(4), nearest, true),
+            "6.02e23"
+        );
+        assert_eq!(
+            format(1.5e-9, Precision::Significant(2), nearest, false),
+            "1.5e-9"
+        );
+
+        // without a precision, only the zero of `1.0` can be trimmed
+        let options = FormatOptions {
+            trim_zeros: true,
+            ..FormatOptions::default()
+        };
+        let result = EvalSuccess {
+            val: 2.0,
+            imag: -0.5,
+            display_base: None,
+            unit: None,
+        };
+        assert_eq!(result.format(&options), "2 - 0.5i");
+        let result = EvalSuccess {
+            val: 2.5,
+            imag: 0.0,
+            display_base: Some(16),
+            unit: None,
+        };
+        let options = FormatOptions {
+            precision: Some(Precision::Decimals(3)),
+            ..FormatOptions::default()
+        };
+        assert_eq!(result.format(&options), format!("{:.3}", result));
+    }
+
+    #[test]
+    fn it_formats_in_scientific_notation() {
+        let format = |val: f64, precision| {
+            let result = EvalSuccess {
+                val,
+                imag: 0.0,
+                display_base: None,
+                unit: None,
+            };
+            result.format(&FormatOptions {
+                precision,
+                notation: Notation::Scientific {
+                    min_exponent: -3,
+                    max_exponent: 6,
+                },
+                ..FormatOptions::default()
+            })
+        };
+        assert_eq!(format(1_234_560_000_000.0, None), "1.23456e+12");
+        assert_eq!(format(-0.000_012_5, None), "-1.25e-5");
+        // values within the thresholds are not changed
+        assert_eq!(format(123_456.0, None), "123456.0");
+        assert_eq!(format(0.001, None), "0.001");
+        assert_eq!(format(0.0, None), "0.0");
+
+        assert_eq!(format(1e6, Some(Precision::Decimals(2))), "1.00e+6");
+        assert_eq!(
+            format(PI * 1e9, Some(Precision::Significant(4))),
+            "3.142e+9"
+        );
+        assert_eq!(
+            format(9.9996e-7, Some(Precision::Significant(4))),
+            "1.000e-6"
+        );
+    }
+
+    #[test]
+    fn it_formats_in_engineering_notation() {
+        let format = |val: f64, precision, si_prefixes| {
+            let result = EvalSuccess {
+                val,
+                imag: 0.0,
+                display_base: None,
+                unit: None,
+            };
+            result.format(&FormatOptions {
+                precision,
+                notation: Notation::Engineering { si_prefixes },
+                ..FormatOptions::default()
+            })
+        };
+        assert_eq!(format(12_345.0, None, false), "12.345e+3");
+        assert_eq!(format(0.000_47, None, false), "470e-6");
+        assert_eq!(format(-1.5, None, false), "-1.5e+0");
+        assert_eq!(format(4700.0, None, true), "4.7k");
+        assert_eq!(format(0.000_001_5, None, true), "1.5µ");
+        assert_eq!(format(1e30, None, true), "1e+30");
+
+        let decimals = Some(Precision::Decimals(2));
+        assert_eq!(format(2.2e-11, decimals, true), "22.00p");
+        assert_eq!(format(999.999, decimals, true), "1.00k");
+        let significant = Some(Precision::Significant(2));
+        assert_eq!(format(123_456.0, significant, true), "120k");
+        assert_eq!(format(1.0 / 3.0, significant, false), "330e-3");
+    }
+
+    #[test]
+    fn it_evaluates_sqrt_of_minus_one_as_a_complex_number() {
         // sqrt(-1)
         let input = common::minus_one().sqrt();
-        let result = eval(&input);
-        assert_eq!(result, Err(EvalError::ComplexRoot));
+        let result = eval(&input).unwrap();
+        assert_eq!((result.val, result.imag), (0.0, 1.0));
+        assert_eq!(result.to_string(), "1.0i");
+    }
+
+    #[test]
+    fn it_evaluates_odd_roots_of_negative_numbers_as_real_numbers() {
+        for expr in &["(-8)^(1/3)", "(-8)^(2/3)", "(-32)^(-3/5)", "cbrt(-27)"] {
+            let node = parse(expr);
+            let result = node.eval().unwrap();
+            assert!(result.imag.approx_eq(0.0, F64Margin::default()), "{}", expr);
+            let exact = ratio_to_f64(&node.eval_exact().unwrap());
+            assert!(
+                result.val.approx_eq(exact, F64Margin::default()),
+                "{}",
+                expr
+            );
+            let simplified = node.simplify().unwrap().result.eval().unwrap().val;
+            assert!(
+                result.val.approx_eq(simplified, F64Margin::default()),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            parse("(-8)^(1/3)").simplify().unwrap().result.to_string(),
+            "-2"
+        );
+        // the even roots are still complex numbers
+        assert_eq!(parse("(-4)^(1/2)").eval().unwrap().to_string(), "2.0i");
+    }
+
+    #[test]
+    fn it_takes_the_principal_roots_of_negative_quotients() {
+        let x = parse("7/10");
+        for (expr, imag) in &[
+            ("sqrt(3/(-1))", 1.7320508075688772),
+            ("sqrt(-3)", 1.7320508075688772),
+            ("(5/(-2))^(-1/2)", -0.6324555320336759),
+            ("sqrt(x^-1 / -3)", 0.6900655593423543),
+        ] {
+            let node = parse(expr).substitute("x", &x);
+            let result = node.eval().unwrap();
+            assert!(result.val.approx_eq(0.0, F64Margin::default()), "{}", expr);
+            assert!(
+                result.imag.approx_eq(*imag, F64Margin::default()),
+                "{}",
+                expr
+            );
+            let simplified = node.simplify().unwrap().result.eval().unwrap();
+            assert!(
+                simplified.imag.approx_eq(*imag, F64Margin::default()),
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn it_multiplies_complex_numbers() {
+        // (2+3i)*(1-i) = 5+i
+        let i = || Node::Const(ConstKind::I);
+        let input = (common::two() + common::three() * i()) * (common::one() - i());
+        let result = eval(&input).unwrap();
+        assert_eq!((result.val, result.imag), (5.0, 1.0));
+        assert_eq!(result.to_string(), "5.0 + 1.0i");
+
+        // e^(i*pi) = -1
+        let input = Node::Exp(
+            Box::new(Node::Const(ConstKind::E)),
+            Box::new(i() * Node::Const(ConstKind::Pi)),
+        );
+        let result = eval(&input).unwrap();
+        assert_eq!((result.val, result.imag), (-1.0, 0.0));
+
+        // only some functions accept complex numbers
+        assert_eq!(eval(&i().floor()), Err(EvalError::NotReal));
     }
 
     #[test]
@@ -321,4 +1116,93 @@ mod tests {
         let result = eval(&input);
         assert_eq!(result, Err(EvalError::Tan90Or270));
     }
+
+    #[test]
+    fn it_formats_results_in_power_of_two_bases() {
+        let format = |val: f64, base: u32| {
+            let r = EvalSuccess {
+                val,
+                imag: 0.0,
+                display_base: Some(base),
+                unit: None,
+            };
+            (format!("{:#}", r), format!("{:#.3}", r))
+        };
+        assert_eq!(format(255.0, 16).0, "0xFF");
+        assert_eq!(format(-10.0, 8).0, "-0o12");
+        assert_eq!(format(0.75, 2), ("0b0.11".to_owned(), "0b0.110".to_owned()));
+        assert_eq!(format(2.5, 16).0, "0x2.8");
+        // the last bits of 0.1 are not exact and must not be shown
+        assert_eq!(format(0.1, 16).0, "0x0.1999999999998…");
+        assert_eq!(format(0.1, 16).1, "0x0.199…");
+        assert_eq!(format(2f64.powi(60), 16).0, "0x1000000000000000");
+    }
+
+    // the functions of exact numbers must give the closest floats
+    #[allow(clippy::float_cmp)]
+    #[test]
+    fn it_evaluates_exact_functions() {
+        let small = Node::Num {
+            val: BigRational::new(5.into(), 10_000_000.into()),
+            input_base: Some(10),
+        };
+        assert_eq!(eval(&small.clone().abs()).unwrap().val, 5e-7);
+        assert_eq!(eval(&(-small.clone()).abs()).unwrap().val, 5e-7);
+        assert_eq!(eval(&(-small).sign()).unwrap().val, -1.0);
+        // only the inverses of sin and cos accept arguments a bit out of
+        // their domain
+        let near_one = Node::Num {
+            val: BigRational::new(10_000_005.into(), 10_000_000.into()),
+            input_base: Some(10),
+        };
+        assert_eq!(eval(&(-near_one.clone()).abs()).unwrap().val, 1.0000005);
+        assert_eq!(eval(&near_one.asin()).unwrap().val, PI / 2.0);
+
+        // 10^400
+        let power = Node::Exp(
+            Box::new(Node::Num {
+                val: BigRational::from_integer(10.into()),
+                input_base: Some(10),
+            }),
+            Box::new(Node::Num {
+                val: BigRational::from_integer(400.into()),
+                input_base: Some(10),
+            }),
+        );
+        assert_eq!(eval(&power.clone().abs()), Err(EvalError::Overflow));
+        assert_eq!(eval(&power.floor()), Err(EvalError::Overflow));
+    }
+
+    // the periodic functions must give exactly zero
+    #[allow(clippy::float_cmp)]
+    #[test]
+    fn it_only_rounds_periodic_functions_to_zero() {
+        let small = Node::Num {
+            val: BigRational::new(5.into(), 10_000_000.into()),
+            input_base: Some(10),
+        };
+        assert!(eval(&small.clone().atan()).unwrap().val > 0.0);
+        assert!(eval(&small.sinh()).unwrap().val > 0.0);
+        // sin(pi)
+        let input = Node::Const(ConstKind::Pi).sin();
+        assert_eq!(eval(&input).unwrap().val, 0.0);
+    }
+
+    #[test]
+    fn it_errors_with_invalid_logarithms() {
+        // ln(0)
+        let input = common::zero().ln();
+        assert_eq!(eval(&input), Err(EvalError::LogOfNonPositive));
+
+        // log(1, 2)
+        let input = common::two().log(common::one());
+        assert_eq!(eval(&input), Err(EvalError::InvalidLogBase));
+
+        // log(2, 8) = 3
+        let input = (common::two() * common::two() * common::two()).log(common::two());
+        assert!(eval(&input)
+            .unwrap()
+            .val
+            .approx_eq(3.0, F64Margin::default()));
+    }
 }
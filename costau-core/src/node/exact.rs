@@ -0,0 +1,177 @@
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Pow, Signed, ToPrimitive, Zero};
+
+use super::budget;
+use super::eval::{eval_real, EvalError, EvalOptions};
+use super::util::ratio_pow;
+use super::{ConstKind, Node, SimplifySuccess};
+
+/// The maximum number of bits of the result of a power, to prevent extremely
+/// big numbers.
+const MAX_POW_BITS: u64 = 1 << 16;
+
+/// Computes the value of the node without any approximation. It fails with
+/// `EvalError::NotExact` if the value is not a rational number, and with
+/// `EvalError::OverBudget` if it does not fit in the memory budget of the
+/// options.
+pub fn eval_exact(node: &Node, options: &EvalOptions) -> Result<BigRational, EvalError> {
+    let eval_exact = |node| eval_exact(node, options);
+    let budget = options.memory_budget;
+    match node {
+        Node::Num { val, .. } => Ok(val.clone()),
+        Node::Sum(children) => {
+            let mut acc: BigRational = Zero::zero();
+            for child in children {
+                let child = eval_exact(child)?;
+                if !budget::fits_sum_or_product(&acc, &child, budget) {
+                    return Err(EvalError::OverBudget);
+                }
+                acc += child;
+            }
+            Ok(acc)
+        }
+        Node::Product(children) => {
+            let mut acc: BigRational = One::one();
+            for child in children {
+                let child = eval_exact(child)?;
+                if !budget::fits_sum_or_product(&acc, &child, budget) {
+                    return Err(EvalError::OverBudget);
+                }
+                acc *= child;
+            }
+            Ok(acc)
+        }
+        Node::Exp(a, b) => exact_pow(&eval_exact(a)?, &eval_exact(b)?, budget),
+        Node::Abs(inner) => Ok(eval_exact(inner)?.abs()),
+        Node::Floor(inner) => Ok(eval_exact(inner)?.floor()),
+        Node::Ceil(inner) => Ok(eval_exact(inner)?.ceil()),
+        Node::Round(inner) => Ok(eval_exact(inner)?.round()),
+        Node::Sign(inner) => Ok(eval_exact(inner)?.signum()),
+        Node::Min(children) => children
+            .iter()
+            .map(eval_exact)
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .min()
+            .ok_or(EvalError::NotAScalar),
+        Node::Max(children) => children
+            .iter()
+            .map(eval_exact)
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .max()
+            .ok_or(EvalError::NotAScalar),
+        Node::Const(ConstKind::I) => Err(EvalError::NotReal),
+        Node::Const(_) => Err(EvalError::NotExact),
+        Node::Var(_) => Err(EvalError::UnboundVar),
+        Node::Vector(_) | Node::Ratio(_) | Node::Dice { .. } => Err(EvalError::NotAScalar),
+        // functions such as `sin(pi/6)` can have rational values, which
+        // `simplify` knows about, but it does not simplify the values from
+        // its tables
+        node => match node
+            .clone()
+            .simplify_with_options(options)
+            .and_then(|x| x.result.simplify_with_options(options))
+        {
+            Ok(SimplifySuccess {
+                result: Node::Num { val, .. },
+                ..
+            }) => Ok(val),
+            // report the errors of the domain of the functions before the
+            // lack of exactness
+            _ => match eval_real(node) {
+                Err(err) => Err(err),
+                Ok(_) => Err(EvalError::NotExact),
+            },
+        },
+    }
+}
+
+fn exact_pow(
+    a: &BigRational,
+    b: &BigRational,
+    budget: Option<usize>,
+) -> Result<BigRational, EvalError> {
+    if a.is_zero() {
+        return if b.is_positive() {
+            Ok(Zero::zero())
+        } else if *b == -BigRational::one() {
+            Err(EvalError::DivisionByZero)
+        } else {
+            Err(EvalError::ZeroToPowerOfNonPositive)
+        };
+    }
+    if a.is_negative() && b.denom().is_even() {
+        return Err(EvalError::ComplexRoot);
+    }
+    let a_bits = a.numer().bits() + a.denom().bits();
+    let expon = b.numer().to_i32().ok_or(EvalError::NotExact)?;
+    let bits = a_bits as f64 * f64::from(expon).abs();
+    if bits > MAX_POW_BITS as f64 {
+        return Err(EvalError::NotExact);
+    }
+    if !budget::fits_bits(bits, budget) {
+        return Err(EvalError::OverBudget);
+    }
+    let a = ratio_pow(a, expon);
+    if b.denom().is_one() {
+        return Ok(a);
+    }
+
+    // a^(p/q) is rational if the numerator and denominator of a^p are perfect
+    // q-th powers
+    let root = b.denom().to_u32().ok_or(EvalError::NotExact)?;
+    let numer = a.numer().nth_root(root);
+    let denom = a.denom().nth_root(root);
+    if numer.clone().pow(root) == *a.numer() && denom.clone().pow(root) == *a.denom() {
+        Ok(BigRational::new(numer, denom))
+    } else {
+        Err(EvalError::NotExact)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::node::EvalError;
+    use crate::test_util::parse;
+    use num_rational::BigRational;
+
+    fn eval_exact_str(expr: &str) -> Result<BigRational, EvalError> {
+        parse(expr).eval_exact()
+    }
+
+    fn ratio(numer: i64, denom: i64) -> Result<BigRational, EvalError> {
+        Ok(BigRational::new(numer.into(), denom.into()))
+    }
+
+    #[test]
+    fn it_computes_rational_results_exactly() {
+        assert_eq!(eval_exact_str("1/3 + 1/6"), ratio(1, 2));
+        assert_eq!(eval_exact_str("0.1 + 0.2"), ratio(3, 10));
+        assert_eq!(eval_exact_str("2^-3 * max(1, 4)"), ratio(1, 2));
+        assert_eq!(eval_exact_str("sqrt(9/4) + (-8)^(1/3)"), ratio(-1, 2));
+        assert_eq!(eval_exact_str("sin(pi/6)"), ratio(1, 2));
+    }
+
+    #[test]
+    fn it_refuses_irrational_results() {
+        assert_eq!(eval_exact_str("sqrt(2)"), Err(EvalError::NotExact));
+        assert_eq!(eval_exact_str("pi - 3"), Err(EvalError::NotExact));
+        assert_eq!(eval_exact_str("sin(1) + 1"), Err(EvalError::NotExact));
+        assert_eq!(eval_exact_str("ln(2)"), Err(EvalError::NotExact));
+    }
+
+    #[test]
+    fn it_reports_domain_errors_before_inexact_results() {
+        assert_eq!(eval_exact_str("(-4)^(1/2)"), Err(EvalError::ComplexRoot));
+        assert_eq!(eval_exact_str("1/(2 - 2)"), Err(EvalError::DivisionByZero));
+        assert_eq!(
+            eval_exact_str("0^-2"),
+            Err(EvalError::ZeroToPowerOfNonPositive)
+        );
+        assert_eq!(eval_exact_str("x + 1"), Err(EvalError::UnboundVar));
+        assert_eq!(eval_exact_str("ln(-2)"), Err(EvalError::LogOfNonPositive));
+        assert_eq!(eval_exact_str("(1, 2)"), Err(EvalError::NotAScalar));
+    }
+}
@@ -0,0 +1,126 @@
+use num_traits::{Signed, ToPrimitive};
+
+use super::budget;
+use super::{EvalOptions, Node, SimplifyError};
+
+/// The highest power of a sum that is expanded, because the number of terms
+/// grows quickly.
+const MAX_EXPAND_POWER: u32 = 64;
+
+fn into_terms(node: Node) -> Vec<Node> {
+    match node {
+        Node::Sum(terms) => terms,
+        node => vec![node],
+    }
+}
+
+fn into_factors(node: Node) -> Vec<Node> {
+    match node {
+        Node::Product(factors) => factors,
+        node => vec![node],
+    }
+}
+
+/// Multiplies two nodes by distributing every term of one over every term of
+/// the other. Fails if the new terms do not fit in the memory budget.
+fn distribute(a: Node, b: Node, budget: Option<usize>) -> Result<Node, SimplifyError> {
+    let a = into_terms(a);
+    let b = into_terms(b);
+    if !budget::fits_nodes(a.len().saturating_mul(b.len()), budget) {
+        return Err(SimplifyError::OverBudget);
+    }
+    let mut terms: Vec<Node> = a
+        .into_iter()
+        .flat_map(|x| {
+            b.iter().map(move |y| {
+                let mut factors = into_factors(x.clone());
+                factors.extend(into_factors(y.clone()));
+                // the coefficient goes first
+                factors.sort_by_key(|x| !matches!(x, Node::Num { .. }));
+                Node::Product(factors)
+            })
+        })
+        .collect();
+    if terms.len() == 1 {
+        return Ok(terms.pop().unwrap());
+    }
+    Ok(Node::Sum(terms))
+}
+
+/// Distributes the products over the sums and expands the positive integer
+/// powers of sums, such as `(x + 1)^2` becoming `x*x + x*1 + 1*x + 1*1`.
+/// The result should be simplified to collect the terms. The new terms must
+/// fit in the memory budget of the options.
+pub fn expand(node: Node, options: &EvalOptions) -> Result<Node, SimplifyError> {
+    let budget = options.memory_budget;
+    let mut error = None;
+    let node = node.map_children(|x| {
+        expand(x, options).unwrap_or_else(|err| {
+            error = Some(err);
+            Node::Sum(Vec::new())
+        })
+    });
+    if let Some(err) = error {
+        return Err(err);
+    }
+    Ok(match node {
+        Node::Product(factors) => {
+            let mut iter = factors.into_iter();
+            match iter.next() {
+                Some(first) => iter.try_fold(first, |a, b| distribute(a, b, budget))?,
+                None => Node::Product(Vec::new()),
+            }
+        }
+        Node::Exp(base, expon) => {
+            let power = match (&*base, &*expon) {
+                (Node::Sum(_), Node::Num { val, .. }) if val.is_integer() && val.is_positive() => {
+                    val.to_integer().to_u32().filter(|&x| x <= MAX_EXPAND_POWER)
+                }
+                _ => None,
+            };
+            let power = match power {
+                Some(x) => x,
+                None => return Ok(Node::Exp(base, expon)),
+            };
+            let mut result = (*base).clone();
+            for _ in 1..power {
+                result = distribute(result, (*base).clone(), budget)?;
+                // collect the terms as we go so that their number does not
+                // double at each step
+                if let Ok(simplified) = result.clone().simplify_with_options(options) {
+                    result = simplified.result;
+                }
+            }
+            result
+        }
+        node => node,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::parse;
+
+    fn expand_str(expr: &str) -> String {
+        let node = parse(expr);
+        node.expand().unwrap().to_string()
+    }
+
+    #[test]
+    fn it_distributes_products() {
+        assert_eq!(expand_str("2 * (x + 1)"), "2 + 2x");
+        assert_eq!(expand_str("x * (x + 1) - x^2"), "x");
+        assert_eq!(
+            expand_str("(a + b) * (c + d)"),
+            "c * a + d * a + c * b + d * b"
+        );
+    }
+
+    #[test]
+    fn it_expands_powers_of_sums() {
+        assert_eq!(expand_str("(x + 1)^3"), "1 + 3x + x^3 + 3 * x^2");
+        assert_eq!(expand_str("(x - y)^2 - x^2 - y^2"), "-2y * x");
+        // the other powers are kept
+        assert_eq!(expand_str("(x + 1)^(1/2) * 2"), "2 * (1 + x)^(1/2)");
+    }
+}
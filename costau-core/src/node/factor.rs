@@ -0,0 +1,297 @@
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+use super::util::common;
+use super::util::common::num;
+use super::Node;
+
+/// The highest constant term or leading coefficient whose divisors are tried
+/// as rational roots, because they are found by trial division.
+const MAX_ROOT_SEARCH: u64 = 1_000_000_000_000;
+
+/// Multiplies the coefficient and the degree of a term of a polynomial in
+/// `var`, such as `3 * x^2`, into `coef` and `degree`.
+fn collect_term(node: &Node, var: &str, coef: &mut BigRational, degree: &mut usize) -> Option<()> {
+    match node {
+        Node::Num { val, .. } => *coef *= val,
+        Node::Var(name) if name == var => *degree += 1,
+        Node::Exp(base, expon) => match (&**base, &**expon) {
+            (Node::Var(name), Node::Num { val, .. })
+                if name == var && val.is_integer() && val.is_positive() =>
+            {
+                *degree += val.to_integer().to_usize()?
+            }
+            _ => return None,
+        },
+        Node::Product(factors) => {
+            for factor in factors {
+                collect_term(factor, var, coef, degree)?;
+            }
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Returns the coefficients of the polynomial in `var`, from the constant
+/// term to the leading coefficient, if the node is one.
+pub(super) fn get_coefs(node: &Node, var: &str) -> Option<Vec<BigRational>> {
+    let expanded = node.clone().expand().ok()?;
+    let terms = match expanded {
+        Node::Sum(terms) => terms,
+        node => vec![node],
+    };
+    let mut coefs = Vec::new();
+    for term in &terms {
+        let mut coef = BigRational::one();
+        let mut degree = 0;
+        collect_term(term, var, &mut coef, &mut degree)?;
+        if coefs.len() <= degree {
+            coefs.resize(degree + 1, BigRational::zero());
+        }
+        coefs[degree] += coef;
+    }
+    while coefs.last().map(Zero::is_zero) == Some(true) {
+        coefs.pop();
+    }
+    Some(coefs)
+}
+
+fn divisors(n: &BigInt) -> Option<Vec<u64>> {
+    let n = n.abs().to_u64().filter(|&x| x <= MAX_ROOT_SEARCH)?;
+    let mut small = Vec::new();
+    let mut large = Vec::new();
+    let mut d = 1;
+    while d * d <= n {
+        if n % d == 0 {
+            small.push(d);
+            if d * d != n {
+                large.push(n / d);
+            }
+        }
+        d += 1;
+    }
+    large.reverse();
+    small.extend(large);
+    Some(small)
+}
+
+/// Returns the possible rational roots `r/s` of the polynomial with integer
+/// coefficients, where `r` divides the constant term and `s` divides the
+/// leading coefficient by the rational root theorem.
+fn root_candidates(coefs: &[BigInt]) -> Vec<(BigInt, BigInt)> {
+    let mut candidates = Vec::new();
+    if coefs.len() < 2 {
+        return candidates;
+    }
+    let (rs, ss) = match (divisors(&coefs[0]), divisors(&coefs[coefs.len() - 1])) {
+        (Some(rs), Some(ss)) => (rs, ss),
+        _ => return candidates,
+    };
+    for r in &rs {
+        for s in &ss {
+            if r.gcd(s) == 1 {
+                candidates.push((BigInt::from(*r), BigInt::from(*s)));
+                candidates.push((-BigInt::from(*r), BigInt::from(*s)));
+            }
+        }
+    }
+    candidates
+}
+
+/// Divides the polynomial with integer coefficients by `s*x - r` if `r/s` is
+/// a root, and returns the quotient.
+fn divide_by_root(coefs: &[BigInt], r: &BigInt, s: &BigInt) -> Option<Vec<BigInt>> {
+    // synthetic division from the leading coefficient, which stays exact
+    // because the polynomial is primitive
+    let mut quotient = vec![BigInt::zero(); coefs.len() - 1];
+    let mut carry = BigInt::zero();
+    for i in (1..coefs.len()).rev() {
+        let val = &coefs[i] + &carry;
+        if !val.is_multiple_of(s) {
+            return None;
+        }
+        let q = val / s;
+        carry = &q * r;
+        quotient[i - 1] = q;
+    }
+    if (&coefs[0] + carry).is_zero() {
+        Some(quotient)
+    } else {
+        None
+    }
+}
+
+/// Builds the node of the polynomial with the given coefficients.
+fn poly_node(coefs: &[BigRational], var: &str) -> Node {
+    let x = Node::Var(var.to_owned());
+    let mut terms = Vec::new();
+    for (degree, coef) in coefs.iter().enumerate().rev() {
+        if coef.is_zero() {
+            continue;
+        }
+        let power = match degree {
+            0 => {
+                terms.push(num(coef.clone()));
+                continue;
+            }
+            1 => x.clone(),
+            _ => Node::Exp(
+                Box::new(x.clone()),
+                Box::new(num(BigRational::from_integer(degree.into()))),
+            ),
+        };
+        terms.push(if coef.is_one() {
+            power
+        } else {
+            Node::Product(vec![num(coef.clone()), power])
+        });
+    }
+    match terms.len() {
+        1 => terms.pop().unwrap(),
+        _ => Node::Sum(terms),
+    }
+}
+
+/// Builds `s*x - r`.
+fn linear_factor(r: &BigInt, s: &BigInt, var: &str) -> Node {
+    let x = Node::Var(var.to_owned());
+    let sx = if s.is_one() {
+        x
+    } else {
+        Node::Product(vec![num(BigRational::from_integer(s.clone())), x])
+    };
+    let abs_r = num(BigRational::from_integer(r.abs()));
+    if r.is_positive() {
+        Node::Sum(vec![sx, Node::Product(vec![common::minus_one(), abs_r])])
+    } else {
+        Node::Sum(vec![sx, abs_r])
+    }
+}
+
+/// Factors the node if it is a polynomial in a single variable with rational
+/// coefficients.
+pub fn factor(node: &Node) -> Option<Node> {
+    let vars = node.vars();
+    if vars.len() != 1 {
+        return None;
+    }
+    let var = &vars[0];
+    let coefs = get_coefs(node, var)?;
+    if coefs.len() < 2 {
+        return None;
+    }
+
+    // the content makes the coefficients coprime integers with a positive
+    // leading coefficient
+    let lcm = coefs
+        .iter()
+        .fold(BigInt::one(), |acc, x| acc.lcm(x.denom()));
+    let mut ints: Vec<BigInt> = coefs.iter().map(|x| (x * &lcm).to_integer()).collect();
+    let mut gcd = ints.iter().fold(BigInt::zero(), |acc, x| acc.gcd(x));
+    if ints.last().unwrap().is_negative() {
+        gcd = -gcd;
+    }
+    for x in ints.iter_mut() {
+        *x = &*x / &gcd;
+    }
+    let content = BigRational::new(gcd, lcm);
+
+    let mut factors = Vec::new();
+    if !content.is_one() {
+        factors.push(num(content));
+    }
+    let zeros = ints.iter().take_while(|x| x.is_zero()).count();
+    ints.drain(..zeros);
+    let x = Node::Var(var.to_owned());
+    match zeros {
+        0 => {}
+        1 => factors.push(x),
+        n => factors.push(Node::Exp(
+            Box::new(x),
+            Box::new(num(BigRational::from_integer(n.into()))),
+        )),
+    }
+
+    for (r, s) in root_candidates(&ints) {
+        let mut multiplicity = 0u32;
+        while ints.len() > 1 {
+            match divide_by_root(&ints, &r, &s) {
+                Some(quotient) => ints = quotient,
+                None => break,
+            }
+            multiplicity += 1;
+        }
+        if multiplicity > 0 {
+            let factor = linear_factor(&r, &s, var);
+            factors.push(if multiplicity == 1 {
+                factor
+            } else {
+                let multiplicity = BigRational::from_integer(multiplicity.into());
+                Node::Exp(Box::new(factor), Box::new(num(multiplicity)))
+            });
+        }
+    }
+
+    // what is left has no rational root
+    if ints.len() > 1 {
+        let rest: Vec<BigRational> = ints.into_iter().map(BigRational::from_integer).collect();
+        factors.push(poly_node(&rest, var));
+    }
+    Some(match factors.len() {
+        1 => factors.pop().unwrap(),
+        _ => Node::Product(factors),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::parse;
+
+    fn factor_str(expr: &str) -> Option<String> {
+        let node = parse(expr);
+        node.factor().map(|x| x.to_string())
+    }
+
+    #[test]
+    fn it_factors_with_rational_roots() {
+        assert_eq!(
+            factor_str("x^2 - 5x + 6"),
+            Some("(x - 2) * (x - 3)".to_owned())
+        );
+        assert_eq!(
+            factor_str("4 * x^2 - 1"),
+            Some("(2x - 1) * (2x + 1)".to_owned())
+        );
+        assert_eq!(
+            factor_str("x^3 - 3 * x^2 + 3x - 1"),
+            Some("(x - 1)^3".to_owned())
+        );
+    }
+
+    #[test]
+    fn it_extracts_common_factors() {
+        assert_eq!(
+            factor_str("2 * x^3 - 2x"),
+            Some("2x * (x - 1) * (x + 1)".to_owned())
+        );
+        assert_eq!(
+            factor_str("x^2 / 2 - 1/2"),
+            Some("1/2 * (x - 1) * (x + 1)".to_owned())
+        );
+        // irreducible over the rationals
+        assert_eq!(
+            factor_str("(x^2 + 2) * x"),
+            Some("x * (x^2 + 2)".to_owned())
+        );
+    }
+
+    #[test]
+    fn it_only_factors_polynomials() {
+        assert_eq!(factor_str("sin(x) + 1"), None);
+        assert_eq!(factor_str("x^2 + y"), None);
+        assert_eq!(factor_str("x^(1/2) + 1"), None);
+    }
+}
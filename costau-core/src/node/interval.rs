@@ -0,0 +1,466 @@
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Signed};
+use std::f64::consts::{E, PI};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use super::eval::EvalError;
+use super::{ConstKind, Node};
+use crate::ratio2flt::ratio_to_f64;
+
+/// The number of ULPs the bounds are moved outwards after a call to a
+/// function of the standard library, whose results are not always correctly
+/// rounded.
+const LIBM_ULPS: u32 = 2;
+
+/// The number of ULPs the bounds are moved outwards after a power, which can
+/// lose more precision.
+const POW_ULPS: u32 = 8;
+
+/// An interval of real numbers that is guaranteed to contain a value despite
+/// the rounding of floating point numbers.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Interval {
+    /// The lower bound
+    pub lo: f64,
+
+    /// The upper bound
+    pub hi: f64,
+}
+
+impl Interval {
+    pub fn new(lo: f64, hi: f64) -> Interval {
+        Interval { lo, hi }
+    }
+
+    /// Returns an interval that contains only one number.
+    pub fn point(val: f64) -> Interval {
+        Interval { lo: val, hi: val }
+    }
+
+    /// Returns the interval of all the real numbers.
+    pub fn entire() -> Interval {
+        Interval {
+            lo: f64::NEG_INFINITY,
+            hi: f64::INFINITY,
+        }
+    }
+
+    pub fn width(&self) -> f64 {
+        self.hi - self.lo
+    }
+
+    /// Returns the number in the middle of the interval.
+    pub fn mid(&self) -> f64 {
+        if self.lo.is_infinite() || self.hi.is_infinite() {
+            return self.lo + self.hi;
+        }
+        self.lo / 2.0 + self.hi / 2.0
+    }
+
+    /// Returns whether `val` is the only value in the interval. The bounds are
+    /// compared exactly because an interval that is only close to `val` also
+    /// contains other values.
+    #[allow(clippy::float_cmp)]
+    pub fn is_point(&self, val: f64) -> bool {
+        self.lo == val && self.hi == val
+    }
+
+    pub fn contains(&self, val: f64) -> bool {
+        self.lo <= val && val <= self.hi
+    }
+
+    /// Returns the number of significant digits that are the same for all the
+    /// values of the interval, at most 17. If this is less than the number of
+    /// digits displayed for an approximation, then the last ones are not
+    /// reliable.
+    pub fn reliable_digits(&self) -> u32 {
+        if self.width() == 0.0 {
+            return 17;
+        }
+        if !self.width().is_finite() || self.contains(0.0) {
+            return 0;
+        }
+        let relative = self.width() / self.mid().abs();
+        let digits = (-relative.log10()).floor();
+        if digits < 0.0 {
+            0
+        } else if digits > 17.0 {
+            17
+        } else {
+            digits as u32
+        }
+    }
+
+    /// Moves the bounds outwards by the given number of ULPs.
+    fn widen(self, ulps: u32) -> Interval {
+        let mut result = self;
+        for _ in 0..ulps {
+            result.lo = next_down(result.lo);
+            result.hi = next_up(result.hi);
+        }
+        result
+    }
+
+    /// Returns the interval of the values of a function that is increasing.
+    fn map_increasing<F: Fn(f64) -> f64>(self, f: F) -> Interval {
+        Interval::new(f(self.lo), f(self.hi)).widen(LIBM_ULPS)
+    }
+
+    /// Returns the smallest interval that contains all of the numbers.
+    fn hull(values: &[f64]) -> Interval {
+        let lo = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let hi = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        Interval::new(lo, hi)
+    }
+
+    fn add(self, other: Interval) -> Interval {
+        Interval::new(self.lo + other.lo, self.hi + other.hi).widen(1)
+    }
+
+    fn mul(self, other: Interval) -> Interval {
+        Interval::hull(&[
+            mul_bounds(self.lo, other.lo),
+            mul_bounds(self.lo, other.hi),
+            mul_bounds(self.hi, other.lo),
+            mul_bounds(self.hi, other.hi),
+        ])
+        .widen(1)
+    }
+
+    fn div(self, other: Interval) -> Result<Interval, EvalError> {
+        if other.lo == 0.0 && other.hi == 0.0 {
+            return Err(EvalError::DivisionByZero);
+        }
+        if other.contains(0.0) {
+            return Ok(Interval::entire());
+        }
+        Ok(Interval::hull(&[
+            self.lo / other.lo,
+            self.lo / other.hi,
+            self.hi / other.lo,
+            self.hi / other.hi,
+        ])
+        .widen(1))
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, out: &mut Formatter) -> fmt::Result {
+        write!(out, "[{}, {}]", self.lo, self.hi)
+    }
+}
+
+fn next_up(x: f64) -> f64 {
+    if x.is_nan() || x == f64::INFINITY {
+        return x;
+    }
+    if x == 0.0 {
+        return f64::from_bits(1);
+    }
+    let bits = x.to_bits();
+    f64::from_bits(if x > 0.0 { bits + 1 } else { bits - 1 })
+}
+
+fn next_down(x: f64) -> f64 {
+    -next_up(-x)
+}
+
+/// Multiplies two bounds, with `0 * inf = 0` because the zero bound is
+/// exact.
+fn mul_bounds(a: f64, b: f64) -> f64 {
+    if a == 0.0 || b == 0.0 {
+        0.0
+    } else {
+        a * b
+    }
+}
+
+/// Returns whether the interval contains an angle of the form
+/// `offset + 2k*pi`. This is conservative, so it can return true for an
+/// angle that is only very close.
+fn contains_angle(x: Interval, offset: f64) -> bool {
+    let period = PI * 2.0;
+    let margin = 1e-9 * (1.0 + x.lo.abs().max(x.hi.abs()));
+    let k = ((x.lo - offset) / period).floor();
+    (0..3).any(|i| {
+        let angle = offset + (k + f64::from(i)) * period;
+        x.lo - margin <= angle && angle <= x.hi + margin
+    })
+}
+
+/// Evaluates the node while keeping track of the rounding errors.
+pub fn eval_interval(node: &Node) -> Result<Interval, EvalError> {
+    Ok(match node {
+        Node::Const(kind) => match kind {
+            ConstKind::Pi => Interval::point(PI).widen(1),
+            ConstKind::Tau => Interval::point(PI * 2.0).widen(1),
+            ConstKind::E => Interval::point(E).widen(1),
+            ConstKind::I => return Err(EvalError::NotReal),
+        },
+        Node::Var(_) => return Err(EvalError::UnboundVar),
+        Node::Num { val, .. } => {
+            let approx = ratio_to_f64(val);
+            match BigRational::from_float(approx) {
+                Some(ref exact) if exact == val => Interval::point(approx),
+                _ => Interval::point(approx).widen(2),
+            }
+        }
+        Node::Vector(_) | Node::Ratio(_) | Node::Dice { .. } => return Err(EvalError::NotAScalar),
+        Node::Time { .. } => return Err(EvalError::NoClock),
+        Node::Quantity(inner, _) => eval_interval(inner)?,
+        Node::Sum(children) => {
+            let mut acc = Interval::point(0.0);
+            for child in children {
+                acc = acc.add(eval_interval(child)?);
+            }
+            acc
+        }
+        Node::Product(children) => {
+            let mut acc = Interval::point(1.0);
+            for child in children {
+                acc = acc.mul(eval_interval(child)?);
+            }
+            acc
+        }
+        Node::Min(children) | Node::Max(children) => {
+            let values = children
+                .iter()
+                .map(eval_interval)
+                .collect::<Result<Vec<_>, _>>()?;
+            let (lo, hi): (Vec<f64>, Vec<f64>) = values.iter().map(|x| (x.lo, x.hi)).unzip();
+            let bounds = if let Node::Min(_) = node {
+                (Interval::hull(&lo).lo, Interval::hull(&hi).lo)
+            } else {
+                (Interval::hull(&lo).hi, Interval::hull(&hi).hi)
+            };
+            Interval::new(bounds.0, bounds.1)
+        }
+        Node::Exp(a, b) => eval_pow(a, b)?,
+        Node::Sin(inner) | Node::Cos(inner) => {
+            let x = eval_interval(inner)?;
+            let (f, max_at): (fn(f64) -> f64, f64) = match node {
+                Node::Sin(_) => (f64::sin, PI / 2.0),
+                _ => (f64::cos, 0.0),
+            };
+            if x.width() >= PI * 2.0 {
+                return Ok(Interval::new(-1.0, 1.0));
+            }
+            let mut result = Interval::hull(&[f(x.lo), f(x.hi)]).widen(LIBM_ULPS);
+            if contains_angle(x, max_at) {
+                result.hi = 1.0;
+            }
+            if contains_angle(x, max_at + PI) {
+                result.lo = -1.0;
+            }
+            Interval::new(result.lo.max(-1.0), result.hi.min(1.0))
+        }
+        Node::Tan(inner) => {
+            let x = eval_interval(inner)?;
+            if x.width() >= PI || contains_angle(x, PI / 2.0) || contains_angle(x, PI * 3.0 / 2.0) {
+                return Err(EvalError::Tan90Or270);
+            }
+            x.map_increasing(f64::tan)
+        }
+        Node::Asin(inner) | Node::Acos(inner) => {
+            let x = eval_interval(inner)?;
+            if x.hi < -1.0 || x.lo > 1.0 {
+                return Err(EvalError::OutOfDomain);
+            }
+            // the part outside of the domain comes from rounding errors
+            let x = Interval::new(x.lo.max(-1.0), x.hi.min(1.0));
+            match node {
+                Node::Asin(_) => x.map_increasing(f64::asin),
+                _ => Interval::new(x.hi.acos(), x.lo.acos()).widen(LIBM_ULPS),
+            }
+        }
+        Node::Atan(inner) => eval_interval(inner)?.map_increasing(f64::atan),
+        Node::Atan2(y, x) => {
+            let y = eval_interval(y)?;
+            let x = eval_interval(x)?;
+            if y.lo == 0.0 && y.hi == 0.0 && x.lo == 0.0 && x.hi == 0.0 {
+                return Err(EvalError::OutOfDomain);
+            }
+            // the angle jumps from pi to -pi on the negative side of the x
+            // axis
+            if (x.contains(0.0) && y.contains(0.0)) || (x.lo < 0.0 && y.contains(0.0)) {
+                Interval::new(-PI, PI).widen(1)
+            } else {
+                Interval::hull(&[
+                    y.lo.atan2(x.lo),
+                    y.lo.atan2(x.hi),
+                    y.hi.atan2(x.lo),
+                    y.hi.atan2(x.hi),
+                ])
+                .widen(LIBM_ULPS)
+            }
+        }
+        Node::Sinh(inner) => eval_interval(inner)?.map_increasing(f64::sinh),
+        Node::Tanh(inner) => eval_interval(inner)?.map_increasing(f64::tanh),
+        Node::Cosh(inner) => {
+            let x = eval_interval(inner)?;
+            let result = Interval::hull(&[x.lo.cosh(), x.hi.cosh()]).widen(LIBM_ULPS);
+            if x.contains(0.0) {
+                Interval::new(1.0, result.hi)
+            } else {
+                Interval::new(result.lo.max(1.0), result.hi)
+            }
+        }
+        Node::Abs(inner) => {
+            let x = eval_interval(inner)?;
+            if x.contains(0.0) {
+                Interval::new(0.0, x.lo.abs().max(x.hi.abs()))
+            } else {
+                Interval::hull(&[x.lo.abs(), x.hi.abs()])
+            }
+        }
+        // these functions are exact
+        Node::Floor(inner) => {
+            let x = eval_interval(inner)?;
+            Interval::new(x.lo.floor(), x.hi.floor())
+        }
+        Node::Ceil(inner) => {
+            let x = eval_interval(inner)?;
+            Interval::new(x.lo.ceil(), x.hi.ceil())
+        }
+        Node::Round(inner) => {
+            let x = eval_interval(inner)?;
+            Interval::new(x.lo.round(), x.hi.round())
+        }
+        Node::Sign(inner) => {
+            let x = eval_interval(inner)?;
+            let sign = |x: f64| if x == 0.0 { 0.0 } else { x.signum() };
+            Interval::new(sign(x.lo), sign(x.hi))
+        }
+        Node::Ln(inner) => eval_ln(eval_interval(inner)?)?,
+        Node::Log(base, inner) => {
+            let base = eval_interval(base)?;
+            if base.hi <= 0.0 || base.is_point(1.0) {
+                return Err(EvalError::InvalidLogBase);
+            }
+            let x = eval_ln(eval_interval(inner)?)?;
+            x.div(eval_ln(base)?)?
+        }
+    })
+}
+
+fn eval_ln(x: Interval) -> Result<Interval, EvalError> {
+    if x.hi <= 0.0 {
+        return Err(EvalError::LogOfNonPositive);
+    }
+    let lo = if x.lo <= 0.0 {
+        f64::NEG_INFINITY
+    } else {
+        x.lo.ln()
+    };
+    Ok(Interval::new(lo, x.hi.ln()).widen(LIBM_ULPS))
+}
+
+fn eval_pow(base: &Node, exponent: &Node) -> Result<Interval, EvalError> {
+    let x = eval_interval(base)?;
+
+    // rational exponents with an odd denominator such as in `x^-1` or `x^(1/3)`
+    // also work with negative numbers
+    if let Ok(expon) = exponent.eval_exact() {
+        if expon.denom().is_odd() {
+            let even_numer = expon.numer().is_even();
+            let y = ratio_to_f64(&expon);
+            let pow = |v: f64| {
+                let abs = v.abs().powf(y);
+                if v < 0.0 && !even_numer {
+                    -abs
+                } else {
+                    abs
+                }
+            };
+            if x.contains(0.0) && !expon.is_positive() {
+                if x.lo == 0.0 && x.hi == 0.0 {
+                    return Err(if expon == -BigRational::one() {
+                        EvalError::DivisionByZero
+                    } else {
+                        EvalError::ZeroToPowerOfNonPositive
+                    });
+                }
+                return Ok(Interval::entire());
+            }
+            let mut values = vec![pow(x.lo), pow(x.hi)];
+            if x.contains(0.0) {
+                values.push(0.0);
+            }
+            return Ok(Interval::hull(&values).widen(POW_ULPS));
+        }
+    }
+
+    let y = eval_interval(exponent)?;
+    if x.lo < 0.0 {
+        return Err(EvalError::ComplexRoot);
+    }
+    if x.lo == 0.0 && y.lo <= 0.0 {
+        if x.hi == 0.0 {
+            return Err(EvalError::ZeroToPowerOfNonPositive);
+        }
+        return Ok(Interval::new(0.0, f64::INFINITY));
+    }
+    // x^y is monotonic in each argument for positive x, so the bounds are at
+    // the corners
+    Ok(Interval::hull(&[
+        x.lo.powf(y.lo),
+        x.lo.powf(y.hi),
+        x.hi.powf(y.lo),
+        x.hi.powf(y.hi),
+    ])
+    .widen(POW_ULPS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    fn eval_interval_str(expr: &str) -> Result<Interval, EvalError> {
+        parse(expr).eval_interval()
+    }
+
+    #[test]
+    fn it_encloses_the_exact_result() {
+        let x = eval_interval_str("0.1 + 0.2").unwrap();
+        assert!(x.lo < 0.3 && 0.3 < x.hi);
+        assert!(x.reliable_digits() >= 15);
+
+        let x = eval_interval_str("sin(pi)").unwrap();
+        assert!(x.contains(0.0));
+        assert!(x.width() < 1e-15);
+
+        let x = eval_interval_str("cos(1)^2 + sin(1)^2").unwrap();
+        assert!(x.contains(1.0));
+
+        let x = eval_interval_str("(-8)^(1/3) * 2^-1").unwrap();
+        assert!(x.contains(-1.0));
+        let x = eval_interval_str("sin(1/2) * 0").unwrap();
+        assert!(x.contains(0.0) && x.width() < 1e-300);
+    }
+
+    #[test]
+    fn it_flags_unreliable_digits() {
+        let x = eval_interval_str("10^16 + 1 - 10^16").unwrap();
+        assert!(x.contains(1.0));
+        assert_eq!(x.reliable_digits(), 0);
+        assert_eq!(eval_interval_str("3").unwrap().reliable_digits(), 17);
+    }
+
+    #[test]
+    fn it_reports_errors_for_the_whole_interval() {
+        assert_eq!(eval_interval_str("tan(pi/2)"), Err(EvalError::Tan90Or270));
+        assert_eq!(eval_interval_str("sqrt(-2)"), Err(EvalError::ComplexRoot));
+        assert_eq!(eval_interval_str("0^-1"), Err(EvalError::DivisionByZero));
+        assert_eq!(
+            eval_interval_str("0^(-1/3)"),
+            Err(EvalError::ZeroToPowerOfNonPositive)
+        );
+        assert_eq!(
+            eval_interval_str("ln(-1)"),
+            Err(EvalError::LogOfNonPositive)
+        );
+    }
+}
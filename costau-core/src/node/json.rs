@@ -0,0 +1,288 @@
+//! A versioned JSON format for expressions, so that other tools can build
+//! expressions for the library to simplify and evaluate.
+//!
+//! A document is `{"version": 1, "expr": <expr>}` where an expression is an
+//! object with an `op` field:
+//!
+//! - `{"op": "num", "value": "3/4"}`: a rational number, written as a string
+//!   to stay exact, or a JSON integer. An optional `base` field is the base
+//!   it was written in.
+//! - `{"op": "var", "name": "x"}`
+//! - `{"op": "const", "name": "pi"}`: `pi`, `tau`, `e` or `i`
+//! - `{"op": "dice", "count": 2, "sides": 6}`
+//! - `{"op": "time", "offset": 32400}`: the time of the clock shifted by
+//!   the offset of a time zone in seconds, such as `time_in("UTC+9")`
+//! - `{"op": "add", "args": [...]}` and the same for `mul`, `vector`,
+//!   `ratio`, `min` and `max`
+//! - `{"op": "pow", "args": [base, expon]}`, `atan2` with `[y, x]` and `log`
+//!   with `[base, x]`
+//! - `{"op": "sin", "args": [x]}` and the same for the other functions of
+//!   one argument
+//! - `{"op": "quantity", "unit": "km", "args": [x]}`: `x` in a unit, with
+//!   the symbols of `Unit::from_symbol`
+//!
+//! Documents of a newer version are rejected, and the fields that are not
+//! listed here are ignored.
+
+use num_rational::BigRational;
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+use super::sexpr::{get_unary, UNARY};
+use super::{ConstKind, Node, Unit};
+
+/// The version of the format that is written, and the newest one that can be
+/// read.
+pub const JSON_VERSION: u64 = 1;
+
+/// A description of why a JSON document cannot be read as an expression.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum JsonError {
+    /// The text is not valid JSON
+    Syntax(String),
+    /// The document was written by a newer version of the format
+    UnsupportedVersion(u64),
+    /// The `op` field has an unknown value
+    UnknownOp(String),
+    /// The operation got the wrong number of arguments
+    WrongArity(String),
+    /// A field is missing or has the wrong type
+    Invalid(String),
+}
+
+fn write_args(op: &str, args: &[&Node]) -> Value {
+    let args: Vec<Value> = args.iter().map(|x| write_expr(x)).collect();
+    json!({ "op": op, "args": args })
+}
+
+fn write_list(op: &str, nodes: &[Node]) -> Value {
+    write_args(op, &nodes.iter().collect::<Vec<_>>())
+}
+
+fn write_expr(node: &Node) -> Value {
+    if let Some((name, inner)) = get_unary(node) {
+        return write_args(name, &[inner]);
+    }
+    match node {
+        Node::Const(kind) => {
+            let name = match kind {
+                ConstKind::Pi => "pi",
+                ConstKind::Tau => "tau",
+                ConstKind::E => "e",
+                ConstKind::I => "i",
+            };
+            json!({ "op": "const", "name": name })
+        }
+        Node::Var(name) => json!({ "op": "var", "name": name }),
+        Node::Num { val, input_base } => match input_base {
+            Some(base) => json!({ "op": "num", "value": val.to_string(), "base": base }),
+            None => json!({ "op": "num", "value": val.to_string() }),
+        },
+        Node::Sum(children) => write_list("add", children),
+        Node::Product(children) => write_list("mul", children),
+        Node::Exp(a, b) => write_args("pow", &[a, b]),
+        Node::Vector(items) => write_list("vector", items),
+        Node::Ratio(terms) => write_list("ratio", terms),
+        Node::Dice { count, sides } => json!({ "op": "dice", "count": count, "sides": sides }),
+        Node::Time { offset } => json!({ "op": "time", "offset": offset }),
+        Node::Atan2(y, x) => write_args("atan2", &[y, x]),
+        Node::Min(children) => write_list("min", children),
+        Node::Max(children) => write_list("max", children),
+        Node::Log(base, inner) => write_args("log", &[base, inner]),
+        Node::Quantity(inner, unit) => {
+            json!({ "op": "quantity", "unit": unit.symbol(), "args": [write_expr(inner)] })
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Writes the node as a JSON document of the current version.
+pub fn to_json(node: &Node) -> String {
+    json!({ "version": JSON_VERSION, "expr": write_expr(node) }).to_string()
+}
+
+fn field<'a>(expr: &'a Value, name: &str) -> Result<&'a Value, JsonError> {
+    expr.get(name)
+        .ok_or_else(|| JsonError::Invalid(format!("missing field `{}`", name)))
+}
+
+fn str_field<'a>(expr: &'a Value, name: &str) -> Result<&'a str, JsonError> {
+    field(expr, name)?
+        .as_str()
+        .ok_or_else(|| JsonError::Invalid(format!("`{}` is not a string", name)))
+}
+
+fn u32_field(expr: &Value, name: &str) -> Result<u32, JsonError> {
+    field(expr, name)?
+        .as_u64()
+        .and_then(|x| {
+            if x <= u64::from(u32::MAX) {
+                Some(x as u32)
+            } else {
+                None
+            }
+        })
+        .ok_or_else(|| JsonError::Invalid(format!("`{}` is not a natural number", name)))
+}
+
+fn read_num(expr: &Value) -> Result<Node, JsonError> {
+    let value = field(expr, "value")?;
+    let invalid = || JsonError::Invalid(format!("`{}` is not a rational number", value));
+    let val = match value {
+        Value::String(s) => BigRational::from_str(s).map_err(|_| invalid())?,
+        Value::Number(n) => match n.as_i64() {
+            Some(n) => BigRational::from_integer(n.into()),
+            None => return Err(invalid()),
+        },
+        _ => return Err(invalid()),
+    };
+    let input_base = match expr.get("base") {
+        Some(_) => Some(u32_field(expr, "base")?),
+        None => None,
+    };
+    Ok(Node::Num { val, input_base })
+}
+
+fn read_expr(expr: &Value) -> Result<Node, JsonError> {
+    let op = str_field(expr, "op")?;
+    match op {
+        "num" => return read_num(expr),
+        "var" => return Ok(Node::Var(str_field(expr, "name")?.to_owned())),
+        "const" => {
+            return match str_field(expr, "name")? {
+                "pi" => Ok(Node::Const(ConstKind::Pi)),
+                "tau" => Ok(Node::Const(ConstKind::Tau)),
+                "e" => Ok(Node::Const(ConstKind::E)),
+                "i" => Ok(Node::Const(ConstKind::I)),
+                name => Err(JsonError::Invalid(format!("unknown constant `{}`", name))),
+            }
+        }
+        "dice" => {
+            return Ok(Node::Dice {
+                count: u32_field(expr, "count")?,
+                sides: u32_field(expr, "sides")?,
+            })
+        }
+        "time" => {
+            let offset = field(expr, "offset")?
+                .as_i64()
+                .ok_or_else(|| JsonError::Invalid("`offset` is not an integer".to_owned()))?;
+            return Ok(Node::Time { offset });
+        }
+        _ => {}
+    }
+
+    let mut args = field(expr, "args")?
+        .as_array()
+        .ok_or_else(|| JsonError::Invalid("`args` is not an array".to_owned()))?
+        .iter()
+        .map(read_expr)
+        .collect::<Result<Vec<Node>, JsonError>>()?;
+    let arity_ok = match op {
+        "add" | "mul" | "vector" | "ratio" | "min" | "max" => true,
+        "pow" | "atan2" | "log" => args.len() == 2,
+        _ => args.len() == 1,
+    };
+    if !arity_ok
+        && (UNARY.iter().any(|x| x.0 == op) || ["pow", "atan2", "log", "quantity"].contains(&op))
+    {
+        return Err(JsonError::WrongArity(op.to_owned()));
+    }
+    let binary = |mut args: Vec<Node>, f: fn(Box<Node>, Box<Node>) -> Node| {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        f(Box::new(a), Box::new(b))
+    };
+    Ok(match op {
+        "add" => Node::Sum(args),
+        "mul" => Node::Product(args),
+        "vector" => Node::Vector(args),
+        "ratio" => Node::Ratio(args),
+        "min" => Node::Min(args),
+        "max" => Node::Max(args),
+        "pow" => binary(args, Node::Exp),
+        "atan2" => binary(args, Node::Atan2),
+        "log" => binary(args, Node::Log),
+        "quantity" => {
+            let symbol = str_field(expr, "unit")?;
+            let unit = Unit::from_symbol(symbol)
+                .ok_or_else(|| JsonError::Invalid(format!("unknown unit `{}`", symbol)))?;
+            Node::Quantity(Box::new(args.pop().unwrap()), unit)
+        }
+        name => match UNARY.iter().find(|x| x.0 == name) {
+            Some((_, f)) => f(Box::new(args.pop().unwrap())),
+            None => return Err(JsonError::UnknownOp(op.to_owned())),
+        },
+    })
+}
+
+/// Reads an expression from a JSON document, after checking that its
+/// version can be read.
+pub fn from_json(text: &str) -> Result<Node, JsonError> {
+    let doc: Value = serde_json::from_str(text).map_err(|e| JsonError::Syntax(e.to_string()))?;
+    let version = field(&doc, "version")?
+        .as_u64()
+        .ok_or_else(|| JsonError::Invalid("`version` is not a natural number".to_owned()))?;
+    if version > JSON_VERSION {
+        return Err(JsonError::UnsupportedVersion(version));
+    }
+    read_expr(field(&doc, "expr")?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    #[test]
+    fn it_writes_and_reads_documents() {
+        assert_eq!(
+            to_json(&Node::Var("x".to_owned()).sin()),
+            r#"{"expr":{"args":[{"name":"x","op":"var"}],"op":"sin"},"version":1}"#
+        );
+        for expr in &[
+            "1 + x^2",
+            "atan2(y, 0xFF) * ln(e)",
+            "max(1, 2)",
+            "3:2",
+            "log(2, 8)",
+            "5 km + 300 m in mi",
+        ] {
+            let node = parse(expr);
+            assert_eq!(from_json(&to_json(&node)), Ok(node));
+        }
+    }
+
+    #[test]
+    fn it_reads_documents_of_other_tools() {
+        let doc = r#"{
+            "version": 1,
+            "generator": "some tool",
+            "expr": {"op": "mul", "args": [
+                {"op": "num", "value": 2},
+                {"op": "num", "value": "1/3"},
+                {"op": "const", "name": "pi"}
+            ]}
+        }"#;
+        let node = from_json(doc).unwrap();
+        assert_eq!(node.simplify().unwrap().result.to_string(), "2/3 * pi");
+    }
+
+    #[test]
+    fn it_rejects_incompatible_documents() {
+        let expr = r#"{"op": "var", "name": "x"}"#;
+        assert_eq!(
+            from_json(&format!(r#"{{"version": 2, "expr": {}}}"#, expr)),
+            Err(JsonError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            from_json(r#"{"version": 1, "expr": {"op": "gamma", "args": []}}"#),
+            Err(JsonError::UnknownOp("gamma".to_owned()))
+        );
+        assert_eq!(
+            from_json(r#"{"version": 1, "expr": {"op": "pow", "args": []}}"#),
+            Err(JsonError::WrongArity("pow".to_owned()))
+        );
+        assert!(matches!(from_json("{"), Err(JsonError::Syntax(_))));
+    }
+}
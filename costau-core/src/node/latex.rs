@@ -0,0 +1,350 @@
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, Zero};
+
+use super::display::{get_matrix_rows, get_node_priority, NodePriority};
+use super::units::has_units;
+use super::util::common::num;
+use super::{ConstKind, FormatOptions, Node};
+use crate::builtins::clock::format_zone;
+
+/// Returns `n` if the exponent is `1/n`, which is written as a root.
+fn get_root_index(expon: &Node) -> Option<BigInt> {
+    match expon {
+        Node::Num { val, .. } if val.numer().is_one() && !val.denom().is_one() => {
+            Some(val.denom().clone())
+        }
+        _ => None,
+    }
+}
+
+/// Whether the node is a power with a negative numeric exponent, which goes
+/// in the denominator of a fraction.
+fn is_inverse(node: &Node) -> bool {
+    matches!(node, Node::Exp(_, b) if matches!(&**b, Node::Num { val, .. } if val.is_negative()))
+}
+
+struct Writer {
+    out: String,
+}
+
+impl Writer {
+    fn push(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    fn write_with_paren(&mut self, node: &Node, curr_prio: NodePriority, left_assoc: bool) {
+        let prio = get_node_priority(node, &FormatOptions::default());
+        let needs_paren = if left_assoc {
+            prio < curr_prio
+        } else {
+            prio <= curr_prio
+        };
+        if needs_paren {
+            self.push("\\left(");
+        }
+        self.write_node(node);
+        if needs_paren {
+            self.push("\\right)");
+        }
+    }
+
+    fn write_list(&mut self, items: &[&Node]) {
+        self.push("\\left(");
+        for (i, item) in items.iter().enumerate() {
+            if i != 0 {
+                self.push(", ");
+            }
+            self.write_node(item);
+        }
+        self.push("\\right)");
+    }
+
+    fn write_func(&mut self, name: &str, inner: &Node) {
+        self.push(name);
+        if get_node_priority(inner, &FormatOptions::default()) < NodePriority::Exp {
+            self.write_list(&[inner]);
+        } else {
+            self.push(" ");
+            self.write_node(inner);
+        }
+    }
+
+    fn write_delimited(&mut self, open: &str, inner: &Node, close: &str) {
+        self.push(open);
+        self.write_node(inner);
+        self.push(close);
+    }
+
+    fn write_number(&mut self, val: &BigRational) {
+        if val.is_negative() {
+            self.push("-");
+        }
+        let val = val.abs();
+        if val.is_integer() {
+            self.push(&val.numer().to_string());
+        } else {
+            self.push(&format!("\\frac{{{}}}{{{}}}", val.numer(), val.denom()));
+        }
+    }
+
+    fn write_var(&mut self, name: &str) {
+        let (base, sub) = match name.find('_') {
+            Some(i) => (&name[..i], Some(&name[i + 1..])),
+            None => (name, None),
+        };
+        if base.chars().count() > 1 {
+            self.push(&format!("\\mathit{{{}}}", base));
+        } else {
+            self.push(base);
+        }
+        if let Some(sub) = sub {
+            self.push(&format!("_{{{}}}", sub));
+        }
+    }
+
+    /// Writes the factors one after the other, with a multiplication dot
+    /// only before numbers where juxtaposition would merge the digits.
+    fn write_factors(&mut self, factors: &[Node]) {
+        for (i, factor) in factors.iter().enumerate() {
+            if i != 0 {
+                let starts_with_digit = match factor {
+                    Node::Num { .. } => true,
+                    Node::Exp(a, b) => {
+                        matches!(&**a, Node::Num { .. }) && get_root_index(b).is_none()
+                    }
+                    _ => false,
+                };
+                if starts_with_digit {
+                    self.push(" \\cdot ");
+                } else if !self.out.ends_with(|c: char| c.is_ascii_digit()) {
+                    self.push(" ");
+                }
+            }
+            if factors.len() == 1 {
+                self.write_node(factor);
+            } else {
+                self.write_with_paren(factor, NodePriority::MulOrDiv, true);
+            }
+        }
+    }
+
+    /// Writes the product as a fraction if some of its factors are
+    /// inverses or rational numbers.
+    fn write_product(&mut self, factors: &[Node]) {
+        let mut coef = BigRational::one();
+        let mut numer = Vec::new();
+        let mut denom = Vec::new();
+        let mut stack: Vec<&Node> = factors.iter().rev().collect();
+        while let Some(factor) = stack.pop() {
+            match factor {
+                // nested products are flattened so that the coefficient
+                // comes first
+                Node::Product(inner) => stack.extend(inner.iter().rev()),
+                Node::Num { val, .. } => coef *= val,
+                Node::Exp(a, b) if is_inverse(factor) => match &**b {
+                    Node::Num { val, .. } if (-val).is_one() => denom.push((**a).clone()),
+                    Node::Num { val, .. } => {
+                        denom.push(Node::Exp(a.clone(), Box::new(num(-val))));
+                    }
+                    _ => unreachable!(),
+                },
+                _ => numer.push(factor.clone()),
+            }
+        }
+        if coef.is_zero() {
+            self.push("0");
+            return;
+        }
+        if coef.is_negative() {
+            self.push("-");
+            coef = -coef;
+        }
+        if !coef.numer().is_one() || numer.is_empty() {
+            numer.insert(0, num(BigRational::from_integer(coef.numer().clone())));
+        }
+        if !coef.denom().is_one() {
+            denom.insert(0, num(BigRational::from_integer(coef.denom().clone())));
+        }
+        if denom.is_empty() {
+            self.write_factors(&numer);
+            return;
+        }
+        self.push("\\frac{");
+        self.write_factors(&numer);
+        self.push("}{");
+        self.write_factors(&denom);
+        self.push("}");
+    }
+
+    fn write_node(&mut self, node: &Node) {
+        match node {
+            Node::Const(kind) => match kind {
+                ConstKind::Pi => self.push("\\pi"),
+                ConstKind::Tau => self.push("\\tau"),
+                ConstKind::E => self.push("e"),
+                ConstKind::I => self.push("i"),
+            },
+            Node::Var(name) => self.write_var(name),
+            Node::Num { val, .. } => self.write_number(val),
+            Node::Sum(children) => {
+                for (i, child) in children.iter().enumerate() {
+                    let mut term = Writer { out: String::new() };
+                    term.write_with_paren(child, NodePriority::AddOrSub, true);
+                    if i == 0 {
+                        self.push(&term.out);
+                    } else if term.out.starts_with('-') {
+                        // subtractions are written with a minus sign like in
+                        // the text output
+                        self.push(" - ");
+                        self.push(&term.out[1..]);
+                    } else {
+                        self.push(" + ");
+                        self.push(&term.out);
+                    }
+                }
+            }
+            Node::Product(children) => self.write_product(children),
+            Node::Exp(..) if is_inverse(node) => self.write_product(std::slice::from_ref(node)),
+            Node::Exp(a, b) => match get_root_index(b) {
+                Some(n) => {
+                    if n == 2.into() {
+                        self.push("\\sqrt{");
+                    } else {
+                        self.push(&format!("\\sqrt[{}]{{", n));
+                    }
+                    self.write_node(a);
+                    self.push("}");
+                }
+                None => {
+                    let is_negative = matches!(&**a, Node::Num { val, .. } if val.is_negative());
+                    if is_negative {
+                        self.write_list(&[a]);
+                    } else {
+                        self.write_with_paren(a, NodePriority::Exp, false);
+                    }
+                    self.write_delimited("^{", b, "}");
+                }
+            },
+            Node::Vector(items) => match get_matrix_rows(node) {
+                Some(rows) => {
+                    self.push("\\begin{pmatrix}");
+                    for (i, row) in rows.iter().enumerate() {
+                        if i != 0 {
+                            self.push(" \\\\ ");
+                        }
+                        for (j, item) in row.iter().enumerate() {
+                            if j != 0 {
+                                self.push(" & ");
+                            }
+                            self.write_node(item);
+                        }
+                    }
+                    self.push("\\end{pmatrix}");
+                }
+                None => self.write_list(&items.iter().collect::<Vec<_>>()),
+            },
+            Node::Ratio(terms) => {
+                for (i, term) in terms.iter().enumerate() {
+                    if i != 0 {
+                        self.push(" : ");
+                    }
+                    self.write_with_paren(term, NodePriority::Ratio, false);
+                }
+            }
+            Node::Dice { count, sides } => {
+                self.push(&format!("{}\\mathrm{{d}}{}", count, sides));
+            }
+            Node::Time { offset: 0 } => self.push("\\operatorname{now}\\left(\\right)"),
+            Node::Time { offset } => self.push(&format!(
+                "\\operatorname{{time\\_in}}\\left(\\text{{\"{}\"}}\\right)",
+                format_zone(*offset)
+            )),
+            Node::Quantity(inner, unit) if has_units(inner) => {
+                self.write_with_paren(inner, NodePriority::Convert, true);
+                self.push(&format!(" \\text{{ in }} \\mathrm{{{}}}", unit));
+            }
+            Node::Quantity(inner, unit) => {
+                self.write_with_paren(inner, NodePriority::Exp, false);
+                self.push(&format!("\\,\\mathrm{{{}}}", unit));
+            }
+            // functions
+            Node::Sin(inner) => self.write_func("\\sin", inner),
+            Node::Cos(inner) => self.write_func("\\cos", inner),
+            Node::Tan(inner) => self.write_func("\\tan", inner),
+            Node::Asin(inner) => self.write_func("\\arcsin", inner),
+            Node::Acos(inner) => self.write_func("\\arccos", inner),
+            Node::Atan(inner) => self.write_func("\\arctan", inner),
+            Node::Atan2(y, x) => {
+                self.push("\\operatorname{atan2}");
+                self.write_list(&[y, x]);
+            }
+            Node::Sinh(inner) => self.write_func("\\sinh", inner),
+            Node::Cosh(inner) => self.write_func("\\cosh", inner),
+            Node::Tanh(inner) => self.write_func("\\tanh", inner),
+            Node::Abs(inner) => self.write_delimited("\\left|", inner, "\\right|"),
+            Node::Floor(inner) => self.write_delimited("\\left\\lfloor ", inner, "\\right\\rfloor"),
+            Node::Ceil(inner) => self.write_delimited("\\left\\lceil ", inner, "\\right\\rceil"),
+            Node::Round(inner) => self.write_func("\\operatorname{round}", inner),
+            Node::Sign(inner) => self.write_func("\\operatorname{sign}", inner),
+            Node::Min(children) => {
+                self.push("\\min");
+                self.write_list(&children.iter().collect::<Vec<_>>());
+            }
+            Node::Max(children) => {
+                self.push("\\max");
+                self.write_list(&children.iter().collect::<Vec<_>>());
+            }
+            Node::Ln(inner) => self.write_func("\\ln", inner),
+            Node::Log(base, inner) => {
+                self.write_delimited("\\log_{", base, "}");
+                self.write_list(&[inner]);
+            }
+        }
+    }
+}
+
+/// Formats the node as a LaTeX math expression, without the surrounding
+/// dollar signs.
+pub fn to_latex(node: &Node) -> String {
+    let mut writer = Writer { out: String::new() };
+    writer.write_node(node);
+    writer.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    fn latex_str(expr: &str) -> String {
+        let node = parse(expr);
+        to_latex(&node.simplify().unwrap().result)
+    }
+
+    #[test]
+    fn it_writes_fractions_and_roots() {
+        assert_eq!(latex_str("1/2"), "\\frac{1}{2}");
+        assert_eq!(latex_str("x/2"), "\\frac{x}{2}");
+        assert_eq!(latex_str("-3/(4y)"), "-\\frac{3}{4y}");
+        assert_eq!(latex_str("sqrt(x)"), "\\sqrt{x}");
+        assert_eq!(latex_str("cbrt(2)"), "\\sqrt[3]{2}");
+        assert_eq!(latex_str("3sqrt(2)"), "3\\sqrt{2}");
+    }
+
+    #[test]
+    fn it_writes_powers_and_parentheses() {
+        assert_eq!(latex_str("x^2 + 1"), "1 + x^{2}");
+        assert_eq!(latex_str("(x + 1)^y"), "\\left(1 + x\\right)^{y}");
+        assert_eq!(latex_str("x^(y + 1)"), "x^{1 + y}");
+        assert_eq!(latex_str("2 pi x - y"), "2\\pi x - y");
+    }
+
+    #[test]
+    fn it_writes_functions() {
+        assert_eq!(latex_str("sin(x)"), "\\sin x");
+        assert_eq!(latex_str("sin(2x)"), "\\sin\\left(2x\\right)");
+        assert_eq!(latex_str("abs(x_a)"), "\\left|x_{a}\\right|");
+        assert_eq!(latex_str("log(2, x)"), "\\log_{2}\\left(x\\right)");
+    }
+}
@@ -0,0 +1,172 @@
+use num_rational::BigRational;
+use num_traits::{One, Signed};
+
+use super::Node;
+
+/// Returns the base and the argument of the node if it is a logarithm, where
+/// the base is `None` for the natural logarithm.
+fn get_log(node: &Node) -> Option<(Option<&Node>, &Node)> {
+    match node {
+        Node::Ln(inner) => Some((None, inner)),
+        Node::Log(base, inner) => Some((Some(base), inner)),
+        _ => None,
+    }
+}
+
+fn make_log(base: Option<&Node>, inner: Node) -> Node {
+    match base {
+        Some(base) => inner.log(base.clone()),
+        None => inner.ln(),
+    }
+}
+
+/// Splits the logarithm of a product into a sum of logarithms and takes the
+/// exponents out of the logarithms.
+fn expand_log(base: Option<&Node>, inner: &Node) -> Node {
+    match inner {
+        // the rule does not hold for negative factors
+        Node::Product(factors)
+            if !factors
+                .iter()
+                .any(|x| matches!(x, Node::Num { val, .. } if val.is_negative())) =>
+        {
+            Node::Sum(factors.iter().map(|x| expand_log(base, x)).collect())
+        }
+        Node::Exp(a, b) => Node::Product(vec![(**b).clone(), expand_log(base, a)]),
+        _ => make_log(base, inner.clone()),
+    }
+}
+
+/// Rewrites `ln(a * b)` as `ln(a) + ln(b)` and `ln(a^b)` as `b * ln(a)`.
+pub fn expand_logs(node: Node) -> Node {
+    let node = node.map_children(expand_logs);
+    match get_log(&node) {
+        Some((base, inner)) => expand_log(base, inner),
+        None => node,
+    }
+}
+
+/// Returns the coefficient, the base and the argument of the term if it is a
+/// logarithm times a number, such as `2 * ln(x)`.
+fn get_log_term(node: &Node) -> Option<(BigRational, Option<&Node>, &Node)> {
+    if let Some((base, inner)) = get_log(node) {
+        return Some((BigRational::one(), base, inner));
+    }
+    match node {
+        Node::Product(factors) => match &factors[..] {
+            [Node::Num { val, .. }, log] | [log, Node::Num { val, .. }] => {
+                let (base, inner) = get_log(log)?;
+                Some((val.clone(), base, inner))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn power(inner: &Node, coef: BigRational) -> Node {
+    if coef.is_one() {
+        inner.clone()
+    } else {
+        Node::Exp(
+            Box::new(inner.clone()),
+            Box::new(Node::Num {
+                val: coef,
+                input_base: None,
+            }),
+        )
+    }
+}
+
+/// Rewrites `ln(a) + ln(b)` as `ln(a * b)` and `b * ln(a)` as `ln(a^b)`,
+/// where `b` is a number.
+pub fn combine_logs(node: Node) -> Node {
+    let node = node.map_children(combine_logs);
+    match &node {
+        Node::Sum(terms) => {
+            // the logarithms with the same base are multiplied together, in
+            // the order of the first term with each base
+            let mut groups: Vec<(Option<&Node>, Vec<Node>)> = Vec::new();
+            let mut rest = Vec::new();
+            for term in terms {
+                let (coef, base, inner) = match get_log_term(term) {
+                    Some(x) => x,
+                    None => {
+                        rest.push(term.clone());
+                        continue;
+                    }
+                };
+                let factor = power(inner, coef);
+                match groups.iter_mut().find(|x| x.0 == base) {
+                    Some(group) => group.1.push(factor),
+                    None => groups.push((base, vec![factor])),
+                }
+            }
+            if groups.iter().all(|x| x.1.len() == 1) {
+                return node.clone();
+            }
+            let mut terms: Vec<Node> = groups
+                .into_iter()
+                .map(|(base, mut factors)| {
+                    let inner = if factors.len() == 1 {
+                        factors.pop().unwrap()
+                    } else {
+                        Node::Product(factors)
+                    };
+                    make_log(base, inner)
+                })
+                .collect();
+            terms.extend(rest);
+            if terms.len() == 1 {
+                terms.pop().unwrap()
+            } else {
+                Node::Sum(terms)
+            }
+        }
+        Node::Product(_) => match get_log_term(&node) {
+            Some((coef, base, inner)) => make_log(base, power(inner, coef)),
+            None => node,
+        },
+        _ => node,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{LogForm, SimplifyOptions};
+    use crate::test_util::parse;
+
+    fn simplify_str(expr: &str, logs: Option<LogForm>) -> String {
+        let node = parse(expr);
+        let options = SimplifyOptions {
+            logs,
+            ..Default::default()
+        };
+        node.simplify_with(&options).unwrap().result.to_string()
+    }
+
+    #[test]
+    fn it_expands_logarithms() {
+        let expand = Some(LogForm::Expand);
+        assert_eq!(simplify_str("ln(a * b^2)", expand), "ln a + 2 * ln b");
+        assert_eq!(
+            simplify_str("log(2, x / y)", expand),
+            "log(2, x) - log(2, y)"
+        );
+        assert_eq!(simplify_str("ln(-2 * a)", expand), "ln(-2a)");
+    }
+
+    #[test]
+    fn it_combines_logarithms() {
+        let combine = Some(LogForm::Combine);
+        assert_eq!(
+            simplify_str("ln(a) + ln(b) - ln(c)", combine),
+            "ln(a * b / c)"
+        );
+        assert_eq!(
+            simplify_str("2 * log(2, x) + 1", combine),
+            "1 + log(2, x^2)"
+        );
+        assert_eq!(simplify_str("ln(a) + ln(b)", None), "ln a + ln b");
+    }
+}
@@ -0,0 +1,404 @@
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, Zero};
+
+use super::display::{get_matrix_rows, get_node_priority, NodePriority};
+use super::units::has_units;
+use super::util::common::num;
+use super::{ConstKind, FormatOptions, Node};
+use crate::builtins::clock::format_zone;
+
+/// The invisible operator between the name of a function and its argument.
+const APPLY: &str = "<mo>&#x2061;</mo>";
+
+/// The invisible operator between factors written next to each other.
+const TIMES: &str = "<mo>&#x2062;</mo>";
+
+const MINUS: &str = "<mo>-</mo>";
+
+/// Returns `n` if the exponent is `1/n`, which is written as a root.
+fn get_root_index(expon: &Node) -> Option<BigInt> {
+    match expon {
+        Node::Num { val, .. } if val.numer().is_one() && !val.denom().is_one() => {
+            Some(val.denom().clone())
+        }
+        _ => None,
+    }
+}
+
+/// Whether the node is a power with a negative numeric exponent, which goes
+/// in the denominator of a fraction.
+fn is_inverse(node: &Node) -> bool {
+    matches!(node, Node::Exp(_, b) if matches!(&**b, Node::Num { val, .. } if val.is_negative()))
+}
+
+/// Whether the node is written as a single element, which does not need to
+/// be grouped in an `mrow` to be the argument of a layout element.
+fn is_element(node: &Node) -> bool {
+    match node {
+        Node::Const(_) => true,
+        Node::Var(name) => !name.contains('_'),
+        Node::Num { val, .. } => val.is_integer() && !val.is_negative(),
+        _ => false,
+    }
+}
+
+struct Writer {
+    out: String,
+}
+
+impl Writer {
+    fn push(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    /// Writes the node as a single element so that it can be the argument
+    /// of `msup`, `mfrac` and the other layout elements.
+    fn write_row(&mut self, node: &Node) {
+        if is_element(node) {
+            self.write_node(node);
+        } else {
+            self.push("<mrow>");
+            self.write_node(node);
+            self.push("</mrow>");
+        }
+    }
+
+    fn write_fenced(&mut self, open: &str, inner: &Node, close: &str) {
+        self.push(&format!("<mrow><mo>{}</mo>", open));
+        self.write_node(inner);
+        self.push(&format!("<mo>{}</mo></mrow>", close));
+    }
+
+    fn write_with_paren(&mut self, node: &Node, curr_prio: NodePriority, left_assoc: bool) {
+        let prio = get_node_priority(node, &FormatOptions::default());
+        let needs_paren = if left_assoc {
+            prio < curr_prio
+        } else {
+            prio <= curr_prio
+        };
+        if needs_paren {
+            self.write_fenced("(", node, ")");
+        } else {
+            self.write_node(node);
+        }
+    }
+
+    fn write_list(&mut self, items: &[&Node]) {
+        self.push("<mrow><mo>(</mo>");
+        for (i, item) in items.iter().enumerate() {
+            if i != 0 {
+                self.push("<mo>,</mo>");
+            }
+            self.write_node(item);
+        }
+        self.push("<mo>)</mo></mrow>");
+    }
+
+    fn write_func(&mut self, name: &str, inner: &Node) {
+        self.push(&format!("<mi>{}</mi>{}", name, APPLY));
+        if get_node_priority(inner, &FormatOptions::default()) < NodePriority::Exp {
+            self.write_list(&[inner]);
+        } else {
+            self.write_node(inner);
+        }
+    }
+
+    fn write_number(&mut self, val: &BigRational) {
+        if val.is_negative() {
+            self.push(MINUS);
+        }
+        let val = val.abs();
+        if val.is_integer() {
+            self.push(&format!("<mn>{}</mn>", val.numer()));
+        } else {
+            self.push(&format!(
+                "<mfrac><mn>{}</mn><mn>{}</mn></mfrac>",
+                val.numer(),
+                val.denom()
+            ));
+        }
+    }
+
+    fn write_var(&mut self, name: &str) {
+        match name.find('_') {
+            Some(i) => self.push(&format!(
+                "<msub><mi>{}</mi><mi>{}</mi></msub>",
+                &name[..i],
+                &name[i + 1..]
+            )),
+            None => self.push(&format!("<mi>{}</mi>", name)),
+        }
+    }
+
+    /// Writes the factors one after the other, with a visible
+    /// multiplication dot only before numbers.
+    fn write_factors(&mut self, factors: &[Node]) {
+        if factors.len() == 1 {
+            return self.write_node(&factors[0]);
+        }
+        for (i, factor) in factors.iter().enumerate() {
+            if i != 0 {
+                let starts_with_digit = match factor {
+                    Node::Num { .. } => true,
+                    Node::Exp(a, b) => {
+                        matches!(&**a, Node::Num { .. }) && get_root_index(b).is_none()
+                    }
+                    _ => false,
+                };
+                self.push(if starts_with_digit {
+                    "<mo>⋅</mo>"
+                } else {
+                    TIMES
+                });
+            }
+            self.write_with_paren(factor, NodePriority::MulOrDiv, true);
+        }
+    }
+
+    /// Writes the product as a fraction if some of its factors are
+    /// inverses or rational numbers.
+    fn write_product(&mut self, factors: &[Node]) {
+        let mut coef = BigRational::one();
+        let mut numer = Vec::new();
+        let mut denom = Vec::new();
+        let mut stack: Vec<&Node> = factors.iter().rev().collect();
+        while let Some(factor) = stack.pop() {
+            match factor {
+                // nested products are flattened so that the coefficient
+                // comes first
+                Node::Product(inner) => stack.extend(inner.iter().rev()),
+                Node::Num { val, .. } => coef *= val,
+                Node::Exp(a, b) if is_inverse(factor) => match &**b {
+                    Node::Num { val, .. } if (-val).is_one() => denom.push((**a).clone()),
+                    Node::Num { val, .. } => {
+                        denom.push(Node::Exp(a.clone(), Box::new(num(-val))));
+                    }
+                    _ => unreachable!(),
+                },
+                _ => numer.push(factor.clone()),
+            }
+        }
+        if coef.is_zero() {
+            self.push("<mn>0</mn>");
+            return;
+        }
+        if coef.is_negative() {
+            self.push(MINUS);
+            coef = -coef;
+        }
+        if !coef.numer().is_one() || numer.is_empty() {
+            numer.insert(0, num(BigRational::from_integer(coef.numer().clone())));
+        }
+        if !coef.denom().is_one() {
+            denom.insert(0, num(BigRational::from_integer(coef.denom().clone())));
+        }
+        if denom.is_empty() {
+            self.write_factors(&numer);
+            return;
+        }
+        self.push("<mfrac><mrow>");
+        self.write_factors(&numer);
+        self.push("</mrow><mrow>");
+        self.write_factors(&denom);
+        self.push("</mrow></mfrac>");
+    }
+
+    fn write_node(&mut self, node: &Node) {
+        match node {
+            Node::Const(kind) => match kind {
+                ConstKind::Pi => self.push("<mi>π</mi>"),
+                ConstKind::Tau => self.push("<mi>τ</mi>"),
+                ConstKind::E => self.push("<mi>e</mi>"),
+                ConstKind::I => self.push("<mi>i</mi>"),
+            },
+            Node::Var(name) => self.write_var(name),
+            Node::Num { val, .. } => self.write_number(val),
+            Node::Sum(children) => {
+                for (i, child) in children.iter().enumerate() {
+                    let mut term = Writer { out: String::new() };
+                    term.write_with_paren(child, NodePriority::AddOrSub, true);
+                    // subtractions are written with a minus sign like in the
+                    // text output
+                    if i != 0 && !term.out.starts_with(MINUS) {
+                        self.push("<mo>+</mo>");
+                    }
+                    self.push(&term.out);
+                }
+            }
+            Node::Product(children) => self.write_product(children),
+            Node::Exp(..) if is_inverse(node) => self.write_product(std::slice::from_ref(node)),
+            Node::Exp(a, b) => match get_root_index(b) {
+                Some(n) if n == 2.into() => {
+                    self.push("<msqrt>");
+                    self.write_node(a);
+                    self.push("</msqrt>");
+                }
+                Some(n) => {
+                    self.push("<mroot>");
+                    self.write_row(a);
+                    self.push(&format!("<mn>{}</mn></mroot>", n));
+                }
+                None => {
+                    self.push("<msup>");
+                    let is_negative = matches!(&**a, Node::Num { val, .. } if val.is_negative());
+                    let needs_paren = is_negative
+                        || get_node_priority(a, &FormatOptions::default()) <= NodePriority::Exp;
+                    if needs_paren {
+                        self.write_fenced("(", a, ")");
+                    } else {
+                        self.write_row(a);
+                    }
+                    self.write_row(b);
+                    self.push("</msup>");
+                }
+            },
+            Node::Vector(items) => match get_matrix_rows(node) {
+                Some(rows) => {
+                    self.push("<mrow><mo>(</mo><mtable>");
+                    for row in rows {
+                        self.push("<mtr>");
+                        for item in row {
+                            self.push("<mtd>");
+                            self.write_node(item);
+                            self.push("</mtd>");
+                        }
+                        self.push("</mtr>");
+                    }
+                    self.push("</mtable><mo>)</mo></mrow>");
+                }
+                None => self.write_list(&items.iter().collect::<Vec<_>>()),
+            },
+            Node::Ratio(terms) => {
+                for (i, term) in terms.iter().enumerate() {
+                    if i != 0 {
+                        self.push("<mo>:</mo>");
+                    }
+                    self.write_with_paren(term, NodePriority::Ratio, false);
+                }
+            }
+            Node::Dice { count, sides } => {
+                self.push(&format!("<mn>{}</mn><mi>d</mi><mn>{}</mn>", count, sides));
+            }
+            Node::Time { offset: 0 } => {
+                self.push(&format!(
+                    "<mi>now</mi>{}<mrow><mo>(</mo><mo>)</mo></mrow>",
+                    APPLY
+                ));
+            }
+            Node::Time { offset } => self.push(&format!(
+                "<mi>time_in</mi>{}<mrow><mo>(</mo><ms>{}</ms><mo>)</mo></mrow>",
+                APPLY,
+                format_zone(*offset)
+            )),
+            Node::Quantity(inner, unit) if has_units(inner) => {
+                self.write_with_paren(inner, NodePriority::Convert, true);
+                self.push(&format!(
+                    "<mtext> in </mtext><mi mathvariant=\"normal\">{}</mi>",
+                    unit
+                ));
+            }
+            Node::Quantity(inner, unit) => {
+                self.write_with_paren(inner, NodePriority::Exp, false);
+                self.push(&format!(
+                    "<mspace width=\"0.2em\"/><mi mathvariant=\"normal\">{}</mi>",
+                    unit
+                ));
+            }
+            // functions
+            Node::Sin(inner) => self.write_func("sin", inner),
+            Node::Cos(inner) => self.write_func("cos", inner),
+            Node::Tan(inner) => self.write_func("tan", inner),
+            Node::Asin(inner) => self.write_func("arcsin", inner),
+            Node::Acos(inner) => self.write_func("arccos", inner),
+            Node::Atan(inner) => self.write_func("arctan", inner),
+            Node::Atan2(y, x) => {
+                self.push(&format!("<mi>atan2</mi>{}", APPLY));
+                self.write_list(&[y, x]);
+            }
+            Node::Sinh(inner) => self.write_func("sinh", inner),
+            Node::Cosh(inner) => self.write_func("cosh", inner),
+            Node::Tanh(inner) => self.write_func("tanh", inner),
+            Node::Abs(inner) => self.write_fenced("|", inner, "|"),
+            Node::Floor(inner) => self.write_fenced("⌊", inner, "⌋"),
+            Node::Ceil(inner) => self.write_fenced("⌈", inner, "⌉"),
+            Node::Round(inner) => self.write_func("round", inner),
+            Node::Sign(inner) => self.write_func("sign", inner),
+            Node::Min(children) => {
+                self.push(&format!("<mi>min</mi>{}", APPLY));
+                self.write_list(&children.iter().collect::<Vec<_>>());
+            }
+            Node::Max(children) => {
+                self.push(&format!("<mi>max</mi>{}", APPLY));
+                self.write_list(&children.iter().collect::<Vec<_>>());
+            }
+            Node::Ln(inner) => self.write_func("ln", inner),
+            Node::Log(base, inner) => {
+                self.push("<msub><mi>log</mi>");
+                self.write_row(base);
+                self.push(&format!("</msub>{}", APPLY));
+                self.write_list(&[inner]);
+            }
+        }
+    }
+}
+
+/// Formats the node as a presentation MathML `math` element, which browsers
+/// can render natively.
+pub fn to_mathml(node: &Node) -> String {
+    let mut writer = Writer { out: String::new() };
+    writer.push("<math xmlns=\"http://www.w3.org/1998/Math/MathML\">");
+    writer.write_node(node);
+    writer.push("</math>");
+    writer.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    fn mathml_str(expr: &str) -> String {
+        let node = parse(expr);
+        let mathml = to_mathml(&node.simplify().unwrap().result);
+        let prefix = "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">";
+        assert!(mathml.starts_with(prefix) && mathml.ends_with("</math>"));
+        mathml[prefix.len()..mathml.len() - "</math>".len()].to_owned()
+    }
+
+    #[test]
+    fn it_writes_fractions_and_roots() {
+        assert_eq!(mathml_str("1/2"), "<mfrac><mn>1</mn><mn>2</mn></mfrac>");
+        assert_eq!(
+            mathml_str("-x/2"),
+            "<mo>-</mo><mfrac><mrow><mi>x</mi></mrow><mrow><mn>2</mn></mrow></mfrac>"
+        );
+        assert_eq!(mathml_str("sqrt(x)"), "<msqrt><mi>x</mi></msqrt>");
+        assert_eq!(mathml_str("cbrt(2)"), "<mroot><mn>2</mn><mn>3</mn></mroot>");
+    }
+
+    #[test]
+    fn it_writes_powers_and_sums() {
+        assert_eq!(
+            mathml_str("x^2 - y"),
+            "<msup><mi>x</mi><mn>2</mn></msup><mo>-</mo><mi>y</mi>"
+        );
+        assert_eq!(
+            mathml_str("(x + 1)^y"),
+            "<msup><mrow><mo>(</mo><mn>1</mn><mo>+</mo><mi>x</mi><mo>)</mo></mrow><mi>y</mi></msup>"
+        );
+        assert_eq!(mathml_str("2pi"), "<mn>2</mn><mo>&#x2062;</mo><mi>π</mi>");
+    }
+
+    #[test]
+    fn it_writes_functions() {
+        assert_eq!(
+            mathml_str("sin(x)"),
+            "<mi>sin</mi><mo>&#x2061;</mo><mi>x</mi>"
+        );
+        assert_eq!(
+            mathml_str("abs(x)"),
+            "<mrow><mo>|</mo><mi>x</mi><mo>|</mo></mrow>"
+        );
+    }
+}
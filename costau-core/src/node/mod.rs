@@ -1,28 +1,94 @@
+mod angle;
+mod braille;
+mod budget;
+mod bytecode;
+mod calculate;
+mod calculus;
+mod compile;
+mod complex;
+mod digits;
 mod display;
 mod eval;
+mod exact;
+mod expand;
+mod factor;
+mod interval;
+#[cfg(feature = "with-serde")]
+mod json;
+mod latex;
+mod logs;
+mod mathml;
+mod mutate;
+mod partial;
+mod precise;
+mod radicals;
+mod rates;
+mod rearrange;
+mod sexpr;
 mod simplify;
+mod solve;
+mod steps;
+mod telemetry;
+mod terms;
+mod together;
+mod trig;
+pub(crate) mod units;
 pub(crate) mod util;
 
 use num_rational::BigRational;
+#[cfg(feature = "with-serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::ops::*;
 
+pub use self::angle::AngleUnit;
+pub use self::braille::BrailleCode;
+pub use self::bytecode::{BytecodeError, BYTECODE_VERSION};
+pub use self::calculate::Calculation;
+pub use self::calculus::LimitSide;
+pub use self::compile::Compiled;
+pub use self::digits::{DigitsError, MAX_CONSTANT_DIGITS};
+pub use self::display::{
+    group_digits, DigitGrouping, FormatOptions, Locale, Notation, Precision, Rounding,
+};
+pub use self::eval::{EvalError, EvalOptions, EvalSuccess, MultiBase, NonFinite};
+pub use self::interval::Interval;
+#[cfg(feature = "with-serde")]
+pub use self::json::{JsonError, JSON_VERSION};
+pub use self::mutate::mutate;
+pub use self::precise::{PreciseSuccess, MAX_DIGITS};
+pub use self::rates::RateProvider;
+pub use self::rearrange::{Operation, RearrangeStep, Rearrangement};
+pub use self::sexpr::SexprError;
+pub use self::simplify::{LogForm, SimplifyError, SimplifyOptions, SimplifySuccess};
+pub use self::solve::{solve_system, Solutions, SolveError, SystemSolutions};
+pub use self::steps::Step;
+pub use self::telemetry::{Pass, Rule, Telemetry};
+pub use self::units::{CompoundUnit, Dimension, DimensionError, DimensionMismatch, Unit};
+
 use self::eval::*;
 use self::simplify::*;
 use self::util::common;
 
 /// A constant in mathematics
 #[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub enum ConstKind {
     Pi,
     Tau,
     E,
+    /// The imaginary unit
+    I,
 }
 
 /// A node is an operation in the AST (abstract syntax tree).
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub enum Node {
     Const(ConstKind),
-    UnknownConst(String),
+    /// A variable, which is treated as an unknown value until it is
+    /// substituted
+    Var(String),
     Num {
         /// The number the node represents
         val: BigRational,
@@ -34,10 +100,53 @@ pub enum Node {
     Sum(Vec<Node>),
     Product(Vec<Node>),
     Exp(Box<Node>, Box<Node>),
+    /// An ordered list of values, such as a point `(x, y)`
+    Vector(Vec<Node>),
+    /// A ratio between quantities, such as `3:2`
+    Ratio(Vec<Node>),
+    /// The sum of the rolls of `count` fair dice with `sides` sides each
+    Dice {
+        count: u32,
+        sides: u32,
+    },
+    /// The time of the clock of the `EvalOptions` when the node is
+    /// calculated, shifted by the offset of a time zone in seconds, such as
+    /// `time_in("UTC+9")`. It is a quantity in seconds since 1970-01-01 00:00
+    /// UTC.
+    Time {
+        offset: i64,
+    },
+    /// The value of the node in a unit. If the node has a unit of the same
+    /// dimension, it is converted, such as `5 km in mi`.
+    Quantity(Box<Node>, Unit),
     // functions
     Sin(Box<Node>),
     Cos(Box<Node>),
     Tan(Box<Node>),
+    Asin(Box<Node>),
+    Acos(Box<Node>),
+    Atan(Box<Node>),
+    /// The angle of the point `(x, y)` where the first node is `y` and the
+    /// second node is `x`
+    Atan2(Box<Node>, Box<Node>),
+    Sinh(Box<Node>),
+    Cosh(Box<Node>),
+    Tanh(Box<Node>),
+    Abs(Box<Node>),
+    Floor(Box<Node>),
+    Ceil(Box<Node>),
+    /// Rounds to the nearest integer, and away from zero for halves
+    Round(Box<Node>),
+    /// `-1`, `0` or `1` depending on the sign of the node
+    Sign(Box<Node>),
+    /// The smallest of the nodes
+    Min(Vec<Node>),
+    /// The largest of the nodes
+    Max(Vec<Node>),
+    /// The natural logarithm
+    Ln(Box<Node>),
+    /// The logarithm of the second node in the base of the first node
+    Log(Box<Node>, Box<Node>),
 }
 
 impl Node {
@@ -46,15 +155,275 @@ impl Node {
         eval(self)
     }
 
+    /// Approximates the node value with the options of the calculation, such
+    /// as the exchange rates that convert the currencies.
+    pub fn eval_with_options(&self, options: &EvalOptions) -> Result<EvalSuccess, EvalError> {
+        eval_with_options(self, options)
+    }
+
+    /// Approximates the node value with the angles of the trigonometric
+    /// functions in the given unit.
+    pub fn eval_in(&self, unit: AngleUnit) -> Result<EvalSuccess, EvalError> {
+        eval(&self.clone().in_radians(unit))
+    }
+
+    /// Returns the part of the node that makes its value overflow or not be a
+    /// number, for frontends that explain `EvalError::Overflow` and
+    /// `EvalError::Invalid`.
+    pub fn find_non_finite(&self) -> Option<NonFinite> {
+        find_non_finite(self)
+    }
+
+    /// Returns the unit of the value of the node, or the part of the node
+    /// whose quantities cannot be combined, for frontends that explain
+    /// `EvalError::IncompatibleUnits` and `EvalError::UnknownRate`.
+    pub fn check_dimensions(&self) -> Result<Option<CompoundUnit>, DimensionError> {
+        units::resolve(self, &EvalOptions::default()).map(|x| x.1)
+    }
+
+    /// Computes the value of the node without any approximation. This fails
+    /// with `EvalError::NotExact` instead of rounding irrational values.
+    pub fn eval_exact(&self) -> Result<BigRational, EvalError> {
+        exact::eval_exact(self, &EvalOptions::default())
+    }
+
+    /// Like `eval_exact`, but with the options of the calculation, such as
+    /// the memory budget.
+    pub fn eval_exact_with_options(&self, options: &EvalOptions) -> Result<BigRational, EvalError> {
+        exact::eval_exact(self, options)
+    }
+
+    /// Computes an interval that is guaranteed to contain the value of the
+    /// node, which tells how many digits of `eval` are reliable.
+    pub fn eval_interval(&self) -> Result<Interval, EvalError> {
+        // the value in the unit of the result
+        interval::eval_interval(&units::resolve(self, &EvalOptions::default())?.0)
+    }
+
+    /// Approximates the value of the node with the given number of
+    /// significant digits, which can be more than what an `f64` holds.
+    pub fn eval_precise(&self, digits: u32) -> Result<PreciseSuccess, EvalError> {
+        precise::eval_precise(self, digits)
+    }
+
+    /// Computes the first decimal places of `pi`, `tau`, `e` or the square
+    /// root of a natural number, truncated.
+    pub fn digits(&self, places: u32) -> Result<String, DigitsError> {
+        digits::digits(self, places)
+    }
+
+    /// Writes the sums of fractions over a common denominator, such as
+    /// `1/x + 1/y` becoming `(x + y)/(x * y)`.
+    pub fn together(self) -> Node {
+        together::together(self)
+    }
+
+    /// Replaces the variables in `bindings` with their values and computes
+    /// everything that no longer depends on a variable, such as to draw a
+    /// curve of a family where the parameters are fixed.
+    pub fn partial_eval(&self, bindings: &HashMap<String, Node>) -> Node {
+        partial::partial_eval(self, bindings)
+    }
+
+    /// Compiles the node to evaluate it quickly for many values of the
+    /// variables `vars`.
+    pub fn compile(&self, vars: &[&str]) -> Result<Compiled, EvalError> {
+        Compiled::new(self, vars)
+    }
+
+    /// Like `compile`, but with the options of the evaluation.
+    pub fn compile_with_options(
+        &self,
+        vars: &[&str],
+        options: &EvalOptions,
+    ) -> Result<Compiled, EvalError> {
+        Compiled::with_options(self, vars, options)
+    }
+
+    /// Formats the node on a single line with the given options.
+    pub fn format(&self, options: &FormatOptions) -> String {
+        display::format(self, options)
+    }
+
+    /// Transcribes the node to braille for braille displays.
+    pub fn to_braille(&self, code: BrailleCode) -> String {
+        braille::to_braille(self, code)
+    }
+
+    /// Formats the node as LaTeX for front-ends that render math.
+    pub fn to_latex(&self) -> String {
+        latex::to_latex(self)
+    }
+
+    /// Formats the node as presentation MathML for web front-ends.
+    pub fn to_mathml(&self) -> String {
+        mathml::to_mathml(self)
+    }
+
+    /// Writes the structure of the node as an S-expression, such as
+    /// `(+ 1#10 x)`, for debugging and snapshot tests.
+    pub fn to_sexpr(&self) -> String {
+        sexpr::to_sexpr(self)
+    }
+
+    /// Parses a node written by `to_sexpr`.
+    pub fn from_sexpr(text: &str) -> Result<Node, SexprError> {
+        sexpr::from_sexpr(text)
+    }
+
+    /// Writes the node as a versioned JSON document that other tools can
+    /// read and build.
+    #[cfg(feature = "with-serde")]
+    pub fn to_json(&self) -> String {
+        json::to_json(self)
+    }
+
+    /// Reads a node from a JSON document written by `to_json` or another
+    /// tool.
+    #[cfg(feature = "with-serde")]
+    pub fn from_json(text: &str) -> Result<Node, JsonError> {
+        json::from_json(text)
+    }
+
+    /// Formats the node on a single line without the spaces that are not
+    /// needed, such as `2x*(y+1)`, which can be parsed back.
+    pub fn to_compact_string(&self) -> String {
+        display::compact(self)
+    }
+
+    /// Formats the node for a multi-line output such as a terminal, with
+    /// matrices shown as a grid. `to_string` gives the compact single-line
+    /// form instead.
+    pub fn to_pretty_string(&self) -> String {
+        display::pretty(self)
+    }
+
     /// Simplifies the node.
     pub fn simplify(self) -> Result<SimplifySuccess, SimplifyError> {
-        simplify(self)
+        self.simplify_with_options(&EvalOptions::default())
+    }
+
+    /// Simplifies the node with the options of the calculation, such as the
+    /// exchange rates that convert the currencies.
+    pub fn simplify_with_options(
+        self,
+        options: &EvalOptions,
+    ) -> Result<SimplifySuccess, SimplifyError> {
+        units::simplify_units(self, &mut Context::new(options, &mut ()))
+    }
+
+    /// Simplifies the node and applies the rules selected by the options,
+    /// such as splitting the logarithms of products.
+    pub fn simplify_with(
+        self,
+        options: &SimplifyOptions,
+    ) -> Result<SimplifySuccess, SimplifyError> {
+        simplify_with(self, options)
+    }
+
+    /// Like `simplify_with`, but reports the rules that fired and the time of
+    /// each pass to the telemetry.
+    pub fn simplify_with_telemetry(
+        self,
+        options: &SimplifyOptions,
+        telemetry: &mut dyn Telemetry,
+    ) -> Result<SimplifySuccess, SimplifyError> {
+        simplify_with_telemetry(self, options, telemetry)
+    }
+
+    /// Simplifies the node and approximates the result, so that both can be
+    /// shown together.
+    pub fn calculate(self) -> Result<Calculation, SimplifyError> {
+        calculate::calculate(self)
+    }
+
+    /// Like `calculate`, but reports the rules that fired and the time of
+    /// each pass to the telemetry.
+    pub fn calculate_with_telemetry(
+        self,
+        telemetry: &mut dyn Telemetry,
+    ) -> Result<Calculation, SimplifyError> {
+        calculate::calculate_with_telemetry(self, telemetry)
+    }
+
+    /// Like `calculate`, but with the options of the calculation, such as the
+    /// exchange rates that convert the currencies.
+    pub fn calculate_with_options(
+        self,
+        options: &EvalOptions,
+    ) -> Result<Calculation, SimplifyError> {
+        calculate::calculate_with_options(self, options, &mut ())
+    }
+
+    /// Simplifies the node after distributing the products over the sums and
+    /// expanding the integer powers of sums, which `simplify` does not always
+    /// do.
+    pub fn expand(self) -> Result<Node, SimplifyError> {
+        self.expand_with_options(&EvalOptions::default())
+    }
+
+    /// Like `expand`, but with the options of the calculation, such as the
+    /// memory budget.
+    pub fn expand_with_options(self, options: &EvalOptions) -> Result<Node, SimplifyError> {
+        let expanded = expand::expand(self, options)?;
+        let simplified = simplify_node(expanded, &mut Context::new(options, &mut ()))?.result;
+        // the simplification collects the terms with a common factor again
+        expand::expand(simplified, options)
+    }
+
+    /// Returns the coefficient of `var^power` if the node is a polynomial in
+    /// the variable, such as `3` for the power 1 in `3(x - 2) + 1`.
+    pub fn coefficient(&self, var: &str, power: u32) -> Option<Node> {
+        terms::coefficient(self, var, power)
+    }
+
+    /// Returns the terms of the expanded node that are the pattern times
+    /// factors without its variables, such as `a*x^2` for the pattern `x^2`.
+    pub fn terms_matching(&self, pattern: &Node) -> Vec<Node> {
+        terms::terms_matching(self, pattern)
+    }
+
+    /// Factors the node over the rationals if it is a polynomial in a single
+    /// variable, such as `x^2 - 5x + 6` becoming `(x - 2) * (x - 3)`.
+    pub fn factor(&self) -> Option<Node> {
+        factor::factor(self)
+    }
+
+    /// Simplifies the node and applies the trigonometric identities where
+    /// they make it simpler, such as `2 * sin(x) * cos(x)` becoming
+    /// `sin(2x)`.
+    pub fn simplify_trig(self) -> Result<Node, SimplifyError> {
+        trig::simplify_trig(self)
+    }
+
+    /// Simplifies the node and returns the notable steps of the
+    /// simplification along with the result.
+    pub fn simplify_with_steps(self) -> Result<(SimplifySuccess, Vec<Step>), SimplifyError> {
+        let steps = steps::find_steps(&self);
+        Ok((self.simplify()?, steps))
+    }
+
+    /// Simplifies the node with the angles of the trigonometric functions in
+    /// the given unit.
+    pub fn simplify_in(self, unit: AngleUnit) -> Result<SimplifySuccess, SimplifyError> {
+        self.in_radians(unit).simplify()
+    }
+
+    /// Converts the angles of the trigonometric functions from the given unit
+    /// to radians.
+    pub fn in_radians(self, unit: AngleUnit) -> Node {
+        angle::to_radians(self, unit)
     }
 
     pub fn inverse(self) -> Node {
         Node::Exp(Box::new(self), Box::new(common::minus_one()))
     }
 
+    /// Divides the node by one unit, such as `60 km per h`.
+    pub fn per(self, unit: Unit) -> Node {
+        units::per(self, unit)
+    }
+
     pub fn sqr(self) -> Node {
         Node::Exp(Box::new(self), Box::new(common::two()))
     }
@@ -78,6 +447,227 @@ impl Node {
     pub fn tan(self) -> Node {
         Node::Tan(Box::new(self))
     }
+
+    pub fn asin(self) -> Node {
+        Node::Asin(Box::new(self))
+    }
+
+    pub fn acos(self) -> Node {
+        Node::Acos(Box::new(self))
+    }
+
+    pub fn atan(self) -> Node {
+        Node::Atan(Box::new(self))
+    }
+
+    pub fn sinh(self) -> Node {
+        Node::Sinh(Box::new(self))
+    }
+
+    pub fn cosh(self) -> Node {
+        Node::Cosh(Box::new(self))
+    }
+
+    pub fn tanh(self) -> Node {
+        Node::Tanh(Box::new(self))
+    }
+
+    pub fn abs(self) -> Node {
+        Node::Abs(Box::new(self))
+    }
+
+    pub fn floor(self) -> Node {
+        Node::Floor(Box::new(self))
+    }
+
+    pub fn ceil(self) -> Node {
+        Node::Ceil(Box::new(self))
+    }
+
+    pub fn round(self) -> Node {
+        Node::Round(Box::new(self))
+    }
+
+    pub fn sign(self) -> Node {
+        Node::Sign(Box::new(self))
+    }
+
+    pub fn ln(self) -> Node {
+        Node::Ln(Box::new(self))
+    }
+
+    /// The logarithm of the node in the given base.
+    pub fn log(self, base: Node) -> Node {
+        Node::Log(Box::new(base), Box::new(self))
+    }
+
+    /// Returns the derivative of the node with respect to the variable `var`,
+    /// which can be simplified with `simplify`.
+    pub fn derivative(&self, var: &str) -> Node {
+        calculus::derivative(self, var)
+    }
+
+    /// Returns an antiderivative of the node with respect to the variable
+    /// `var`, or `None` if the integrand is not supported.
+    pub fn integrate(&self, var: &str) -> Option<Node> {
+        // this merges the powers of the variable such as in `x * x^2`
+        let simplified = self.clone().simplify().ok()?.result;
+        calculus::integrate(&simplified, var)
+    }
+
+    /// Returns the limit of the node when the variable `var` approaches
+    /// `point` from the given side, or `None` if it does not exist or cannot
+    /// be found.
+    pub fn limit(&self, var: &str, point: &Node, side: LimitSide) -> Option<Node> {
+        calculus::limit(self, var, point, side)
+    }
+
+    /// Returns the Taylor polynomial of the node with respect to the
+    /// variable `var` around `point`, up to the power `order`, or `None` if
+    /// the node cannot be differentiated enough times at the point.
+    pub fn series(&self, var: &str, point: &Node, order: u32) -> Option<Node> {
+        calculus::series(self, var, point, order)
+    }
+
+    /// Finds a value of the variable `var` where the node is zero with Newton's
+    /// method, starting from `guess`, or `None` if it does not converge in
+    /// `max_iter` steps.
+    pub fn find_root(&self, var: &str, guess: f64, tolerance: f64, max_iter: usize) -> Option<f64> {
+        calculus::find_root(self, var, guess, tolerance, max_iter)
+    }
+
+    /// Solves the equation `self = rhs` for the variable `var`, if it is
+    /// linear or quadratic in the variable.
+    pub fn solve(&self, rhs: &Node, var: &str) -> Result<Solutions, SolveError> {
+        solve::solve(self, rhs, var)
+    }
+
+    /// Makes the variable `var` the subject of the equation `self = rhs` with
+    /// inverse operations, such as `v = u + a*t` becoming `t = (v - u) / a`,
+    /// and records the operations.
+    pub fn rearrange(&self, rhs: &Node, var: &str) -> Result<Rearrangement, SolveError> {
+        rearrange::rearrange(self, rhs, var)
+    }
+
+    /// Returns a copy of the node where every occurrence of the variable
+    /// `name` is replaced with `replacement`.
+    pub fn substitute(&self, name: &str, replacement: &Node) -> Node {
+        self.clone().replace_var(name, replacement)
+    }
+
+    fn replace_var(self, name: &str, replacement: &Node) -> Node {
+        match self {
+            Node::Var(ref var) if var == name => replacement.clone(),
+            node => node.map_children(|child| child.replace_var(name, replacement)),
+        }
+    }
+
+    /// Returns the node after applying `f` on each of its direct children.
+    pub fn map_children<F: FnMut(Node) -> Node>(self, mut f: F) -> Node {
+        match self {
+            Node::Const(_)
+            | Node::Var(_)
+            | Node::Num { .. }
+            | Node::Dice { .. }
+            | Node::Time { .. } => self,
+            Node::Sum(children) => Node::Sum(children.into_iter().map(f).collect()),
+            Node::Product(children) => Node::Product(children.into_iter().map(f).collect()),
+            Node::Exp(a, b) => Node::Exp(Box::new(f(*a)), Box::new(f(*b))),
+            Node::Vector(items) => Node::Vector(items.into_iter().map(f).collect()),
+            Node::Min(children) => Node::Min(children.into_iter().map(f).collect()),
+            Node::Max(children) => Node::Max(children.into_iter().map(f).collect()),
+            Node::Ratio(terms) => Node::Ratio(terms.into_iter().map(f).collect()),
+            Node::Sin(inner) => Node::Sin(Box::new(f(*inner))),
+            Node::Cos(inner) => Node::Cos(Box::new(f(*inner))),
+            Node::Tan(inner) => Node::Tan(Box::new(f(*inner))),
+            Node::Asin(inner) => Node::Asin(Box::new(f(*inner))),
+            Node::Acos(inner) => Node::Acos(Box::new(f(*inner))),
+            Node::Atan(inner) => Node::Atan(Box::new(f(*inner))),
+            Node::Atan2(y, x) => Node::Atan2(Box::new(f(*y)), Box::new(f(*x))),
+            Node::Sinh(inner) => Node::Sinh(Box::new(f(*inner))),
+            Node::Cosh(inner) => Node::Cosh(Box::new(f(*inner))),
+            Node::Tanh(inner) => Node::Tanh(Box::new(f(*inner))),
+            Node::Abs(inner) => Node::Abs(Box::new(f(*inner))),
+            Node::Floor(inner) => Node::Floor(Box::new(f(*inner))),
+            Node::Ceil(inner) => Node::Ceil(Box::new(f(*inner))),
+            Node::Round(inner) => Node::Round(Box::new(f(*inner))),
+            Node::Sign(inner) => Node::Sign(Box::new(f(*inner))),
+            Node::Ln(inner) => Node::Ln(Box::new(f(*inner))),
+            Node::Log(base, inner) => Node::Log(Box::new(f(*base)), Box::new(f(*inner))),
+            Node::Quantity(inner, unit) => Node::Quantity(Box::new(f(*inner)), unit),
+        }
+    }
+
+    /// Returns the direct children of the node.
+    pub fn children(&self) -> Vec<&Node> {
+        match self {
+            Node::Const(_)
+            | Node::Var(_)
+            | Node::Num { .. }
+            | Node::Dice { .. }
+            | Node::Time { .. } => vec![],
+            Node::Sum(children)
+            | Node::Product(children)
+            | Node::Vector(children)
+            | Node::Ratio(children)
+            | Node::Min(children)
+            | Node::Max(children) => children.iter().collect(),
+            Node::Exp(a, b) | Node::Atan2(a, b) | Node::Log(a, b) => vec![a, b],
+            Node::Sin(inner)
+            | Node::Cos(inner)
+            | Node::Tan(inner)
+            | Node::Asin(inner)
+            | Node::Acos(inner)
+            | Node::Atan(inner)
+            | Node::Sinh(inner)
+            | Node::Cosh(inner)
+            | Node::Tanh(inner)
+            | Node::Abs(inner)
+            | Node::Floor(inner)
+            | Node::Ceil(inner)
+            | Node::Round(inner)
+            | Node::Sign(inner)
+            | Node::Ln(inner)
+            | Node::Quantity(inner, _) => vec![inner],
+        }
+    }
+
+    /// Returns whether the variable `name` appears in the node.
+    pub fn contains_var(&self, name: &str) -> bool {
+        match self {
+            Node::Var(var) => var == name,
+            node => node.children().iter().any(|child| child.contains_var(name)),
+        }
+    }
+
+    /// Returns the names of the variables that appear in the node, in the
+    /// order of their first appearance.
+    pub fn vars(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        self.collect_vars(&mut names);
+        names
+    }
+
+    fn collect_vars(&self, names: &mut Vec<String>) {
+        match self {
+            Node::Var(name) if !names.contains(name) => names.push(name.clone()),
+            node => {
+                for child in node.children() {
+                    child.collect_vars(names);
+                }
+            }
+        }
+    }
+
+    /// Returns the items of the node if it is a vector, or the node itself
+    /// otherwise.
+    /// This is used to get the arguments of a function.
+    pub fn into_items(self) -> Vec<Node> {
+        match self {
+            Node::Vector(items) => items,
+            node => vec![node],
+        }
+    }
 }
 
 impl Add for Node {
@@ -120,3 +710,57 @@ impl Div for Node {
         self * rhs.inverse()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::{ApproxEq, F64Margin};
+    use num_rational::BigRational;
+
+    use super::*;
+
+    #[test]
+    fn it_substitutes_variables() {
+        let x = || Node::Var("x".to_string());
+        let y = Node::Var("y".to_string());
+        let node = common::two() * x() + x().sin() * y.clone();
+
+        let substituted = node.substitute("x", &common::three());
+        assert_eq!(
+            substituted,
+            common::two() * common::three() + common::three().sin() * y
+        );
+        assert_eq!(node.substitute("z", &common::three()), node);
+    }
+
+    #[test]
+    fn it_combines_like_terms_before_substitution() {
+        let x = || Node::Var("x".to_string());
+        // 2x + 3 + 6x = 8x + 3
+        let node = common::two() * x() + common::three() + x() * common::two() * common::three();
+        let simplified = node.simplify().unwrap().result;
+        assert_eq!(simplified.to_string(), "3 + 8x");
+
+        let result = simplified.substitute("x", &common::two()).eval().unwrap();
+        assert!(result.val.approx_eq(19.0, F64Margin::default()));
+        assert_eq!(
+            simplified
+                .substitute("x", &common::two())
+                .simplify()
+                .unwrap()
+                .result,
+            Node::Num {
+                val: BigRational::from_integer(19.into()),
+                input_base: None,
+            }
+        );
+    }
+
+    #[cfg(feature = "with-serde")]
+    #[test]
+    fn it_serializes_nodes() {
+        let node =
+            (Node::Var("x".to_string()) + common::three()).sin() * Node::Const(ConstKind::Pi);
+        let json = serde_json::to_string(&node).unwrap();
+        assert_eq!(serde_json::from_str::<Node>(&json).unwrap(), node);
+    }
+}
@@ -0,0 +1,155 @@
+use float_cmp::{ApproxEq, F64Margin};
+use num_rational::BigRational;
+use num_traits::{One, Zero};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use super::sexpr::{get_unary, UNARY};
+use super::util::common;
+use super::{ConstKind, Node, Unit};
+
+/// The number of times a mutation is tried again if it gives the same value.
+const MAX_TRIES: usize = 10;
+
+fn count_nodes(node: &Node) -> usize {
+    1 + node.children().into_iter().map(count_nodes).sum::<usize>()
+}
+
+/// Changes the node itself, but not its children.
+fn mutate_node<R: Rng>(node: Node, rng: &mut R) -> Node {
+    if let Some((name, inner)) = get_unary(&node) {
+        let others: Vec<_> = UNARY.iter().filter(|x| x.0 != name).collect();
+        let f = others.choose(rng).unwrap().1;
+        return f(Box::new(inner.clone()));
+    }
+    match node {
+        Node::Num { val, input_base } => {
+            let val = match rng.gen_range(0, 4) {
+                0 => val + BigRational::one(),
+                1 => val - BigRational::one(),
+                2 if !val.is_zero() => -val,
+                _ if !val.is_zero() => val * BigRational::from_integer(2.into()),
+                _ => BigRational::one(),
+            };
+            Node::Num { val, input_base }
+        }
+        Node::Const(kind) => {
+            let others: Vec<_> = [ConstKind::Pi, ConstKind::Tau, ConstKind::E]
+                .iter()
+                .filter(|&&x| x != kind)
+                .cloned()
+                .collect();
+            Node::Const(*others.choose(rng).unwrap())
+        }
+        Node::Var(name) => Node::Sum(vec![Node::Var(name), common::one()]),
+        Node::Sum(children) => Node::Product(children),
+        Node::Product(children) => Node::Sum(children),
+        Node::Min(children) => Node::Max(children),
+        Node::Max(children) => Node::Min(children),
+        Node::Exp(a, b) => Node::Exp(b, a),
+        Node::Atan2(y, x) => Node::Atan2(x, y),
+        Node::Log(base, inner) => Node::Log(inner, base),
+        Node::Dice { count, sides } => Node::Dice {
+            count,
+            sides: sides + 1,
+        },
+        Node::Time { offset } => Node::Time {
+            offset: offset + 3600,
+        },
+        Node::Vector(mut items) => {
+            if let Some(item) = items.first_mut() {
+                *item = -item.clone();
+            }
+            Node::Vector(items)
+        }
+        Node::Ratio(mut terms) => {
+            terms.reverse();
+            Node::Ratio(terms)
+        }
+        Node::Quantity(inner, unit) => {
+            let others: Vec<_> = Unit::ALL
+                .iter()
+                .filter(|x| x.dimension() == unit.dimension() && **x != unit)
+                .collect();
+            Node::Quantity(inner, **others.choose(rng).unwrap())
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Mutates the node at the position `index` in the pre-order traversal.
+fn mutate_at<R: Rng>(node: Node, index: &mut Option<usize>, rng: &mut R) -> Node {
+    match *index {
+        Some(0) => {
+            *index = None;
+            mutate_node(node, rng)
+        }
+        Some(i) => {
+            *index = Some(i - 1);
+            node.map_children(|child| mutate_at(child, index, rng))
+        }
+        None => node,
+    }
+}
+
+/// Returns a neighbor of the node with a different meaning, to test that a
+/// solver or a checker notices the difference. A random part of the node is
+/// changed by swapping an operator, such as a sum for a product or a
+/// function for another one, by perturbing a number or a constant, or by
+/// swapping the operands of a power or the unit of a quantity for another
+/// one. If the node has a value, the mutation
+/// is tried again a few times while it gives the same value.
+pub fn mutate<R: Rng>(node: &Node, rng: &mut R) -> Node {
+    let len = count_nodes(node);
+    let mut mutated = node.clone();
+    for _ in 0..MAX_TRIES {
+        let mut index = Some(rng.gen_range(0, len));
+        mutated = mutate_at(node.clone(), &mut index, rng);
+        let same_value = match (node.eval(), mutated.eval()) {
+            (Ok(a), Ok(b)) => {
+                let margin = F64Margin::default();
+                a.val.approx_eq(b.val, margin)
+                    && a.imag.approx_eq(b.imag, margin)
+                    && a.unit == b.unit
+            }
+            _ => false,
+        };
+        if !same_value {
+            break;
+        }
+    }
+    mutated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn it_mutates_expressions() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for expr in &["2 + 2", "sin(pi / 3) * 2^3", "max(1, ln(e))", "x^2 - 1"] {
+            let node = parse(expr);
+            for _ in 0..50 {
+                let mutated = mutate(&node, &mut rng);
+                assert_ne!(mutated, node);
+                if let (Ok(a), Ok(b)) = (node.eval(), mutated.eval()) {
+                    assert!((a.val - b.val).abs() > 1e-9 || (a.imag - b.imag).abs() > 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_mutates_reproducibly() {
+        let node = parse("atan2(1, 2) + cos(x)");
+        let mutations = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..10).map(|_| mutate(&node, &mut rng)).collect::<Vec<_>>()
+        };
+        assert_eq!(mutations(1), mutations(1));
+    }
+}
@@ -0,0 +1,89 @@
+use num_rational::BigRational;
+use std::collections::HashMap;
+
+use super::eval::eval_real;
+use super::simplify::simplify;
+use super::Node;
+
+/// Replaces the node with its value if it does not depend on any variable.
+/// The value is exact when it is rational, such as for `1/3`, and
+/// approximated otherwise, such as for `sin(2)`.
+fn fold(node: Node) -> Node {
+    let node = node.map_children(fold);
+    if let Node::Num { .. } = node {
+        return node;
+    }
+    if !node.vars().is_empty() {
+        return node;
+    }
+    if let Ok(val) = node.eval_exact() {
+        return Node::Num {
+            val,
+            input_base: None,
+        };
+    }
+    let approx = eval_real(&node)
+        .ok()
+        .and_then(|x| BigRational::from_float(x.val));
+    match approx {
+        Some(val) => Node::Num {
+            val,
+            input_base: None,
+        },
+        None => node,
+    }
+}
+
+/// Replaces the variables in `bindings` with their values and computes the
+/// parts of the node that no longer depend on a variable, which leaves a
+/// smaller expression of the other variables.
+pub fn partial_eval(node: &Node, bindings: &HashMap<String, Node>) -> Node {
+    let mut node = node.clone();
+    for (name, val) in bindings {
+        node = node.substitute(name, val);
+    }
+    let folded = fold(node);
+    match simplify(folded.clone()) {
+        Ok(x) => x.result,
+        Err(_) => folded,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    fn partial_eval_str(expr: &str, bindings: &[(&str, i64)]) -> String {
+        let node = parse(expr);
+        let bindings = bindings
+            .iter()
+            .map(|&(name, val)| {
+                let val = Node::Num {
+                    val: BigRational::from_integer(val.into()),
+                    input_base: None,
+                };
+                (name.to_owned(), val)
+            })
+            .collect();
+        node.partial_eval(&bindings).to_string()
+    }
+
+    #[test]
+    fn it_keeps_the_unbound_variables() {
+        assert_eq!(
+            partial_eval_str("a * x^2 + b", &[("a", 3), ("b", 1)]),
+            "1 + 3 * x^2"
+        );
+        assert_eq!(partial_eval_str("x / (a + 1)", &[("a", 2)]), "1/3 * x");
+        assert_eq!(partial_eval_str("x + y", &[]), "x + y");
+    }
+
+    #[test]
+    fn it_approximates_irrational_parts() {
+        let result = partial_eval_str("x * ln(a)", &[("a", 2)]);
+        assert!(result.starts_with("6243314768165359/9007199254740992"));
+        assert!(result.ends_with('x'));
+        assert_eq!(partial_eval_str("sin(a) + 1", &[("a", 0)]), "1");
+    }
+}
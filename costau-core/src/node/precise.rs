@@ -0,0 +1,546 @@
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use super::digits::pi_scaled;
+use super::eval::{EvalError, EvalOptions};
+use super::units::resolve;
+use super::{ConstKind, Node};
+
+/// The maximum number of significant digits that can be requested.
+pub const MAX_DIGITS: u32 = 2000;
+
+/// The number of bits that are computed in addition to the requested
+/// precision to absorb the rounding errors of the calculation.
+const GUARD_BITS: usize = 64;
+
+/// The number of times the precision is raised when the result is too small.
+const MAX_RETRIES: usize = 6;
+
+/// The value returned by `eval_precise` when it succeeds.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PreciseSuccess {
+    /// An approximation of the result that is accurate to at least `digits`
+    /// significant digits
+    pub val: BigRational,
+
+    /// The number of significant digits that were requested
+    pub digits: u32,
+}
+
+/// Approximates the node value with the given number of significant digits,
+/// which is capped to `MAX_DIGITS`.
+pub fn eval_precise(node: &Node, digits: u32) -> Result<PreciseSuccess, EvalError> {
+    let digits = match digits {
+        0 => 1,
+        d => d.min(MAX_DIGITS),
+    };
+    // log2(10) < 10/3
+    let needed = digits as u64 * 10 / 3 + 1;
+    // the value in the unit of the result
+    let node = &resolve(node, &EvalOptions::default())?.0;
+    let mut ctx = Ctx {
+        prec: needed as usize + GUARD_BITS,
+    };
+    // the precision is absolute, so small results need another try with
+    // more bits
+    for _ in 0..MAX_RETRIES {
+        let val = ctx.eval(node)?;
+        if val.bits() >= needed {
+            return Ok(PreciseSuccess {
+                val: ctx.to_ratio(&val),
+                digits,
+            });
+        }
+        ctx.prec = if val.is_zero() {
+            ctx.prec * 2
+        } else {
+            ctx.prec + (needed - val.bits()) as usize + GUARD_BITS
+        };
+    }
+    // the result is zero or too small to tell
+    Ok(PreciseSuccess {
+        val: ctx.to_ratio(&ctx.eval(node)?),
+        digits,
+    })
+}
+
+/// The numbers are represented in fixed point, as integers that must be
+/// divided by `2^prec`.
+struct Ctx {
+    prec: usize,
+}
+
+impl Ctx {
+    fn one(&self) -> BigInt {
+        BigInt::one() << self.prec
+    }
+
+    fn fixed(&self, r: &BigRational) -> BigInt {
+        div_round(&(r.numer() << self.prec), r.denom())
+    }
+
+    fn to_ratio(&self, x: &BigInt) -> BigRational {
+        BigRational::new(x.clone(), self.one())
+    }
+
+    fn mul(&self, a: &BigInt, b: &BigInt) -> BigInt {
+        (a * b) >> self.prec
+    }
+
+    fn div(&self, a: &BigInt, b: &BigInt) -> BigInt {
+        div_round(&(a << self.prec), b)
+    }
+
+    /// Computes `x^n` where `n` is an integer.
+    fn powi(&self, x: &BigInt, n: i64) -> Result<BigInt, EvalError> {
+        if x.is_zero() {
+            return if n > 0 {
+                Ok(BigInt::zero())
+            } else if n == -1 {
+                Err(EvalError::DivisionByZero)
+            } else {
+                Err(EvalError::ZeroToPowerOfNonPositive)
+            };
+        }
+        let mut result = self.one();
+        let mut base = x.clone();
+        let mut e = n.abs();
+        while e > 0 {
+            if e & 1 == 1 {
+                result = self.mul(&result, &base);
+            }
+            base = self.mul(&base, &base);
+            e >>= 1;
+        }
+        Ok(if n < 0 {
+            self.div(&self.one(), &result)
+        } else {
+            result
+        })
+    }
+
+    /// Computes the `n`-th root of a nonnegative number.
+    fn root(&self, x: &BigInt, n: u32) -> BigInt {
+        (x << (self.prec * (n as usize - 1))).nth_root(n)
+    }
+
+    fn pi(&self) -> BigInt {
+        pi_scaled(&self.one())
+    }
+
+    /// The Taylor series of `atan`, which converges quickly for small values.
+    fn atan_series(&self, x: &BigInt) -> BigInt {
+        let x2 = self.mul(x, x);
+        let mut power = x.clone();
+        let mut sum = x.clone();
+        let mut k = 1u32;
+        loop {
+            power = -self.mul(&power, &x2);
+            let term = &power / (2 * k + 1);
+            if term.is_zero() {
+                return sum;
+            }
+            sum += term;
+            k += 1;
+        }
+    }
+
+    /// The Taylor series of `atanh`, which converges quickly for small values.
+    fn atanh_series(&self, x: &BigInt) -> BigInt {
+        let x2 = self.mul(x, x);
+        let mut power = x.clone();
+        let mut sum = x.clone();
+        let mut k = 1u32;
+        loop {
+            power = self.mul(&power, &x2);
+            let term = &power / (2 * k + 1);
+            if term.is_zero() {
+                return sum;
+            }
+            sum += term;
+            k += 1;
+        }
+    }
+
+    fn atan(&self, x: &BigInt) -> BigInt {
+        if x.is_negative() {
+            return -self.atan(&-x);
+        }
+        let one = self.one();
+        if *x > one {
+            // atan(x) = pi/2 - atan(1/x)
+            return (self.pi() >> 1) - self.atan(&self.div(&one, x));
+        }
+        if *x > &one >> 2 {
+            // atan(x) = 2 atan(x / (1 + sqrt(1 + x^2))) to make x smaller
+            let s = self.root(&(&one + self.mul(x, x)), 2);
+            return self.atan_series(&self.div(x, &(one + s))) * 2;
+        }
+        self.atan_series(x)
+    }
+
+    fn exp(&self, x: &BigInt) -> BigInt {
+        // compute exp(x/2^s) with a series, and square it s times
+        let s = (x.bits() as usize + 1).saturating_sub(self.prec);
+        let ctx = Ctx {
+            prec: self.prec + s + 8,
+        };
+        // with the extra bits, this is x/2^s
+        let y = x << 8;
+        let mut term = ctx.one();
+        let mut sum = ctx.one();
+        let mut k = 1u32;
+        loop {
+            term = ctx.mul(&term, &y) / k;
+            if term.is_zero() {
+                break;
+            }
+            sum += &term;
+            k += 1;
+        }
+        for _ in 0..s {
+            sum = ctx.mul(&sum, &sum);
+        }
+        sum >> (s + 8)
+    }
+
+    /// The natural logarithm of a positive number.
+    fn ln(&self, x: &BigInt) -> BigInt {
+        // x = m * 2^k with 1 <= m < 2
+        let k = x.bits() as i64 - self.prec as i64 - 1;
+        let m = if k >= 0 {
+            x >> k as usize
+        } else {
+            x << (-k) as usize
+        };
+        let one = self.one();
+        // ln(m) = 2 atanh((m - 1) / (m + 1))
+        let ln_m = self.atanh_series(&self.div(&(&m - &one), &(&m + &one))) * 2;
+        let ln_2 = self.atanh_series(&(one / 3)) * 2;
+        ln_m + ln_2 * k
+    }
+
+    fn sin_cos(&self, x: &BigInt) -> (BigInt, BigInt) {
+        // bring x into [-pi; pi] with enough precision for big angles
+        let ctx = Ctx {
+            prec: self.prec + x.bits().saturating_sub(self.prec as u64) as usize + 8,
+        };
+        let x = x << (ctx.prec - self.prec);
+        let two_pi = ctx.pi() * 2;
+        let turns = div_round(&x, &two_pi);
+        let r = x - turns * two_pi;
+
+        let r2 = ctx.mul(&r, &r);
+        let mut sin = r.clone();
+        let mut cos = ctx.one();
+        let mut sin_term = r;
+        let mut cos_term = ctx.one();
+        let mut k = 1u32;
+        loop {
+            sin_term = -ctx.mul(&sin_term, &r2) / ((2 * k) * (2 * k + 1));
+            cos_term = -ctx.mul(&cos_term, &r2) / ((2 * k - 1) * (2 * k));
+            if sin_term.is_zero() && cos_term.is_zero() {
+                break;
+            }
+            sin += &sin_term;
+            cos += &cos_term;
+            k += 1;
+        }
+        let shift = ctx.prec - self.prec;
+        (sin >> shift, cos >> shift)
+    }
+
+    fn asin(&self, x: &BigInt) -> Result<BigInt, EvalError> {
+        let one = self.one();
+        if x.abs() > one {
+            return Err(EvalError::OutOfDomain);
+        }
+        if x.abs() == one {
+            let half_pi: BigInt = self.pi() >> 1;
+            return Ok(if x.is_negative() { -half_pi } else { half_pi });
+        }
+        // asin(x) = atan(x / sqrt(1 - x^2))
+        let s = self.root(&(one - self.mul(x, x)), 2);
+        Ok(self.atan(&self.div(x, &s)))
+    }
+
+    fn eval_list(&self, children: &[Node]) -> Result<Vec<BigInt>, EvalError> {
+        children.iter().map(|c| self.eval(c)).collect()
+    }
+
+    fn eval(&self, node: &Node) -> Result<BigInt, EvalError> {
+        Ok(match node {
+            Node::Const(kind) => match kind {
+                ConstKind::Pi => self.pi(),
+                ConstKind::Tau => self.pi() * 2,
+                ConstKind::E => self.exp(&self.one()),
+                ConstKind::I => return Err(EvalError::NotReal),
+            },
+            Node::Var(_) => return Err(EvalError::UnboundVar),
+            Node::Num { val, .. } => self.fixed(val),
+            Node::Vector(_) | Node::Ratio(_) | Node::Dice { .. } => {
+                return Err(EvalError::NotAScalar)
+            }
+            Node::Time { .. } => return Err(EvalError::NoClock),
+            Node::Quantity(inner, _) => self.eval(inner)?,
+            Node::Sum(children) => self.eval_list(children)?.into_iter().sum(),
+            Node::Product(children) => {
+                let mut acc = self.one();
+                for child in children {
+                    acc = self.mul(&acc, &self.eval(child)?);
+                }
+                acc
+            }
+            Node::Exp(a, b) => self.eval_pow(a, b)?,
+            Node::Sin(inner) => self.sin_cos(&self.eval(inner)?).0,
+            Node::Cos(inner) => self.sin_cos(&self.eval(inner)?).1,
+            Node::Tan(inner) => {
+                let (sin, cos) = self.sin_cos(&self.eval(inner)?);
+                if cos.bits() < GUARD_BITS as u64 / 2 {
+                    return Err(EvalError::Tan90Or270);
+                }
+                self.div(&sin, &cos)
+            }
+            Node::Asin(inner) => self.asin(&self.eval(inner)?)?,
+            Node::Acos(inner) => (self.pi() >> 1) - self.asin(&self.eval(inner)?)?,
+            Node::Atan(inner) => self.atan(&self.eval(inner)?),
+            Node::Atan2(y, x) => {
+                let y = self.eval(y)?;
+                let x = self.eval(x)?;
+                if x.is_zero() {
+                    if y.is_zero() {
+                        return Err(EvalError::OutOfDomain);
+                    }
+                    let half_pi: BigInt = self.pi() >> 1;
+                    if y.is_negative() {
+                        -half_pi
+                    } else {
+                        half_pi
+                    }
+                } else {
+                    let angle = self.atan(&self.div(&y, &x));
+                    if x.is_positive() {
+                        angle
+                    } else if y.is_negative() {
+                        angle - self.pi()
+                    } else {
+                        angle + self.pi()
+                    }
+                }
+            }
+            Node::Sinh(inner) | Node::Cosh(inner) | Node::Tanh(inner) => {
+                let x = self.eval(inner)?;
+                let a = self.exp(&x);
+                let b = self.exp(&-x);
+                match node {
+                    Node::Sinh(_) => (a - b) >> 1,
+                    Node::Cosh(_) => (a + b) >> 1,
+                    _ => self.div(&(&a - &b), &(a + b)),
+                }
+            }
+            Node::Abs(inner) => self.eval(inner)?.abs(),
+            Node::Floor(inner) => (self.eval(inner)? >> self.prec) << self.prec,
+            Node::Ceil(inner) => -((-self.eval(inner)? >> self.prec) << self.prec),
+            Node::Round(inner) => {
+                let x = self.eval(inner)?;
+                let half = self.one() >> 1;
+                let rounded: BigInt = ((x.abs() + half) >> self.prec) << self.prec;
+                if x.is_negative() {
+                    -rounded
+                } else {
+                    rounded
+                }
+            }
+            Node::Sign(inner) => {
+                let x = self.eval(inner)?;
+                self.one() * x.signum()
+            }
+            Node::Min(children) => self
+                .eval_list(children)?
+                .into_iter()
+                .min()
+                .ok_or(EvalError::NotAScalar)?,
+            Node::Max(children) => self
+                .eval_list(children)?
+                .into_iter()
+                .max()
+                .ok_or(EvalError::NotAScalar)?,
+            Node::Ln(inner) => {
+                let x = self.eval(inner)?;
+                if !x.is_positive() {
+                    return Err(EvalError::LogOfNonPositive);
+                }
+                self.ln(&x)
+            }
+            Node::Log(base, inner) => {
+                let base = self.eval(base)?;
+                let x = self.eval(inner)?;
+                if !base.is_positive() || base == self.one() {
+                    return Err(EvalError::InvalidLogBase);
+                }
+                if !x.is_positive() {
+                    return Err(EvalError::LogOfNonPositive);
+                }
+                self.div(&self.ln(&x), &self.ln(&base))
+            }
+        })
+    }
+
+    fn eval_pow(&self, a: &Node, b: &Node) -> Result<BigInt, EvalError> {
+        let x = self.eval(a)?;
+        // use exact roots and powers for rational exponents such as in `sqrt`
+        if let Node::Num { val: expon, .. } = b {
+            if let (Some(p), Some(q)) = (expon.numer().to_i64(), expon.denom().to_u32()) {
+                if p.abs() <= 1 << 20 && q <= 64 {
+                    if x.is_negative() && q % 2 == 0 {
+                        return Err(EvalError::ComplexRoot);
+                    }
+                    let power = self.powi(&x, p)?;
+                    if q == 1 {
+                        return Ok(power);
+                    }
+                    let root = self.root(&power.abs(), q);
+                    return Ok(if power.is_negative() { -root } else { root });
+                }
+            }
+        }
+        let y = self.eval(b)?;
+        if x.is_zero() {
+            return if y.is_positive() {
+                Ok(BigInt::zero())
+            } else {
+                Err(EvalError::ZeroToPowerOfNonPositive)
+            };
+        }
+        if x.is_negative() {
+            return Err(EvalError::ComplexRoot);
+        }
+        // x^y = e^(y ln(x))
+        Ok(self.exp(&self.mul(&y, &self.ln(&x))))
+    }
+}
+
+/// Divides and rounds to the nearest integer.
+fn div_round(a: &BigInt, b: &BigInt) -> BigInt {
+    let (q, r): (BigInt, BigInt) = a.div_mod_floor(b);
+    // the remainder has the sign of `b`
+    if (r * BigInt::from(2)).abs() >= b.abs() {
+        if b.is_negative() {
+            q - 1
+        } else {
+            q + 1
+        }
+    } else {
+        q
+    }
+}
+
+impl Display for PreciseSuccess {
+    fn fmt(&self, out: &mut Formatter) -> fmt::Result {
+        if self.val.is_zero() {
+            return out.write_str("0");
+        }
+        if self.val.is_negative() {
+            out.write_str("-")?;
+        }
+        let abs = self.val.abs();
+        let digits = self.digits as i64;
+        let ten = BigInt::from(10);
+        let pow10 = |e: i64| BigRational::from_integer(num_traits::pow(ten.clone(), e as usize));
+
+        // find the exponent so that the digits are between 10^(digits-1) and
+        // 10^digits
+        let mut expon = abs.numer().to_string().len() as i64 - abs.denom().to_string().len() as i64;
+        let n = loop {
+            let shift = digits - 1 - expon;
+            let scaled = if shift >= 0 {
+                &abs * pow10(shift)
+            } else {
+                &abs / pow10(-shift)
+            };
+            let n = scaled.round().to_integer();
+            if n >= num_traits::pow(ten.clone(), digits as usize) {
+                expon += 1;
+            } else if n < num_traits::pow(ten.clone(), digits as usize - 1) {
+                expon -= 1;
+            } else {
+                break n;
+            }
+        };
+
+        let s = n.to_string();
+        if expon >= -7 && expon < digits.max(21) {
+            if expon >= 0 {
+                let int_len = expon as usize + 1;
+                if int_len >= s.len() {
+                    out.write_str(&s)?;
+                    for _ in s.len()..int_len {
+                        out.write_str("0")?;
+                    }
+                    return Ok(());
+                }
+                let frac = s[int_len..].trim_end_matches('0');
+                out.write_str(&s[..int_len])?;
+                if !frac.is_empty() {
+                    write!(out, ".{}", frac)?;
+                }
+                Ok(())
+            } else {
+                out.write_str("0.")?;
+                for _ in 0..(-expon - 1) {
+                    out.write_str("0")?;
+                }
+                out.write_str(s.trim_end_matches('0'))
+            }
+        } else {
+            let frac = s[1..].trim_end_matches('0');
+            out.write_str(&s[..1])?;
+            if !frac.is_empty() {
+                write!(out, ".{}", frac)?;
+            }
+            write!(out, "e{}", expon)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::parse;
+
+    fn eval_str(expr: &str, digits: u32) -> String {
+        let node = parse(expr);
+        node.eval_precise(digits).unwrap().to_string()
+    }
+
+    #[test]
+    fn it_computes_constants_with_many_digits() {
+        assert_eq!(
+            eval_str("pi", 50),
+            "3.1415926535897932384626433832795028841971693993751"
+        );
+        assert_eq!(eval_str("e", 30), "2.71828182845904523536028747135");
+        assert_eq!(
+            eval_str("sqrt(2)", 40),
+            "1.41421356237309504880168872420969807857"
+        );
+        assert_eq!(eval_str("1/3", 20), "0.33333333333333333333");
+    }
+
+    #[test]
+    fn it_computes_functions_with_many_digits() {
+        assert_eq!(eval_str("ln(10)", 30), "2.30258509299404568401799145468");
+        assert_eq!(eval_str("sin(1)", 30), "0.84147098480789650665250232163");
+        assert_eq!(
+            eval_str("atan(1) * 4", 30),
+            "3.14159265358979323846264338328"
+        );
+        assert_eq!(eval_str("2^(1/3)", 30), "1.25992104989487316476721060728");
+        assert_eq!(eval_str("2^pi", 30), "8.82497782707628762385642960421");
+        assert_eq!(eval_str("2^100", 40), "1267650600228229401496703205376");
+        assert_eq!(eval_str("10^-30 / 3", 5), "3.3333e-31");
+    }
+}
@@ -0,0 +1,216 @@
+use num_rational::BigRational;
+use num_traits::{One, Signed, Zero};
+
+use super::util::common::num;
+use super::util::{ratio_pow, ratio_to_i32};
+use super::Node;
+
+/// Returns `n` if the node is the square root of the number `n`.
+fn get_square_root(node: &Node) -> Option<&BigRational> {
+    match node {
+        Node::Exp(base, expon) => match (&**base, &**expon) {
+            (Node::Num { val: n, .. }, Node::Num { val, .. })
+                if *val == BigRational::new(1.into(), 2.into()) =>
+            {
+                Some(n)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the square of the node and whether it has a square root, if it is
+/// a number, a square root of a number or a number times such a square root.
+fn square_radical_term(node: &Node) -> Option<(BigRational, bool)> {
+    match node {
+        Node::Num { val, .. } => Some((val * val, false)),
+        Node::Product(factors) => match &factors[..] {
+            [Node::Num { val, .. }, root] => Some((val * val * get_square_root(root)?, true)),
+            _ => None,
+        },
+        node => Some((get_square_root(node)?.clone(), true)),
+    }
+}
+
+/// Returns `b` and `c` if the node is `b * sqrt(c)` where `b` and `c` are
+/// numbers, such as `2 * sqrt(2)` which is simplified to `2^(3/2)`.
+fn get_radical_term(node: &Node) -> Option<(BigRational, BigRational)> {
+    let (coef, root) = match node {
+        Node::Product(factors) => match &factors[..] {
+            [Node::Num { val, .. }, root] => (val.clone(), root),
+            _ => return None,
+        },
+        root => (BigRational::one(), root),
+    };
+    match root {
+        Node::Exp(base, expon) => match (&**base, &**expon) {
+            (Node::Num { val: base, .. }, Node::Num { val: expon, .. })
+                if *expon.denom() == 2.into() =>
+            {
+                // n^(k/2) = n^((k-1)/2) * sqrt(n)
+                let whole = ratio_to_i32(&expon.floor())?;
+                Some((coef * ratio_pow(base, whole), base.clone()))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the square root of the number if it is rational.
+fn rational_sqrt(val: &BigRational) -> Option<BigRational> {
+    if val.is_negative() {
+        return None;
+    }
+    let numer = val.numer().sqrt();
+    let denom = val.denom().sqrt();
+    if &numer * &numer == *val.numer() && &denom * &denom == *val.denom() {
+        Some(BigRational::new(numer, denom))
+    } else {
+        None
+    }
+}
+
+/// Denests the square root of `a + b * sqrt(c)` when it is the sum of two
+/// square roots of numbers, which is when `a^2 - b^2 * c` is the square of a
+/// rational number `d`. Then it is `sqrt((a + d)/2) + sqrt((a - d)/2)`, with
+/// the sign of `b`, such as `sqrt(3 + 2 * sqrt(2)) = 1 + sqrt(2)`.
+/// The result should be simplified.
+pub(crate) fn denest_sqrt(node: &Node) -> Option<Node> {
+    let (a, (b, c)) = match node {
+        Node::Sum(terms) => match &terms[..] {
+            [Node::Num { val, .. }, radical] | [radical, Node::Num { val, .. }] => {
+                (val, get_radical_term(radical)?)
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    if !a.is_positive() {
+        return None;
+    }
+    let d = rational_sqrt(&(a * a - &b * &b * c))?;
+    let half = BigRational::new(1.into(), 2.into());
+    // sqrt(n/m) is written as sqrt(n * m)/m
+    let sqrt = |val: BigRational| {
+        let inside = BigRational::from_integer(val.numer() * val.denom());
+        let outside = BigRational::new(1.into(), val.denom().clone());
+        num(outside) * Node::Exp(Box::new(num(inside)), Box::new(num(half.clone())))
+    };
+    let left = sqrt((a + &d) * &half);
+    let right = sqrt((a - &d) * &half);
+    Some(if b.is_negative() {
+        left - right
+    } else {
+        left + right
+    })
+}
+
+/// Rewrites a negative fractional power of an integer, such as `2^(-1/2)`,
+/// as a number times a positive power, such as `1/2 * 2^(1/2)`.
+fn rationalize_power(base: &BigRational, expon: &BigRational) -> Option<Node> {
+    if !base.is_integer() || *base <= BigRational::one() {
+        return None;
+    }
+    if !expon.is_negative() || expon.is_integer() {
+        return None;
+    }
+    let whole = expon.floor();
+    let fraction = expon - &whole;
+    let whole = ratio_to_i32(&whole)?;
+    Some(Node::Product(vec![
+        num(ratio_pow(base, whole)),
+        Node::Exp(Box::new(num(base.clone())), Box::new(num(fraction))),
+    ]))
+}
+
+/// Multiplies the numerator and the denominator of `1/(a + b)` by the
+/// conjugate `a - b` when it removes the square roots from the denominator.
+fn rationalize_sum(terms: &[Node]) -> Option<Node> {
+    let (a, b) = match terms {
+        [a, b] => (a, b),
+        _ => return None,
+    };
+    let (a_squared, a_has_root) = square_radical_term(a)?;
+    let (b_squared, b_has_root) = square_radical_term(b)?;
+    let denom = a_squared - b_squared;
+    if !(a_has_root || b_has_root) || denom.is_zero() {
+        return None;
+    }
+    // the signs of the conjugate are swapped to keep the factor positive
+    let conjugate = if denom.is_negative() {
+        Node::Sum(vec![-a.clone(), b.clone()])
+    } else {
+        Node::Sum(vec![a.clone(), -b.clone()])
+    };
+    Some(Node::Product(vec![
+        conjugate,
+        num(BigRational::one() / denom.abs()),
+    ]))
+}
+
+/// Removes the square roots from the denominators, such as `1/sqrt(2)`
+/// becoming `sqrt(2)/2` and `1/(1 + sqrt(3))` becoming `(sqrt(3) - 1)/2`.
+/// The result should be simplified.
+pub fn rationalize(node: Node) -> Node {
+    let node = node.map_children(rationalize);
+    let rationalized = match &node {
+        Node::Exp(base, expon) => match (&**base, &**expon) {
+            (Node::Num { val: base, .. }, Node::Num { val: expon, .. }) => {
+                rationalize_power(base, expon)
+            }
+            (Node::Sum(terms), Node::Num { val, .. }) if *val == -BigRational::one() => {
+                rationalize_sum(terms)
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+    rationalized.unwrap_or(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::SimplifyOptions;
+    use crate::test_util::{parse, simplify_str};
+
+    fn rationalize_str(expr: &str) -> String {
+        let node = parse(expr);
+        let options = SimplifyOptions {
+            rationalize: true,
+            ..Default::default()
+        };
+        node.simplify_with(&options).unwrap().result.to_string()
+    }
+
+    #[test]
+    fn it_rationalizes_square_roots() {
+        assert_eq!(rationalize_str("1/sqrt(2)"), "1/2 * 2^(1/2)");
+        assert_eq!(rationalize_str("x/sqrt(3)"), "1/3 * x * 3^(1/2)");
+        assert_eq!(rationalize_str("2^(-3/2)"), "1/4 * 2^(1/2)");
+    }
+
+    #[test]
+    fn it_denests_square_roots() {
+        assert_eq!(simplify_str("sqrt(3 + 2 * sqrt(2))"), "1 + 2^(1/2)");
+        assert_eq!(simplify_str("sqrt(5 - 2 * sqrt(6))"), "3^(1/2) - 2^(1/2)");
+        assert_eq!(
+            simplify_str("sqrt(2 + sqrt(3))"),
+            "1/2 * 6^(1/2) + 1/2 * 2^(1/2)"
+        );
+        // 1 - 2 is not a square
+        assert_eq!(simplify_str("sqrt(1 + sqrt(2))"), "(1 + 2^(1/2))^(1/2)");
+    }
+
+    #[test]
+    fn it_multiplies_by_the_conjugate() {
+        assert_eq!(rationalize_str("1/(1 + sqrt(3))"), "1/2 * (-1 + 3^(1/2))");
+        assert_eq!(
+            rationalize_str("1/(sqrt(2) - sqrt(3))"),
+            "-1 * 2^(1/2) - 3^(1/2)"
+        );
+        // the conjugate does not help
+        assert_eq!(rationalize_str("1/(1 + x)"), "1/(1 + x)");
+    }
+}
@@ -0,0 +1,94 @@
+use num_rational::BigRational;
+use num_traits::{One, Zero};
+
+use super::{EvalOptions, Unit};
+
+/// Gives the exchange rates between currencies. The library never fetches
+/// them itself, so the application chooses where they come from and when
+/// they are updated, and gives it to the calculations in the `rates` of the
+/// `EvalOptions`.
+pub trait RateProvider {
+    /// The value of one `from` in the currency `to`, or `None` if the rate is
+    /// not known. The inverse rate is tried if this one is not known, so that
+    /// providers can only give the rates to one currency.
+    fn rate(&self, from: Unit, to: Unit) -> Option<BigRational>;
+}
+
+/// Returns the value of one `from` in `to` with the provider of the options.
+pub(super) fn rate(from: Unit, to: Unit, options: &EvalOptions) -> Option<BigRational> {
+    if from == to {
+        return Some(BigRational::one());
+    }
+    let rates = options.rates.as_ref()?;
+    match rates.rate(from, to) {
+        Some(rate) => Some(rate),
+        None => rates
+            .rate(to, from)
+            .filter(|x| !x.is_zero())
+            .map(|x| x.recip()),
+    }
+    .filter(|x| *x > BigRational::zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{EvalError, SimplifyError};
+    use super::*;
+    use crate::test_util::parse;
+    use std::sync::Arc;
+
+    // The rates to the US dollar.
+    struct ToDollar;
+
+    impl RateProvider for ToDollar {
+        fn rate(&self, from: Unit, to: Unit) -> Option<BigRational> {
+            let rate = match from {
+                Unit::Euro => BigRational::new(108.into(), 100.into()),
+                Unit::Yen => BigRational::new(1.into(), 150.into()),
+                _ => return None,
+            };
+            if to == Unit::UsDollar {
+                Some(rate)
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn it_converts_currencies_with_the_given_rates() {
+        let options = EvalOptions {
+            rates: Some(Arc::new(ToDollar)),
+            ..EvalOptions::default()
+        };
+        let simplify_str = |expr: &str| {
+            parse(expr)
+                .simplify_with_options(&options)
+                .map(|x| x.result.to_string())
+        };
+        assert_eq!(simplify_str("25 EUR in USD"), Ok("27 USD".to_owned()));
+        // with the inverse rate
+        assert_eq!(simplify_str("3 USD in JPY"), Ok("450 JPY".to_owned()));
+        assert_eq!(
+            simplify_str("10 EUR + 5 USD"),
+            Ok("(395/27) EUR".to_owned())
+        );
+        assert_eq!(
+            simplify_str("1 EUR in GBP"),
+            Err(SimplifyError::UnknownRate)
+        );
+
+        let eval = parse("2 USD in EUR").eval_with_options(&options);
+        assert!((eval.unwrap().val - 2.0 / 1.08).abs() < 1e-12);
+        let calculation = parse("25 EUR in USD")
+            .calculate_with_options(&options)
+            .unwrap();
+        assert_eq!(calculation.to_string(), "27 USD");
+        // the provider is only used with the options
+        assert_eq!(parse("25 EUR in USD").eval(), Err(EvalError::UnknownRate));
+        assert_eq!(
+            parse("2 EUR + 3 EUR").eval().unwrap().to_string(),
+            "5.0 EUR"
+        );
+    }
+}
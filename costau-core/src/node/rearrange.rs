@@ -0,0 +1,267 @@
+use super::solve::SolveError;
+use super::util::common;
+use super::{ConstKind, Node};
+
+/// The number of operations after which the rearrangement gives up, so that
+/// it always ends.
+const MAX_STEPS: usize = 64;
+
+/// An inverse operation that was applied to both sides of an equation.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Operation {
+    /// The sides were swapped so that the variable is on the left
+    Swap,
+    /// The right side was subtracted from both sides because the variable is
+    /// on both sides
+    MoveToLeft,
+    /// The terms with the variable were collected, such as `a*x + x` becoming
+    /// `(a + 1) * x`
+    Collect,
+    Subtract(Node),
+    Divide(Node),
+    /// Both sides were raised to this power, such as 2 to undo a square root
+    Power(Node),
+    /// The logarithm in this base was taken on both sides, to undo a power
+    /// whose exponent has the variable
+    Log(Node),
+    /// Both sides became the exponents of this base, to undo a logarithm
+    Exponentiate(Node),
+    /// The inverse function with this name was applied to both sides, such as
+    /// `asin` to undo `sin`
+    Inverse(&'static str),
+}
+
+/// An operation and the equation that it gave.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RearrangeStep {
+    pub operation: Operation,
+    pub lhs: Node,
+    pub rhs: Node,
+}
+
+/// The result of making a variable the subject of an equation.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Rearrangement {
+    /// The value of the variable, which is the right side of the last step
+    pub result: Node,
+    pub steps: Vec<RearrangeStep>,
+}
+
+fn simplify(node: Node) -> Result<Node, SolveError> {
+    Ok(node.simplify()?.result)
+}
+
+/// Collects the terms of a sum with the variable if it is linear in it, and
+/// returns the term without the variable if there is one.
+fn collect(lhs: &Node, var: &str) -> Result<(Node, Option<Node>), SolveError> {
+    let x = Node::Var(var.to_owned());
+    let slope = lhs.coefficient(var, 1).ok_or(SolveError::Unsupported)?;
+    let intercept = lhs.coefficient(var, 0).ok_or(SolveError::Unsupported)?;
+    let zero = common::zero();
+    let is_linear = !simplify(lhs.derivative(var))?.contains_var(var);
+    if slope == zero || !is_linear {
+        return Err(SolveError::Unsupported);
+    }
+    let collected = slope * x;
+    if intercept == zero {
+        Ok((collected, None))
+    } else {
+        Ok((collected, Some(intercept)))
+    }
+}
+
+/// Returns the operation that undoes the outermost operation of `lhs` and
+/// the new sides, when only `lhs` has the variable. There is no operation if
+/// the node only had to be written differently.
+fn undo(lhs: Node, rhs: Node, var: &str) -> Result<(Option<Operation>, Node, Node), SolveError> {
+    let has_var = |x: &Node| x.contains_var(var);
+    let (operation, lhs, rhs) = match lhs {
+        Node::Sum(terms) => {
+            let (with, without): (Vec<Node>, Vec<Node>) = terms.into_iter().partition(has_var);
+            if with.len() > 1 || without.is_empty() {
+                let (collected, intercept) = collect(&Node::Sum(with), var)?;
+                let lhs = match intercept {
+                    Some(intercept) => {
+                        let mut terms = vec![collected, intercept];
+                        terms.extend(without);
+                        Node::Sum(terms)
+                    }
+                    None if without.is_empty() => collected,
+                    None => {
+                        let mut terms = vec![collected];
+                        terms.extend(without);
+                        Node::Sum(terms)
+                    }
+                };
+                return Ok((Some(Operation::Collect), lhs, rhs));
+            }
+            let others = simplify(Node::Sum(without))?;
+            let rhs = rhs - others.clone();
+            (
+                Operation::Subtract(others),
+                with.into_iter().next().unwrap(),
+                rhs,
+            )
+        }
+        Node::Product(factors) => {
+            let (with, without): (Vec<Node>, Vec<Node>) = factors.into_iter().partition(has_var);
+            if with.len() > 1 {
+                return Err(SolveError::Unsupported);
+            }
+            let others = simplify(Node::Product(without))?;
+            if others == common::one() {
+                return Ok((None, with.into_iter().next().unwrap(), rhs));
+            }
+            let rhs = rhs / others.clone();
+            (
+                Operation::Divide(others),
+                with.into_iter().next().unwrap(),
+                rhs,
+            )
+        }
+        Node::Exp(base, expon) => match (has_var(&base), has_var(&expon)) {
+            (true, false) => {
+                let power = simplify(common::one() / *expon)?;
+                let rhs = Node::Exp(Box::new(rhs), Box::new(power.clone()));
+                (Operation::Power(power), *base, rhs)
+            }
+            (false, true) => {
+                let rhs = rhs.log((*base).clone());
+                (Operation::Log(*base), *expon, rhs)
+            }
+            _ => return Err(SolveError::Unsupported),
+        },
+        Node::Ln(inner) => {
+            let e = Node::Const(ConstKind::E);
+            let rhs = Node::Exp(Box::new(e.clone()), Box::new(rhs));
+            (Operation::Exponentiate(e), *inner, rhs)
+        }
+        Node::Log(base, inner) if !has_var(&base) => {
+            let rhs = Node::Exp(base.clone(), Box::new(rhs));
+            (Operation::Exponentiate(*base), *inner, rhs)
+        }
+        Node::Sin(inner) => (Operation::Inverse("asin"), *inner, rhs.asin()),
+        Node::Cos(inner) => (Operation::Inverse("acos"), *inner, rhs.acos()),
+        Node::Tan(inner) => (Operation::Inverse("atan"), *inner, rhs.atan()),
+        Node::Asin(inner) => (Operation::Inverse("sin"), *inner, rhs.sin()),
+        Node::Acos(inner) => (Operation::Inverse("cos"), *inner, rhs.cos()),
+        Node::Atan(inner) => (Operation::Inverse("tan"), *inner, rhs.tan()),
+        _ => return Err(SolveError::Unsupported),
+    };
+    Ok((Some(operation), lhs, rhs))
+}
+
+/// Makes the variable `var` the subject of the equation `lhs = rhs` by
+/// applying inverse operations to both sides, such as `v = u + a*t` becoming
+/// `t = (v - u) / a`. The inverse functions give the principal values, the
+/// even roots are the positive ones, and the divisors are assumed not to be
+/// zero.
+pub fn rearrange(lhs: &Node, rhs: &Node, var: &str) -> Result<Rearrangement, SolveError> {
+    let mut steps = Vec::new();
+    let mut push = |operation, lhs: &Node, rhs: &Node| {
+        steps.push(RearrangeStep {
+            operation,
+            lhs: lhs.clone(),
+            rhs: rhs.clone(),
+        })
+    };
+    let (mut lhs, mut rhs) = (lhs.clone(), rhs.clone());
+    match (lhs.contains_var(var), rhs.contains_var(var)) {
+        (true, false) => {}
+        (false, true) => {
+            std::mem::swap(&mut lhs, &mut rhs);
+            push(Operation::Swap, &lhs, &rhs);
+        }
+        (true, true) => {
+            lhs = simplify(lhs - rhs)?;
+            rhs = common::zero();
+            push(Operation::MoveToLeft, &lhs, &rhs);
+        }
+        (false, false) => return Err(SolveError::Unsupported),
+    }
+
+    let x = Node::Var(var.to_owned());
+    for _ in 0..MAX_STEPS {
+        // a sum or a product of a single node is the node
+        lhs = match lhs {
+            Node::Sum(mut children) | Node::Product(mut children) if children.len() == 1 => {
+                children.pop().unwrap()
+            }
+            lhs => lhs,
+        };
+        if lhs == x {
+            return Ok(Rearrangement { result: rhs, steps });
+        }
+        let (operation, new_lhs, new_rhs) = undo(lhs, rhs, var)?;
+        lhs = new_lhs;
+        rhs = simplify(new_rhs)?;
+        if let Some(operation) = operation {
+            push(operation, &lhs, &rhs);
+        }
+    }
+    Err(SolveError::Unsupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    fn rearrange_str(lhs: &str, rhs: &str, var: &str) -> Result<String, SolveError> {
+        parse(lhs)
+            .rearrange(&parse(rhs), var)
+            .map(|x| x.result.to_string())
+    }
+
+    #[test]
+    fn it_makes_a_variable_the_subject() {
+        let rearrangement = parse("v").rearrange(&parse("u + a*t"), "t").unwrap();
+        assert_eq!(rearrangement.result.to_string(), "1/a * v + u * -1 / a");
+        let operations: Vec<Operation> = rearrangement
+            .steps
+            .iter()
+            .map(|x| x.operation.clone())
+            .collect();
+        assert_eq!(
+            operations,
+            vec![
+                Operation::Swap,
+                Operation::Subtract(parse("u")),
+                Operation::Divide(parse("a")),
+            ]
+        );
+
+        assert_eq!(
+            rearrange_str("A", "pi * r^2", "r"),
+            Ok("(A / pi)^(1/2)".to_owned())
+        );
+        assert_eq!(
+            rearrange_str("F", "9/5 * C + 32", "C"),
+            Ok("5/9 * (-32 + F)".to_owned())
+        );
+        assert_eq!(
+            rearrange_str("1/f", "1/u + 1/v", "v"),
+            Ok("1/(1/f - 1/u)".to_owned())
+        );
+        assert_eq!(
+            rearrange_str("y", "ln(x) + 1", "x"),
+            Ok("e^(-1 + y)".to_owned())
+        );
+        assert_eq!(rearrange_str("P", "2^n", "n"), Ok("log(2, P)".to_owned()));
+        assert_eq!(
+            rearrange_str("sin(x)", "1/2", "x"),
+            Ok("1/6 * pi".to_owned())
+        );
+
+        // the variable is on both sides
+        let rearrangement = parse("a*x + b").rearrange(&parse("c - x"), "x").unwrap();
+        assert_eq!(rearrangement.result.to_string(), "-1 * (-c + b) / (1 + a)");
+        assert_eq!(rearrangement.steps[0].operation, Operation::MoveToLeft);
+        assert_eq!(rearrangement.steps[1].operation, Operation::Collect);
+        assert_eq!(
+            rearrange_str("x^2 + x", "1", "x"),
+            Err(SolveError::Unsupported)
+        );
+        assert_eq!(rearrange_str("y", "1", "x"), Err(SolveError::Unsupported));
+    }
+}
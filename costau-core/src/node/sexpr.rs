@@ -0,0 +1,403 @@
+//! A text form of the AST as S-expressions, such as `(+ 1#10 (^ x 2#10))`,
+//! which shows the structure of the nodes exactly and can be parsed back.
+//!
+//! Numbers are written as fractions followed by `#` and the base they were
+//! written in, if any. Constants start with `#`, such as `#pi`, and the names
+//! of variables are quoted if they are not identifiers.
+
+use num_rational::BigRational;
+use std::str::FromStr;
+
+use super::{ConstKind, Node, Unit};
+
+/// The reason why a text cannot be parsed as an S-expression.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SexprError {
+    /// The text ends in the middle of an expression
+    UnexpectedEnd,
+    /// A parenthesis or an atom that is not allowed there
+    UnexpectedToken(String),
+    /// The first item of a list is not a known operator
+    UnknownOperator(String),
+    /// The operator got the wrong number of arguments
+    WrongArity(String),
+    /// An atom that looks like a number but is not one
+    InvalidNumber(String),
+    /// There is something after the expression
+    TrailingInput,
+}
+
+pub(super) type UnaryFn = fn(Box<Node>) -> Node;
+
+/// The functions that take a single argument.
+pub(super) const UNARY: [(&str, UnaryFn); 15] = [
+    ("sin", Node::Sin),
+    ("cos", Node::Cos),
+    ("tan", Node::Tan),
+    ("asin", Node::Asin),
+    ("acos", Node::Acos),
+    ("atan", Node::Atan),
+    ("sinh", Node::Sinh),
+    ("cosh", Node::Cosh),
+    ("tanh", Node::Tanh),
+    ("abs", Node::Abs),
+    ("floor", Node::Floor),
+    ("ceil", Node::Ceil),
+    ("round", Node::Round),
+    ("sign", Node::Sign),
+    ("ln", Node::Ln),
+];
+
+/// Returns the name and the argument of the node if it is a function that
+/// takes a single argument.
+pub(super) fn get_unary(node: &Node) -> Option<(&'static str, &Node)> {
+    let (i, inner) = match node {
+        Node::Sin(inner) => (0, inner),
+        Node::Cos(inner) => (1, inner),
+        Node::Tan(inner) => (2, inner),
+        Node::Asin(inner) => (3, inner),
+        Node::Acos(inner) => (4, inner),
+        Node::Atan(inner) => (5, inner),
+        Node::Sinh(inner) => (6, inner),
+        Node::Cosh(inner) => (7, inner),
+        Node::Tanh(inner) => (8, inner),
+        Node::Abs(inner) => (9, inner),
+        Node::Floor(inner) => (10, inner),
+        Node::Ceil(inner) => (11, inner),
+        Node::Round(inner) => (12, inner),
+        Node::Sign(inner) => (13, inner),
+        Node::Ln(inner) => (14, inner),
+        _ => return None,
+    };
+    Some((UNARY[i].0, inner))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn write_list(out: &mut String, op: &str, items: &[&Node]) {
+    out.push('(');
+    out.push_str(op);
+    for item in items {
+        out.push(' ');
+        write_node(out, item);
+    }
+    out.push(')');
+}
+
+fn write_node(out: &mut String, node: &Node) {
+    if let Some((name, inner)) = get_unary(node) {
+        return write_list(out, name, &[inner]);
+    }
+    match node {
+        Node::Const(kind) => out.push_str(match kind {
+            ConstKind::Pi => "#pi",
+            ConstKind::Tau => "#tau",
+            ConstKind::E => "#e",
+            ConstKind::I => "#i",
+        }),
+        Node::Var(name) if is_identifier(name) => out.push_str(name),
+        Node::Var(name) => {
+            out.push('"');
+            for c in name.chars() {
+                if c == '"' || c == '\\' {
+                    out.push('\\');
+                }
+                out.push(c);
+            }
+            out.push('"');
+        }
+        Node::Num { val, input_base } => {
+            out.push_str(&val.to_string());
+            if let Some(base) = input_base {
+                out.push_str(&format!("#{}", base));
+            }
+        }
+        Node::Sum(children) => write_list(out, "+", &children.iter().collect::<Vec<_>>()),
+        Node::Product(children) => write_list(out, "*", &children.iter().collect::<Vec<_>>()),
+        Node::Exp(a, b) => write_list(out, "^", &[a, b]),
+        Node::Vector(items) => write_list(out, "vector", &items.iter().collect::<Vec<_>>()),
+        Node::Ratio(terms) => write_list(out, "ratio", &terms.iter().collect::<Vec<_>>()),
+        Node::Dice { count, sides } => out.push_str(&format!("(dice {} {})", count, sides)),
+        Node::Time { offset } => out.push_str(&format!("(time {})", offset)),
+        Node::Atan2(y, x) => write_list(out, "atan2", &[y, x]),
+        Node::Min(children) => write_list(out, "min", &children.iter().collect::<Vec<_>>()),
+        Node::Max(children) => write_list(out, "max", &children.iter().collect::<Vec<_>>()),
+        Node::Log(base, inner) => write_list(out, "log", &[base, inner]),
+        Node::Quantity(inner, unit) => {
+            out.push_str(&format!("(unit {} ", unit));
+            write_node(out, inner);
+            out.push(')');
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Writes the node as an S-expression on a single line.
+pub fn to_sexpr(node: &Node) -> String {
+    let mut out = String::new();
+    write_node(&mut out, node);
+    out
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Open,
+    Close,
+    Atom(String),
+    Quoted(String),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, SexprError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => name.push(chars.next().ok_or(SexprError::UnexpectedEnd)?),
+                        Some(c) => name.push(c),
+                        None => return Err(SexprError::UnexpectedEnd),
+                    }
+                }
+                tokens.push(Token::Quoted(name));
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            _ => {
+                let mut atom = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c == '(' || c == ')' || c == '"' || c.is_whitespace() {
+                        break;
+                    }
+                    atom.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Atom(atom));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_atom(atom: &str) -> Result<Node, SexprError> {
+    let invalid = || SexprError::InvalidNumber(atom.to_owned());
+    match atom {
+        "#pi" => return Ok(Node::Const(ConstKind::Pi)),
+        "#tau" => return Ok(Node::Const(ConstKind::Tau)),
+        "#e" => return Ok(Node::Const(ConstKind::E)),
+        "#i" => return Ok(Node::Const(ConstKind::I)),
+        _ => {}
+    }
+    let is_number = atom
+        .trim_start_matches('-')
+        .starts_with(|c: char| c.is_ascii_digit());
+    if !is_number {
+        return if is_identifier(atom) {
+            Ok(Node::Var(atom.to_owned()))
+        } else {
+            Err(SexprError::UnexpectedToken(atom.to_owned()))
+        };
+    }
+    let (val, input_base) = match atom.find('#') {
+        Some(i) => {
+            let base = u32::from_str(&atom[i + 1..]).map_err(|_| invalid())?;
+            (&atom[..i], Some(base))
+        }
+        None => (atom, None),
+    };
+    let val = BigRational::from_str(val).map_err(|_| invalid())?;
+    Ok(Node::Num { val, input_base })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Result<Token, SexprError> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token.ok_or(SexprError::UnexpectedEnd)
+    }
+
+    fn parse_u32(&mut self) -> Result<u32, SexprError> {
+        match self.next()? {
+            Token::Atom(atom) => u32::from_str(&atom).map_err(|_| SexprError::InvalidNumber(atom)),
+            token => Err(SexprError::UnexpectedToken(format!("{:?}", token))),
+        }
+    }
+
+    fn parse_i64(&mut self) -> Result<i64, SexprError> {
+        match self.next()? {
+            Token::Atom(atom) => i64::from_str(&atom).map_err(|_| SexprError::InvalidNumber(atom)),
+            token => Err(SexprError::UnexpectedToken(format!("{:?}", token))),
+        }
+    }
+
+    fn parse_node(&mut self) -> Result<Node, SexprError> {
+        match self.next()? {
+            Token::Atom(atom) => parse_atom(&atom),
+            Token::Quoted(name) => Ok(Node::Var(name)),
+            Token::Close => Err(SexprError::UnexpectedToken(")".to_owned())),
+            Token::Open => {
+                let op = match self.next()? {
+                    Token::Atom(op) => op,
+                    token => return Err(SexprError::UnexpectedToken(format!("{:?}", token))),
+                };
+                if op == "dice" {
+                    let count = self.parse_u32()?;
+                    let sides = self.parse_u32()?;
+                    return match self.next()? {
+                        Token::Close => Ok(Node::Dice { count, sides }),
+                        _ => Err(SexprError::WrongArity(op)),
+                    };
+                }
+                if op == "time" {
+                    let offset = self.parse_i64()?;
+                    return match self.next()? {
+                        Token::Close => Ok(Node::Time { offset }),
+                        _ => Err(SexprError::WrongArity(op)),
+                    };
+                }
+                if op == "unit" {
+                    let unit = match self.next()? {
+                        Token::Atom(symbol) => {
+                            Unit::from_symbol(&symbol).ok_or(SexprError::UnexpectedToken(symbol))?
+                        }
+                        token => return Err(SexprError::UnexpectedToken(format!("{:?}", token))),
+                    };
+                    let inner = self.parse_node()?;
+                    return match self.next()? {
+                        Token::Close => Ok(Node::Quantity(Box::new(inner), unit)),
+                        _ => Err(SexprError::WrongArity(op)),
+                    };
+                }
+                let mut args = Vec::new();
+                while self.tokens.get(self.pos) != Some(&Token::Close) {
+                    args.push(self.parse_node()?);
+                }
+                self.pos += 1;
+                build(op, args)
+            }
+        }
+    }
+}
+
+/// Builds the node of the operator `op` with its arguments.
+fn build(op: String, mut args: Vec<Node>) -> Result<Node, SexprError> {
+    let binary = |mut args: Vec<Node>, f: fn(Box<Node>, Box<Node>) -> Node| {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        f(Box::new(a), Box::new(b))
+    };
+    let arity_ok = match op.as_str() {
+        "+" | "*" | "vector" | "ratio" | "min" | "max" => true,
+        "^" | "atan2" | "log" => args.len() == 2,
+        _ => args.len() == 1,
+    };
+    if !arity_ok {
+        return Err(SexprError::WrongArity(op));
+    }
+    Ok(match op.as_str() {
+        "+" => Node::Sum(args),
+        "*" => Node::Product(args),
+        "vector" => Node::Vector(args),
+        "ratio" => Node::Ratio(args),
+        "min" => Node::Min(args),
+        "max" => Node::Max(args),
+        "^" => binary(args, Node::Exp),
+        "atan2" => binary(args, Node::Atan2),
+        "log" => binary(args, Node::Log),
+        name => match UNARY.iter().find(|x| x.0 == name) {
+            Some((_, f)) => f(Box::new(args.pop().unwrap())),
+            None => return Err(SexprError::UnknownOperator(op)),
+        },
+    })
+}
+
+/// Parses a node written by `to_sexpr`.
+pub fn from_sexpr(text: &str) -> Result<Node, SexprError> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        pos: 0,
+    };
+    let node = parser.parse_node()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(SexprError::TrailingInput);
+    }
+    Ok(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    #[test]
+    fn it_writes_the_structure_of_nodes() {
+        assert_eq!(to_sexpr(&parse("1 + x^2")), "(+ 1#10 (^ x 2#10))");
+        assert_eq!(to_sexpr(&parse("sin(pi / 2)")), "(sin (* #pi (^ 2#10 -1)))");
+        let node = Node::Product(vec![
+            Node::Num {
+                val: BigRational::new((-3).into(), 4.into()),
+                input_base: None,
+            },
+            Node::Var("a b".to_owned()),
+        ]);
+        assert_eq!(to_sexpr(&node), "(* -3/4 \"a b\")");
+    }
+
+    #[test]
+    fn it_parses_back_what_it_writes() {
+        for expr in &[
+            "1 + x^2",
+            "atan2(y, 0xFF) * ln(e)",
+            "min(1, 2, 3)",
+            "3:2",
+            "log(2, 8)",
+            "5 km + 300 m in mi",
+        ] {
+            let node = parse(expr);
+            assert_eq!(from_sexpr(&to_sexpr(&node)), Ok(node));
+        }
+        let dice = Node::Dice { count: 2, sides: 6 };
+        assert_eq!(from_sexpr(&to_sexpr(&dice)), Ok(dice));
+        let time = Node::Time { offset: -12_600 };
+        assert_eq!(from_sexpr(&to_sexpr(&time)), Ok(time));
+    }
+
+    #[test]
+    fn it_reports_invalid_expressions() {
+        assert_eq!(from_sexpr("(+ 1 2"), Err(SexprError::UnexpectedEnd));
+        assert_eq!(
+            from_sexpr("(foo 1)"),
+            Err(SexprError::UnknownOperator("foo".to_owned()))
+        );
+        assert_eq!(
+            from_sexpr("(sin 1 2)"),
+            Err(SexprError::WrongArity("sin".to_owned()))
+        );
+        assert_eq!(from_sexpr("x y"), Err(SexprError::TrailingInput));
+    }
+}
@@ -1,4 +1,6 @@
 use itertools::Itertools;
+use num_bigint::BigInt;
+use num_integer::Integer;
 use num_rational::BigRational;
 use num_traits::{One, Pow, Signed, ToPrimitive, Zero};
 use std::collections::hash_map::DefaultHasher;
@@ -8,15 +10,43 @@ use std::hash::{Hash, Hasher};
 use std::iter;
 use std::ops::{Add, Mul};
 
-use super::util::{common, get_op_result_base, ratio_to_i32};
-use super::{ConstKind, Node};
+use super::budget;
+use super::logs;
+use super::radicals;
+use super::telemetry::{run_pass, Pass, Rule, Telemetry};
+use super::together;
+use super::units;
+use super::util::{common, get_op_result_base, is_minus_one, ratio_pow, ratio_to_i32};
+use super::{ConstKind, EvalOptions, Node};
+use crate::ratio2flt::ratio_to_f64;
+
+/// The highest prime that is tried when taking perfect powers out of roots,
+/// such as `sqrt(12)` becoming `2 * sqrt(3)`.
+const MAX_RADICAL_FACTOR: u32 = 1000;
 
 /// A description of an error that happened while trying to simplify a node.
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum SimplifyError {
+    DivisionByZero,
     ZeroToPowerOfNonPositive,
     ComplexRoot,
     Tan90Or270,
+    OutOfDomain,
+    LogOfNonPositive,
+    InvalidLogBase,
+    /// Quantities of different dimensions were combined, such as meters
+    /// added to seconds
+    IncompatibleUnits,
+    /// The exchange rate between two currencies is not known
+    UnknownRate,
+    /// The calculation has a time, such as `now()`, but the options have no
+    /// clock
+    NoClock,
+    /// The calculation would take more memory than the budget of the
+    /// `EvalOptions`
+    OverBudget,
+    /// A vector was added to a scalar, such as in `(1, 2) + 3`
+    VectorAndScalar,
 }
 
 /// The value returned by the `simplify` function when it succeeds.
@@ -29,6 +59,121 @@ pub struct SimplifySuccess {
     pub did_something: bool,
 }
 
+/// The form that the logarithms are rewritten to after the simplification.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum LogForm {
+    /// `ln(a * b)` becomes `ln(a) + ln(b)` and `ln(a^b)` becomes `b * ln(a)`
+    Expand,
+    /// `ln(a) + ln(b)` becomes `ln(a * b)` and `b * ln(a)` becomes `ln(a^b)`
+    /// when `b` is a number
+    Combine,
+}
+
+/// The options that change the result of the simplification, for rules that
+/// do not always make a node simpler.
+#[derive(Debug, PartialEq, Eq, Default, Copy, Clone)]
+pub struct SimplifyOptions {
+    /// The form of the logarithms, which are kept as they are if it is
+    /// `None`
+    pub logs: Option<LogForm>,
+    /// Whether the square roots are removed from the denominators, such as
+    /// `1/sqrt(2)` becoming `sqrt(2)/2`
+    pub rationalize: bool,
+    /// Whether the sums of fractions are written over a common denominator,
+    /// such as `1/x + 1/y` becoming `(x + y)/(x * y)`
+    pub together: bool,
+}
+
+/// What the rules need besides the node, which is passed down the
+/// simplification.
+pub(super) struct Context<'a> {
+    /// The options of the calculation, such as the exchange rates
+    pub options: &'a EvalOptions,
+    /// Receives the rules that fire
+    pub telemetry: &'a mut dyn Telemetry,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(options: &'a EvalOptions, telemetry: &'a mut dyn Telemetry) -> Context<'a> {
+        Context { options, telemetry }
+    }
+}
+
+/// Simplifies the node and applies the rules selected by the options.
+pub fn simplify_with(
+    node: Node,
+    options: &SimplifyOptions,
+) -> Result<SimplifySuccess, SimplifyError> {
+    simplify_with_telemetry(node, options, &mut ())
+}
+
+/// Like `simplify_with`, but reports the rules that fired and the time of
+/// each pass.
+pub fn simplify_with_telemetry(
+    node: Node,
+    options: &SimplifyOptions,
+    telemetry: &mut dyn Telemetry,
+) -> Result<SimplifySuccess, SimplifyError> {
+    simplify_passes(node, options, &EvalOptions::default(), telemetry)
+}
+
+/// Runs the passes that the options select with the options of the
+/// calculation.
+pub(super) fn simplify_passes(
+    node: Node,
+    options: &SimplifyOptions,
+    eval_options: &EvalOptions,
+    telemetry: &mut dyn Telemetry,
+) -> Result<SimplifySuccess, SimplifyError> {
+    let simplified = run_pass(telemetry, Pass::Simplify, |telemetry| {
+        units::simplify_units(node, &mut Context::new(eval_options, telemetry))
+    })?;
+    let logs = match options.logs {
+        None => simplified.result.clone(),
+        Some(LogForm::Expand) => run_pass(telemetry, Pass::Logs, |_| {
+            logs::expand_logs(simplified.result.clone())
+        }),
+        Some(LogForm::Combine) => run_pass(telemetry, Pass::Logs, |_| {
+            logs::combine_logs(simplified.result.clone())
+        }),
+    };
+    let mut rewritten = if options.rationalize {
+        run_pass(telemetry, Pass::Rationalize, |_| {
+            radicals::rationalize(logs)
+        })
+    } else {
+        logs
+    };
+    if rewritten != simplified.result {
+        rewritten = run_pass(telemetry, Pass::Simplify, |telemetry| {
+            simplify_node(rewritten, &mut Context::new(eval_options, telemetry))
+        })?
+        .result;
+    }
+    // the simplification would expand the fractions again
+    if options.together {
+        rewritten = run_pass(telemetry, Pass::Together, |telemetry| {
+            together::together_in(rewritten, &mut Context::new(eval_options, telemetry))
+        });
+    }
+    if rewritten == simplified.result {
+        return Ok(simplified);
+    }
+    Ok(SimplifySuccess {
+        result: rewritten,
+        did_something: true,
+    })
+}
+
+/// Returns the result of a rule that changed the node.
+fn fired(rule: Rule, result: Node, cx: &mut Context<'_>) -> SimplifySuccess {
+    cx.telemetry.rule_fired(rule);
+    SimplifySuccess {
+        result,
+        did_something: true,
+    }
+}
+
 /// Simplifies the node.
 /// If the algorithm fails to simplify the node because the node is already
 /// simplified or because it is too complex, then the function will return a
@@ -37,7 +182,16 @@ pub struct SimplifySuccess {
 /// invalid, then the function will return an error.
 /// Otherwise, it returns a success with the simplified node.
 pub fn simplify(node: Node) -> Result<SimplifySuccess, SimplifyError> {
+    simplify_node(node, &mut Context::new(&EvalOptions::default(), &mut ()))
+}
+
+/// Like `simplify`, but with the context of the calculation.
+pub(super) fn simplify_node(
+    node: Node,
+    cx: &mut Context<'_>,
+) -> Result<SimplifySuccess, SimplifyError> {
     match node {
+        node @ Node::Quantity(..) => units::simplify_units(node, cx),
         Node::Const(ConstKind::Tau) => {
             Ok(SimplifySuccess {
                 result: Node::Const(ConstKind::Pi) * common::two(),
@@ -46,11 +200,25 @@ pub fn simplify(node: Node) -> Result<SimplifySuccess, SimplifyError> {
                 did_something: false,
             })
         }
-        Node::Sum(children) => simplify_vararg_op(children, true),
-        Node::Product(children) => simplify_vararg_op(children, false),
-        Node::Exp(lhs, rhs) => simplify_exp(*lhs, *rhs),
+        Node::Sum(children) => simplify_vararg_op(children, true, cx),
+        Node::Product(children) => simplify_vararg_op(children, false, cx),
+        Node::Exp(lhs, rhs) => simplify_exp(*lhs, *rhs, cx),
+        Node::Ratio(terms) => simplify_ratio(terms, cx),
+        Node::Vector(items) => {
+            let mut did_something = false;
+            let mut result = Vec::with_capacity(items.len());
+            for item in items {
+                let tmp = simplify_node(item, cx)?;
+                did_something |= tmp.did_something;
+                result.push(tmp.result);
+            }
+            Ok(SimplifySuccess {
+                result: Node::Vector(result),
+                did_something,
+            })
+        }
         Node::Sin(ref inner) | Node::Cos(ref inner) | Node::Tan(ref inner) => {
-            let inner_simplified = simplify(*inner.clone())?;
+            let inner_simplified = simplify_node(*inner.clone(), cx)?;
             if let Some(mut pi_factor) = get_pi_factor(&inner_simplified.result) {
                 // simplify (2a + b)pi as b*pi with -1 <= b <= 1
                 pi_factor %= BigRational::from_integer(2.into());
@@ -60,25 +228,27 @@ pub fn simplify(node: Node) -> Result<SimplifySuccess, SimplifyError> {
                     pi_factor += BigRational::from_integer(2.into());
                 }
                 if pi_factor.is_zero() {
-                    return Ok(SimplifySuccess {
-                        result: match &node {
+                    return Ok(fired(
+                        Rule::ExactTrig,
+                        match &node {
                             Node::Sin(_) => common::zero(),
                             Node::Cos(_) => common::one(),
                             Node::Tan(_) => common::zero(),
                             _ => unreachable!(),
                         },
-                        did_something: true,
-                    });
+                        cx,
+                    ));
                 } else if pi_factor.is_one() {
-                    return Ok(SimplifySuccess {
-                        result: match &node {
+                    return Ok(fired(
+                        Rule::ExactTrig,
+                        match &node {
                             Node::Sin(_) => common::zero(),
                             Node::Cos(_) => common::minus_one(),
                             Node::Tan(_) => common::zero(),
                             _ => unreachable!(),
                         },
-                        did_something: true,
-                    });
+                        cx,
+                    ));
                 } else if *pi_factor.denom() == 2.into() {
                     // could be 1/2 or 3/2
                     let simplified_node = if pi_factor.numer().is_one() {
@@ -96,18 +266,16 @@ pub fn simplify(node: Node) -> Result<SimplifySuccess, SimplifyError> {
                             _ => unreachable!(),
                         }
                     };
-                    return Ok(SimplifySuccess {
-                        result: simplified_node,
-                        did_something: true,
-                    });
+                    return Ok(fired(Rule::ExactTrig, simplified_node, cx));
                 } else if *pi_factor.denom() == 3.into() {
                     // pi/2 < x < 3pi/2
                     let is_left = *pi_factor.numer() > 1.into() && *pi_factor.numer() < 5.into();
                     // 0 < x < pi
                     let is_top = *pi_factor.numer() < 3.into();
 
-                    return Ok(SimplifySuccess {
-                        result: match &node {
+                    return Ok(fired(
+                        Rule::ExactTrig,
+                        match &node {
                             Node::Sin(_) if is_top => common::three().sqrt() / common::two(),
                             Node::Sin(_) if !is_top => -common::three().sqrt() / common::two(),
                             Node::Cos(_) if !is_left => common::two().inverse(),
@@ -116,46 +284,99 @@ pub fn simplify(node: Node) -> Result<SimplifySuccess, SimplifyError> {
                             Node::Tan(_) if is_top == is_left => -common::three().sqrt(),
                             _ => unreachable!(),
                         },
-                        did_something: true,
-                    });
+                        cx,
+                    ));
                 } else if *pi_factor.denom() == 4.into() {
                     // pi/2 < x < 3pi/2
                     let is_left = *pi_factor.numer() > 2.into() && *pi_factor.numer() < 6.into();
                     // 0 < x < pi
                     let is_top = *pi_factor.numer() < 4.into();
 
-                    return Ok(SimplifySuccess {
-                        result: match &node {
-                            Node::Sin(_) if is_top => common::two().sqrt().inverse(),
-                            Node::Sin(_) if !is_top => -common::two().sqrt().inverse(),
-                            Node::Cos(_) if !is_left => common::two().sqrt().inverse(),
-                            Node::Cos(_) if is_left => -common::two().sqrt().inverse(),
+                    return Ok(fired(
+                        Rule::ExactTrig,
+                        match &node {
+                            Node::Sin(_) if is_top => common::two().sqrt() / common::two(),
+                            Node::Sin(_) if !is_top => -common::two().sqrt() / common::two(),
+                            Node::Cos(_) if !is_left => common::two().sqrt() / common::two(),
+                            Node::Cos(_) if is_left => -common::two().sqrt() / common::two(),
                             Node::Tan(_) if is_top != is_left => common::one(),
                             Node::Tan(_) if is_top == is_left => common::minus_one(),
                             _ => unreachable!(),
                         },
-                        did_something: true,
-                    });
+                        cx,
+                    ));
                 } else if *pi_factor.denom() == 6.into() {
                     // pi/2 < x < 3pi/2
                     let is_left = *pi_factor.numer() > 3.into() && *pi_factor.numer() < 9.into();
                     // 0 < x < pi
                     let is_top = *pi_factor.numer() < 6.into();
 
-                    return Ok(SimplifySuccess {
-                        result: match &node {
+                    return Ok(fired(
+                        Rule::ExactTrig,
+                        match &node {
                             Node::Sin(_) if is_top => common::two().inverse(),
                             Node::Sin(_) if !is_top => -common::two().inverse(),
                             Node::Cos(_) if !is_left => common::three().sqrt() / common::two(),
                             Node::Cos(_) if is_left => -common::three().sqrt() / common::two(),
-                            Node::Tan(_) if is_top != is_left => common::three().sqrt().inverse(),
-                            Node::Tan(_) if is_top == is_left => -common::three().sqrt().inverse(),
+                            Node::Tan(_) if is_top != is_left => {
+                                common::three().sqrt() / common::three()
+                            }
+                            Node::Tan(_) if is_top == is_left => {
+                                -common::three().sqrt() / common::three()
+                            }
                             _ => unreachable!(),
                         },
-                        did_something: true,
-                    });
+                        cx,
+                    ));
+                } else if *pi_factor.denom() == 12.into() {
+                    let numer = pi_factor.numer();
+                    // pi/2 < x < 3pi/2
+                    let is_left = *numer > 6.into() && *numer < 18.into();
+                    // 0 < x < pi
+                    let is_top = *numer < 12.into();
+                    // the angle is pi/12 or 5pi/12 away from the x-axis
+                    let is_small = *numer == 1.into()
+                        || *numer == 11.into()
+                        || *numer == 13.into()
+                        || *numer == 23.into();
+
+                    let six = Node::Num {
+                        val: BigRational::from_integer(6.into()),
+                        input_base: None,
+                    };
+                    let four = Node::Num {
+                        val: BigRational::from_integer(4.into()),
+                        input_base: None,
+                    };
+                    // (sqrt(6) - sqrt(2))/4 and (sqrt(6) + sqrt(2))/4
+                    let low = (six.clone().sqrt() - common::two().sqrt()) / four.clone();
+                    let high = (six.sqrt() + common::two().sqrt()) / four;
+                    let (sin, cos, tan) = if is_small {
+                        (low, high, common::two() - common::three().sqrt())
+                    } else {
+                        (high, low, common::two() + common::three().sqrt())
+                    };
+                    return Ok(fired(
+                        Rule::ExactTrig,
+                        match &node {
+                            Node::Sin(_) if is_top => sin,
+                            Node::Sin(_) => -sin,
+                            Node::Cos(_) if !is_left => cos,
+                            Node::Cos(_) => -cos,
+                            Node::Tan(_) if is_top != is_left => tan,
+                            Node::Tan(_) => -tan,
+                            _ => unreachable!(),
+                        },
+                        cx,
+                    ));
                 }
             }
+            if let Some(result) = simplify_symmetry(&node, &inner_simplified.result) {
+                return Ok(fired(Rule::Symmetry, simplify_node(result, cx)?.result, cx));
+            }
+            if let Some(result) = shift_trig_argument(&node, &inner_simplified.result) {
+                return Ok(fired(Rule::Symmetry, simplify_node(result, cx)?.result, cx));
+            }
             // failed to simplify with common angle
             Ok(SimplifySuccess {
                 result: match &node {
@@ -167,6 +388,44 @@ pub fn simplify(node: Node) -> Result<SimplifySuccess, SimplifyError> {
                 did_something: inner_simplified.did_something,
             })
         }
+        Node::Asin(_) | Node::Acos(_) | Node::Atan(_) => simplify_inverse_trig(node, cx),
+        Node::Atan2(y, x) => simplify_atan2(*y, *x, cx),
+        Node::Sinh(ref inner) | Node::Cosh(ref inner) | Node::Tanh(ref inner) => {
+            let inner_simplified = simplify_node(*inner.clone(), cx)?;
+            if let Node::Num { val, .. } = &inner_simplified.result {
+                if val.is_zero() {
+                    return Ok(fired(
+                        Rule::ExactTrig,
+                        match &node {
+                            Node::Sinh(_) => common::zero(),
+                            Node::Cosh(_) => common::one(),
+                            Node::Tanh(_) => common::zero(),
+                            _ => unreachable!(),
+                        },
+                        cx,
+                    ));
+                }
+            }
+            if let Some(result) = simplify_symmetry(&node, &inner_simplified.result) {
+                return Ok(fired(Rule::Symmetry, simplify_node(result, cx)?.result, cx));
+            }
+            Ok(SimplifySuccess {
+                result: match &node {
+                    Node::Sinh(_) => inner_simplified.result.sinh(),
+                    Node::Cosh(_) => inner_simplified.result.cosh(),
+                    Node::Tanh(_) => inner_simplified.result.tanh(),
+                    _ => unreachable!(),
+                },
+                did_something: inner_simplified.did_something,
+            })
+        }
+        Node::Abs(_) | Node::Floor(_) | Node::Ceil(_) | Node::Round(_) | Node::Sign(_) => {
+            simplify_rounding(node, cx)
+        }
+        Node::Min(children) => simplify_min_max(children, true, cx),
+        Node::Max(children) => simplify_min_max(children, false, cx),
+        Node::Ln(inner) => simplify_log(Node::Const(ConstKind::E), *inner, true, cx),
+        Node::Log(base, inner) => simplify_log(*base, *inner, false, cx),
 
         // fallback to doing nothing
         node => Ok(SimplifySuccess {
@@ -176,6 +435,146 @@ pub fn simplify(node: Node) -> Result<SimplifySuccess, SimplifyError> {
     }
 }
 
+/// Returns the opposite of the node if it is written with a minus sign, for
+/// example `x` for `-x`, `2x` for `-2x` and `x - y` for `-x + y`.
+fn strip_negation(node: &Node) -> Option<Node> {
+    match node {
+        Node::Num { val, input_base } if val.is_negative() => Some(Node::Num {
+            val: -val,
+            input_base: *input_base,
+        }),
+        Node::Product(children) => {
+            let index = children
+                .iter()
+                .position(|c| matches!(c, Node::Num { val, .. } if val.is_negative()))?;
+            let mut children = children.clone();
+            match strip_negation(&children[index])? {
+                Node::Num { ref val, .. } if val.is_one() => {
+                    children.remove(index);
+                }
+                positive => children[index] = positive,
+            }
+            Some(if children.len() == 1 {
+                children.pop().unwrap()
+            } else {
+                Node::Product(children)
+            })
+        }
+        Node::Sum(children) => {
+            // the first term decides so that the result is canonical
+            strip_negation(children.first()?)?;
+            Some(Node::Sum(
+                children
+                    .iter()
+                    .map(|c| strip_negation(c).unwrap_or_else(|| -c.clone()))
+                    .collect(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Takes the minus sign out of the argument of an even or odd function, for
+/// example `cos(-x) = cos(x)` and `sin(-x) = -sin(x)`.
+fn simplify_symmetry(node: &Node, inner: &Node) -> Option<Node> {
+    let is_even = match node {
+        Node::Cos(_) | Node::Cosh(_) | Node::Abs(_) => true,
+        Node::Sin(_)
+        | Node::Tan(_)
+        | Node::Asin(_)
+        | Node::Atan(_)
+        | Node::Sinh(_)
+        | Node::Tanh(_)
+        | Node::Sign(_) => false,
+        _ => return None,
+    };
+    let positive = strip_negation(inner)?;
+    let result = match node {
+        Node::Cos(_) => positive.cos(),
+        Node::Cosh(_) => positive.cosh(),
+        Node::Abs(_) => positive.abs(),
+        Node::Sin(_) => positive.sin(),
+        Node::Tan(_) => positive.tan(),
+        Node::Asin(_) => positive.asin(),
+        Node::Atan(_) => positive.atan(),
+        Node::Sinh(_) => positive.sinh(),
+        Node::Tanh(_) => positive.tanh(),
+        Node::Sign(_) => positive.sign(),
+        _ => unreachable!(),
+    };
+    Some(if is_even { result } else { -result })
+}
+
+/// Removes the multiples of `pi/2` that are added to the argument of a
+/// trigonometric function, for example `sin(x + 2pi) = sin(x)` and
+/// `cos(x + pi) = -cos(x)`.
+fn shift_trig_argument(node: &Node, inner: &Node) -> Option<Node> {
+    let terms = match inner {
+        Node::Sum(terms) => terms,
+        _ => return None,
+    };
+    let mut pi_factor: BigRational = Zero::zero();
+    let mut rest = Vec::new();
+    for term in terms {
+        match get_pi_factor(term) {
+            Some(factor) => pi_factor += factor,
+            None => rest.push(term.clone()),
+        }
+    }
+    if pi_factor.is_zero() || rest.is_empty() {
+        return None;
+    }
+    let quarter_turns = pi_factor * BigRational::from_integer(2.into());
+    if !quarter_turns.is_integer() {
+        return None;
+    }
+    let quarter_turns = quarter_turns.numer().mod_floor(&4.into()).to_u32().unwrap();
+    let x = if rest.len() == 1 {
+        rest.pop().unwrap()
+    } else {
+        Node::Sum(rest)
+    };
+    Some(match (node, quarter_turns) {
+        (Node::Sin(_), 0) | (Node::Cos(_), 3) => x.sin(),
+        (Node::Sin(_), 1) | (Node::Cos(_), 0) => x.cos(),
+        (Node::Sin(_), 2) | (Node::Cos(_), 1) => -x.sin(),
+        (Node::Sin(_), 3) | (Node::Cos(_), 2) => -x.cos(),
+        (Node::Tan(_), 0) | (Node::Tan(_), 2) => x.tan(),
+        // tan(x + pi/2) = -1/tan(x)
+        (Node::Tan(_), _) => -x.tan().inverse(),
+        _ => unreachable!(),
+    })
+}
+
+/// Returns the number that the node is equal to, if it is made of numbers
+/// only, for example `3 * 1/4`.
+fn get_num_value(node: &Node) -> Option<BigRational> {
+    match node {
+        Node::Num { val, .. } => Some(val.clone()),
+        Node::Exp(a, b) if is_minus_one(b) => match get_num_value(a) {
+            Some(val) if !val.is_zero() => Some(val.recip()),
+            _ => None,
+        },
+        Node::Product(children) => {
+            let mut total: BigRational = One::one();
+            for child in children {
+                total *= get_num_value(child)?;
+            }
+            Some(total)
+        }
+        Node::Sum(children) => {
+            let mut total: BigRational = Zero::zero();
+            for child in children {
+                total += get_num_value(child)?;
+            }
+            Some(total)
+        }
+        _ => None,
+    }
+}
+
+/// Returns `x` if the node is equal to `x * pi` where `x` is a rational
+/// number, for example `3pi/4` or `pi/2 + pi`.
 fn get_pi_factor(node: &Node) -> Option<BigRational> {
     match node {
         Node::Const(ConstKind::Pi) => Some(BigRational::from_integer(1.into())),
@@ -185,7 +584,7 @@ fn get_pi_factor(node: &Node) -> Option<BigRational> {
             let mut total_factor: BigRational = One::one();
             let mut has_pi = false;
             for child in children {
-                if let Node::Num { val, .. } = child {
+                if let Some(val) = get_num_value(child) {
                     if val.is_zero() {
                         // zero times anything is zero
                         return Some(Zero::zero());
@@ -197,9 +596,8 @@ fn get_pi_factor(node: &Node) -> Option<BigRational> {
                         return Some(Zero::zero());
                     }
                     if has_pi {
-                        // We already have pi, so this will be pi^2 which we
-                        // do not support here because we're supposed to
-                        // return the multiplier of pi as an integer.
+                        // We already have pi, so this will be pi^2 which is
+                        // not a rational multiple of pi.
                         return None;
                     }
                     total_factor *= factor;
@@ -215,11 +613,516 @@ fn get_pi_factor(node: &Node) -> Option<BigRational> {
                 None
             }
         }
-        _ => None,
+        Node::Sum(children) => {
+            // every term must be a multiple of pi
+            let mut total_factor: BigRational = Zero::zero();
+            for child in children {
+                total_factor += get_pi_factor(child)?;
+            }
+            Some(total_factor)
+        }
+        _ => None,
+    }
+}
+
+/// Simplifies a ratio so that its terms are coprime integers if they are all
+/// numbers, for example `6:4 = 3:2` and `0.5:1 = 1:2`.
+fn simplify_ratio(
+    terms: Vec<Node>,
+    cx: &mut Context<'_>,
+) -> Result<SimplifySuccess, SimplifyError> {
+    let mut did_something = false;
+    let mut result = Vec::with_capacity(terms.len());
+    for term in terms {
+        let tmp = simplify_node(term, cx)?;
+        did_something |= tmp.did_something;
+        result.push(tmp.result);
+    }
+
+    let mut values = Vec::with_capacity(result.len());
+    for term in &result {
+        match term {
+            Node::Num { val, input_base } => values.push((val, *input_base)),
+            _ => {
+                return Ok(SimplifySuccess {
+                    result: Node::Ratio(result),
+                    did_something,
+                })
+            }
+        }
+    }
+    // multiply by the LCM of the denominators and divide by the GCD of the
+    // numerators
+    let mut lcm: BigInt = One::one();
+    let mut gcd: BigInt = Zero::zero();
+    for (val, _) in &values {
+        lcm = lcm.lcm(val.denom());
+        gcd = gcd.gcd(val.numer());
+    }
+    if gcd.is_zero() {
+        // all terms are zero
+        return Ok(SimplifySuccess {
+            result: Node::Ratio(result),
+            did_something,
+        });
+    }
+    let factor = BigRational::new(lcm, gcd);
+    if factor.is_one() {
+        return Ok(SimplifySuccess {
+            result: Node::Ratio(result),
+            did_something,
+        });
+    }
+    Ok(fired(
+        Rule::Ratio,
+        Node::Ratio(
+            values
+                .into_iter()
+                .map(|(val, input_base)| Node::Num {
+                    val: val * &factor,
+                    input_base,
+                })
+                .collect(),
+        ),
+        cx,
+    ))
+}
+
+/// Simplifies `asin`, `acos` and `atan`.
+fn simplify_inverse_trig(
+    node: Node,
+    cx: &mut Context<'_>,
+) -> Result<SimplifySuccess, SimplifyError> {
+    let (inner, (min_angle, max_angle)) = match node {
+        Node::Asin(ref inner) => (inner, (-1, 1)),
+        Node::Acos(ref inner) => (inner, (0, 2)),
+        Node::Atan(ref inner) => (inner, (-1, 1)),
+        _ => unreachable!(),
+    };
+    let inner_simplified = simplify_node(*inner.clone(), cx)?;
+    if let Node::Num { val, .. } = &inner_simplified.result {
+        if val.abs() > One::one() && !matches!(node, Node::Atan(_)) {
+            return Err(SimplifyError::OutOfDomain);
+        }
+    }
+
+    // Look for the angle in the range of the function whose sine, cosine or
+    // tangent has the same value, using the common angles that `simplify`
+    // knows about. The range is [min_angle*pi/2; max_angle*pi/2].
+    // The same value can be written in different ways, for example
+    // `1/sqrt(2)` and `sqrt(2)/2`, so the values are compared by their sign
+    // and their exact square.
+    if let Some(inner_square) = signed_square(&inner_simplified.result) {
+        for denom in &[1, 2, 3, 4, 6] {
+            for numer in (min_angle * denom)..=(max_angle * denom) {
+                let pi_factor = Node::Num {
+                    val: BigRational::new(numer.into(), (2 * denom).into()),
+                    input_base: None,
+                };
+                let angle = simplify_node(pi_factor * Node::Const(ConstKind::Pi), cx)?.result;
+                let value = match &node {
+                    Node::Asin(_) => simplify_node(angle.clone().sin(), cx),
+                    Node::Acos(_) => simplify_node(angle.clone().cos(), cx),
+                    Node::Atan(_) => simplify_node(angle.clone().tan(), cx),
+                    _ => unreachable!(),
+                };
+                // The values from the table of common angles are not
+                // simplified.
+                let value = match value.and_then(|x| simplify_node(x.result, cx)) {
+                    Ok(x) => x.result,
+                    // tan(pi/2) is undefined
+                    Err(SimplifyError::Tan90Or270) => continue,
+                    Err(err) => return Err(err),
+                };
+                if signed_square(&value).as_ref() == Some(&inner_square) {
+                    return Ok(fired(Rule::InverseTrig, angle, cx));
+                }
+            }
+        }
+    }
+
+    if let Some(result) = simplify_symmetry(&node, &inner_simplified.result) {
+        return Ok(fired(Rule::Symmetry, simplify_node(result, cx)?.result, cx));
+    }
+
+    // failed to simplify with common angle
+    Ok(SimplifySuccess {
+        result: match &node {
+            Node::Asin(_) => inner_simplified.result.asin(),
+            Node::Acos(_) => inner_simplified.result.acos(),
+            Node::Atan(_) => inner_simplified.result.atan(),
+            _ => unreachable!(),
+        },
+        did_something: inner_simplified.did_something,
+    })
+}
+
+/// Returns the rational value of a number, or of a product of integer powers
+/// of numbers.
+fn exact_rational(node: &Node) -> Option<BigRational> {
+    match node {
+        Node::Num { val, .. } => Some(val.clone()),
+        Node::Exp(base, expon) => {
+            let base = exact_rational(base)?;
+            let expon = ratio_to_i32(&exact_rational(expon)?)?;
+            if expon.abs() > 4 || (base.is_zero() && expon < 0) {
+                return None;
+            }
+            Some(ratio_pow(&base, expon))
+        }
+        Node::Product(factors) => factors
+            .iter()
+            .try_fold(BigRational::one(), |acc, x| Some(acc * exact_rational(x)?)),
+        _ => None,
+    }
+}
+
+/// Returns whether the value of a node is negative and its square, if it is
+/// a product of rational numbers and square roots of rational numbers, which
+/// are the forms of the sines, cosines and tangents of the common angles.
+fn signed_square(node: &Node) -> Option<(bool, BigRational)> {
+    if let Some(val) = exact_rational(node) {
+        return Some((val.is_negative(), &val * &val));
+    }
+    match node {
+        Node::Product(factors) => {
+            factors
+                .iter()
+                .try_fold((false, BigRational::one()), |(acc_neg, acc_square), x| {
+                    let (neg, square) = signed_square(x)?;
+                    Some((acc_neg != neg, acc_square * square))
+                })
+        }
+        Node::Exp(base, expon) => {
+            let expon = exact_rational(expon)?;
+            let numer = expon.numer().to_i32().filter(|x| x.abs() <= 4)?;
+            let (neg, square) = if expon.denom().is_one() {
+                let (neg, square) = signed_square(base)?;
+                (neg && numer % 2 != 0, square)
+            } else if *expon.denom() == 2.into() {
+                // the square root of a non-negative number
+                let base = exact_rational(base).filter(|x| !x.is_negative())?;
+                (false, base)
+            } else {
+                return None;
+            };
+            if square.is_zero() && numer < 0 {
+                return None;
+            }
+            Some((neg, ratio_pow(&square, numer)))
+        }
+        _ => None,
+    }
+}
+
+/// Simplifies `abs`, `floor`, `ceil`, `round` and `sign`. They are computed
+/// exactly on numbers, and also on other nodes when an interval that contains
+/// their value is far enough from the points where the function changes its
+/// behavior.
+fn simplify_rounding(node: Node, cx: &mut Context<'_>) -> Result<SimplifySuccess, SimplifyError> {
+    let (inner, rebuild): (Node, fn(Node) -> Node) = match node {
+        Node::Abs(inner) => (*inner, Node::abs),
+        Node::Floor(inner) => (*inner, Node::floor),
+        Node::Ceil(inner) => (*inner, Node::ceil),
+        Node::Round(inner) => (*inner, Node::round),
+        Node::Sign(inner) => (*inner, Node::sign),
+        _ => unreachable!(),
+    };
+    let tmp = simplify_node(inner, cx)?;
+    let node = rebuild(tmp.result);
+    let inner = match &node {
+        Node::Abs(inner)
+        | Node::Floor(inner)
+        | Node::Ceil(inner)
+        | Node::Round(inner)
+        | Node::Sign(inner) => inner,
+        _ => unreachable!(),
+    };
+
+    if let Node::Num { val, input_base } = &**inner {
+        let (val, input_base) = match &node {
+            Node::Abs(_) => (val.abs(), *input_base),
+            Node::Floor(_) => (val.floor(), *input_base),
+            Node::Ceil(_) => (val.ceil(), *input_base),
+            Node::Round(_) => (val.round(), *input_base),
+            Node::Sign(_) => (val.signum(), None),
+            _ => unreachable!(),
+        };
+        return Ok(fired(Rule::Rounding, Node::Num { val, input_base }, cx));
+    }
+
+    if let Some(result) = simplify_symmetry(&node, inner) {
+        return Ok(fired(Rule::Symmetry, simplify_node(result, cx)?.result, cx));
+    }
+
+    // |c * x| = |c| * |x|
+    if let (Node::Abs(_), Node::Product(children)) = (&node, &**inner) {
+        let (nums, others): (Vec<_>, Vec<_>) = children
+            .iter()
+            .cloned()
+            .partition(|c| matches!(c, Node::Num { .. }));
+        if !nums.is_empty() {
+            let nums = nums.into_iter().map(|c| c.abs()).collect();
+            return Ok(fired(
+                Rule::Rounding,
+                simplify_node(Node::Product(nums) * Node::Product(others).abs(), cx)?.result,
+                cx,
+            ));
+        }
+    }
+
+    // Constants such as `pi` cannot be represented exactly, but we can still
+    // use an interval that is guaranteed to contain the value if the function
+    // is the same on all of it. Unlike a fixed margin around the
+    // approximation, its width grows with the rounding errors, such as when
+    // large terms cancel out.
+    let bounds = match inner.eval_interval() {
+        Ok(x) if x.lo.is_finite() && x.hi.is_finite() => x,
+        _ => {
+            return Ok(SimplifySuccess {
+                result: node,
+                did_something: tmp.did_something,
+            })
+        }
+    };
+    // the bounds are compared as exact numbers, which they are
+    let lo = BigRational::from_float(bounds.lo).unwrap();
+    let hi = BigRational::from_float(bounds.hi).unwrap();
+    let integer = |val: BigRational| Node::Num {
+        val,
+        input_base: None,
+    };
+    let result = match &node {
+        Node::Abs(_) if bounds.lo > 0.0 => Some((**inner).clone()),
+        Node::Abs(_) if bounds.hi < 0.0 => {
+            let negated = match &**inner {
+                // distribute so that `-(pi - 4)` becomes `4 - pi`
+                Node::Sum(children) => Node::Sum(children.iter().map(|c| -c.clone()).collect()),
+                inner => -inner.clone(),
+            };
+            Some(simplify_node(negated, cx)?.result)
+        }
+        Node::Sign(_) if bounds.lo > 0.0 => Some(integer(BigRational::one())),
+        Node::Sign(_) if bounds.hi < 0.0 => Some(integer(-BigRational::one())),
+        Node::Floor(_) if lo.floor() == hi.floor() => Some(integer(lo.floor())),
+        Node::Ceil(_) if lo.ceil() == hi.ceil() => Some(integer(lo.ceil())),
+        Node::Round(_) if lo.round() == hi.round() => Some(integer(lo.round())),
+        _ => None,
+    };
+    Ok(match result {
+        Some(result) => fired(Rule::Rounding, result, cx),
+        None => SimplifySuccess {
+            result: node,
+            did_something: tmp.did_something,
+        },
+    })
+}
+
+/// Simplifies `min` or `max` by flattening the nested calls of the same
+/// function, keeping only the smallest or largest number and removing
+/// duplicates.
+fn simplify_min_max(
+    children: Vec<Node>,
+    is_min: bool,
+    cx: &mut Context<'_>,
+) -> Result<SimplifySuccess, SimplifyError> {
+    let mut did_something = false;
+    // whether the rule changed the node, and not only its children
+    let mut changed = false;
+    let mut flattened = Vec::with_capacity(children.len());
+    let mut stack: Vec<Node> = children.into_iter().rev().collect();
+    while let Some(child) = stack.pop() {
+        let tmp = simplify_node(child, cx)?;
+        did_something |= tmp.did_something;
+        match tmp.result {
+            // min(a, min(b, c)) = min(a, b, c)
+            Node::Min(sub_children) if is_min => {
+                changed = true;
+                stack.extend(sub_children.into_iter().rev());
+            }
+            Node::Max(sub_children) if !is_min => {
+                changed = true;
+                stack.extend(sub_children.into_iter().rev());
+            }
+            result => flattened.push(result),
+        }
+    }
+
+    let mut best_num: Option<Node> = None;
+    let mut others: Vec<Node> = Vec::new();
+    for child in flattened {
+        if let Node::Num { val, .. } = &child {
+            let is_better = match &best_num {
+                Some(Node::Num { val: best, .. }) => {
+                    if is_min {
+                        val < best
+                    } else {
+                        val > best
+                    }
+                }
+                _ => true,
+            };
+            if best_num.is_some() {
+                changed = true;
+            }
+            if is_better {
+                best_num = Some(child);
+            }
+        } else if others.contains(&child) {
+            changed = true;
+        } else {
+            others.push(child);
+        }
+    }
+    others.extend(best_num);
+
+    let result = if others.len() == 1 {
+        changed = true;
+        others.pop().unwrap()
+    } else if is_min {
+        Node::Min(others)
+    } else {
+        Node::Max(others)
+    };
+    if changed {
+        cx.telemetry.rule_fired(Rule::MinMax);
+    }
+    Ok(SimplifySuccess {
+        result,
+        did_something: did_something || changed,
+    })
+}
+
+/// Simplifies `atan2(y, x)` into `atan(y/x)` when the signs of `x` and `y`
+/// are known.
+fn simplify_atan2(
+    y: Node,
+    x: Node,
+    cx: &mut Context<'_>,
+) -> Result<SimplifySuccess, SimplifyError> {
+    let tmp = simplify_node(y, cx)?;
+    let y = tmp.result;
+    let mut did_something = tmp.did_something;
+    let tmp = simplify_node(x, cx)?;
+    let x = tmp.result;
+    did_something |= tmp.did_something;
+
+    let pi = || Node::Const(ConstKind::Pi);
+    let half_pi = || pi() / common::two();
+    let result = match (&y, &x) {
+        (Node::Num { val: y_val, .. }, Node::Num { val: x_val, .. }) => {
+            if x_val.is_zero() {
+                if y_val.is_positive() {
+                    half_pi()
+                } else if y_val.is_negative() {
+                    -half_pi()
+                } else {
+                    return Err(SimplifyError::OutOfDomain);
+                }
+            } else if x_val.is_positive() {
+                (y / x).atan()
+            } else if y_val.is_negative() {
+                (y / x).atan() - pi()
+            } else {
+                (y / x).atan() + pi()
+            }
+        }
+        (_, Node::Num { val: x_val, .. }) if x_val.is_positive() => (y / x).atan(),
+        _ => {
+            return Ok(SimplifySuccess {
+                result: Node::Atan2(Box::new(y), Box::new(x)),
+                did_something,
+            })
+        }
+    };
+    Ok(fired(Rule::Atan2, simplify_node(result, cx)?.result, cx))
+}
+
+/// Simplifies `log(base, inner)`.
+/// If `is_ln` is `true`, then the base must be `e` and the result is
+/// displayed as a natural logarithm.
+fn simplify_log(
+    base: Node,
+    inner: Node,
+    is_ln: bool,
+    cx: &mut Context<'_>,
+) -> Result<SimplifySuccess, SimplifyError> {
+    let tmp = simplify_node(base, cx)?;
+    let base = tmp.result;
+    let mut did_something = tmp.did_something;
+    let tmp = simplify_node(inner, cx)?;
+    let inner = tmp.result;
+    did_something |= tmp.did_something;
+
+    if let Node::Num { val, .. } = &base {
+        if !val.is_positive() || val.is_one() {
+            return Err(SimplifyError::InvalidLogBase);
+        }
+    }
+    if let Node::Num { val, .. } = &inner {
+        if !val.is_positive() {
+            return Err(SimplifyError::LogOfNonPositive);
+        }
+    }
+
+    // the rules below drop the base, so they must not hide that it is invalid
+    let base_is_valid =
+        is_ln || matches!(base.eval_interval(), Ok(x) if x.lo > 0.0 && !x.contains(1.0));
+    if base_is_valid {
+        // log(b, 1) = 0
+        if matches!(&inner, Node::Num { val, .. } if val.is_one()) {
+            return Ok(fired(Rule::Logarithm, common::zero(), cx));
+        }
+        // log(b, b) = 1
+        if base == inner {
+            return Ok(fired(Rule::Logarithm, common::one(), cx));
+        }
+        // log(b, b^x) = x
+        if let Node::Exp(a, x) = &inner {
+            if **a == base {
+                return Ok(fired(Rule::Logarithm, (**x).clone(), cx));
+            }
+        }
+    }
+    // log(10, 1000) = 3
+    if let (Node::Num { val: base_val, .. }, Node::Num { val: inner_val, .. }) = (&base, &inner) {
+        if let Some(expon) = exact_log(base_val, inner_val) {
+            return Ok(fired(
+                Rule::Logarithm,
+                Node::Num {
+                    val: BigRational::from_integer(expon.into()),
+                    input_base: None,
+                },
+                cx,
+            ));
+        }
+    }
+
+    Ok(SimplifySuccess {
+        result: if is_ln { inner.ln() } else { inner.log(base) },
+        did_something,
+    })
+}
+
+/// Finds the integer `n` such that `base^n = x`, if it exists.
+fn exact_log(base: &BigRational, x: &BigRational) -> Option<i32> {
+    let approx = ratio_to_f64(x).ln() / ratio_to_f64(base).ln();
+    if !approx.is_finite() || approx.abs() > 4096.0 {
+        return None;
+    }
+    let expon = approx.round() as i32;
+    if ratio_pow(base, expon) == *x {
+        Some(expon)
+    } else {
+        None
     }
 }
 
-fn group_and_fold_numbers<I>(nodes: I, is_sum: bool) -> (Vec<Node>, bool)
+fn group_and_fold_numbers<I>(
+    nodes: I,
+    is_sum: bool,
+    cx: &mut Context<'_>,
+) -> Result<(Vec<Node>, bool), SimplifyError>
 where
     I: Iterator<Item = Node>,
 {
@@ -234,7 +1137,11 @@ where
                 let f = if is_sum { Add::add } else { Mul::mul };
                 acc = Some(match acc {
                     Some(lhs) => {
+                        if !budget::fits_sum_or_product(&lhs, &val, cx.options.memory_budget) {
+                            return Err(SimplifyError::OverBudget);
+                        }
                         did_something = true;
+                        cx.telemetry.rule_fired(Rule::FoldNumbers);
                         f(lhs, val)
                     }
                     None => val,
@@ -257,7 +1164,17 @@ where
             );
         }
     }
-    (result, did_something)
+    Ok((result, did_something))
+}
+
+/// Whether the node is a vector or a multiple of a vector, such as
+/// `-(1, 2)`.
+fn is_vector(node: &Node) -> bool {
+    match node {
+        Node::Vector(_) => true,
+        Node::Product(factors) => factors.iter().any(is_vector),
+        _ => false,
+    }
 }
 
 /// Turns add(add(1, add(2)), 3) into add(1, 2, 3).
@@ -298,7 +1215,65 @@ fn expand_product<'a>(factors: &'a [Node]) -> Box<dyn Iterator<Item = Node> + 'a
     }
 }
 
-fn simplify_vararg_op<I>(children: I, is_sum: bool) -> Result<SimplifySuccess, SimplifyError>
+/// Multiplies together the roots of positive integers that have the same
+/// exponent, such as `2^(1/2) * 3^(1/2)` becoming `6^(1/2)`, if there are
+/// any.
+fn combine_radicals(factors: &[Node]) -> Option<Vec<Node>> {
+    let mut result: Vec<Node> = Vec::with_capacity(factors.len());
+    let mut changed = false;
+    for factor in factors {
+        if let Some((base, expon)) = get_integer_radical(factor) {
+            let same = result
+                .iter_mut()
+                .find_map(|x| match get_integer_radical(x) {
+                    Some((other, other_expon)) if other_expon == expon => Some((x, other)),
+                    _ => None,
+                });
+            if let Some((existing, other)) = same {
+                *existing = Node::Exp(
+                    Box::new(Node::Num {
+                        val: BigRational::from_integer(base * other),
+                        input_base: None,
+                    }),
+                    Box::new(Node::Num {
+                        val: expon.clone(),
+                        input_base: None,
+                    }),
+                );
+                changed = true;
+                continue;
+            }
+        }
+        result.push(factor.clone());
+    }
+    if changed {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+/// Returns the base and the exponent of the node if it is a positive integer
+/// to a fractional power.
+fn get_integer_radical(node: &Node) -> Option<(BigInt, &BigRational)> {
+    match node {
+        Node::Exp(base, expon) => match (&**base, &**expon) {
+            (Node::Num { val: base, .. }, Node::Num { val: expon, .. })
+                if base.is_integer() && base.is_positive() && !expon.is_integer() =>
+            {
+                Some((base.to_integer(), expon))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn simplify_vararg_op<I>(
+    children: I,
+    is_sum: bool,
+    cx: &mut Context<'_>,
+) -> Result<SimplifySuccess, SimplifyError>
 where
     I: IntoIterator<Item = Node>,
 {
@@ -306,15 +1281,25 @@ where
 
     let children: Vec<SimplifySuccess> = children
         .into_iter()
-        .map(simplify)
+        .map(|x| simplify_node(x, cx))
         .collect::<Result<Vec<_>, _>>()?;
     for child in children.iter() {
         did_something |= child.did_something;
     }
+    // a child may have been simplified into the same operation
+    let (children, tmp) = deep_flatten_children(children.into_iter().map(|c| c.result), is_sum);
+    did_something |= tmp;
+
+    if is_sum
+        && children.iter().any(is_vector)
+        && children.iter().any(|x| !is_vector(x) && x.eval().is_ok())
+    {
+        return Err(SimplifyError::VectorAndScalar);
+    }
 
     if !is_sum {
         for child in children.iter() {
-            if let Node::Num { val, .. } = &child.result {
+            if let Node::Num { val, .. } = child {
                 if val.is_zero() {
                     // Zero short circuits multiplication.
                     // TODO: maybe we should not be ignoring the base of the
@@ -329,19 +1314,22 @@ where
     }
 
     // transform `3*2+pi*2+4+9` into `19+pi*2`
-    let tmp = group_and_fold_numbers(children.into_iter().map(|c| c.result), is_sum);
+    let tmp = group_and_fold_numbers(children.into_iter(), is_sum, cx)?;
     let children = tmp.0;
     did_something |= tmp.1;
 
     if !is_sum {
+        // transform `sqrt(2)*sqrt(8)` into `sqrt(16)`
+        if let Some(combined) = combine_radicals(&children) {
+            let new_node = simplify_vararg_op(combined, false, cx)?.result;
+            return Ok(fired(Rule::CombineRadicals, new_node, cx));
+        }
+
         // expand product
         let expanded_terms: Vec<Node> = expand_product(&children).collect();
         if expanded_terms.len() > 1 {
-            let new_node = simplify_vararg_op(expanded_terms.into_iter(), true)?.result;
-            return Ok(SimplifySuccess {
-                result: new_node,
-                did_something: true,
-            });
+            let new_node = simplify_vararg_op(expanded_terms, true, cx)?.result;
+            return Ok(fired(Rule::ExpandProduct, new_node, cx));
         }
     }
 
@@ -420,11 +1408,12 @@ where
     sorted_entries.sort_by_key(|(_, (_, inserted))| *inserted);
 
     let mut children = Vec::new();
+    let mut has_power_of_i = false;
     for (child, factors) in sorted_entries {
         // We always want to use addition here to fold factors:
         // - pi*3 + pi*5 = pi*(3+5)
         // - pi^3 * pi^5 = pi^(3+5)
-        let tmp = group_and_fold_numbers(factors.0.into_iter(), true);
+        let tmp = group_and_fold_numbers(factors.0.into_iter(), true, cx)?;
         let factors = tmp.0;
         did_something |= tmp.1;
 
@@ -437,12 +1426,15 @@ where
                     // If the only factor is 0, then discard because 0
                     // times anything is 0.
                     Node::Num { ref val, .. } if val.is_zero() => continue,
-                    other => fold_helper(child, other, is_sum),
+                    other => {
+                        has_power_of_i |= !is_sum && child == Node::Const(ConstKind::I);
+                        fold_helper(child, other, is_sum)
+                    }
                 };
                 children.push(new_child);
             }
             _ => {
-                let tmp = simplify_vararg_op(factors, true)?;
+                let tmp = simplify_vararg_op(factors, true, cx)?;
                 let factor = tmp.result;
                 did_something |= tmp.did_something;
 
@@ -463,6 +1455,11 @@ where
         _ if is_sum => Node::Sum(children),
         _ => Node::Product(children),
     };
+    if has_power_of_i {
+        // `i*i` became `i^2`, which can be simplified further into `-1`, which
+        // can then be multiplied with the other numbers
+        return Ok(fired(Rule::Power, simplify_node(result, cx)?.result, cx));
+    }
     Ok(SimplifySuccess {
         result,
         did_something,
@@ -480,12 +1477,31 @@ fn fold_helper(x: Node, factor: Node, is_sum: bool) -> Node {
 fn node_factor_heuristic(node: &Node) -> i64 {
     // greater numbers mean "use me as a factor" when factoring
     match node {
-        Node::Sin(_) | Node::Cos(_) | Node::Tan(_) => 5 << 32,
+        Node::Sin(_)
+        | Node::Cos(_)
+        | Node::Tan(_)
+        | Node::Asin(_)
+        | Node::Acos(_)
+        | Node::Atan(_)
+        | Node::Atan2(_, _)
+        | Node::Sinh(_)
+        | Node::Cosh(_)
+        | Node::Tanh(_)
+        | Node::Abs(_)
+        | Node::Floor(_)
+        | Node::Ceil(_)
+        | Node::Round(_)
+        | Node::Sign(_)
+        | Node::Min(_)
+        | Node::Max(_)
+        | Node::Ln(_)
+        | Node::Log(_, _) => 5 << 32,
         Node::Sum(_) | Node::Product(_) => 4 << 32,
         Node::Const(ConstKind::Pi) => 3 << 32,
         Node::Const(ConstKind::Tau) => (3 << 32) + 1,
         Node::Const(ConstKind::E) => (3 << 32) + 2,
-        Node::UnknownConst(s) => {
+        Node::Const(ConstKind::I) => (3 << 32) + 3,
+        Node::Var(s) => {
             // Make sure that the same constants have the same factor heuristic
             // so that they are grouped correctly.
             let mut hasher = DefaultHasher::new();
@@ -498,31 +1514,64 @@ fn node_factor_heuristic(node: &Node) -> i64 {
     }
 }
 
-fn simplify_exp(lhs: Node, rhs: Node) -> Result<SimplifySuccess, SimplifyError> {
-    let tmp = simplify(rhs)?;
+fn simplify_exp(
+    lhs: Node,
+    rhs: Node,
+    cx: &mut Context<'_>,
+) -> Result<SimplifySuccess, SimplifyError> {
+    let tmp = simplify_node(rhs, cx)?;
     let rhs = tmp.result;
     let mut did_something = tmp.did_something;
 
     // This must be done before we expand the exponent below by
     // simplifying the LHS.
-    if let Node::Exp(lhs_base, lhs_exp) = lhs {
-        // (a^b)^c = a^(b*c)
-        let new_base = simplify(*lhs_base)?;
-        did_something |= new_base.did_something;
-        let new_exp = simplify((*lhs_exp) * rhs)?;
-        did_something |= new_exp.did_something;
-        let tmp = simplify_exp(new_base.result, new_exp.result)?;
-        did_something |= tmp.did_something;
-        return Ok(SimplifySuccess {
-            result: tmp.result,
-            did_something,
-        });
+    if let Node::Exp(lhs_base, lhs_exp) = &lhs {
+        // (a^b)^c = a^(b*c), which is wrong for a negative `a` unless `c` is
+        // an integer, such as in `sqrt((-3)^2)`
+        let is_integer = matches!(&rhs, Node::Num { val, .. } if val.is_integer());
+        let is_non_negative = matches!(lhs_base.eval_interval(), Ok(x) if x.lo >= 0.0);
+        if is_integer || is_non_negative {
+            let new_base = simplify_node((**lhs_base).clone(), cx)?;
+            did_something |= new_base.did_something;
+            let new_exp = simplify_node((**lhs_exp).clone() * rhs, cx)?;
+            did_something |= new_exp.did_something;
+            let tmp = simplify_exp(new_base.result, new_exp.result, cx)?;
+            did_something |= tmp.did_something;
+            return Ok(SimplifySuccess {
+                result: tmp.result,
+                did_something,
+            });
+        }
     }
 
-    let tmp = simplify(lhs)?;
+    let tmp = simplify_node(lhs, cx)?;
     let lhs = tmp.result;
     did_something |= tmp.did_something;
 
+    if let Node::Num { val, .. } = &rhs {
+        if *val == BigRational::new(1.into(), 2.into()) {
+            // sqrt(3 + 2sqrt(2)) = 1 + sqrt(2)
+            if let Some(result) = radicals::denest_sqrt(&lhs) {
+                return Ok(fired(Rule::Power, simplify_node(result, cx)?.result, cx));
+            }
+        }
+    }
+
+    if let (Node::Const(ConstKind::I), Node::Num { val: rhs_val, .. }) = (&lhs, &rhs) {
+        if rhs_val.is_integer() {
+            // the powers of i are 1, i, -1, -i, 1, i, ...
+            let i = || Node::Const(ConstKind::I);
+            let result = match rhs_val.numer().mod_floor(&4.into()).to_u32() {
+                Some(0) => common::one(),
+                Some(1) => i(),
+                Some(2) => common::minus_one(),
+                Some(3) => common::minus_one() * i(),
+                _ => unreachable!(),
+            };
+            return Ok(fired(Rule::Power, result, cx));
+        }
+    }
+
     if let Node::Num {
         val: lhs_val,
         input_base: lhs_input_base,
@@ -533,32 +1582,56 @@ fn simplify_exp(lhs: Node, rhs: Node) -> Result<SimplifySuccess, SimplifyError>
             input_base: rhs_input_base,
         } = &rhs
         {
-            // actually try compute the exponent's result
-            match simplify_exp_nums(lhs_val, rhs_val, *lhs_input_base, *rhs_input_base) {
-                Some(Ok(simplified_node)) => {
-                    return Ok(SimplifySuccess {
-                        result: simplified_node,
-                        did_something: true,
-                    })
+            if lhs_val.is_negative() && *rhs_val.denom() == 2.into() {
+                // (-a)^(k/2) = a^(k/2) * i^k
+                let positive = Node::Num {
+                    val: -lhs_val,
+                    input_base: *lhs_input_base,
+                };
+                let i_power = Node::Num {
+                    val: BigRational::from_integer(rhs_val.numer().clone()),
+                    input_base: None,
+                };
+                let result = Node::Exp(Box::new(positive), Box::new(rhs))
+                    * Node::Exp(Box::new(Node::Const(ConstKind::I)), Box::new(i_power));
+                return Ok(fired(Rule::Power, simplify_node(result, cx)?.result, cx));
+            }
+            if rhs_val.is_integer() && rhs_val.is_negative() && !lhs_val.is_zero() {
+                // (a/b)^-n = (b/a)^n, which stays exact even if the power is
+                // too big to be computed
+                let recip = Node::Num {
+                    val: lhs_val.recip(),
+                    input_base: *lhs_input_base,
+                };
+                if is_minus_one(&rhs) {
+                    return Ok(fired(Rule::Power, recip, cx));
                 }
+                let expon = Node::Num {
+                    val: -rhs_val,
+                    input_base: *rhs_input_base,
+                };
+                return Ok(fired(
+                    Rule::Power,
+                    simplify_exp(recip, expon, cx)?.result,
+                    cx,
+                ));
+            }
+            // actually try compute the exponent's result
+            let budget = cx.options.memory_budget;
+            match simplify_exp_nums(lhs_val, rhs_val, *lhs_input_base, *rhs_input_base, budget) {
+                Some(Ok(simplified_node)) => return Ok(fired(Rule::Power, simplified_node, cx)),
                 Some(Err(err)) => return Err(err),
                 None => {}
             }
         }
         if lhs_val.is_one() {
             // 1^x = 1
-            return Ok(SimplifySuccess {
-                result: common::one(),
-                did_something: true,
-            });
+            return Ok(fired(Rule::Power, common::one(), cx));
         }
     } else if let Node::Num { val: rhs_val, .. } = &rhs {
         if rhs_val.is_one() {
             // x^1 = x
-            return Ok(SimplifySuccess {
-                result: lhs,
-                did_something: true,
-            });
+            return Ok(fired(Rule::Power, lhs, cx));
         } else if ratio_to_i32(&rhs_val) == Some(-1) {
             // (a/b)^-1 = b/a
             if let Node::Num {
@@ -570,18 +1643,12 @@ fn simplify_exp(lhs: Node, rhs: Node) -> Result<SimplifySuccess, SimplifyError>
                     val: BigRational::new(lhs_val.denom().clone(), lhs_val.numer().clone()),
                     input_base: *lhs_input_base,
                 };
-                return Ok(SimplifySuccess {
-                    result: inverse,
-                    did_something: true,
-                });
+                return Ok(fired(Rule::Power, inverse, cx));
             }
         } else if rhs_val.is_zero() {
             if let Node::Const(_) = &lhs {
                 // our constants are never zero, so we won't have 0^0
-                return Ok(SimplifySuccess {
-                    result: common::one(),
-                    did_something: true,
-                });
+                return Ok(fired(Rule::Power, common::one(), cx));
             }
         }
     }
@@ -593,7 +1660,7 @@ fn simplify_exp(lhs: Node, rhs: Node) -> Result<SimplifySuccess, SimplifyError>
                 let factors: Vec<Node> = iter::repeat(lhs)
                     .take(rhs_i32.try_into().unwrap())
                     .collect();
-                return simplify_vararg_op(factors, false);
+                return simplify_vararg_op(factors, false, cx);
             }
         }
     }
@@ -605,18 +1672,44 @@ fn simplify_exp(lhs: Node, rhs: Node) -> Result<SimplifySuccess, SimplifyError>
     })
 }
 
+/// Splits the positive integer `n` into `k^root * m` with the biggest `k`
+/// that can be found by trial division, if `k` is more than one.
+fn extract_perfect_power(n: &BigInt, root: u32) -> Option<(BigInt, BigInt)> {
+    let mut outside = BigInt::one();
+    let mut inside = n.clone();
+    for p in 2..=MAX_RADICAL_FACTOR {
+        let factor = BigInt::from(p).pow(root);
+        if factor > inside {
+            break;
+        }
+        while (&inside % &factor).is_zero() {
+            inside /= &factor;
+            outside *= p;
+        }
+    }
+    if outside.is_one() {
+        None
+    } else {
+        Some((outside, inside))
+    }
+}
+
 fn simplify_exp_nums(
     lhs: &BigRational,
     rhs: &BigRational,
     lhs_base: Option<u32>,
     rhs_base: Option<u32>,
+    budget: Option<usize>,
 ) -> Option<Result<Node, SimplifyError>> {
     if lhs.is_zero() {
         if rhs.is_positive() {
             // 0^x = 0 when x > 0
             return Some(Ok(common::zero()));
+        } else if *rhs == -BigRational::one() {
+            // 0^(-1) is a division by zero
+            return Some(Err(SimplifyError::DivisionByZero));
         } else {
-            // 0^(-1) is undefined
+            // 0^(-2) is undefined
             return Some(Err(SimplifyError::ZeroToPowerOfNonPositive));
         }
     }
@@ -633,25 +1726,13 @@ fn simplify_exp_nums(
             .unwrap_or(false)
     }
     if let Some(int_expon) = ratio_to_i32(&rhs) {
-        // Maybe I don't know how to use the API but I couldn't
-        // manage to use the `.pow` method on `BigRational`.
-        fn my_pow(a: &BigRational, expon: i32) -> BigRational {
-            // this is handled in another match arm
-            assert_ne!(expon, 0);
-            if expon > 0 {
-                BigRational::new(a.numer().pow(expon as u32), a.denom().pow(expon as u32))
-            } else {
-                let pos_expon = expon.abs();
-                BigRational::new(
-                    a.denom().pow(pos_expon as u32),
-                    a.numer().pow(pos_expon as u32),
-                )
-            }
-        }
         if let Some(lhs_bits) = lhs.denom().bits().checked_add(lhs.numer().bits()) {
-            if is_pow_safe(lhs_bits, int_expon) {
+            // the powers that do not fit in the memory budget are kept, so
+            // that they are approximated
+            let bits = lhs_bits as f64 * f64::from(int_expon).abs();
+            if is_pow_safe(lhs_bits, int_expon) && budget::fits_bits(bits, budget) {
                 return Some(Ok(Node::Num {
-                    val: my_pow(&lhs, int_expon),
+                    val: ratio_pow(&lhs, int_expon),
                     input_base: get_op_result_base(lhs_base, rhs_base),
                 }));
             }
@@ -702,6 +1783,32 @@ fn simplify_exp_nums(
                         }));
                     }
                 }
+                if lhs.is_positive() {
+                    // sqrt(12) = 2 * sqrt(3)
+                    if let Some((outside, inside)) = extract_perfect_power(&lhs, root_u32) {
+                        // see comment above about x^(-1/n)
+                        let outside = if rhs_inv.is_negative() {
+                            BigRational::new(One::one(), outside)
+                        } else {
+                            BigRational::from_integer(outside)
+                        };
+                        let root = Node::Exp(
+                            Box::new(Node::Num {
+                                val: BigRational::from_integer(inside),
+                                input_base: lhs_base,
+                            }),
+                            Box::new(Node::Num {
+                                val: rhs.clone(),
+                                input_base: rhs_base,
+                            }),
+                        );
+                        let outside = Node::Num {
+                            val: outside,
+                            input_base: lhs_base,
+                        };
+                        return Some(Ok(Node::Product(vec![outside, root])));
+                    }
+                }
             }
         }
     }
@@ -712,10 +1819,25 @@ fn simplify_exp_nums(
 
 #[cfg(test)]
 mod tests {
-    use float_cmp::{ApproxEq, F64Margin};
-
     use super::*;
     use crate::node::EvalError;
+    use crate::test_util::{parse, simplify_str};
+    use float_cmp::{ApproxEq, F64Margin};
+
+    #[test]
+    fn it_errors_with_a_vector_added_to_a_scalar() {
+        let simplify_str = |expr: &str| parse(expr).simplify().map(|x| x.result.to_string());
+        assert_eq!(
+            simplify_str("(1, 2) + 3"),
+            Err(SimplifyError::VectorAndScalar)
+        );
+        assert_eq!(
+            simplify_str("pi - (1, 2)"),
+            Err(SimplifyError::VectorAndScalar)
+        );
+        // the variables can be vectors
+        assert_eq!(simplify_str("(1, 2) + x"), Ok("(1, 2) + x".to_owned()));
+    }
 
     #[test]
     fn it_errors_with_0_to_power_of_non_positive() {
@@ -733,6 +1855,11 @@ mod tests {
         let input = Node::Exp(Box::new(common::zero()), Box::new(-common::two()));
         let result = simplify(input);
         assert_eq!(result, Err(SimplifyError::ZeroToPowerOfNonPositive));
+
+        // 1/(2-2)
+        let input = common::one() / (common::two() - common::two());
+        let result = simplify(input);
+        assert_eq!(result, Err(SimplifyError::DivisionByZero));
     }
 
     #[test]
@@ -745,7 +1872,7 @@ mod tests {
                 vec![
                     Node::Const(ConstKind::Pi),
                     Node::Const(ConstKind::E)
-                        + Node::UnknownConst("hello".to_string())
+                        + Node::Var("hello".to_string())
                         + Node::Const(ConstKind::Tau),
                 ],
                 true
@@ -754,7 +1881,7 @@ mod tests {
             vec![
                 Node::Const(ConstKind::Pi),
                 Node::Const(ConstKind::E),
-                Node::UnknownConst("hello".to_string()),
+                Node::Var("hello".to_string()),
                 Node::Const(ConstKind::Tau),
             ],
         );
@@ -774,13 +1901,20 @@ mod tests {
                 input_base: Some(8),
             }
         );
-        // we cannot simplify 256^(1/3)
+        // 256^(1/3) = 4 * 4^(1/3)
+        let four = Node::Num {
+            val: BigRational::from_integer(4.into()),
+            input_base: Some(8),
+        };
         assert_eq!(
             simplify(num.clone().cbrt()).unwrap().result,
-            Node::Exp(
-                Box::new(num.clone()),
-                Box::new(simplify(common::three().inverse()).unwrap().result),
-            ),
+            Node::Product(vec![
+                four.clone(),
+                Node::Exp(
+                    Box::new(four),
+                    Box::new(simplify(common::three().inverse()).unwrap().result),
+                ),
+            ]),
         );
         // 256^(1/4) = 4
         assert_eq!(
@@ -803,13 +1937,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_combines_and_denests_radicals() {
+        assert_eq!(simplify_str("sqrt(2) * sqrt(8)"), "4");
+        assert_eq!(simplify_str("sqrt(2) * sqrt(3)"), "6^(1/2)");
+        assert_eq!(simplify_str("sqrt(12)"), "2 * 3^(1/2)");
+        assert_eq!(simplify_str("cbrt(54)"), "3 * 2^(1/3)");
+        assert_eq!(simplify_str("sqrt(a) * sqrt(a)"), "a");
+    }
+
+    #[test]
+    fn it_computes_negative_powers_exactly() {
+        assert_eq!(simplify_str("(2/3)^-2"), "9/4");
+        assert_eq!(simplify_str("(-2)^-3"), "-1/8");
+        assert_eq!(simplify_str("100000^-2"), "1/10000000000");
+        assert_eq!(simplify_str("12345^-1"), "1/12345");
+        // the power is too big, but there is no negative exponent left
+        assert_eq!(simplify_str("(2/3)^-200"), "(3/2)^200");
+    }
+
     #[test]
     fn it_expands_products() {
-        let a = Node::UnknownConst("a".to_string());
-        let b = Node::UnknownConst("b".to_string());
+        let a = Node::Var("a".to_string());
+        let b = Node::Var("b".to_string());
         // TODO: we can't just compare for equality for now because the terms
         //  do not have a deterministic order yet
-        let ok = match simplify((a.clone() + b.clone()).sqr()) {
+        let ok = match simplify((a + b).sqr()) {
             Ok(SimplifySuccess {
                 result: Node::Sum(_),
                 ..
@@ -821,10 +1974,49 @@ mod tests {
 
     #[test]
     fn it_detects_impossible_real_roots() {
-        // sqrt(-1) = error
+        // (-1)^(1/4) = error
+        let input = Node::Exp(
+            Box::new(common::minus_one()),
+            Box::new(Node::Num {
+                val: BigRational::new(1.into(), 4.into()),
+                input_base: None,
+            }),
+        );
+        assert_eq!(simplify(input), Err(SimplifyError::ComplexRoot));
+    }
+
+    #[test]
+    fn it_simplifies_powers_of_i() {
+        let i = || Node::Const(ConstKind::I);
+        // sqrt(-1) = i
+        assert_eq!(simplify(common::minus_one().sqrt()).unwrap().result, i());
+        // sqrt(-4) = 2i
+        let minus_four = Node::Num {
+            val: BigRational::from_integer((-4).into()),
+            input_base: None,
+        };
+        assert_eq!(
+            simplify(minus_four.sqrt()).unwrap().result.to_string(),
+            "2i"
+        );
+        assert_eq!(simplify(i() * i()).unwrap().result, common::minus_one());
+        let expon = |n: i64| Node::Num {
+            val: BigRational::from_integer(n.into()),
+            input_base: None,
+        };
+        assert_eq!(
+            simplify(Node::Exp(Box::new(i()), Box::new(expon(7))))
+                .unwrap()
+                .result
+                .to_string(),
+            "-i"
+        );
         assert_eq!(
-            simplify(common::minus_one().sqrt()),
-            Err(SimplifyError::ComplexRoot),
+            simplify(Node::Exp(Box::new(i()), Box::new(expon(-1))))
+                .unwrap()
+                .result
+                .to_string(),
+            "-i"
         );
     }
 
@@ -838,12 +2030,346 @@ mod tests {
         // 0 <= x < 2pi
         test_trigonometric_functions_on_range(0, 8, 4);
 
+        // x = n*pi/12
+        // 0 <= x < 2pi
+        test_trigonometric_functions_on_range(0, 24, 12);
+        test_trigonometric_functions_on_range(-30, -18, 12);
+
         // test outside [0; 2pi] range
         test_trigonometric_functions_on_range(30, 40, 4);
         // test negative
         test_trigonometric_functions_on_range(-100, -90, 3);
     }
 
+    #[test]
+    fn it_simplifies_inverse_trigonometric_functions_with_common_values() {
+        let pi = || Node::Const(ConstKind::Pi);
+        let num = |n: i32, d: i32| Node::Num {
+            val: BigRational::new(n.into(), d.into()),
+            input_base: None,
+        };
+        let check = |input: Node, expected: Node| {
+            assert_eq!(
+                simplify(input).unwrap().result,
+                simplify(expected).unwrap().result
+            );
+        };
+
+        check(common::one().atan(), pi() / num(4, 1));
+        check(common::one().asin(), pi() / num(2, 1));
+        check(num(-1, 2).asin(), -pi() / num(6, 1));
+        check(num(-1, 2).acos(), num(2, 3) * pi());
+        check(common::three().sqrt().atan(), pi() / num(3, 1));
+        check(common::zero().acos(), pi() / num(2, 1));
+        // atan2(-1, -1) = -3pi/4
+        check(
+            Node::Atan2(Box::new(common::minus_one()), Box::new(common::minus_one())),
+            num(-3, 4) * pi(),
+        );
+        // atan(2) cannot be simplified
+        check(common::two().atan(), Node::Atan(Box::new(common::two())));
+        // the same values written in other ways
+        assert_eq!(simplify_str("acos(1/sqrt(2))"), "1/4 * pi");
+        assert_eq!(simplify_str("acos(-sqrt(2)/2)"), "3/4 * pi");
+        assert_eq!(simplify_str("asin(sqrt(3/4))"), "1/3 * pi");
+        assert_eq!(simplify_str("atan(-1/sqrt(3))"), "-1/6 * pi");
+        // values close to the common ones are not simplified
+        assert_eq!(
+            simplify_str("acos(0.70710678118654752)"),
+            "acos(2209708691207961/3125000000000000)"
+        );
+        assert_eq!(
+            simplify_str("asin(sqrt(3) / 2 + 10^-15)"),
+            "asin(1/1000000000000000 + 1/2 * 3^(1/2))"
+        );
+        assert_eq!(
+            simplify(common::two().asin()),
+            Err(SimplifyError::OutOfDomain)
+        );
+    }
+
+    #[test]
+    fn it_simplifies_hyperbolic_functions_at_zero() {
+        let zero = || Node::Num {
+            val: Zero::zero(),
+            input_base: Some(10),
+        };
+        assert_eq!(simplify(zero().sinh()).unwrap().result, common::zero());
+        assert_eq!(simplify(zero().cosh()).unwrap().result, common::one());
+        assert_eq!(simplify(zero().tanh()).unwrap().result, common::zero());
+        assert_eq!(
+            simplify((zero() + common::two()).cosh())
+                .unwrap()
+                .result
+                .to_string(),
+            "cosh 2"
+        );
+    }
+
+    #[test]
+    fn it_rounds_numbers_exactly() {
+        let num = |numer: i64, denom: i64| Node::Num {
+            val: BigRational::new(numer.into(), denom.into()),
+            input_base: None,
+        };
+        assert_eq!(simplify(num(7, 2).floor()).unwrap().result, num(3, 1));
+        assert_eq!(simplify(num(-7, 2).floor()).unwrap().result, num(-4, 1));
+        assert_eq!(simplify(num(7, 2).ceil()).unwrap().result, num(4, 1));
+        assert_eq!(simplify(num(5, 2).round()).unwrap().result, num(3, 1));
+        assert_eq!(simplify(num(-5, 2).round()).unwrap().result, num(-3, 1));
+        assert_eq!(simplify(num(-3, 4).abs()).unwrap().result, num(3, 4));
+        assert_eq!(simplify(num(-3, 4).sign()).unwrap().result, num(-1, 1));
+        assert_eq!(simplify(num(0, 1).sign()).unwrap().result, num(0, 1));
+    }
+
+    #[test]
+    fn it_rounds_irrational_numbers() {
+        let pi = || Node::Const(ConstKind::Pi);
+        assert_eq!(simplify(pi().floor()).unwrap().result.to_string(), "3");
+        assert_eq!(simplify((-pi()).ceil()).unwrap().result.to_string(), "-3");
+        assert_eq!(
+            simplify((pi() - common::two() - common::two()).abs())
+                .unwrap()
+                .result
+                .to_string(),
+            "4 - pi"
+        );
+        let x = || Node::Var("x".to_owned());
+        assert_eq!(simplify(x().abs()).unwrap().result, x().abs());
+    }
+
+    #[test]
+    fn it_does_not_round_values_that_cancel_out() {
+        // the exact value is about -1.5e-6, but the terms are too large to
+        // know its sign from a float
+        let inner = "(3141592653589.793237 - 10^12*pi)";
+        for func in &["floor", "ceil", "sign", "abs"] {
+            let result = simplify_str(&format!("{}{}", func, inner));
+            assert!(result.starts_with(func), "{} gave {}", func, result);
+        }
+    }
+
+    #[test]
+    fn it_finds_rational_multiples_of_pi() {
+        let pi = || Node::Const(ConstKind::Pi);
+        let num = |n: i64| Node::Num {
+            val: BigRational::from_integer(n.into()),
+            input_base: None,
+        };
+        let ratio = |numer: i64, denom: i64| BigRational::new(numer.into(), denom.into());
+
+        assert_eq!(get_pi_factor(&(pi() / num(2))), Some(ratio(1, 2)));
+        assert_eq!(get_pi_factor(&(num(3) * pi() / num(4))), Some(ratio(3, 4)));
+        assert_eq!(
+            get_pi_factor(&(pi() / num(2) + pi() * (num(1) / num(3)))),
+            Some(ratio(5, 6))
+        );
+        assert_eq!(get_pi_factor(&(pi() + num(1))), None);
+        assert_eq!(get_pi_factor(&(pi() * pi())), None);
+
+        // the special values are found without simplifying the argument first
+        assert_eq!(
+            simplify((num(3) * pi() / num(4)).cos())
+                .unwrap()
+                .result
+                .to_string(),
+            "-1 * 2^(1/2) / 2"
+        );
+        assert_eq!(
+            simplify((pi() / num(12)).cos()).unwrap().result.to_string(),
+            "(6^(1/2) + 2^(1/2)) / 4"
+        );
+        assert_eq!(
+            simplify((num(5) * pi() / num(12)).tan())
+                .unwrap()
+                .result
+                .to_string(),
+            "2 + 3^(1/2)"
+        );
+        assert_eq!(
+            simplify((pi() / num(12)).sin()).unwrap().result.to_string(),
+            "(6^(1/2) - 2^(1/2)) / 4"
+        );
+        assert_eq!(
+            simplify((num(19) * pi() / num(12)).sin())
+                .unwrap()
+                .result
+                .to_string(),
+            "-1 * (6^(1/2) + 2^(1/2)) / 4"
+        );
+
+        // multipliers that do not fit in a machine integer
+        let huge = Node::Exp(Box::new(num(10)), Box::new(num(30)));
+        assert_eq!(
+            simplify((huge.clone() * pi() + pi() / num(2)).sin())
+                .unwrap()
+                .result,
+            common::one()
+        );
+        let x = Node::Var("x".to_owned());
+        assert_eq!(
+            simplify((x.clone() + (huge + num(1)) * pi()).sin())
+                .unwrap()
+                .result,
+            -x.clone().sin()
+        );
+        // fractional multipliers
+        assert_eq!(
+            simplify((num(3) * pi() / num(2)).cos()).unwrap().result,
+            common::zero()
+        );
+        assert_eq!(
+            simplify((x.clone() + num(3) * pi() / num(2)).sin())
+                .unwrap()
+                .result,
+            -x.cos()
+        );
+    }
+
+    #[test]
+    fn it_simplifies_min_and_max() {
+        let num = |numer: i64, denom: i64| Node::Num {
+            val: BigRational::new(numer.into(), denom.into()),
+            input_base: None,
+        };
+        let x = || Node::Var("x".to_owned());
+
+        let node = Node::Min(vec![num(1, 2), num(1, 3), num(3, 1)]);
+        assert_eq!(simplify(node).unwrap().result, num(1, 3));
+        let node = Node::Max(vec![num(1, 2), Node::Max(vec![num(7, 2), x()]), x()]);
+        assert_eq!(
+            simplify(node).unwrap().result,
+            Node::Max(vec![x(), num(7, 2)])
+        );
+        // different functions are not flattened
+        let node = Node::Min(vec![x(), Node::Max(vec![x(), num(1, 1)])]);
+        assert!(!simplify(node).unwrap().did_something);
+    }
+
+    #[test]
+    fn it_removes_periods_from_trigonometric_functions() {
+        let x = || Node::Var("x".to_owned());
+        let pi = || Node::Const(ConstKind::Pi);
+        let check = |input: Node, expected: Node| {
+            assert_eq!(
+                simplify(input).unwrap().result,
+                simplify(expected).unwrap().result
+            );
+        };
+        check((x() + common::two() * pi()).sin(), x().sin());
+        check((x() + pi()).cos(), -x().cos());
+        check((pi() / common::two() + x()).sin(), x().cos());
+        check((x() - common::three() * pi()).tan(), x().tan());
+        check(
+            (x() + Node::Const(ConstKind::Tau) + common::one()).cos(),
+            (x() + common::one()).cos(),
+        );
+        // pi/3 is not a multiple of pi/2
+        let input = (x() + pi() / common::three()).sin();
+        assert!(matches!(simplify(input).unwrap().result, Node::Sin(_)));
+    }
+
+    #[test]
+    fn it_uses_the_symmetry_of_functions() {
+        let x = || Node::Var("x".to_owned());
+        let y = || Node::Var("y".to_owned());
+        let check = |input: Node, expected: Node| {
+            assert_eq!(
+                simplify(input).unwrap().result,
+                simplify(expected).unwrap().result
+            );
+        };
+        check((-x()).sin(), -x().sin());
+        check((-x()).cos(), x().cos());
+        check((-x()).abs(), x().abs());
+        check((-common::two() * x()).tanh(), -(common::two() * x()).tanh());
+        check((-x() + y()).cosh(), (x() - y()).cosh());
+        check((-x() + y()).atan(), -(x() - y()).atan());
+        // -sin(2) is shorter than sin(-2)
+        check(common::two().sin(), -(-common::two()).sin());
+    }
+
+    #[test]
+    fn it_reduces_ratios() {
+        let num = |n: i32, d: i32| Node::Num {
+            val: BigRational::new(n.into(), d.into()),
+            input_base: Some(10),
+        };
+        // 6:4 = 3:2
+        assert_eq!(
+            simplify(Node::Ratio(vec![num(6, 1), num(4, 1)])).unwrap(),
+            SimplifySuccess {
+                result: Node::Ratio(vec![num(3, 1), num(2, 1)]),
+                did_something: true,
+            }
+        );
+        // 1/2:1:3/4 = 2:4:3
+        assert_eq!(
+            simplify(Node::Ratio(vec![num(1, 2), num(1, 1), num(3, 4)]))
+                .unwrap()
+                .result,
+            Node::Ratio(vec![num(2, 1), num(4, 1), num(3, 1)])
+        );
+        // 3:2 is already reduced
+        assert!(
+            !simplify(Node::Ratio(vec![num(3, 1), num(2, 1)]))
+                .unwrap()
+                .did_something
+        );
+    }
+
+    #[test]
+    fn it_simplifies_logarithms() {
+        let x = Node::Var("x".to_string());
+        let ten = Node::Num {
+            val: BigRational::from_integer(10.into()),
+            input_base: Some(10),
+        };
+        let num = |n: i32, d: i32| Node::Num {
+            val: BigRational::new(n.into(), d.into()),
+            input_base: Some(10),
+        };
+
+        // ln(e) = 1
+        assert_eq!(
+            simplify(Node::Const(ConstKind::E).ln()).unwrap().result,
+            common::one()
+        );
+        // log(2, 2^x) = x
+        let input = Node::Exp(Box::new(common::two()), Box::new(x.clone())).log(common::two());
+        assert_eq!(simplify(input).unwrap().result, x);
+        // log(10, 1000) = 3
+        assert_eq!(
+            simplify(num(1000, 1).log(ten.clone())).unwrap().result,
+            common::three()
+        );
+        // log(10, 1/100) = -2
+        assert_eq!(
+            simplify(num(1, 100).log(ten.clone())).unwrap().result,
+            Node::Num {
+                val: BigRational::from_integer((-2).into()),
+                input_base: None,
+            }
+        );
+        // log(10, 2) cannot be simplified
+        assert_eq!(
+            simplify(common::two().log(ten.clone())).unwrap().result,
+            Node::Log(Box::new(ten), Box::new(common::two()))
+        );
+        // ln(-1) = error
+        assert_eq!(
+            simplify(common::minus_one().ln()),
+            Err(SimplifyError::LogOfNonPositive)
+        );
+        // the base is negative, so the logarithm is not 0
+        let input = parse("log(atan(-3pi), 1)");
+        assert_eq!(input.eval(), Err(EvalError::InvalidLogBase));
+        assert_eq!(
+            simplify(input).unwrap().result.eval(),
+            Err(EvalError::InvalidLogBase)
+        );
+    }
+
     fn test_trigonometric_functions(input: &Node) {
         const TRIGO_FUNCS: [&dyn Fn(Box<Node>) -> Node; 3] = [&Node::Sin, &Node::Cos, &Node::Tan];
         for func in &TRIGO_FUNCS {
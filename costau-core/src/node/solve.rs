@@ -0,0 +1,351 @@
+use num_rational::BigRational;
+use num_traits::{One, Signed, Zero};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use super::simplify::SimplifyError;
+use super::util::common;
+use super::{EvalError, FormatOptions, Node};
+
+/// The real solutions of an equation.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Solutions {
+    /// The equation holds only for these values, in increasing order when
+    /// they can be compared. The list is empty if there is no solution.
+    Finite(Vec<Node>),
+    /// The equation holds for every value of the variable
+    All,
+}
+
+impl Solutions {
+    /// Writes the solutions with the words of the locale of the options, such
+    /// as `-1 or 1`.
+    pub fn format(&self, options: &FormatOptions) -> String {
+        match self {
+            Solutions::Finite(solutions) if solutions.is_empty() => {
+                options.locale.no_solution.to_owned()
+            }
+            Solutions::Finite(solutions) => solutions
+                .iter()
+                .map(|x| x.format(options))
+                .collect::<Vec<_>>()
+                .join(&format!(" {} ", options.locale.or)),
+            Solutions::All => options.locale.any_value.to_owned(),
+        }
+    }
+}
+
+/// The solutions of a system of linear equations.
+#[derive(Debug, PartialEq, Clone)]
+pub enum SystemSolutions {
+    /// The value of each variable of the only solution
+    Unique(HashMap<String, BigRational>),
+    NoSolution,
+    /// Infinitely many solutions, where the `free` variables can take any
+    /// value and the other variables depend on them
+    Family {
+        free: Vec<String>,
+        values: HashMap<String, Node>,
+    },
+}
+
+/// A description of the error of the `solve` functions.
+#[derive(Debug, PartialEq)]
+pub enum SolveError {
+    /// The equation is not linear or quadratic in the variable
+    Unsupported,
+    Simplify(SimplifyError),
+}
+
+impl From<SimplifyError> for SolveError {
+    fn from(err: SimplifyError) -> SolveError {
+        SolveError::Simplify(err)
+    }
+}
+
+/// Returns whether the node is only piecewise a polynomial, so that its
+/// derivatives do not tell its degree.
+fn is_piecewise(node: &Node) -> bool {
+    match node {
+        Node::Abs(_)
+        | Node::Sign(_)
+        | Node::Floor(_)
+        | Node::Ceil(_)
+        | Node::Round(_)
+        | Node::Min(_)
+        | Node::Max(_) => true,
+        node => node.children().into_iter().any(is_piecewise),
+    }
+}
+
+fn simplify(node: Node) -> Result<Node, SolveError> {
+    Ok(node.simplify()?.result)
+}
+
+/// Returns the value of the node when the variable is zero.
+fn value_at_zero(node: &Node, var: &str) -> Result<Node, SolveError> {
+    let val = simplify(node.substitute(var, &common::zero()))?;
+    match val.eval() {
+        Ok(x) if x.val.is_finite() && x.imag == 0.0 => Ok(val),
+        // the coefficients can depend on other variables
+        Err(EvalError::UnboundVar) => Ok(val),
+        _ => Err(SolveError::Unsupported),
+    }
+}
+
+/// Returns the coefficients `[c, b, a]` of the polynomial `a x^2 + b x + c`
+/// that the node is equal to.
+fn get_coefs(node: &Node, var: &str) -> Result<[Node; 3], SolveError> {
+    if is_piecewise(node) {
+        return Err(SolveError::Unsupported);
+    }
+    let first = simplify(node.derivative(var))?;
+    let second = simplify(first.derivative(var))?;
+    if simplify(second.derivative(var))? != common::zero() {
+        return Err(SolveError::Unsupported);
+    }
+    let a = simplify(value_at_zero(&second, var)? / common::two())?;
+    Ok([value_at_zero(node, var)?, value_at_zero(&first, var)?, a])
+}
+
+/// Sorts the solutions in increasing order if they all have a value.
+fn sort(mut solutions: Vec<Node>) -> Vec<Node> {
+    let vals: Option<Vec<f64>> = solutions
+        .iter()
+        .map(|x| x.eval().ok().map(|x| x.val))
+        .collect();
+    if let Some(vals) = vals {
+        let mut pairs: Vec<(f64, Node)> = vals.into_iter().zip(solutions).collect();
+        pairs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        solutions = pairs.into_iter().map(|x| x.1).collect();
+    }
+    solutions
+}
+
+/// Solves the equation `lhs = rhs` for the variable `var`, if it is linear or
+/// quadratic in the variable.
+pub fn solve(lhs: &Node, rhs: &Node, var: &str) -> Result<Solutions, SolveError> {
+    let diff = simplify(lhs.clone() - rhs.clone())?;
+    let [c, b, a] = get_coefs(&diff, var)?;
+    let zero = common::zero();
+
+    if a == zero {
+        if b == zero {
+            return Ok(if c == zero {
+                Solutions::All
+            } else {
+                Solutions::Finite(Vec::new())
+            });
+        }
+        return Ok(Solutions::Finite(vec![simplify(-c / b)?]));
+    }
+
+    let discriminant = simplify(b.clone().sqr() - common::two() * common::two() * a.clone() * c)?;
+    let sign = match discriminant.eval_exact() {
+        Ok(x) if x.is_zero() => Some(Ordering::Equal),
+        Ok(x) if x.is_negative() => Some(Ordering::Less),
+        Ok(_) => Some(Ordering::Greater),
+        Err(_) => match discriminant.eval() {
+            Ok(x) if x.val < 0.0 => Some(Ordering::Less),
+            Ok(_) => Some(Ordering::Greater),
+            // the sign depends on other variables
+            Err(_) => None,
+        },
+    };
+    let denom = common::two() * a;
+    let solutions = match sign {
+        Some(Ordering::Less) => Vec::new(),
+        Some(Ordering::Equal) => vec![simplify(-b / denom)?],
+        _ => {
+            let root = discriminant.sqrt();
+            vec![
+                simplify((-b.clone() - root.clone()) / denom.clone())?,
+                simplify((-b + root) / denom)?,
+            ]
+        }
+    };
+    Ok(Solutions::Finite(sort(solutions)))
+}
+
+/// Returns the row `[a_1, ..., a_n, b]` of the linear equation
+/// `a_1 x_1 + ... + a_n x_n = b` that is equivalent to `lhs = rhs`.
+fn get_row(lhs: &Node, rhs: &Node, vars: &[&str]) -> Result<Vec<BigRational>, SolveError> {
+    let diff = simplify(lhs.clone() - rhs.clone())?;
+    let mut row = Vec::with_capacity(vars.len() + 1);
+    let mut constant = diff.clone();
+    for var in vars {
+        // the coefficient of a linear equation does not depend on any
+        // variable
+        let coef = simplify(diff.derivative(var))?;
+        row.push(coef.eval_exact().map_err(|_| SolveError::Unsupported)?);
+        constant = constant.substitute(var, &common::zero());
+    }
+    let constant = simplify(constant)?
+        .eval_exact()
+        .map_err(|_| SolveError::Unsupported)?;
+    row.push(-constant);
+    Ok(row)
+}
+
+/// Solves the system of the equations `lhs = rhs` for the variables `vars`,
+/// if they are linear with rational coefficients.
+pub fn solve_system(
+    equations: &[(Node, Node)],
+    vars: &[&str],
+) -> Result<SystemSolutions, SolveError> {
+    let mut rows = Vec::with_capacity(equations.len());
+    for (lhs, rhs) in equations {
+        rows.push(get_row(lhs, rhs, vars)?);
+    }
+
+    // Gauss-Jordan elimination, which leaves the matrix in reduced row echelon
+    // form
+    let mut pivots = Vec::new();
+    for col in 0..vars.len() {
+        let r = pivots.len();
+        let pivot = match (r..rows.len()).find(|&i| !rows[i][col].is_zero()) {
+            Some(i) => i,
+            None => continue,
+        };
+        rows.swap(r, pivot);
+        let inv = BigRational::one() / &rows[r][col];
+        for x in rows[r].iter_mut() {
+            *x *= &inv;
+        }
+        let pivot_row = rows[r].clone();
+        for (i, row) in rows.iter_mut().enumerate() {
+            if i != r && !row[col].is_zero() {
+                let factor = row[col].clone();
+                for (x, y) in row.iter_mut().zip(&pivot_row).skip(col) {
+                    *x -= &factor * y;
+                }
+            }
+        }
+        pivots.push(col);
+    }
+
+    // the rows without a pivot say `0 = b`
+    if rows[pivots.len()..]
+        .iter()
+        .any(|x| !x[vars.len()].is_zero())
+    {
+        return Ok(SystemSolutions::NoSolution);
+    }
+    if pivots.len() == vars.len() {
+        let values = vars
+            .iter()
+            .zip(rows)
+            .map(|(var, row)| (var.to_string(), row[vars.len()].clone()))
+            .collect();
+        return Ok(SystemSolutions::Unique(values));
+    }
+
+    let free: Vec<usize> = (0..vars.len()).filter(|x| !pivots.contains(x)).collect();
+    let mut values = HashMap::new();
+    for (row, &col) in rows.iter().zip(&pivots) {
+        let mut terms = vec![Node::Num {
+            val: row[vars.len()].clone(),
+            input_base: None,
+        }];
+        for &j in &free {
+            let coef = Node::Num {
+                val: -row[j].clone(),
+                input_base: None,
+            };
+            terms.push(coef * Node::Var(vars[j].to_owned()));
+        }
+        values.insert(vars[col].to_owned(), simplify(Node::Sum(terms))?);
+    }
+    Ok(SystemSolutions::Family {
+        free: free.into_iter().map(|x| vars[x].to_owned()).collect(),
+        values,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    fn solve_str(lhs: &str, rhs: &str) -> Result<Vec<String>, SolveError> {
+        match parse(lhs).solve(&parse(rhs), "x")? {
+            Solutions::Finite(solutions) => Ok(solutions.iter().map(|x| x.to_string()).collect()),
+            Solutions::All => Ok(vec!["all".to_owned()]),
+        }
+    }
+
+    #[test]
+    fn it_solves_linear_equations() {
+        assert_eq!(solve_str("3x + 1", "7"), Ok(vec!["2".to_owned()]));
+        assert_eq!(solve_str("x / 4", "x - 3/2"), Ok(vec!["2".to_owned()]));
+        assert_eq!(solve_str("2x + 1", "2x"), Ok(vec![]));
+        assert_eq!(
+            solve_str("2 * (x + 1)", "2x + 2"),
+            Ok(vec!["all".to_owned()])
+        );
+    }
+
+    #[test]
+    fn it_solves_quadratic_equations() {
+        assert_eq!(
+            solve_str("x^2 - 5x + 6", "0"),
+            Ok(vec!["2".to_owned(), "3".to_owned()])
+        );
+        assert_eq!(solve_str("x^2 + 1", "0"), Ok(vec![]));
+        assert_eq!(solve_str("(x - 1)^2", "0"), Ok(vec!["1".to_owned()]));
+
+        let solutions = match parse("x^2").solve(&parse("2"), "x").unwrap() {
+            Solutions::Finite(x) => x,
+            Solutions::All => panic!("x^2 = 2 does not hold for every x"),
+        };
+        let vals: Vec<f64> = solutions.iter().map(|x| x.eval().unwrap().val).collect();
+        assert!((vals[0] + 2f64.sqrt()).abs() < 1e-12);
+        assert!((vals[1] - 2f64.sqrt()).abs() < 1e-12);
+    }
+
+    fn solve_system_str(equations: &[(&str, &str)]) -> SystemSolutions {
+        let equations: Vec<(Node, Node)> = equations
+            .iter()
+            .map(|&(lhs, rhs)| (parse(lhs), parse(rhs)))
+            .collect();
+        solve_system(&equations, &["x", "y", "z"]).unwrap()
+    }
+
+    #[test]
+    fn it_solves_systems_of_linear_equations() {
+        let solutions =
+            solve_system_str(&[("x + y + z", "6"), ("2x - y", "0"), ("x / 2 + 3z", "19/2")]);
+        let num = |x: i64| BigRational::from_integer(x.into());
+        let expected = vec![("x", num(1)), ("y", num(2)), ("z", num(3))];
+        let expected = expected
+            .into_iter()
+            .map(|(k, v)| (k.to_owned(), v))
+            .collect();
+        assert_eq!(solutions, SystemSolutions::Unique(expected));
+
+        let solutions = solve_system_str(&[("x + y", "1"), ("x + y", "2"), ("z", "0")]);
+        assert_eq!(solutions, SystemSolutions::NoSolution);
+    }
+
+    #[test]
+    fn it_parameterizes_underdetermined_systems() {
+        let (free, values) = match solve_system_str(&[("x + y + z", "2"), ("2x + 2y", "2")]) {
+            SystemSolutions::Family { free, values } => (free, values),
+            other => panic!("expected a family of solutions, got {:?}", other),
+        };
+        assert_eq!(free, vec!["y".to_owned()]);
+        assert_eq!(values["x"].to_string(), "1 - y");
+        assert_eq!(values["z"].to_string(), "1");
+    }
+
+    #[test]
+    fn it_refuses_other_equations() {
+        assert_eq!(solve_str("x^3", "8"), Err(SolveError::Unsupported));
+        assert_eq!(solve_str("sin(x)", "0"), Err(SolveError::Unsupported));
+        assert_eq!(solve_str("abs(x)", "1"), Err(SolveError::Unsupported));
+        assert_eq!(
+            solve_system(&[(parse("x * y"), parse("1"))], &["x", "y"]),
+            Err(SolveError::Unsupported)
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Signed, Zero};
+
+use super::Node;
+
+/// A notable change that the simplification made, which educational
+/// frontends can explain to the user.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Step {
+    /// A fraction of integers was reduced by their greatest common divisor,
+    /// such as `2/4` becoming `1/2`
+    ReduceFraction {
+        numer: BigInt,
+        denom: BigInt,
+        result: BigRational,
+    },
+}
+
+/// Returns the steps that the simplification of the node will make.
+pub fn find_steps(node: &Node) -> Vec<Step> {
+    let mut steps = Vec::new();
+    collect_steps(node, &mut steps);
+    steps
+}
+
+fn collect_steps(node: &Node, steps: &mut Vec<Step>) {
+    if let Node::Product(_) = node {
+        let mut numer = BigInt::one();
+        let mut denom = BigInt::one();
+        let mut others = Vec::new();
+        collect_factors(node, &mut numer, &mut denom, &mut others);
+        let gcd = numer.gcd(&denom);
+        if !denom.is_one() && !gcd.is_one() {
+            steps.push(Step::ReduceFraction {
+                result: BigRational::new(numer.clone(), denom.clone()),
+                numer,
+                denom,
+            });
+        }
+        for other in others {
+            collect_steps(other, steps);
+        }
+        return;
+    }
+    for child in node.children() {
+        collect_steps(child, steps);
+    }
+}
+
+/// Multiplies the integers of a product into `numer`, the inverses of
+/// integers into `denom`, and keeps the other factors.
+fn collect_factors<'a>(
+    node: &'a Node,
+    numer: &mut BigInt,
+    denom: &mut BigInt,
+    others: &mut Vec<&'a Node>,
+) {
+    match node {
+        Node::Product(children) => {
+            for child in children {
+                collect_factors(child, numer, denom, others);
+            }
+        }
+        Node::Num { val, .. } if val.is_integer() => *numer *= val.to_integer(),
+        Node::Exp(a, b) => match (&**a, &**b) {
+            (Node::Num { val: a, .. }, Node::Num { val: b, .. })
+                if a.is_integer() && !a.is_zero() && *b == -BigRational::one() =>
+            {
+                let a = a.to_integer();
+                if a.is_negative() {
+                    *numer = -&*numer;
+                }
+                *denom *= a.abs();
+            }
+            _ => others.push(node),
+        },
+        _ => others.push(node),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{parse, simplify_str};
+
+    fn steps_str(expr: &str) -> Vec<Step> {
+        let node = parse(expr);
+        node.simplify_with_steps().unwrap().1
+    }
+
+    fn result_str(expr: &str) -> String {
+        parse(expr)
+            .simplify_with_steps()
+            .unwrap()
+            .0
+            .result
+            .to_string()
+    }
+
+    fn reduce(numer: i64, denom: i64) -> Step {
+        Step::ReduceFraction {
+            numer: numer.into(),
+            denom: denom.into(),
+            result: BigRational::new(numer.into(), denom.into()),
+        }
+    }
+
+    #[test]
+    fn it_records_reduced_fractions() {
+        assert_eq!(steps_str("2/4"), vec![reduce(2, 4)]);
+        assert_eq!(steps_str("x - 6/4 * x"), vec![reduce(-6, 4)]);
+        assert_eq!(
+            steps_str("sin(10/15) + 3/9"),
+            vec![reduce(10, 15), reduce(3, 9)]
+        );
+    }
+
+    #[test]
+    fn it_does_not_record_irreducible_fractions() {
+        assert_eq!(steps_str("1/2 + 2/3"), vec![]);
+        assert_eq!(steps_str("4 * 6"), vec![]);
+        assert_eq!(steps_str("4 / x"), vec![]);
+    }
+
+    #[test]
+    fn it_simplifies_units_like_simplify() {
+        assert_eq!(result_str("2 m + 50 cm"), simplify_str("2 m + 50 cm"));
+        assert_eq!(steps_str("(6/4) km"), vec![reduce(6, 4)]);
+    }
+}
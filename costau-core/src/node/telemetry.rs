@@ -0,0 +1,189 @@
+use std::time::{Duration, Instant};
+
+/// A family of simplification rules.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Rule {
+    /// The numbers of a sum or a product were added or multiplied
+    FoldNumbers,
+    /// A product of square roots became a single root, such as
+    /// `sqrt(2) * sqrt(8)`
+    CombineRadicals,
+    /// A product of sums was distributed
+    ExpandProduct,
+    /// A power was computed or rewritten, such as `sqrt(12)` becoming
+    /// `2 * sqrt(3)`
+    Power,
+    /// A trigonometric function of a common angle was replaced with its
+    /// exact value, such as `sin(pi/6)` becoming `1/2`
+    ExactTrig,
+    /// An odd or even function or a periodic function was rewritten, such as
+    /// `sin(-x)` becoming `-sin(x)`
+    Symmetry,
+    /// An inverse trigonometric function of a common value became an angle
+    InverseTrig,
+    Atan2,
+    Logarithm,
+    /// `abs`, `floor`, `ceil`, `round` or `sign` of a known value
+    Rounding,
+    MinMax,
+    Ratio,
+}
+
+impl Rule {
+    /// A stable name for the reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::FoldNumbers => "fold_numbers",
+            Rule::CombineRadicals => "combine_radicals",
+            Rule::ExpandProduct => "expand_product",
+            Rule::Power => "power",
+            Rule::ExactTrig => "exact_trig",
+            Rule::Symmetry => "symmetry",
+            Rule::InverseTrig => "inverse_trig",
+            Rule::Atan2 => "atan2",
+            Rule::Logarithm => "logarithm",
+            Rule::Rounding => "rounding",
+            Rule::MinMax => "min_max",
+            Rule::Ratio => "ratio",
+        }
+    }
+}
+
+/// A step of a calculation that is timed.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Pass {
+    Simplify,
+    /// Rewriting the logarithms with `SimplifyOptions::logs`
+    Logs,
+    Rationalize,
+    Together,
+    /// Approximating the result
+    Eval,
+}
+
+/// Receives the rules that fired and the time of each pass during a
+/// calculation. The reports are only collected in memory, so that the
+/// frontend decides what to do with them.
+pub trait Telemetry {
+    /// Called for each time a rule changed the node during the pass that is
+    /// reported next.
+    fn rule_fired(&mut self, _rule: Rule) {}
+
+    /// Called at the end of each pass with the time it took.
+    fn pass_finished(&mut self, _pass: Pass, _duration: Duration) {}
+
+    /// Whether the passes are timed. The clock is not read otherwise, since
+    /// some targets such as WebAssembly in browsers have none.
+    fn is_enabled(&self) -> bool {
+        true
+    }
+}
+
+/// Ignores the reports.
+impl Telemetry for () {
+    fn is_enabled(&self) -> bool {
+        false
+    }
+}
+
+/// Runs a pass, which reports its rules to the telemetry it is given, and
+/// reports its time.
+pub(super) fn run_pass<T, F>(telemetry: &mut dyn Telemetry, pass: Pass, f: F) -> T
+where
+    F: FnOnce(&mut dyn Telemetry) -> T,
+{
+    if !telemetry.is_enabled() {
+        return f(telemetry);
+    }
+    let start = Instant::now();
+    let res = f(telemetry);
+    telemetry.pass_finished(pass, start.elapsed());
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::SimplifyOptions;
+    use super::*;
+    use crate::test_util::parse;
+
+    #[derive(Default)]
+    struct Recorder {
+        rules: Vec<Rule>,
+        passes: Vec<Pass>,
+    }
+
+    impl Telemetry for Recorder {
+        fn rule_fired(&mut self, rule: Rule) {
+            self.rules.push(rule);
+        }
+
+        fn pass_finished(&mut self, pass: Pass, _duration: Duration) {
+            self.passes.push(pass);
+        }
+    }
+
+    #[test]
+    fn it_reports_rules_and_passes() {
+        let mut recorder = Recorder::default();
+        let options = SimplifyOptions {
+            rationalize: true,
+            ..SimplifyOptions::default()
+        };
+        let node = parse("sin(pi/6) + 1/sqrt(2)");
+        let result = node.simplify_with_telemetry(&options, &mut recorder);
+        assert_eq!(result.unwrap().result.to_string(), "1/2 + 1/2 * 2^(1/2)");
+        assert!(recorder.rules.contains(&Rule::ExactTrig));
+        assert!(recorder.rules.contains(&Rule::FoldNumbers));
+        // the rationalized node is simplified again
+        assert_eq!(
+            recorder.passes,
+            vec![Pass::Simplify, Pass::Rationalize, Pass::Simplify]
+        );
+
+        let mut recorder = Recorder::default();
+        parse("x")
+            .simplify_with_telemetry(&SimplifyOptions::default(), &mut recorder)
+            .unwrap();
+        assert_eq!(recorder.rules, vec![]);
+        assert_eq!(recorder.passes, vec![Pass::Simplify]);
+    }
+
+    #[test]
+    fn it_does_not_time_the_passes_without_telemetry() {
+        struct Disabled(Vec<Rule>);
+
+        impl Telemetry for Disabled {
+            fn rule_fired(&mut self, rule: Rule) {
+                self.0.push(rule);
+            }
+
+            fn pass_finished(&mut self, _pass: Pass, _duration: Duration) {
+                panic!("the pass was timed");
+            }
+
+            fn is_enabled(&self) -> bool {
+                false
+            }
+        }
+
+        let mut disabled = Disabled(Vec::new());
+        let result =
+            parse("min(1, 2)").simplify_with_telemetry(&SimplifyOptions::default(), &mut disabled);
+        assert_eq!(result.unwrap().result.to_string(), "1");
+        // the rules are still given to the telemetry
+        assert_eq!(disabled.0, vec![Rule::MinMax]);
+    }
+
+    #[test]
+    fn it_times_the_approximation() {
+        let mut recorder = Recorder::default();
+        let calculation = parse("max(1, 2) / 3").calculate_with_telemetry(&mut recorder);
+        assert_eq!(calculation.unwrap().to_string(), "2/3 ≈ 0.6666666666666666");
+        assert_eq!(
+            recorder.rules,
+            vec![Rule::MinMax, Rule::Power, Rule::FoldNumbers]
+        );
+        assert_eq!(recorder.passes, vec![Pass::Simplify, Pass::Eval]);
+    }
+}
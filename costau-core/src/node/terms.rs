@@ -0,0 +1,150 @@
+use num_traits::{Signed, ToPrimitive};
+
+use super::util::common;
+use super::Node;
+
+/// Returns the terms of the node once it is expanded, such as `a*x` and `b`
+/// for `a*x + b`.
+fn expanded_terms(node: &Node) -> Vec<Node> {
+    let expanded = node.clone().expand().unwrap_or_else(|_| node.clone());
+    match expanded {
+        Node::Sum(terms) => terms,
+        node => vec![node],
+    }
+}
+
+fn into_factors(node: Node) -> Vec<Node> {
+    match node {
+        Node::Product(factors) => factors,
+        node => vec![node],
+    }
+}
+
+/// Forgets the bases that the numbers were written in, so that `x^2` is the
+/// same in the pattern and in the simplified node.
+fn without_bases(node: Node) -> Node {
+    match node {
+        Node::Num { val, .. } => Node::Num {
+            val,
+            input_base: None,
+        },
+        node => node.map_children(without_bases),
+    }
+}
+
+fn product(mut factors: Vec<Node>) -> Node {
+    match factors.len() {
+        0 => common::one(),
+        1 => factors.pop().unwrap(),
+        _ => Node::Product(factors),
+    }
+}
+
+/// Returns the power of `var` in the term and the product of its other
+/// factors, or `None` if the variable is not only raised to a positive
+/// integer power, such as in `sin(x)`.
+fn split_power(term: Node, var: &str) -> Option<(u32, Node)> {
+    let mut power = 0;
+    let mut others = Vec::new();
+    for factor in into_factors(term) {
+        match &factor {
+            Node::Var(name) if name == var => power += 1,
+            Node::Exp(base, expon) if base.contains_var(var) => match (&**base, &**expon) {
+                (Node::Var(name), Node::Num { val, .. })
+                    if name == var && val.is_integer() && val.is_positive() =>
+                {
+                    power += val.to_integer().to_u32()?
+                }
+                _ => return None,
+            },
+            factor if factor.contains_var(var) => return None,
+            _ => others.push(factor),
+        }
+    }
+    Some((power, product(others)))
+}
+
+/// Returns the coefficient of `var^power` in the node if it is a polynomial
+/// in the variable, such as `a` for the power 1 and `b` for the power 0 in
+/// `a*x + b`. The other variables can be in the coefficients.
+pub fn coefficient(node: &Node, var: &str, power: u32) -> Option<Node> {
+    let mut coefs = Vec::new();
+    for term in expanded_terms(node) {
+        let (term_power, coef) = split_power(term, var)?;
+        if term_power == power {
+            coefs.push(coef);
+        }
+    }
+    let sum = match coefs.len() {
+        0 => return Some(common::zero()),
+        1 => coefs.pop().unwrap(),
+        _ => Node::Sum(coefs),
+    };
+    Some(sum.simplify().ok()?.result)
+}
+
+/// Returns the terms of the expanded node that are the pattern times factors
+/// without its variables, such as `3x^2` and `a*x^2` for the pattern `x^2`
+/// in `3x^2 + a*x^2 + x + 1`. The numbers of the pattern are ignored.
+pub fn terms_matching(node: &Node, pattern: &Node) -> Vec<Node> {
+    let vars = pattern.vars();
+    let wanted: Vec<Node> = into_factors(without_bases(pattern.clone()))
+        .into_iter()
+        .filter(|x| !matches!(x, Node::Num { .. }))
+        .collect();
+    expanded_terms(node)
+        .into_iter()
+        .filter(|term| {
+            let mut factors = into_factors(without_bases(term.clone()));
+            for factor in &wanted {
+                match factors.iter().position(|x| x == factor) {
+                    Some(i) => {
+                        factors.remove(i);
+                    }
+                    None => return false,
+                }
+            }
+            factors
+                .iter()
+                .all(|x| vars.iter().all(|var| !x.contains_var(var)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::parse;
+
+    #[test]
+    fn it_extracts_coefficients() {
+        let coef_str =
+            |expr: &str, power| parse(expr).coefficient("x", power).map(|x| x.to_string());
+        // the slope and the intercept
+        assert_eq!(coef_str("3(x - 2) + 1", 1), Some("3".to_owned()));
+        assert_eq!(coef_str("3(x - 2) + 1", 0), Some("-5".to_owned()));
+        assert_eq!(coef_str("(x + 1)^2", 2), Some("1".to_owned()));
+        assert_eq!(coef_str("(x + 1)^2", 1), Some("2".to_owned()));
+        assert_eq!(coef_str("x^2 + 1", 3), Some("0".to_owned()));
+        assert_eq!(coef_str("a*x + b*x + c", 1), Some("a + b".to_owned()));
+        assert_eq!(coef_str("sin(x) + 1", 0), None);
+        assert_eq!(coef_str("x^(1/2)", 0), None);
+    }
+
+    #[test]
+    fn it_filters_terms() {
+        let terms_str = |expr: &str, pattern: &str| {
+            parse(expr)
+                .terms_matching(&parse(pattern))
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            terms_str("3x^2 + a*x^2 + x + 1", "x^2"),
+            vec!["3 * x^2", "x^2 * a"]
+        );
+        assert_eq!(terms_str("2x*y + x + y", "x*y"), vec!["2y * x"]);
+        assert_eq!(terms_str("x*sin(x) + 2sin(x)", "sin(x)"), vec!["2 * sin x"]);
+        assert_eq!(terms_str("x + 1", "y"), Vec::<String>::new());
+    }
+}
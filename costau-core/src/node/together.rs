@@ -0,0 +1,187 @@
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Signed, Zero};
+
+use super::expand::expand;
+use super::simplify::{simplify_node, Context};
+use super::util::common::num;
+use super::util::{ratio_pow, ratio_to_i32};
+use super::{EvalOptions, Node};
+
+/// A term of a sum written as `coef * numer / denom`, where the denominator
+/// is a list of bases with their positive exponents.
+struct Fraction {
+    coef: BigRational,
+    numer: Vec<Node>,
+    denom: Vec<(Node, BigRational)>,
+}
+
+impl Fraction {
+    /// Adds `base^expon` to the fraction.
+    fn add_power(&mut self, base: &Node, expon: &BigRational) {
+        match base {
+            // such as `3^(-1)`
+            Node::Num { val, .. } if expon.is_integer() && !val.is_zero() => {
+                match ratio_to_i32(expon) {
+                    Some(expon) => self.coef *= ratio_pow(val, expon),
+                    None => self.numer.push(power(base.clone(), expon.clone())),
+                }
+            }
+            // such as `(3x^2)^(-1)`
+            Node::Product(factors) if expon.is_integer() => {
+                for factor in factors {
+                    self.add_power(factor, expon);
+                }
+            }
+            Node::Exp(inner, inner_expon) if expon.is_integer() => match &**inner_expon {
+                Node::Num { val, .. } => self.add_power(inner, &(val * expon)),
+                _ => self.numer.push(power(base.clone(), expon.clone())),
+            },
+            _ if expon.is_negative() => self.denom.push((base.clone(), -expon)),
+            _ => self.numer.push(power(base.clone(), expon.clone())),
+        }
+    }
+
+    fn add_factor(&mut self, factor: &Node) {
+        match factor {
+            Node::Num { val, .. } => self.coef *= val,
+            Node::Exp(base, expon) => match &**expon {
+                Node::Num { val, .. } => self.add_power(base, val),
+                _ => self.numer.push(factor.clone()),
+            },
+            _ => self.numer.push(factor.clone()),
+        }
+    }
+
+    fn new(term: &Node) -> Fraction {
+        let mut fraction = Fraction {
+            coef: One::one(),
+            numer: Vec::new(),
+            denom: Vec::new(),
+        };
+        match term {
+            Node::Product(factors) => {
+                for factor in factors {
+                    fraction.add_factor(factor);
+                }
+            }
+            term => fraction.add_factor(term),
+        }
+        fraction
+    }
+
+    fn expon_of(&self, base: &Node) -> Option<&BigRational> {
+        self.denom.iter().find(|x| x.0 == *base).map(|x| &x.1)
+    }
+}
+
+fn power(base: Node, expon: BigRational) -> Node {
+    if expon.is_one() {
+        base
+    } else {
+        Node::Exp(Box::new(base), Box::new(num(expon)))
+    }
+}
+
+/// Writes a sum of fractions over their common denominator.
+fn together_sum(terms: &[Node], cx: &mut Context<'_>) -> Option<Node> {
+    let fractions: Vec<Fraction> = terms.iter().map(Fraction::new).collect();
+    if fractions.iter().all(|x| x.denom.is_empty()) {
+        return None;
+    }
+
+    // the common denominator has the highest power of each base and the LCM
+    // of the numeric denominators
+    let mut denom: Vec<(Node, BigRational)> = Vec::new();
+    let mut lcm: BigInt = One::one();
+    for fraction in &fractions {
+        lcm = lcm.lcm(fraction.coef.denom());
+        for (base, expon) in &fraction.denom {
+            match denom.iter_mut().find(|x| x.0 == *base) {
+                Some(x) if x.1 < *expon => x.1 = expon.clone(),
+                Some(_) => {}
+                None => denom.push((base.clone(), expon.clone())),
+            }
+        }
+    }
+
+    let numer = fractions
+        .into_iter()
+        .map(|fraction| {
+            let mut factors = vec![num(&fraction.coef * BigRational::from_integer(lcm.clone()))];
+            factors.extend(fraction.numer.iter().cloned());
+            for (base, expon) in &denom {
+                let missing = match fraction.expon_of(base) {
+                    Some(own) => expon - own,
+                    None => expon.clone(),
+                };
+                if missing.is_positive() {
+                    factors.push(power(base.clone(), missing));
+                }
+            }
+            Node::Product(factors)
+        })
+        .collect();
+    let numer = simplify_node(expand(Node::Sum(numer), cx.options).ok()?, cx)
+        .ok()?
+        .result;
+
+    // the denominator is kept as a product of its factors
+    let mut denom: Vec<Node> = denom.into_iter().map(|(b, e)| power(b, e)).collect();
+    if !lcm.is_one() {
+        denom.insert(0, num(BigRational::from_integer(lcm)));
+    }
+    let denom = if denom.len() == 1 {
+        denom.pop().unwrap()
+    } else {
+        Node::Product(denom)
+    };
+    Some(Node::Product(vec![numer, denom.inverse()]))
+}
+
+/// Writes the sums of fractions as a single fraction over their common
+/// denominator, such as `1/x + 1/y` becoming `(x + y)/(x * y)`. The result
+/// should not be simplified again because it would expand the fraction.
+pub fn together(node: Node) -> Node {
+    together_in(node, &mut Context::new(&EvalOptions::default(), &mut ()))
+}
+
+/// Like `together`, but with the context of the calculation.
+pub(super) fn together_in(node: Node, cx: &mut Context<'_>) -> Node {
+    let node = node.map_children(|x| together_in(x, cx));
+    match &node {
+        Node::Sum(terms) => together_sum(terms, cx).unwrap_or(node),
+        _ => node,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::SimplifyOptions;
+    use crate::test_util::parse;
+
+    fn together_str(expr: &str) -> String {
+        let node = parse(expr);
+        let options = SimplifyOptions {
+            together: true,
+            ..Default::default()
+        };
+        node.simplify_with(&options).unwrap().result.to_string()
+    }
+
+    #[test]
+    fn it_combines_fractions() {
+        assert_eq!(together_str("1/x + 1/y"), "(y + x) / (x * y)");
+        assert_eq!(
+            together_str("x/2 + 1/(3x^2) + 1"),
+            "(2 + 6 * x^2 + 3 * x^3) / (6 * x^2)"
+        );
+        assert_eq!(together_str("1/(x + 1) - 1/x"), "-1 / ((1 + x) * x)");
+    }
+
+    #[test]
+    fn it_keeps_sums_without_fractions() {
+        assert_eq!(together_str("x/2 + 1"), "1 + 1/2 * x");
+    }
+}
@@ -0,0 +1,190 @@
+use num_rational::BigRational;
+use num_traits::{One, ToPrimitive};
+
+use super::simplify::{simplify, SimplifyError};
+use super::util::common;
+use super::Node;
+
+/// The highest number of sines and cosines in a product that are turned into
+/// a sum, because the number of terms doubles with each of them.
+const MAX_TRIG_FACTORS: usize = 4;
+
+/// The number of nodes in the tree, which tells how complex it is.
+fn size(node: &Node) -> usize {
+    1 + node.children().into_iter().map(size).sum::<usize>()
+}
+
+fn trig(is_sin: bool, angle: Node) -> Node {
+    if is_sin {
+        angle.sin()
+    } else {
+        angle.cos()
+    }
+}
+
+/// Returns whether the node is a sine, and its angle, if it is a sine or a
+/// cosine.
+fn get_trig(node: &Node) -> Option<(bool, &Node)> {
+    match node {
+        Node::Sin(angle) => Some((true, angle)),
+        Node::Cos(angle) => Some((false, angle)),
+        _ => None,
+    }
+}
+
+/// Turns a product of sines and cosines into a sum of sines and cosines with
+/// the product-to-sum identities, such as
+/// `sin(a) * cos(b) = (sin(a + b) + sin(a - b)) / 2`.
+fn linearize_product(factors: &[Node]) -> Option<Node> {
+    let mut trigs = Vec::new();
+    let mut rest = Vec::new();
+    for factor in factors {
+        if let Some(x) = get_trig(factor) {
+            trigs.push(x);
+            continue;
+        }
+        if let Node::Exp(base, expon) = factor {
+            if let (Some(x), Node::Num { val, .. }) = (get_trig(base), &**expon) {
+                let count = val.to_integer().to_usize().filter(|_| val.is_integer());
+                if let Some(count) = count.filter(|x| (2..=MAX_TRIG_FACTORS).contains(x)) {
+                    trigs.extend(vec![x; count]);
+                    continue;
+                }
+            }
+        }
+        rest.push(factor.clone());
+    }
+    if trigs.len() < 2 || trigs.len() > MAX_TRIG_FACTORS {
+        return None;
+    }
+
+    // the empty product is `cos(0)`
+    let mut terms = vec![(BigRational::one(), false, common::zero())];
+    let half = BigRational::new(1.into(), 2.into());
+    for (is_sin_b, b) in trigs {
+        let mut product = Vec::with_capacity(terms.len() * 2);
+        for (coef, is_sin_a, a) in terms {
+            let sum = Node::Sum(vec![a.clone(), b.clone()]);
+            let diff = Node::Sum(vec![a, -b.clone()]);
+            let coef = coef * &half;
+            match (is_sin_a, is_sin_b) {
+                (false, false) => {
+                    product.push((coef.clone(), false, diff));
+                    product.push((coef, false, sum));
+                }
+                (true, false) => {
+                    product.push((coef.clone(), true, sum));
+                    product.push((coef, true, diff));
+                }
+                (false, true) => {
+                    product.push((coef.clone(), true, sum));
+                    product.push((-coef, true, diff));
+                }
+                (true, true) => {
+                    product.push((coef.clone(), false, diff));
+                    product.push((-coef, false, sum));
+                }
+            }
+        }
+        terms = product;
+    }
+
+    Some(Node::Sum(
+        terms
+            .into_iter()
+            .map(|(coef, is_sin, angle)| {
+                let mut factors = vec![
+                    Node::Num {
+                        val: coef,
+                        input_base: None,
+                    },
+                    trig(is_sin, angle),
+                ];
+                factors.extend(rest.iter().cloned());
+                Node::Product(factors)
+            })
+            .collect(),
+    ))
+}
+
+/// Turns the products of sines and cosines in the terms of a sum into sums,
+/// so that the terms can cancel out, such as in `sin(x)^2 + cos(x)^2`.
+fn linearize_sum(terms: &[Node]) -> Option<Node> {
+    let mut changed = false;
+    let terms = terms
+        .iter()
+        .map(|term| {
+            let linear = match term {
+                Node::Product(factors) => linearize_product(factors),
+                Node::Exp(..) => linearize_product(std::slice::from_ref(term)),
+                _ => None,
+            };
+            changed |= linear.is_some();
+            linear.unwrap_or_else(|| term.clone())
+        })
+        .collect();
+    if changed {
+        Some(Node::Sum(terms))
+    } else {
+        None
+    }
+}
+
+fn reduce(node: Node) -> Node {
+    let node = node.map_children(reduce);
+    let candidate = match &node {
+        Node::Sum(terms) => linearize_sum(terms),
+        Node::Product(factors) => linearize_product(factors),
+        Node::Exp(..) => linearize_product(std::slice::from_ref(&node)),
+        _ => None,
+    };
+    // the identities are only applied when they make the node simpler
+    match candidate.and_then(|x| simplify(x).ok()) {
+        Some(x) if size(&x.result) < size(&node) => x.result,
+        _ => node,
+    }
+}
+
+/// Simplifies the node and applies the double-angle, angle-sum and
+/// product-to-sum identities where they make it simpler, such as
+/// `2 * sin(x) * cos(x)` becoming `sin(2x)`.
+pub fn simplify_trig(node: Node) -> Result<Node, SimplifyError> {
+    let simplified = simplify(node)?.result;
+    let reduced = reduce(simplified);
+    Ok(simplify(reduced)?.result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::parse;
+
+    fn simplify_trig_str(expr: &str) -> String {
+        let node = parse(expr);
+        node.simplify_trig().unwrap().to_string()
+    }
+
+    #[test]
+    fn it_applies_double_angle_identities() {
+        assert_eq!(simplify_trig_str("2 * sin(x) * cos(x)"), "sin(2x)");
+        assert_eq!(simplify_trig_str("cos(x)^2 - sin(x)^2"), "cos(2x)");
+        assert_eq!(simplify_trig_str("sin(x)^2 + cos(x)^2"), "1");
+    }
+
+    #[test]
+    fn it_applies_angle_sum_identities() {
+        assert_eq!(
+            simplify_trig_str("sin(x) * cos(y) + cos(x) * sin(y)"),
+            "sin(y + x)"
+        );
+        assert_eq!(
+            simplify_trig_str("cos(x) * cos(y) + sin(x) * sin(y)"),
+            "cos(y - x)"
+        );
+    }
+
+    #[test]
+    fn it_keeps_simpler_forms() {
+        assert_eq!(simplify_trig_str("sin(x) * cos(y)"), "sin x * cos y");
+        assert_eq!(simplify_trig_str("sin(x)^2"), "(sin x)^2");
+    }
+}
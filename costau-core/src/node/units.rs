@@ -0,0 +1,1174 @@
+use num_rational::BigRational;
+use num_traits::{One, ToPrimitive, Zero};
+#[cfg(feature = "with-serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::rates;
+use super::simplify::{simplify_node, Context};
+use super::util::common::num;
+use super::util::{common, is_minus_one};
+use super::{EvalError, EvalOptions, Node, SimplifyError, SimplifySuccess};
+
+/// A kind of quantity. Only the units of the same dimension can be converted
+/// to each other.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Dimension {
+    Length,
+    Mass,
+    Time,
+    Temperature,
+    /// An amount of data, such as the size of a file
+    Data,
+    /// An amount of money, which is converted with the rates of a
+    /// `RateProvider`
+    Currency,
+}
+
+/// A unit of measurement.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum Unit {
+    Meter,
+    Kilometer,
+    Centimeter,
+    Millimeter,
+    Inch,
+    Foot,
+    Yard,
+    Mile,
+    Gram,
+    Kilogram,
+    Milligram,
+    Tonne,
+    Pound,
+    Ounce,
+    Millisecond,
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Kelvin,
+    Celsius,
+    Fahrenheit,
+    Bit,
+    Byte,
+    Kilobyte,
+    Megabyte,
+    Gigabyte,
+    Terabyte,
+    Kibibyte,
+    Mebibyte,
+    Gibibyte,
+    Tebibyte,
+    UsDollar,
+    Euro,
+    PoundSterling,
+    Yen,
+    SwissFranc,
+    CanadianDollar,
+    AustralianDollar,
+    Yuan,
+}
+
+impl Unit {
+    /// All the units, grouped by dimension.
+    pub const ALL: &'static [Unit] = &[
+        Unit::Meter,
+        Unit::Kilometer,
+        Unit::Centimeter,
+        Unit::Millimeter,
+        Unit::Inch,
+        Unit::Foot,
+        Unit::Yard,
+        Unit::Mile,
+        Unit::Gram,
+        Unit::Kilogram,
+        Unit::Milligram,
+        Unit::Tonne,
+        Unit::Pound,
+        Unit::Ounce,
+        Unit::Millisecond,
+        Unit::Second,
+        Unit::Minute,
+        Unit::Hour,
+        Unit::Day,
+        Unit::Week,
+        Unit::Kelvin,
+        Unit::Celsius,
+        Unit::Fahrenheit,
+        Unit::Bit,
+        Unit::Byte,
+        Unit::Kilobyte,
+        Unit::Megabyte,
+        Unit::Gigabyte,
+        Unit::Terabyte,
+        Unit::Kibibyte,
+        Unit::Mebibyte,
+        Unit::Gibibyte,
+        Unit::Tebibyte,
+        Unit::UsDollar,
+        Unit::Euro,
+        Unit::PoundSterling,
+        Unit::Yen,
+        Unit::SwissFranc,
+        Unit::CanadianDollar,
+        Unit::AustralianDollar,
+        Unit::Yuan,
+    ];
+
+    /// Finds the unit with the symbol or the name, such as `km` or
+    /// `kilometers`. `in` is the conversion operator, so the symbol of the
+    /// inch is `inch`.
+    pub fn from_symbol(symbol: &str) -> Option<Unit> {
+        Some(match symbol {
+            "m" | "meter" | "meters" | "metre" | "metres" => Unit::Meter,
+            "km" | "kilometer" | "kilometers" | "kilometre" | "kilometres" => Unit::Kilometer,
+            "cm" | "centimeter" | "centimeters" | "centimetre" | "centimetres" => Unit::Centimeter,
+            "mm" | "millimeter" | "millimeters" | "millimetre" | "millimetres" => Unit::Millimeter,
+            "inch" | "inches" => Unit::Inch,
+            "ft" | "foot" | "feet" => Unit::Foot,
+            "yd" | "yard" | "yards" => Unit::Yard,
+            "mi" | "mile" | "miles" => Unit::Mile,
+            "g" | "gram" | "grams" => Unit::Gram,
+            "kg" | "kilogram" | "kilograms" => Unit::Kilogram,
+            "mg" | "milligram" | "milligrams" => Unit::Milligram,
+            "t" | "tonne" | "tonnes" => Unit::Tonne,
+            "lb" | "lbs" | "pound" | "pounds" => Unit::Pound,
+            "oz" | "ounce" | "ounces" => Unit::Ounce,
+            "ms" | "millisecond" | "milliseconds" => Unit::Millisecond,
+            "s" | "sec" | "second" | "seconds" => Unit::Second,
+            // the parser only reads `min` as a unit when the function is not
+            // called with parentheses
+            "min" | "minute" | "minutes" => Unit::Minute,
+            "h" | "hr" | "hour" | "hours" => Unit::Hour,
+            "day" | "days" => Unit::Day,
+            "week" | "weeks" => Unit::Week,
+            "K" | "kelvin" => Unit::Kelvin,
+            "degC" | "celsius" => Unit::Celsius,
+            "degF" | "fahrenheit" => Unit::Fahrenheit,
+            "bit" | "bits" => Unit::Bit,
+            "B" | "byte" | "bytes" => Unit::Byte,
+            "KB" | "kB" => Unit::Kilobyte,
+            "MB" => Unit::Megabyte,
+            "GB" => Unit::Gigabyte,
+            "TB" => Unit::Terabyte,
+            "KiB" => Unit::Kibibyte,
+            "MiB" => Unit::Mebibyte,
+            "GiB" => Unit::Gibibyte,
+            "TiB" => Unit::Tebibyte,
+            "USD" => Unit::UsDollar,
+            "EUR" | "euro" | "euros" => Unit::Euro,
+            "GBP" => Unit::PoundSterling,
+            "JPY" | "yen" => Unit::Yen,
+            "CHF" => Unit::SwissFranc,
+            "CAD" => Unit::CanadianDollar,
+            "AUD" => Unit::AustralianDollar,
+            "CNY" | "yuan" => Unit::Yuan,
+            _ => return None,
+        })
+    }
+
+    /// The symbol that the unit is displayed with, which `from_symbol`
+    /// reads back.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Meter => "m",
+            Unit::Kilometer => "km",
+            Unit::Centimeter => "cm",
+            Unit::Millimeter => "mm",
+            Unit::Inch => "inch",
+            Unit::Foot => "ft",
+            Unit::Yard => "yd",
+            Unit::Mile => "mi",
+            Unit::Gram => "g",
+            Unit::Kilogram => "kg",
+            Unit::Milligram => "mg",
+            Unit::Tonne => "t",
+            Unit::Pound => "lb",
+            Unit::Ounce => "oz",
+            Unit::Millisecond => "ms",
+            Unit::Second => "s",
+            Unit::Minute => "minute",
+            Unit::Hour => "h",
+            Unit::Day => "day",
+            Unit::Week => "week",
+            Unit::Kelvin => "K",
+            Unit::Celsius => "degC",
+            Unit::Fahrenheit => "degF",
+            Unit::Bit => "bit",
+            Unit::Byte => "B",
+            Unit::Kilobyte => "KB",
+            Unit::Megabyte => "MB",
+            Unit::Gigabyte => "GB",
+            Unit::Terabyte => "TB",
+            Unit::Kibibyte => "KiB",
+            Unit::Mebibyte => "MiB",
+            Unit::Gibibyte => "GiB",
+            Unit::Tebibyte => "TiB",
+            Unit::UsDollar => "USD",
+            Unit::Euro => "EUR",
+            Unit::PoundSterling => "GBP",
+            Unit::Yen => "JPY",
+            Unit::SwissFranc => "CHF",
+            Unit::CanadianDollar => "CAD",
+            Unit::AustralianDollar => "AUD",
+            Unit::Yuan => "CNY",
+        }
+    }
+
+    pub fn dimension(self) -> Dimension {
+        match self {
+            Unit::Meter
+            | Unit::Kilometer
+            | Unit::Centimeter
+            | Unit::Millimeter
+            | Unit::Inch
+            | Unit::Foot
+            | Unit::Yard
+            | Unit::Mile => Dimension::Length,
+            Unit::Gram
+            | Unit::Kilogram
+            | Unit::Milligram
+            | Unit::Tonne
+            | Unit::Pound
+            | Unit::Ounce => Dimension::Mass,
+            Unit::Millisecond
+            | Unit::Second
+            | Unit::Minute
+            | Unit::Hour
+            | Unit::Day
+            | Unit::Week => Dimension::Time,
+            Unit::Kelvin | Unit::Celsius | Unit::Fahrenheit => Dimension::Temperature,
+            Unit::Bit
+            | Unit::Byte
+            | Unit::Kilobyte
+            | Unit::Megabyte
+            | Unit::Gigabyte
+            | Unit::Terabyte
+            | Unit::Kibibyte
+            | Unit::Mebibyte
+            | Unit::Gibibyte
+            | Unit::Tebibyte => Dimension::Data,
+            Unit::UsDollar
+            | Unit::Euro
+            | Unit::PoundSterling
+            | Unit::Yen
+            | Unit::SwissFranc
+            | Unit::CanadianDollar
+            | Unit::AustralianDollar
+            | Unit::Yuan => Dimension::Currency,
+        }
+    }
+
+    /// The value of the unit in the base unit of its dimension, which is the
+    /// meter, the kilogram, the second, the kelvin or the byte. The
+    /// currencies have no fixed value.
+    fn factor(self) -> Option<BigRational> {
+        let (numer, denom): (i64, i64) = match self {
+            Unit::Meter => (1, 1),
+            Unit::Kilometer => (1000, 1),
+            Unit::Centimeter => (1, 100),
+            Unit::Millimeter => (1, 1000),
+            Unit::Inch => (254, 10_000),
+            Unit::Foot => (3048, 10_000),
+            Unit::Yard => (9144, 10_000),
+            Unit::Mile => (1_609_344, 1000),
+            Unit::Gram => (1, 1000),
+            Unit::Kilogram => (1, 1),
+            Unit::Milligram => (1, 1_000_000),
+            Unit::Tonne => (1000, 1),
+            Unit::Pound => (45_359_237, 100_000_000),
+            Unit::Ounce => (45_359_237, 1_600_000_000),
+            Unit::Millisecond => (1, 1000),
+            Unit::Second => (1, 1),
+            Unit::Minute => (60, 1),
+            Unit::Hour => (3600, 1),
+            Unit::Day => (86_400, 1),
+            Unit::Week => (604_800, 1),
+            Unit::Kelvin | Unit::Celsius => (1, 1),
+            Unit::Fahrenheit => (5, 9),
+            Unit::Bit => (1, 8),
+            Unit::Byte => (1, 1),
+            Unit::Kilobyte => (1000, 1),
+            Unit::Megabyte => (1_000_000, 1),
+            Unit::Gigabyte => (1_000_000_000, 1),
+            Unit::Terabyte => (1_000_000_000_000, 1),
+            Unit::Kibibyte => (1 << 10, 1),
+            Unit::Mebibyte => (1 << 20, 1),
+            Unit::Gibibyte => (1 << 30, 1),
+            Unit::Tebibyte => (1 << 40, 1),
+            _ => return None,
+        };
+        Some(BigRational::new(numer.into(), denom.into()))
+    }
+
+    /// The value of zero of the unit in the base unit, which is only not zero
+    /// for the temperatures.
+    fn offset(self) -> BigRational {
+        let (numer, denom): (i64, i64) = match self {
+            Unit::Celsius => (27_315, 100),
+            // 459.67 degrees Fahrenheit
+            Unit::Fahrenheit => (45_967, 180),
+            _ => (0, 1),
+        };
+        BigRational::new(numer.into(), denom.into())
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+/// The number of dimensions, which is the most units a compound unit has.
+const DIMENSIONS: usize = 6;
+
+/// A product of powers of units of different dimensions, such as `km/h`,
+/// which is the unit of a calculation that combines quantities.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Default)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct CompoundUnit {
+    // The units in the order they were combined, with powers that are not
+    // zero, and then `None`. The powers are small so that the errors that
+    // have units are small.
+    factors: [Option<(Unit, i8)>; DIMENSIONS],
+}
+
+impl CompoundUnit {
+    /// Returns `None` if a power is too large.
+    fn from_factors<I: IntoIterator<Item = (Unit, i32)>>(factors: I) -> Option<CompoundUnit> {
+        let mut unit = CompoundUnit::default();
+        let factors = factors.into_iter().filter(|x| x.1 != 0);
+        for (slot, (x, power)) in unit.factors.iter_mut().zip(factors) {
+            *slot = Some((x, power.to_i8()?));
+        }
+        Some(unit)
+    }
+
+    /// The units with their powers, such as `(km, 1)` and `(h, -1)` for
+    /// `km/h`.
+    pub fn factors(&self) -> impl Iterator<Item = (Unit, i32)> + '_ {
+        self.factors
+            .iter()
+            .filter_map(|x| x.map(|(unit, power)| (unit, power.into())))
+    }
+
+    /// The unit itself if it is a single unit, such as `km` but not `km/h`
+    /// or `m^2`.
+    pub fn as_unit(&self) -> Option<Unit> {
+        match self.factors {
+            [Some((unit, 1)), None, ..] => Some(unit),
+            _ => None,
+        }
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        self.factors[0].is_none()
+    }
+
+    /// The unit of the dimension and its power, if it has one.
+    fn get(&self, dimension: Dimension) -> Option<(Unit, i32)> {
+        self.factors().find(|x| x.0.dimension() == dimension)
+    }
+
+    /// Whether the units can be converted to each other, such as `km/h` and
+    /// `mi/minute`.
+    fn same_dimensions(&self, other: &CompoundUnit) -> bool {
+        self.factors().count() == other.factors().count()
+            && self
+                .factors()
+                .all(|(unit, power)| other.get(unit.dimension()).map(|x| x.1) == Some(power))
+    }
+
+    /// Returns `None` if a power of a unit is not an integer, such as in the
+    /// square root of `m`, or if it is too large.
+    fn pow(&self, n: &BigRational) -> Option<CompoundUnit> {
+        let mut factors = Vec::new();
+        for (unit, power) in self.factors() {
+            let power = n * BigRational::from_integer(power.into());
+            if !power.is_integer() {
+                return None;
+            }
+            factors.push((unit, power.to_integer().to_i32()?));
+        }
+        CompoundUnit::from_factors(factors)
+    }
+}
+
+impl From<Unit> for CompoundUnit {
+    fn from(unit: Unit) -> CompoundUnit {
+        CompoundUnit::from_factors(Some((unit, 1))).unwrap()
+    }
+}
+
+impl CompoundUnit {
+    /// Writes the unit like the parser reads it after a value, such as
+    /// `km per h`, `m^2` or `degC m`, with the given word for `per`.
+    pub(crate) fn format(&self, per: &str) -> String {
+        let power = |unit: Unit, power: i32| match power.abs() {
+            1 => unit.to_string(),
+            power => format!("{}^{}", unit, power),
+        };
+        let mut words: Vec<_> = self
+            .factors()
+            .filter(|x| x.1 > 0)
+            .map(|(unit, p)| power(unit, p))
+            .collect();
+        for (unit, p) in self.factors().filter(|x| x.1 < 0) {
+            words.push(format!("{} {}", per, power(unit, p)));
+        }
+        words.join(" ")
+    }
+}
+
+impl fmt::Display for CompoundUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.format("per"))
+    }
+}
+
+/// Whether a part of the node has a unit.
+pub(crate) fn has_units(node: &Node) -> bool {
+    match node {
+        Node::Quantity(..) | Node::Time { .. } => true,
+        _ => node.children().into_iter().any(has_units),
+    }
+}
+
+fn is_one(node: &Node) -> bool {
+    matches!(node, Node::Num { val, .. } if val.is_one())
+}
+
+/// Builds `node per unit`, such as `60 km per h`.
+pub(super) fn per(node: Node, unit: Unit) -> Node {
+    let one = Node::Quantity(Box::new(common::one()), unit);
+    node * Node::Exp(Box::new(one), Box::new(common::minus_one()))
+}
+
+/// Returns the unit of `node` if it is the `per unit` part of a product.
+pub(super) fn get_per(node: &Node) -> Option<Unit> {
+    match node {
+        Node::Exp(base, expon) if is_minus_one(expon) => match &**base {
+            Node::Quantity(inner, unit) if is_one(inner) => Some(*unit),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the unit and the power of a factor such as `(1 m)^2` that
+/// `attach` adds to a product.
+fn unit_factor(node: &Node) -> Option<(Unit, i32)> {
+    match node {
+        Node::Quantity(inner, unit) if is_one(inner) => Some((*unit, 1)),
+        Node::Exp(base, expon) => match (&**base, &**expon) {
+            (Node::Quantity(inner, unit), Node::Num { val, .. })
+                if is_one(inner) && val.is_integer() =>
+            {
+                Some((*unit, val.to_integer().to_i32()?))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the value and the unit of a product that is a value with a
+/// compound unit, such as the result of `attach`, so that it is displayed
+/// like the unit of an approximation, such as `25 m^2`.
+pub(super) fn split_compound(node: &Node) -> Option<(&Node, CompoundUnit)> {
+    let children = match node {
+        Node::Product(children) if children.len() >= 2 => children,
+        _ => return None,
+    };
+    let (value, mut factors) = match &children[0] {
+        Node::Quantity(value, unit) => (&**value, vec![(*unit, 1)]),
+        value => (value, vec![]),
+    };
+    if has_units(value) {
+        return None;
+    }
+    for child in &children[1..] {
+        let (unit, power) = unit_factor(child)?;
+        // such as `2 m * 1 m`, which is not a unit
+        if factors.iter().any(|x| x.0.dimension() == unit.dimension()) {
+            return None;
+        }
+        factors.push((unit, power));
+    }
+    CompoundUnit::from_factors(factors).map(|unit| (value, unit))
+}
+
+/// Multiplies a node by a power of a unit, such as in `25 m^2` or
+/// `10 m per s^2`.
+pub(crate) fn with_unit(node: Node, unit: Unit, power: i32) -> Node {
+    let one = Node::Quantity(Box::new(common::one()), unit);
+    let factor = if power == 1 {
+        one
+    } else {
+        let power = num(BigRational::from_integer(power.into()));
+        Node::Exp(Box::new(one), Box::new(power))
+    };
+    match node {
+        Node::Product(mut children) => {
+            children.push(factor);
+            Node::Product(children)
+        }
+        node => node * factor,
+    }
+}
+
+/// Returns the dimensions of the units of a node that is a value with a
+/// unit, such as `5 km` or `60 km per h`.
+pub(crate) fn unit_dimensions(node: &Node) -> Option<Vec<Dimension>> {
+    let unit = match node {
+        Node::Quantity(inner, unit) if !has_units(inner) => (*unit).into(),
+        node => split_compound(node)?.1,
+    };
+    Some(unit.factors().map(|x| x.0.dimension()).collect())
+}
+
+/// The value of one `from` in `to`, which is the same dimension, or `None` if
+/// the exchange rate between two currencies is not known.
+fn scale(from: Unit, to: Unit, options: &EvalOptions) -> Option<BigRational> {
+    match (from.factor(), to.factor()) {
+        (Some(from), Some(to)) => Some(from / to),
+        _ => rates::rate(from, to, options),
+    }
+}
+
+fn scaled(node: Node, scale: BigRational) -> Node {
+    match node {
+        _ if scale.is_one() => node,
+        Node::Num { val, .. } => num(val * scale),
+        node => node * num(scale),
+    }
+}
+
+fn mismatch<T>(node: &Node, mismatch: DimensionMismatch) -> Result<T, DimensionError> {
+    Err(DimensionError {
+        node: node.clone(),
+        mismatch,
+    })
+}
+
+/// Returns the value of one `from` in `to`, or fails in `node`.
+fn scale_in(
+    node: &Node,
+    from: Unit,
+    to: Unit,
+    options: &EvalOptions,
+) -> Result<BigRational, DimensionError> {
+    match scale(from, to, options) {
+        Some(x) => Ok(x),
+        None => mismatch(node, DimensionMismatch::UnknownRate { from, to }),
+    }
+}
+
+/// Converts a value in the unit `from` to the unit `to` of the same
+/// dimension, where `node` is the conversion. The temperatures are
+/// converted as absolute temperatures.
+fn convert(
+    node: &Node,
+    value: Node,
+    from: Unit,
+    to: Unit,
+    options: &EvalOptions,
+) -> Result<Node, DimensionError> {
+    let scale = scale_in(node, from, to, options)?;
+    let offset = match to.factor() {
+        Some(factor) => (from.offset() - to.offset()) / factor,
+        None => BigRational::zero(),
+    };
+    if let Node::Num { val, .. } = value {
+        // exactly, so that `32 degF in K` gives `273.15 K`
+        return Ok(num(val * scale + offset));
+    }
+    let value = scaled(value, scale);
+    Ok(if offset.is_zero() {
+        value
+    } else {
+        value + num(offset)
+    })
+}
+
+/// Converts a value in the unit `from` to the unit `to`, which have the same
+/// dimensions. Only single units are converted with their offsets, so that
+/// `degC/s` is a rate of change.
+fn convert_compound(
+    node: &Node,
+    value: Node,
+    from: CompoundUnit,
+    to: CompoundUnit,
+    options: &EvalOptions,
+) -> Result<Node, DimensionError> {
+    if let (Some(from), Some(to)) = (from.as_unit(), to.as_unit()) {
+        return convert(node, value, from, to, options);
+    }
+    Ok(scaled(value, compound_scale(node, from, to, options)?))
+}
+
+/// Returns the value of one `from` in `to`, which have the same dimensions,
+/// without the offsets of the temperatures.
+fn compound_scale(
+    node: &Node,
+    from: CompoundUnit,
+    to: CompoundUnit,
+    options: &EvalOptions,
+) -> Result<BigRational, DimensionError> {
+    let mut total = BigRational::one();
+    for (unit, power) in from.factors() {
+        let target = to.get(unit.dimension()).unwrap().0;
+        total *= scale_in(node, unit, target, options)?.pow(power);
+    }
+    Ok(total)
+}
+
+/// Why the quantities of a part of a calculation cannot be combined.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum DimensionMismatch {
+    /// The terms of a sum, a `min` or a `max` have units of different
+    /// dimensions, or only some of them have a unit, such as `5 m + 3 s` or
+    /// `5 m + 3`. These are the units of the first term with a unit and of
+    /// the term that differs.
+    Terms {
+        expected: Option<CompoundUnit>,
+        found: Option<CompoundUnit>,
+    },
+    /// A quantity is raised to a power that gives a unit with a power that
+    /// is not an integer or that is too large, such as `sqrt(2 m)`
+    Power,
+    /// A quantity is converted to a unit of a dimension that it does not
+    /// have, such as `1 kg in s`
+    Conversion { from: CompoundUnit, to: Unit },
+    /// A quantity is given to a function that only accepts numbers, such as
+    /// `sin(5 m)`
+    NotDimensionless,
+    /// The exchange rate between two currencies is not known, which is the
+    /// cause of `EvalError::UnknownRate`
+    UnknownRate { from: Unit, to: Unit },
+    /// The time of `now()` or `time_in` is not known because the options have
+    /// no clock, which is the cause of `EvalError::NoClock`
+    NoClock,
+}
+
+/// A part of a calculation whose quantities cannot be combined, which is the
+/// cause of `EvalError::IncompatibleUnits`, `EvalError::UnknownRate` or
+/// `EvalError::NoClock`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DimensionError {
+    pub node: Node,
+    pub mismatch: DimensionMismatch,
+}
+
+impl From<DimensionError> for EvalError {
+    fn from(error: DimensionError) -> EvalError {
+        match error.mismatch {
+            DimensionMismatch::UnknownRate { .. } => EvalError::UnknownRate,
+            DimensionMismatch::NoClock => EvalError::NoClock,
+            _ => EvalError::IncompatibleUnits,
+        }
+    }
+}
+
+impl From<DimensionError> for SimplifyError {
+    fn from(error: DimensionError) -> SimplifyError {
+        match error.mismatch {
+            DimensionMismatch::UnknownRate { .. } => SimplifyError::UnknownRate,
+            DimensionMismatch::NoClock => SimplifyError::NoClock,
+            _ => SimplifyError::IncompatibleUnits,
+        }
+    }
+}
+
+fn optional(unit: CompoundUnit) -> Option<CompoundUnit> {
+    if unit.is_dimensionless() {
+        None
+    } else {
+        Some(unit)
+    }
+}
+
+/// Resolves the children of `node`, which must all have units of the same
+/// dimensions or no unit, and converts them to the first unit. The
+/// temperatures are converted as absolute temperatures if `absolute` is set,
+/// and as differences of temperatures otherwise.
+fn resolve_same(
+    node: &Node,
+    children: &[Node],
+    absolute: bool,
+    options: &EvalOptions,
+) -> Result<(Vec<Node>, CompoundUnit), DimensionError> {
+    let resolved = children
+        .iter()
+        .map(|x| resolve_units(x, options))
+        .collect::<Result<Vec<_>, _>>()?;
+    let unit = match resolved.iter().find(|x| !x.1.is_dimensionless()) {
+        Some(x) => x.1,
+        None => return Ok((resolved.into_iter().map(|x| x.0).collect(), unit_none())),
+    };
+    let mut converted = Vec::with_capacity(resolved.len());
+    for (child, child_unit) in resolved {
+        if !child_unit.is_dimensionless() && child_unit.same_dimensions(&unit) {
+            converted.push(if absolute {
+                convert_compound(node, child, child_unit, unit, options)?
+            } else {
+                scaled(child, compound_scale(node, child_unit, unit, options)?)
+            });
+        } else {
+            let terms = DimensionMismatch::Terms {
+                expected: Some(unit),
+                found: optional(child_unit),
+            };
+            return mismatch(node, terms);
+        }
+    }
+    Ok((converted, unit))
+}
+
+fn unit_none() -> CompoundUnit {
+    CompoundUnit::default()
+}
+
+/// Multiplies the units of a product by the unit of a factor, whose units
+/// are converted to the ones of the same dimension that the product already
+/// has. Returns the converted factor.
+fn multiply(
+    node: &Node,
+    acc: &mut CompoundUnit,
+    factor: Node,
+    unit: CompoundUnit,
+    options: &EvalOptions,
+) -> Result<Node, DimensionError> {
+    let mut total = BigRational::one();
+    let mut factors: Vec<_> = acc.factors().collect();
+    for (unit, power) in unit.factors() {
+        match factors
+            .iter_mut()
+            .find(|x| x.0.dimension() == unit.dimension())
+        {
+            Some(existing) => {
+                total *= scale_in(node, unit, existing.0, options)?.pow(power);
+                existing.1 += power;
+            }
+            None => factors.push((unit, power)),
+        }
+    }
+    *acc = match CompoundUnit::from_factors(factors) {
+        Some(x) => x,
+        None => return mismatch(node, DimensionMismatch::Power),
+    };
+    Ok(scaled(factor, total))
+}
+
+/// Rewrites the node as a node without units that is a value in the returned
+/// unit, or returns `None` if the value has no unit. Sums such as
+/// `5 km + 300 m` are converted to the unit of their first term, and the
+/// products combine the units of their factors, such as `km per h`.
+pub(crate) fn resolve(
+    node: &Node,
+    options: &EvalOptions,
+) -> Result<(Node, Option<CompoundUnit>), DimensionError> {
+    let (node, unit) = resolve_units(node, options)?;
+    Ok((node, optional(unit)))
+}
+
+fn resolve_units(
+    node: &Node,
+    options: &EvalOptions,
+) -> Result<(Node, CompoundUnit), DimensionError> {
+    if !has_units(node) {
+        return Ok((node.clone(), unit_none()));
+    }
+    Ok(match node {
+        // the clock is read now, so that the same node gives the time of
+        // each calculation
+        Node::Time { offset } => match &options.clock {
+            Some(clock) => {
+                let time = BigRational::from_integer((clock.now() + offset).into());
+                (num(time), Unit::Second.into())
+            }
+            None => return mismatch(node, DimensionMismatch::NoClock),
+        },
+        Node::Quantity(inner, unit) => {
+            let (inner, from) = resolve_units(inner, options)?;
+            if from.is_dimensionless() {
+                return Ok((inner, (*unit).into()));
+            }
+            match from.get(unit.dimension()) {
+                // such as `5 km in mi`
+                Some((single, _)) if from.as_unit().is_some() => (
+                    convert(node, inner, single, *unit, options)?,
+                    (*unit).into(),
+                ),
+                // such as `60 km per h in mi`, which gives `mi/h`
+                Some((old, power)) => {
+                    let inner = scaled(inner, scale_in(node, old, *unit, options)?.pow(power));
+                    let factors = from
+                        .factors()
+                        .map(|x| if x.0 == old { (*unit, x.1) } else { x });
+                    (inner, CompoundUnit::from_factors(factors).unwrap())
+                }
+                None => {
+                    let to = *unit;
+                    return mismatch(node, DimensionMismatch::Conversion { from, to });
+                }
+            }
+        }
+        // the terms of a sum after the first one are differences, so that
+        // `20 degC + 1 K` is `21 degC`
+        Node::Sum(children) => {
+            let (children, unit) = resolve_same(node, children, false, options)?;
+            (Node::Sum(children), unit)
+        }
+        Node::Min(children) => {
+            let (children, unit) = resolve_same(node, children, true, options)?;
+            (Node::Min(children), unit)
+        }
+        Node::Max(children) => {
+            let (children, unit) = resolve_same(node, children, true, options)?;
+            (Node::Max(children), unit)
+        }
+        Node::Product(children) => {
+            let mut unit = unit_none();
+            let mut resolved = Vec::with_capacity(children.len());
+            for child in children {
+                let (child, child_unit) = resolve_units(child, options)?;
+                resolved.push(multiply(node, &mut unit, child, child_unit, options)?);
+            }
+            (Node::Product(resolved), unit)
+        }
+        // the quantities can be raised to integer powers, such as in `km/h`,
+        // and to the fractions that give integer powers of their units, such
+        // as in `sqrt(4 m^2)`
+        Node::Exp(base, expon) => {
+            let (base, unit) = resolve_units(base, options)?;
+            let (expon, expon_unit) = resolve_units(expon, options)?;
+            if !expon_unit.is_dimensionless() {
+                return mismatch(node, DimensionMismatch::NotDimensionless);
+            }
+            match expon.eval_exact().ok().and_then(|x| unit.pow(&x)) {
+                Some(unit) => (Node::Exp(Box::new(base), Box::new(expon)), unit),
+                None => return mismatch(node, DimensionMismatch::Power),
+            }
+        }
+        Node::Abs(inner) => {
+            let (inner, unit) = resolve_units(inner, options)?;
+            (inner.abs(), unit)
+        }
+        Node::Floor(inner) => {
+            let (inner, unit) = resolve_units(inner, options)?;
+            (inner.floor(), unit)
+        }
+        Node::Ceil(inner) => {
+            let (inner, unit) = resolve_units(inner, options)?;
+            (inner.ceil(), unit)
+        }
+        Node::Round(inner) => {
+            let (inner, unit) = resolve_units(inner, options)?;
+            (inner.round(), unit)
+        }
+        Node::Sign(inner) => (resolve_units(inner, options)?.0.sign(), unit_none()),
+        _ => {
+            let mut resolved = Vec::new();
+            for child in node.children() {
+                match resolve_units(child, options)? {
+                    (child, unit) if unit.is_dimensionless() => resolved.push(child),
+                    _ => return mismatch(node, DimensionMismatch::NotDimensionless),
+                }
+            }
+            let mut resolved = resolved.into_iter();
+            let plain = node.clone().map_children(|_| resolved.next().unwrap());
+            (plain, unit_none())
+        }
+    })
+}
+
+/// Gives a value without units its unit back as a node, such as
+/// `60 km per h`.
+pub(super) fn attach(value: Node, unit: CompoundUnit) -> Node {
+    if let Some(unit) = unit.as_unit() {
+        return Node::Quantity(Box::new(value), unit);
+    }
+    // the value is in the first unit with a power of one
+    let first = unit.factors().find(|x| x.1 == 1).map(|x| x.0);
+    let mut product = vec![match first {
+        Some(first) => Node::Quantity(Box::new(value), first),
+        None => value,
+    }];
+    for (unit, power) in unit.factors() {
+        let one = || Node::Quantity(Box::new(common::one()), unit);
+        match power {
+            _ if Some(unit) == first => {}
+            1 => product.push(one()),
+            power => product.push(Node::Exp(
+                Box::new(one()),
+                Box::new(num(BigRational::from_integer(power.into()))),
+            )),
+        }
+    }
+    Node::Product(product)
+}
+
+/// Simplifies a node with units as a node without units in the unit of the
+/// result, which is then given back its unit.
+pub(super) fn simplify_units(
+    node: Node,
+    cx: &mut Context<'_>,
+) -> Result<SimplifySuccess, SimplifyError> {
+    if !has_units(&node) {
+        return simplify_node(node, cx);
+    }
+    let (plain, unit) = resolve_units(&node, cx.options)?;
+    let simplified = simplify_node(plain, cx)?.result;
+    let result = if unit.is_dimensionless() {
+        simplified
+    } else {
+        attach(simplified, unit)
+    };
+    Ok(SimplifySuccess {
+        did_something: result != node,
+        result,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ParseError, Parser};
+    use crate::test_util::{parse, simplify_str, tokens, try_parse};
+    use float_cmp::{ApproxEq, F64Margin};
+
+    fn eval_str(expr: &str) -> Result<String, EvalError> {
+        parse(expr).eval().map(|x| x.to_string())
+    }
+
+    #[test]
+    fn it_parses_units() {
+        let five = parse("5");
+        let km = Node::Quantity(Box::new(five.clone()), Unit::Kilometer);
+        assert_eq!(parse("5 km"), km);
+        assert_eq!(
+            parse("5 km in mi"),
+            Node::Quantity(Box::new(km), Unit::Mile)
+        );
+        assert_eq!(parse("2 * 5 km").to_string(), "2 * 5 km");
+        // the units are only read after a value
+        assert_eq!(parse("m"), Node::Var("m".to_owned()));
+        assert_eq!(parse("5 in"), five * Node::Var("in".to_owned()));
+        for expr in &["5 km + 300 m in mi", "(x + 1) kg", "x mi in km"] {
+            let node = parse(expr);
+            assert_eq!(node.to_string(), *expr);
+            assert_eq!(parse(&node.to_string()), node);
+        }
+    }
+
+    #[test]
+    fn it_reads_symbols_of_units_as_variables() {
+        assert_eq!(simplify_str("2x + 3t"), "2x + 3t");
+        assert_eq!(simplify_str("t^2 + 3t"), "t^2 + 3t");
+        assert_eq!(simplify_str("solve(3t - 1, 5, t)"), "(2)");
+        let derivative = parse("t^2 + 3t").derivative("t");
+        assert_eq!(derivative.simplify().unwrap().result.to_string(), "3 + 2t");
+        let integral = parse("2 * s").integrate("s").unwrap();
+        assert_eq!(integral.simplify().unwrap().result.to_string(), "s^2");
+        // the variables of the parser are never units
+        let node = Parser::new(&tokens("3t"))
+            .with_vars(&["t".to_owned()])
+            .parse();
+        assert_eq!(node.unwrap().to_string(), "3t");
+        // but the same symbols are still units after numbers
+        assert_eq!(
+            parse("3t"),
+            Node::Quantity(Box::new(parse("3")), Unit::Tonne)
+        );
+        assert_eq!(simplify_str("5 s + 3 s"), "8 s");
+    }
+
+    #[test]
+    fn it_converts_within_a_dimension() {
+        assert_eq!(eval_str("5 km + 300 m"), Ok("5.3 km".to_owned()));
+        assert_eq!(
+            eval_str("5 km in mi"),
+            Ok("3.1068559611866697 mi".to_owned())
+        );
+        assert_eq!(eval_str("100 degC in degF"), Ok("212.0 degF".to_owned()));
+        assert_eq!(eval_str("32 degF in K"), Ok("273.15 K".to_owned()));
+        assert_eq!(eval_str("3 lb in oz"), Ok("48.0 oz".to_owned()));
+        assert_eq!(
+            eval_str("2 h + 30 minutes in minute"),
+            Ok("150.0 minute".to_owned())
+        );
+        assert_eq!(eval_str("1 GiB in MB"), Ok("1073.741824 MB".to_owned()));
+        assert_eq!(eval_str("max(1 m, 90 cm) / 2"), Ok("0.5 m".to_owned()));
+        assert_eq!(eval_str("abs(-2 s)"), Ok("2.0 s".to_owned()));
+        assert_eq!(eval_str("1 h in min"), Ok("60.0 minute".to_owned()));
+        assert_eq!(eval_str("90 km per h * 20 min"), Ok("30.0 km".to_owned()));
+        // but `min` is still the function when it is called
+        assert_eq!(simplify_str("2 min(3, 4)"), "6");
+
+        let simplified = parse("5 km + 300 m").simplify().unwrap();
+        assert_eq!(simplified.result.to_string(), "(53/10) km");
+        let simplified = parse("20 degC in K").simplify().unwrap();
+        assert_eq!(simplified.result.to_string(), "(5863/20) K");
+        assert!(!parse("2 kg").simplify().unwrap().did_something);
+    }
+
+    #[test]
+    fn it_adds_temperatures_as_differences() {
+        assert_eq!(simplify_str("0 degC + 0 degC"), "0 degC");
+        assert_eq!(simplify_str("10 degC + 1 K"), "11 degC");
+        assert_eq!(simplify_str("20 degC - 9 degF"), "15 degC");
+        assert_eq!(eval_str("10 degC + 1 K"), Ok("11.0 degC".to_owned()));
+        // the conversions and the comparisons use absolute temperatures
+        assert_eq!(simplify_str("0 degC in K"), "(5463/20) K");
+        assert_eq!(simplify_str("max(0 degC, 300 K)"), "(537/20) degC");
+        // `0 h` is not a number in base 16
+        assert_eq!(simplify_str("0 h + 30 minutes"), "(1/2) h");
+    }
+
+    #[test]
+    fn it_writes_compound_units_like_the_approximations() {
+        for (expr, exact, approx) in &[
+            ("60 km per h * 2.5 / 60", "(5/2) km per h", "2.5 km per h"),
+            ("5 m * 5 m", "25 m^2", "25.0 m^2"),
+            ("(5 m)^2", "25 m^2", "25.0 m^2"),
+            ("10 m per s per s", "10 m per s^2", "10.0 m per s^2"),
+            ("10 degC * 2 m", "20 degC m", "20.0 degC m"),
+            ("3 m * 4 m / 2 s", "6 m^2 per s", "6.0 m^2 per s"),
+        ] {
+            let simplified = simplify_str(expr);
+            assert_eq!(simplified, *exact);
+            assert_eq!(eval_str(expr), Ok((*approx).to_owned()));
+            // they are read back as the same quantity
+            assert_eq!(simplify_str(&simplified), *exact);
+        }
+        // the power after a unit is the one of the unit
+        assert_eq!(simplify_str("25 m^2 in cm"), "250000 cm^2");
+        assert_eq!(simplify_str("(2 m)^2 in cm"), "40000 cm^2");
+        // and not of the conversion, which already converts all its powers
+        assert_eq!(
+            try_parse("(2 m)^2 in cm^2"),
+            Err(ParseError::UnexpectedToken { index: 13 })
+        );
+        // the unit of a conversion is a single unit
+        assert_eq!(
+            try_parse("5 km per h in m/s"),
+            Err(ParseError::UnexpectedToken { index: 15 })
+        );
+        assert_eq!(
+            try_parse("5 km/hour in m per s"),
+            Err(ParseError::UnexpectedToken { index: 15 })
+        );
+        assert_eq!(simplify_str("5 km in m / 2"), "2500 m");
+        assert_eq!(parse("2 m^-1"), parse("2 per m"));
+    }
+
+    #[test]
+    fn it_takes_roots_of_units_with_integer_powers() {
+        assert_eq!(simplify_str("sqrt(4 m^2)"), "2 m");
+        assert_eq!(eval_str("sqrt(4 m^2)"), Ok("2.0 m".to_owned()));
+        assert_eq!(simplify_str("cbrt(8 m^3 per s^3)"), "2 m per s");
+        assert_eq!(simplify_str("(9 m^4)^(1/2)"), "3 m^2");
+    }
+
+    #[test]
+    fn it_rejects_incompatible_units() {
+        for expr in &[
+            "5 km + 3 s",
+            "5 km + 3",
+            "sin(5 km)",
+            "sqrt(2 m)",
+            "1 kg in s",
+        ] {
+            assert_eq!(eval_str(expr), Err(EvalError::IncompatibleUnits));
+            assert_eq!(
+                parse(expr).simplify(),
+                Err(SimplifyError::IncompatibleUnits)
+            );
+        }
+    }
+
+    #[test]
+    fn it_names_the_part_with_incompatible_units() {
+        let check = |expr: &str| {
+            let err = parse(expr).check_dimensions().unwrap_err();
+            (err.node.to_string(), err.mismatch)
+        };
+        let terms = |expected: Unit, found: Option<Unit>| DimensionMismatch::Terms {
+            expected: Some(expected.into()),
+            found: found.map(CompoundUnit::from),
+        };
+        assert_eq!(
+            check("2 * (1 + sin(x) + (5 m + 3 s))"),
+            (
+                "5 m + 3 s".to_owned(),
+                terms(Unit::Meter, Some(Unit::Second))
+            )
+        );
+        assert_eq!(
+            check("1 m + 2"),
+            ("1 m + 2".to_owned(), terms(Unit::Meter, None))
+        );
+        assert_eq!(
+            check("(1 h + 1 kg) in degC"),
+            (
+                "1 h + 1 kg".to_owned(),
+                terms(Unit::Hour, Some(Unit::Kilogram))
+            )
+        );
+        assert_eq!(
+            check("1 + sin(3 kg)"),
+            ("sin(3 kg)".to_owned(), DimensionMismatch::NotDimensionless)
+        );
+        assert_eq!(
+            check("1 + sqrt(2 m)"),
+            ("(2 m)^(1/2)".to_owned(), DimensionMismatch::Power)
+        );
+        let conversion = DimensionMismatch::Conversion {
+            from: Unit::Kilogram.into(),
+            to: Unit::Celsius,
+        };
+        assert_eq!(
+            check("1 kg in degC"),
+            ("1 kg in degC".to_owned(), conversion)
+        );
+
+        let unit = parse("5 km + 3 m").check_dimensions();
+        assert_eq!(unit, Ok(Some(Unit::Kilometer.into())));
+        assert_eq!(parse("x + 1").check_dimensions(), Ok(None));
+    }
+
+    #[test]
+    fn it_combines_the_units_of_rates() {
+        assert_eq!(simplify_str("60 km per hour * 2.5 hours"), "150 km");
+        assert_eq!(simplify_str("$4.50 per kg * 300 g"), "(27/20) USD");
+        assert_eq!(simplify_str("60 km per h + 10 m per s"), "96 km per h");
+        assert_eq!(simplify_str("3 m * 4 m / 2 m"), "6 m");
+        assert_eq!(simplify_str("90 km / 45 km"), "2");
+
+        assert_eq!(eval_str("60 km per h"), Ok("60.0 km per h".to_owned()));
+        let cost = parse("$4.50 per kg * 300 g").eval().unwrap();
+        assert!(cost.val.approx_eq(1.35, F64Margin::default()));
+        assert_eq!(cost.unit, Some(Unit::UsDollar.into()));
+        assert_eq!(eval_str("3 m * 4 m"), Ok("12.0 m^2".to_owned()));
+        assert_eq!(
+            eval_str("90 km per h in mi"),
+            Ok("55.92340730136006 mi per h".to_owned())
+        );
+        assert_eq!(eval_str("2 / 4 s"), Ok("0.5 per s".to_owned()));
+        let unit = parse("1 m per s per s").check_dimensions().unwrap();
+        assert_eq!(unit.unwrap().to_string(), "m per s^2");
+
+        for expr in &["60 km per h", "2 USD per kg * 300 g", "x m per s in km"] {
+            let node = parse(expr);
+            assert_eq!(node.to_string(), *expr);
+            assert_eq!(parse(&node.to_string()), node);
+        }
+        // `per` is only read before a unit
+        assert_eq!(parse("2 per").to_string(), "2per");
+    }
+}
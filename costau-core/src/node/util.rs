@@ -1,6 +1,7 @@
 use num_rational::BigRational;
 use num_traits::{One, ToPrimitive};
 
+use super::eval::eval_real;
 use super::{EvalError, EvalSuccess, Node};
 
 pub(crate) fn ratio_to_i32(ratio: &BigRational) -> Option<i32> {
@@ -13,6 +14,19 @@ pub(crate) fn ratio_to_i32(ratio: &BigRational) -> Option<i32> {
     }
 }
 
+/// Raises a number to an integer power.
+pub(crate) fn ratio_pow(a: &BigRational, expon: i32) -> BigRational {
+    if expon >= 0 {
+        BigRational::new(a.numer().pow(expon as u32), a.denom().pow(expon as u32))
+    } else {
+        let pos_expon = expon.abs();
+        BigRational::new(
+            a.denom().pow(pos_expon as u32),
+            a.numer().pow(pos_expon as u32),
+        )
+    }
+}
+
 pub(crate) fn is_minus_one(node: &Node) -> bool {
     if let Node::Num { val, .. } = node {
         return (val.denom().is_one() && *val.numer() == (-1).into())
@@ -42,17 +56,16 @@ where
     let mut acc = init;
     let mut acc_base = None;
     for n in nodes {
-        let eval = n.eval()?;
+        let eval = eval_real(n)?;
+        // `inf - inf` is not a number, which `eval` reports
         acc = f(acc, eval.val);
-        if acc.is_nan() {
-            // multiplied inf*0, probably because there was a very big number
-            acc = 0.0;
-        }
         acc_base = get_op_result_base(acc_base, eval.display_base);
     }
     Ok(EvalSuccess {
         val: acc,
+        imag: 0.0,
         display_base: acc_base,
+        unit: None,
     })
 }
 
@@ -62,6 +75,14 @@ pub(crate) mod common {
 
     use crate::node::Node;
 
+    /// A number that was not written by the user, so it has no base.
+    pub(crate) fn num(val: BigRational) -> Node {
+        Node::Num {
+            val,
+            input_base: None,
+        }
+    }
+
     pub(crate) fn zero() -> Node {
         Node::Num {
             val: Zero::zero(),
@@ -1,28 +1,250 @@
+use super::builtins::{self, clock, roman};
+use super::functions::FunctionRegistry;
 use super::lexer::{IdentKind, Token, TokenKind};
-use super::node::{ConstKind, Node};
+use super::node::units;
+use super::node::util::common;
+use super::node::{ConstKind, Node, Unit};
 
 use num_rational::BigRational;
+use num_traits::ToPrimitive;
+use std::fmt;
+use std::rc::Rc;
 
 #[derive(PartialEq, Eq)]
 enum StopPolicy {
-    IfWeaker(Power),
-    IfWeakerOrEqual(Power),
+    IfWeaker(u8),
+    IfWeakerOrEqual(u8),
     Never,
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
-enum Power {
-    CloseParen,
-    Add,
-    Mul,
-    Exp,
-    ImplicitMul,
+/// The precedences of the standard operators, from the weakest to the
+/// strongest, which other operators can be placed between.
+pub mod precedence {
+    /// The closing parentheses and the commas, which end every expression
+    pub const CLOSE_PAREN: u8 = 0;
+    /// The conversions to a unit, such as `5 km in mi`
+    pub const CONVERT: u8 = 5;
+    pub const COMPARE: u8 = 10;
+    pub const RATIO: u8 = 20;
+    pub const ADD: u8 = 30;
+    pub const MUL: u8 = 40;
+    pub const EXP: u8 = 50;
+    pub const IMPLICIT_MUL: u8 = 60;
+    /// The signs in front of numbers, such as in `-x`
+    pub const PREFIX: u8 = 70;
+}
+
+/// How a sequence of operators with the same precedence is grouped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Assoc {
+    /// `1-2-3` is `(1-2)-3`
+    Left,
+    /// `1^2^3` is `1^(2^3)`
+    Right,
+}
+
+/// Where the operands of an operator are, with the function that builds its
+/// node from them.
+#[derive(Clone)]
+pub enum Arity {
+    /// Before its operand, such as `-x`
+    Prefix(Rc<dyn Fn(Node) -> Node>),
+    /// Between its operands, such as `x + y`
+    Infix(Rc<dyn Fn(Node, Node) -> Node>),
+}
+
+impl fmt::Debug for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Arity::Prefix(_) => "Prefix",
+            Arity::Infix(_) => "Infix",
+        })
+    }
+}
+
+/// An operator that the parser recognizes.
+#[derive(Debug, Clone)]
+pub struct Operator {
+    pub symbol: TokenKind,
+    /// The operators with a higher precedence are applied first.
+    pub precedence: u8,
+    pub assoc: Assoc,
+    pub arity: Arity,
+}
+
+/// The operators that the parser recognizes, so that the modes of the
+/// calculator and the user can change them without changing the parser.
+#[derive(Debug, Clone)]
+pub struct OperatorTable {
+    operators: Vec<Operator>,
+}
+
+impl OperatorTable {
+    /// A table without any operator.
+    pub fn empty() -> OperatorTable {
+        OperatorTable {
+            operators: Vec::new(),
+        }
+    }
+
+    /// The operators of the standard mode.
+    pub fn standard() -> OperatorTable {
+        use self::Arity::*;
+        use self::Assoc::*;
+
+        let mut table = OperatorTable::empty();
+        let mut add = |symbol, precedence, assoc, arity| {
+            table.insert(Operator {
+                symbol,
+                precedence,
+                assoc,
+                arity,
+            })
+        };
+        add(
+            TokenKind::ApproxEq,
+            precedence::COMPARE,
+            Left,
+            Infix(Rc::new(|left, right| {
+                builtins::compare::approx_equal(left, right, builtins::compare::default_tolerance())
+            })),
+        );
+        add(
+            TokenKind::Colon,
+            precedence::RATIO,
+            Left,
+            Infix(Rc::new(|left, right| match left {
+                // `a:b:c` is a single ratio
+                Node::Ratio(mut terms) => {
+                    terms.push(right);
+                    Node::Ratio(terms)
+                }
+                left => Node::Ratio(vec![left, right]),
+            })),
+        );
+        let plus = Infix(Rc::new(|a, b| a + b));
+        add(TokenKind::Plus, precedence::ADD, Left, plus);
+        let minus = Infix(Rc::new(|a, b| a - b));
+        add(TokenKind::Minus, precedence::ADD, Left, minus);
+        let times = Infix(Rc::new(|a, b| a * b));
+        add(TokenKind::Times, precedence::MUL, Left, times);
+        let slash = Infix(Rc::new(|a, b| a / b));
+        add(TokenKind::Slash, precedence::MUL, Left, slash);
+        let hat = Infix(Rc::new(|a, b| Node::Exp(Box::new(a), Box::new(b))));
+        add(TokenKind::Hat, precedence::EXP, Right, hat);
+        add(
+            TokenKind::Minus,
+            precedence::PREFIX,
+            Right,
+            Prefix(Rc::new(|x| -x)),
+        );
+        add(
+            TokenKind::Plus,
+            precedence::PREFIX,
+            Right,
+            Prefix(Rc::new(|x| x)),
+        );
+        add(
+            TokenKind::Dollar,
+            precedence::PREFIX,
+            Right,
+            Prefix(Rc::new(|x| Node::Quantity(Box::new(x), Unit::UsDollar))),
+        );
+        table
+    }
+
+    /// Adds an operator, replacing the one with the same symbol and the same
+    /// position of the operands if there is one.
+    pub fn insert(&mut self, operator: Operator) {
+        let is_prefix = operator.is_prefix();
+        self.operators
+            .retain(|x| x.symbol != operator.symbol || x.is_prefix() != is_prefix);
+        self.operators.push(operator);
+    }
+
+    /// Removes the operators with the symbol.
+    pub fn remove(&mut self, symbol: &TokenKind) {
+        self.operators.retain(|x| x.symbol != *symbol);
+    }
+
+    fn find(&self, symbol: &TokenKind, prefix: bool) -> Option<&Operator> {
+        self.operators
+            .iter()
+            .find(|x| x.symbol == *symbol && x.is_prefix() == prefix)
+    }
+}
+
+impl Default for OperatorTable {
+    fn default() -> OperatorTable {
+        OperatorTable::standard()
+    }
+}
+
+impl Operator {
+    fn is_prefix(&self) -> bool {
+        matches!(self.arity, Arity::Prefix(_))
+    }
+
+    /// When to stop parsing the operand on the right of the operator.
+    fn operand_policy(&self) -> StopPolicy {
+        match self.assoc {
+            Assoc::Left => StopPolicy::IfWeakerOrEqual(self.precedence),
+            Assoc::Right => StopPolicy::IfWeaker(self.precedence),
+        }
+    }
+}
+
+fn ten() -> Node {
+    Node::Num {
+        val: BigRational::from_integer(10.into()),
+        input_base: None,
+    }
+}
+
+fn hundredth() -> Node {
+    Node::Num {
+        val: BigRational::from_integer(100.into()),
+        input_base: None,
+    }
+    .inverse()
+}
+
+/// Builds `node%`, which is `node / 100`.
+fn percent(node: Node) -> Node {
+    node * hundredth()
+}
+
+/// Whether the node is the whole of a `x%`.
+fn is_percent(node: &Node) -> bool {
+    match node {
+        Node::Product(factors) => factors.len() == 2 && factors[1] == hundredth(),
+        _ => false,
+    }
+}
+
+/// The choices that the parser makes when the input is ambiguous.
+#[derive(Default, Copy, Clone)]
+struct Reading {
+    /// Whether implicit multiplication has the same precedence as explicit
+    /// multiplication, so that `1/2x` is `x/2` instead of `1/(2x)`
+    loose_implicit_mul: bool,
+    /// Whether the argument of a function without parentheses extends over
+    /// implicit multiplications and powers, so that `sin 2x` is `sin(2x)`
+    /// instead of `sin(2) * x`
+    greedy_functions: bool,
 }
 
 /// A parser converts a list of tokens into an AST (abstract syntax tree).
 pub struct Parser<'a> {
     tokens: &'a [Token],
     index: usize,
+    reading: Reading,
+    operators: OperatorTable,
+    functions: Option<&'a FunctionRegistry>,
+    percent_of: bool,
+    /// The names that are variables even if they are the symbols of units,
+    /// such as `t` in `solve(3t = 6, t)`
+    vars: Vec<String>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -30,11 +252,188 @@ pub enum ParseError {
     EarlyEof,
     UnexpectedToken { index: usize },
     UnmatchedParen,
+    InvalidArguments { index: usize },
 }
 
 impl<'a> Parser<'a> {
     pub fn new(tokens: &[Token]) -> Parser {
-        Parser { tokens, index: 0 }
+        Parser::with_operators(tokens, OperatorTable::standard())
+    }
+
+    /// Creates a parser that recognizes the operators of the table instead of
+    /// the standard ones.
+    pub fn with_operators(tokens: &[Token], operators: OperatorTable) -> Parser {
+        Parser {
+            tokens,
+            index: 0,
+            reading: Reading::default(),
+            operators,
+            functions: None,
+            percent_of: false,
+            vars: variable_names(tokens),
+        }
+    }
+
+    /// Makes the parser expand the calls to the functions of the registry,
+    /// such as `f(2)`. Without the parentheses, their names are variables.
+    pub fn with_functions(mut self, functions: &'a FunctionRegistry) -> Parser<'a> {
+        self.functions = Some(functions);
+        self
+    }
+
+    /// Makes the parser read a percentage that is added or subtracted as a
+    /// percentage of the left operand, like handheld calculators: `200 + 10%`
+    /// is `200 * (1 + 10%)`, which is 220, instead of 200.1.
+    pub fn with_percent_of(mut self) -> Parser<'a> {
+        self.percent_of = true;
+        self
+    }
+
+    /// Makes the parser read the names as variables instead of units, such
+    /// as the ones that the user defined.
+    pub fn with_vars(mut self, names: &[String]) -> Parser<'a> {
+        self.vars.extend(names.iter().cloned());
+        self
+    }
+
+    fn peek_kind(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.index).map(|t| &t.kind)
+    }
+
+    /// Returns the unit of the token at `index` if it is the symbol of a
+    /// unit, and not the call of a function with the same name.
+    fn unit_at(&self, index: usize) -> Option<Unit> {
+        let is_open = self.tokens.get(index + 1).map(|t| &t.kind) == Some(&TokenKind::OpenParen);
+        let symbol = match self.tokens.get(index).map(|t| &t.kind) {
+            Some(TokenKind::UnknownIdent(s)) => s.as_str(),
+            // `min` is the minute, such as in `5 min`, unless it is called
+            // like in `5 min(x, y)`
+            Some(TokenKind::Ident(IdentKind::Min)) if !is_open => "min",
+            _ => return None,
+        };
+        let is_call = self.functions.and_then(|x| x.get(symbol)).is_some() && is_open;
+        if is_call {
+            return None;
+        }
+        Unit::from_symbol(symbol)
+    }
+
+    /// Returns the unit of the next token if it is the unit of the value
+    /// before it, such as in `5 km`, and not a variable.
+    fn peek_unit(&self) -> Option<Unit> {
+        match self.peek_kind() {
+            Some(TokenKind::UnknownIdent(s)) if self.vars.contains(s) => None,
+            _ => self.unit_at(self.index),
+        }
+    }
+
+    /// Returns the unit of a conversion such as `in mi` if the next tokens
+    /// are one.
+    fn peek_conversion(&self) -> Option<Unit> {
+        match self.peek_kind() {
+            Some(TokenKind::UnknownIdent(s)) if s == "in" => self.unit_at(self.index + 1),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the tokens at `index` continue the unit of a
+    /// conversion as a compound unit, such as the `/s` of `in m/s`, which the
+    /// conversion does not support.
+    fn continues_conversion(&self, index: usize) -> bool {
+        match self.tokens.get(index).map(|t| &t.kind) {
+            Some(TokenKind::Slash) | Some(TokenKind::Times) => self.unit_at(index + 1).is_some(),
+            Some(TokenKind::UnknownIdent(s)) if s == "per" => self.unit_at(index + 1).is_some(),
+            Some(TokenKind::UnknownIdent(s)) if self.vars.contains(s) => false,
+            _ => self.unit_at(index).is_some(),
+        }
+    }
+
+    /// Returns the unit of a rate such as `per h` if the next tokens are
+    /// one.
+    fn peek_per(&self) -> Option<Unit> {
+        match self.peek_kind() {
+            Some(TokenKind::UnknownIdent(s)) if s == "per" => self.unit_at(self.index + 1),
+            _ => None,
+        }
+    }
+
+    /// Consumes the integer power of a unit, such as the `^2` of `m^2`, and
+    /// returns it, or 1 if there is none.
+    fn parse_unit_power(&mut self) -> i32 {
+        if self.peek_kind() != Some(&TokenKind::Hat) {
+            return 1;
+        }
+        let (negative, index) = match self.tokens.get(self.index + 1).map(|t| &t.kind) {
+            Some(TokenKind::Minus) => (true, self.index + 2),
+            _ => (false, self.index + 1),
+        };
+        let power = match self.tokens.get(index).map(|t| &t.kind) {
+            Some(TokenKind::Num { val, .. }) if val.is_integer() => val.to_integer().to_i32(),
+            _ => None,
+        };
+        match power {
+            // a power of zero is not a unit
+            Some(power) if power != 0 => {
+                self.index = index + 1;
+                if negative {
+                    -power
+                } else {
+                    power
+                }
+            }
+            _ => 1,
+        }
+    }
+
+    /// Consumes the next token, which must be of the given kind.
+    fn expect(&mut self, kind: &TokenKind) -> Result<(), ParseError> {
+        match self.tokens.get(self.index) {
+            Some(token) if token.kind == *kind => {
+                self.index += 1;
+                Ok(())
+            }
+            Some(token) => Err(ParseError::UnexpectedToken { index: token.index }),
+            None => Err(ParseError::EarlyEof),
+        }
+    }
+
+    /// Parses the call to `now()` or `time_in("UTC+9")`, whose argument is a
+    /// string instead of a node. The clock is read when the node is
+    /// calculated.
+    fn parse_clock_call(&mut self, kind: &IdentKind, index: usize) -> Result<Node, ParseError> {
+        self.expect(&TokenKind::OpenParen)?;
+        let zone = match self.peek_kind() {
+            Some(TokenKind::Str(zone)) => Some(zone.clone()),
+            _ => None,
+        };
+        if zone.is_some() {
+            self.index += 1;
+        }
+        self.expect(&TokenKind::CloseParen)?;
+        match (kind, zone) {
+            (IdentKind::Now, None) => Some(0),
+            (IdentKind::TimeIn, Some(zone)) => clock::parse_zone(&zone),
+            _ => None,
+        }
+        .map(|offset| Node::Time { offset })
+        .ok_or(ParseError::InvalidArguments { index })
+    }
+
+    /// Parses the call to `roman("MMXXIV")`, whose argument is a string
+    /// instead of a node.
+    fn parse_roman_call(&mut self, index: usize) -> Result<Node, ParseError> {
+        self.expect(&TokenKind::OpenParen)?;
+        let numeral = match self.tokens.get(self.index) {
+            Some(Token {
+                kind: TokenKind::Str(numeral),
+                ..
+            }) => numeral.clone(),
+            Some(token) => return Err(ParseError::UnexpectedToken { index: token.index }),
+            None => return Err(ParseError::EarlyEof),
+        };
+        self.index += 1;
+        self.expect(&TokenKind::CloseParen)?;
+        roman::from_roman(&numeral).ok_or(ParseError::InvalidArguments { index })
     }
 
     fn parse_nud(&mut self) -> Result<Node, ParseError> {
@@ -54,34 +453,117 @@ impl<'a> Parser<'a> {
                     input_base: Some(input_base),
                 }
             }
+            TokenKind::FeetInches(inches) => {
+                let (numer, denom) = inches.into();
+                let inches = Node::Num {
+                    val: BigRational::new(numer.into(), denom.into()),
+                    input_base: Some(10),
+                };
+                Node::Quantity(Box::new(inches), Unit::Inch)
+            }
+            TokenKind::Dice { count, sides } => Node::Dice { count, sides },
             TokenKind::Ident(kind) => match kind {
                 // constants
                 IdentKind::Pi => Node::Const(ConstKind::Pi),
                 IdentKind::Tau => Node::Const(ConstKind::Tau),
                 IdentKind::E => Node::Const(ConstKind::E),
+                IdentKind::I => Node::Const(ConstKind::I),
+                // clock times
+                IdentKind::Now | IdentKind::TimeIn => self.parse_clock_call(&kind, token.index)?,
+                // roman numerals in a string, while `roman(MMXXIV)` is read
+                // as a call with a variable
+                IdentKind::Roman
+                    if matches!(
+                        self.tokens.get(self.index + 1).map(|t| &t.kind),
+                        Some(TokenKind::Str(_))
+                    ) =>
+                {
+                    self.parse_roman_call(token.index)?
+                }
                 // functions
                 _ => {
-                    let param = Box::new(self.parse_nud()?);
+                    let param = if self.reading.greedy_functions
+                        && self.peek_kind() != Some(&TokenKind::OpenParen)
+                    {
+                        self.parse_range(&StopPolicy::IfWeakerOrEqual(precedence::MUL))?
+                    } else {
+                        self.parse_nud()?
+                    };
                     match kind {
-                        IdentKind::Sin => Node::Sin(param),
-                        IdentKind::Cos => Node::Cos(param),
-                        IdentKind::Tan => Node::Tan(param),
+                        IdentKind::Sin => param.sin(),
+                        IdentKind::Cos => param.cos(),
+                        IdentKind::Tan => param.tan(),
+                        IdentKind::Asin => param.asin(),
+                        IdentKind::Acos => param.acos(),
+                        IdentKind::Atan => param.atan(),
+                        IdentKind::Atan2 => match param {
+                            Node::Vector(mut items) if items.len() == 2 => {
+                                let x = items.pop().unwrap();
+                                Node::Atan2(Box::new(items.pop().unwrap()), Box::new(x))
+                            }
+                            _ => return Err(ParseError::InvalidArguments { index: token.index }),
+                        },
+                        IdentKind::Sinh => param.sinh(),
+                        IdentKind::Cosh => param.cosh(),
+                        IdentKind::Tanh => param.tanh(),
+                        IdentKind::Abs => param.abs(),
+                        IdentKind::Floor => param.floor(),
+                        IdentKind::Ceil => param.ceil(),
+                        IdentKind::Round => param.round(),
+                        IdentKind::Sign => param.sign(),
+                        IdentKind::Min => Node::Min(param.into_items()),
+                        IdentKind::Max => Node::Max(param.into_items()),
+                        IdentKind::Ln => param.ln(),
+                        IdentKind::Log2 => param.log(common::two()),
+                        IdentKind::Log10 => param.log(ten()),
+                        IdentKind::Log => match param {
+                            Node::Vector(mut items) if items.len() == 2 => {
+                                let inner = items.pop().unwrap();
+                                inner.log(items.pop().unwrap())
+                            }
+                            Node::Vector(_) => {
+                                return Err(ParseError::InvalidArguments { index: token.index })
+                            }
+                            // the logarithm is in base 10 by default
+                            param => param.log(ten()),
+                        },
                         IdentKind::Sqrt => param.sqrt(),
                         IdentKind::Cbrt => param.cbrt(),
-                        _ => unreachable!(),
+                        kind => builtins::call(&kind, param.into_items())
+                            .ok_or(ParseError::InvalidArguments { index: token.index })?,
                     }
                 }
             },
-            TokenKind::UnknownIdent(s) => Node::UnknownConst(s),
+            TokenKind::UnknownIdent(s) => match self.functions.and_then(|x| x.get(&s)) {
+                Some(function) if self.peek_kind() == Some(&TokenKind::OpenParen) => {
+                    let args = self.parse_nud()?.into_items();
+                    function
+                        .call(args)
+                        .ok_or(ParseError::InvalidArguments { index: token.index })?
+                }
+                _ => Node::Var(s),
+            },
 
-            TokenKind::Minus => -self.parse_nud()?,
-            TokenKind::Plus => self.parse_nud()?,
             TokenKind::OpenParen => {
-                let expr = self.parse_range(&StopPolicy::IfWeakerOrEqual(Power::CloseParen))?;
-                let is_closed = self
-                    .tokens
-                    .get(self.index)
-                    .map_or(false, |t| t.kind == TokenKind::CloseParen);
+                let mut expr =
+                    self.parse_range(&StopPolicy::IfWeakerOrEqual(precedence::CLOSE_PAREN))?;
+
+                // `(a, b, c)` is a vector
+                if self.peek_kind() == Some(&TokenKind::Comma) {
+                    let mut items = vec![expr];
+                    while self.peek_kind() == Some(&TokenKind::Comma) {
+                        // consume the comma
+                        self.index += 1;
+                        items.push(
+                            self.parse_range(&StopPolicy::IfWeakerOrEqual(
+                                precedence::CLOSE_PAREN,
+                            ))?,
+                        );
+                    }
+                    expr = Node::Vector(items);
+                }
+
+                let is_closed = self.peek_kind() == Some(&TokenKind::CloseParen);
 
                 // closing parentheses are optional
                 if is_closed {
@@ -92,48 +574,102 @@ impl<'a> Parser<'a> {
                 expr
             }
 
-            _ => {
-                self.index = original_index;
-                return Err(ParseError::UnexpectedToken { index: token.index });
-            }
+            kind => match self.operators.find(&kind, true) {
+                Some(operator) => {
+                    let apply = match &operator.arity {
+                        Arity::Prefix(apply) => apply.clone(),
+                        Arity::Infix(_) => unreachable!(),
+                    };
+                    let policy = operator.operand_policy();
+                    apply(self.parse_range(&policy)?)
+                }
+                None => {
+                    self.index = original_index;
+                    return Err(ParseError::UnexpectedToken { index: token.index });
+                }
+            },
         })
     }
 
+    /// Returns the precedence of the next token, which is an operator or the
+    /// start of an implicit multiplication.
+    fn peek_precedence(&self, kind: &TokenKind) -> u8 {
+        match kind {
+            TokenKind::CloseParen | TokenKind::Comma => precedence::CLOSE_PAREN,
+            TokenKind::UnknownIdent(_) if self.peek_conversion().is_some() => precedence::CONVERT,
+            kind => match self.operators.find(kind, false) {
+                Some(operator) => operator.precedence,
+                None if self.reading.loose_implicit_mul => precedence::MUL,
+                None => precedence::IMPLICIT_MUL,
+            },
+        }
+    }
+
     fn parse_led(&mut self, left: Node) -> Result<Node, ParseError> {
         if self.index >= self.tokens.len() {
             return Err(ParseError::EarlyEof);
         }
 
+        // `x in mi`, `5 km` and `60 km per h`, whose units are not consumed
+        // as variables
+        if let Some(unit) = self.peek_conversion() {
+            self.index += 2;
+            // `in cm` already converts all the powers of the length, such as
+            // `m^2` to `cm^2`, so a power would be read as the power of the
+            // whole conversion, and `in m/s` would divide it by a second
+            if self.peek_kind() == Some(&TokenKind::Hat) || self.continues_conversion(self.index) {
+                let index = self.tokens[self.index].index;
+                return Err(ParseError::UnexpectedToken { index });
+            }
+            return Ok(Node::Quantity(Box::new(left), unit));
+        }
+        if let Some(unit) = self.peek_per() {
+            self.index += 2;
+            let power = self.parse_unit_power();
+            return Ok(units::with_unit(left, unit, -power));
+        }
+        if self.peek_kind() == Some(&TokenKind::Percent) {
+            self.index += 1;
+            return Ok(percent(left));
+        }
+        if let Some(unit) = self.peek_unit() {
+            self.index += 1;
+            let power = self.parse_unit_power();
+            // such as `20 degC m`, where the unit is one of another dimension
+            let multiplies = match units::unit_dimensions(&left) {
+                Some(dimensions) => !dimensions.contains(&unit.dimension()),
+                None => false,
+            };
+            if power == 1 && !multiplies {
+                return Ok(Node::Quantity(Box::new(left), unit));
+            }
+            return Ok(units::with_unit(left, unit, power));
+        }
+
         let original_index = self.index;
         let token = self.tokens[self.index].clone();
         self.index += 1;
 
-        Ok(match token.kind {
-            // left associativity
-            TokenKind::Plus => left + self.parse_range(&StopPolicy::IfWeakerOrEqual(Power::Add))?,
-            TokenKind::Minus => {
-                left - self.parse_range(&StopPolicy::IfWeakerOrEqual(Power::Add))?
-            }
-            TokenKind::Slash => {
-                left / self.parse_range(&StopPolicy::IfWeakerOrEqual(Power::Mul))?
+        if let Some(operator) = self.operators.find(&token.kind, false) {
+            let apply = match &operator.arity {
+                Arity::Infix(apply) => apply.clone(),
+                Arity::Prefix(_) => unreachable!(),
+            };
+            let policy = operator.operand_policy();
+            let right = self.parse_range(&policy)?;
+            let is_percent_of = self.percent_of
+                && matches!(token.kind, TokenKind::Plus | TokenKind::Minus)
+                && self.tokens[self.index - 1].kind == TokenKind::Percent
+                && is_percent(&right);
+            if is_percent_of {
+                return Ok(left * apply(common::one(), right));
             }
+            return Ok(apply(left, right));
+        }
 
-            // right associativity: 1^2^3 is parsed as exp(1, exp(2, 3)), not exp(exp(1, 2), 3)
-            TokenKind::Hat => Node::Exp(
-                Box::new(left),
-                Box::new(self.parse_range(&StopPolicy::IfWeaker(Power::Exp))?),
-            ),
-
-            // implicit or explicit multiplication
-            _ => {
-                //
-                if token.kind != TokenKind::Times {
-                    // do not consume the token if it is implicit multiplication
-                    self.index = original_index;
-                }
-                left * self.parse_range(&StopPolicy::IfWeakerOrEqual(Power::Mul))?
-            }
-        })
+        // implicit multiplication, where the token is not consumed
+        self.index = original_index;
+        Ok(left * self.parse_range(&StopPolicy::IfWeakerOrEqual(precedence::MUL))?)
     }
 
     fn parse_range(&mut self, policy: &StopPolicy) -> Result<Node, ParseError> {
@@ -148,32 +684,15 @@ impl<'a> Parser<'a> {
                 // For example, if we are parsing an expression inside of
                 // parentheses, we want to stop when we encounter a closing
                 // parenthesis.
-                match &policy {
-                    StopPolicy::IfWeaker(..) | StopPolicy::IfWeakerOrEqual(..) => {
-                        let maybe_power = match peek.kind {
-                            TokenKind::CloseParen => Some(Power::CloseParen),
-                            TokenKind::Plus | TokenKind::Minus => Some(Power::Add),
-                            TokenKind::Times | TokenKind::Slash => Some(Power::Mul),
-                            TokenKind::Hat => Some(Power::Exp),
-
-                            // implicit multiplication
-                            _ => Some(Power::ImplicitMul),
-                        };
-
-                        if let Some(ref power) = maybe_power {
-                            let (min_power, stop_if_equal) = match &policy {
-                                StopPolicy::IfWeaker(val) => (val, false),
-                                StopPolicy::IfWeakerOrEqual(val) => (val, true),
-                                _ => unreachable!(),
-                            };
-
-                            if power < min_power || (stop_if_equal && power == min_power) {
-                                break 'parse;
-                            }
-                        }
-                    }
+                let power = self.peek_precedence(&peek.kind);
+                let (min_power, stop_if_equal) = match *policy {
+                    StopPolicy::IfWeaker(val) => (val, false),
+                    StopPolicy::IfWeakerOrEqual(val) => (val, true),
                     StopPolicy::Never => unreachable!(),
                 };
+                if power < min_power || (stop_if_equal && power == min_power) {
+                    break 'parse;
+                }
             }
 
             node = match self.parse_led(node) {
@@ -193,11 +712,99 @@ impl<'a> Parser<'a> {
     }
 }
 
+/// Finds the symbols of a single letter that are variables in the whole
+/// expression rather than units: the ones that start an operand, such as the
+/// `t` of `t^2 + 3t` or of `solve(3t = 6, t)`, and all of them if the
+/// expression has a variable of a single letter after a number, such as
+/// `2x + 3t`.
+fn variable_names(tokens: &[Token]) -> Vec<String> {
+    let is_ambiguous = |s: &str| s.chars().count() == 1 && Unit::from_symbol(s).is_some();
+    let mut names = Vec::new();
+    let mut is_algebraic = false;
+    for (i, token) in tokens.iter().enumerate() {
+        let symbol = match &token.kind {
+            TokenKind::UnknownIdent(s) => s,
+            _ => continue,
+        };
+        // whether the token comes right after a value, where it can be a unit
+        let (after_value, after_number) = match i.checked_sub(1).map(|i| &tokens[i].kind) {
+            Some(TokenKind::Num { .. }) => (true, true),
+            Some(TokenKind::UnknownIdent(_)) | Some(TokenKind::CloseParen) => (true, false),
+            Some(TokenKind::FeetInches(_)) | Some(TokenKind::Percent) => (true, false),
+            _ => (false, false),
+        };
+        if is_ambiguous(symbol) && !after_value {
+            names.push(symbol.clone());
+        } else if after_number && symbol.chars().count() == 1 && Unit::from_symbol(symbol).is_none()
+        {
+            is_algebraic = true;
+        }
+    }
+    if is_algebraic {
+        for token in tokens {
+            match &token.kind {
+                TokenKind::UnknownIdent(s) if is_ambiguous(s) => names.push(s.clone()),
+                _ => {}
+            }
+        }
+    }
+    names
+}
+
+/// Parses the tokens in all of the ways that ambiguous input such as `1/2x`
+/// or `sin 2x` can be read, for frontends that show the alternatives, such as
+/// with handwriting or voice input.
+/// Returns the different interpretations with their likelihood, which adds
+/// up to one, from the most likely one, which is the one `parse` gives.
+pub fn parse_ambiguous(tokens: &[Token]) -> Result<Vec<(Node, f64)>, ParseError> {
+    let mut results: Vec<(Node, f64)> = Vec::new();
+    let mut first_err = None;
+    for &loose_implicit_mul in &[false, true] {
+        for &greedy_functions in &[false, true] {
+            let mut parser = Parser::new(tokens);
+            parser.reading = Reading {
+                loose_implicit_mul,
+                greedy_functions,
+            };
+            let node = match parser.parse() {
+                Ok(x) => x,
+                Err(err) => {
+                    first_err.get_or_insert(err);
+                    continue;
+                }
+            };
+            // each unusual choice halves the likelihood
+            let weight = match (loose_implicit_mul, greedy_functions) {
+                (false, false) => 1.0,
+                (true, true) => 0.25,
+                _ => 0.5,
+            };
+            match results.iter_mut().find(|x| x.0 == node) {
+                Some(result) => result.1 += weight,
+                None => results.push((node, weight)),
+            }
+        }
+    }
+    if results.is_empty() {
+        return Err(first_err.unwrap());
+    }
+
+    let total: f64 = results.iter().map(|x| x.1).sum();
+    for result in results.iter_mut() {
+        result.1 /= total;
+    }
+    // the sort is stable so that the usual reading stays first on a tie
+    results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    Ok(results)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::functions::UserFunction;
     use crate::lexer::Lexer;
+    use crate::test_util::{simplify_str, tokens, try_parse};
     use num_traits::One;
 
     #[test]
@@ -236,4 +843,139 @@ mod tests {
         };
         assert_eq!(root_node, one / (two * Node::Const(ConstKind::Pi)));
     }
+
+    #[test]
+    fn it_parses_vectors() {
+        let tokens: Vec<Token> = Lexer::new("(1, (1, 1), 1+1)").map(|x| x.unwrap()).collect();
+        let root_node = Parser::new(&tokens).parse().unwrap();
+
+        let one = || Node::Num {
+            val: One::one(),
+            input_base: Some(10),
+        };
+        assert_eq!(
+            root_node,
+            Node::Vector(vec![one(), Node::Vector(vec![one(), one()]), one() + one()])
+        );
+    }
+
+    #[test]
+    fn it_parses_ratios_with_a_low_precedence() {
+        let tokens: Vec<Token> = Lexer::new("1+1:2:3").map(|x| x.unwrap()).collect();
+        let root_node = Parser::new(&tokens).parse().unwrap();
+
+        let num = |n: i32| Node::Num {
+            val: BigRational::from_integer(n.into()),
+            input_base: Some(10),
+        };
+        assert_eq!(
+            root_node,
+            Node::Ratio(vec![num(1) + num(1), num(2), num(3)])
+        );
+    }
+
+    #[test]
+    fn it_returns_the_interpretations_of_ambiguous_input() {
+        let parse = |expr: &str| parse_ambiguous(&tokens(expr)).unwrap();
+        let names =
+            |results: &[(Node, f64)]| results.iter().map(|x| x.0.to_string()).collect::<Vec<_>>();
+
+        let results = parse("1/2x");
+        assert_eq!(names(&results), vec!["1 / (2x)", "1 / 2 * x"]);
+        assert!((results[0].1 - 2.0 / 3.0).abs() < 1e-12);
+        let results = parse("sin2x");
+        assert_eq!(names(&results), vec!["sin 2 * x", "sin(2x)"]);
+        let results = parse("1 + 2");
+        assert_eq!(names(&results), vec!["1 + 2"]);
+        assert!((results[0].1 - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn it_parses_with_a_custom_operator_table() {
+        let parse = |expr: &str, operators: &OperatorTable| {
+            Parser::with_operators(&tokens(expr), operators.clone())
+                .parse()
+                .map(|x| x.to_string())
+        };
+
+        // a mode where `:` is a division and `^` is left associative
+        let mut operators = OperatorTable::standard();
+        operators.insert(Operator {
+            symbol: TokenKind::Colon,
+            precedence: precedence::MUL,
+            assoc: Assoc::Left,
+            arity: Arity::Infix(Rc::new(|a, b| a / b)),
+        });
+        operators.insert(Operator {
+            symbol: TokenKind::Hat,
+            precedence: precedence::EXP,
+            assoc: Assoc::Left,
+            arity: Arity::Infix(Rc::new(|a, b| Node::Exp(Box::new(a), Box::new(b)))),
+        });
+        assert_eq!(parse("1 + 6:3", &operators), Ok("1 + 6 / 3".to_owned()));
+        assert_eq!(parse("2^3^2", &operators), Ok("(2^3)^2".to_owned()));
+
+        // without the prefix minus, `-1` is not an expression
+        operators.remove(&TokenKind::Minus);
+        assert_eq!(
+            parse("-1", &operators),
+            Err(ParseError::UnexpectedToken { index: 0 })
+        );
+    }
+
+    #[test]
+    fn it_expands_user_functions() {
+        let mut functions = FunctionRegistry::new();
+        functions
+            .define(UserFunction {
+                name: "sqsum".to_owned(),
+                params: vec!["a".to_owned(), "b".to_owned()],
+                body: Node::Exp(
+                    Box::new(Node::Var("a".to_owned()) + Node::Var("b".to_owned())),
+                    Box::new(common::two()),
+                ),
+            })
+            .unwrap();
+        let parse = |expr: &str| {
+            Parser::new(&tokens(expr))
+                .with_functions(&functions)
+                .parse()
+        };
+        let node = parse("sqsum(3, b) + sqsum").unwrap();
+        assert_eq!(node.to_string(), "(3 + b)^2 + sqsum");
+        assert_eq!(
+            parse("sqsum(1)"),
+            Err(ParseError::InvalidArguments { index: 0 })
+        );
+    }
+
+    #[test]
+    fn it_checks_the_arguments_of_functions() {
+        assert_eq!(
+            try_parse("1 + area_triangle(1, 2, 3)"),
+            Err(ParseError::InvalidArguments { index: 4 })
+        );
+    }
+
+    #[test]
+    fn it_parses_percentages() {
+        let simplify = |expr: &str| {
+            Parser::new(&tokens(expr))
+                .with_percent_of()
+                .parse()
+                .unwrap()
+                .simplify()
+                .unwrap()
+                .result
+                .to_string()
+        };
+        assert_eq!(simplify_str("15%"), "3/20");
+        assert_eq!(simplify_str("200 + 10%"), "2001/10");
+        assert_eq!(simplify("200 + 10%"), "220");
+        assert_eq!(simplify("80 - 25%"), "60");
+        assert_eq!(simplify("50% * 30"), "15");
+        // only a whole percentage is taken from the left operand
+        assert_eq!(simplify("200 + 2 * 10%"), "1001/5");
+        assert_eq!(simplify("x + 10% x"), "11/10 * x");
+    }
 }
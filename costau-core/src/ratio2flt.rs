@@ -60,9 +60,14 @@ pub fn ratio_to_f64(num: &BigRational) -> f64 {
             match add_result {
                 Some(val) => fraction = val,
                 None => {
-                    // The exponent cannot be larger than 1023.
+                    // The exponent cannot be larger than 1023, so the number
+                    // is too large to be represented.
                     if exp >= 1023 + 1023 {
-                        return f64::MAX;
+                        return if !is_negative {
+                            f64::INFINITY
+                        } else {
+                            f64::NEG_INFINITY
+                        };
                     }
 
                     let will_lose_one = (fraction as u8) & 1u8 == 1u8
@@ -105,7 +110,11 @@ pub fn ratio_to_f64(num: &BigRational) -> f64 {
         // floating point number so the last bit is implied to be a one).
         while fraction >= (1 << 53) {
             if exp >= 1023 + 1023 {
-                return if !is_negative { f64::MAX } else { -f64::MIN };
+                return if !is_negative {
+                    f64::INFINITY
+                } else {
+                    f64::NEG_INFINITY
+                };
             }
 
             let will_lose_one = (fraction as u8) & 1u8 == 1u8;
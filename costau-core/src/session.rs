@@ -0,0 +1,1102 @@
+//! A session keeps the history of the calculations of a user and the
+//! variables they defined, so that frontends can compare a result with the
+//! previous ones or undo changes.
+
+use num_rational::BigRational;
+use rand::rngs::StdRng;
+#[cfg(feature = "with-serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::functions::{FunctionError, FunctionRegistry, UserFunction};
+use crate::lexer::{Lexer, LexerError, TokenKind};
+use crate::montecarlo::{montecarlo_with_rng, Distribution, MonteCarloError, Summary};
+use crate::node::util::common;
+use crate::node::{EvalOptions, Node, SexprError, SimplifyError, Unit};
+use crate::parser::{Arity, Assoc, Operator, OperatorTable, ParseError, Parser};
+
+/// A description of the error of a calculation in a session.
+#[derive(Debug, PartialEq)]
+pub enum SessionError {
+    Lexer(LexerError),
+    Parser(ParseError),
+    Simplify(SimplifyError),
+}
+
+/// An infix operator defined by the user, such as `a // b` for the integer
+/// division.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CustomOperator {
+    pub symbol: String,
+    /// The names of the left and right operands in the body
+    pub params: (String, String),
+    /// The value of the operator in terms of its operands
+    pub body: Node,
+    /// The precedence, between the ones in `parser::precedence`
+    pub precedence: u8,
+    pub assoc: Assoc,
+}
+
+/// A description of why an operator cannot be defined.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum OperatorError {
+    /// The symbol is empty or has letters, digits, spaces or parentheses,
+    /// which are read as other tokens
+    InvalidSymbol,
+    /// The symbol is already the one of a builtin operator or of another
+    /// operator defined by the user
+    Collision { symbol: String },
+}
+
+/// A description of why a debug bundle cannot be imported.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum BundleError {
+    /// The text does not start with the header of a bundle
+    NotABundle,
+    /// A line of the bundle cannot be read, counting from 1
+    Malformed { line: usize },
+    /// A node of the bundle cannot be parsed
+    Sexpr { line: usize, error: SexprError },
+}
+
+/// A calculation that was done in a session.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct Entry {
+    /// The expression that the user typed
+    pub input: String,
+
+    /// The simplified result
+    pub result: Node,
+
+    /// The approximation of the result, if it is a real number
+    pub approx: Option<f64>,
+}
+
+/// The change between the results of two calculations.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Delta {
+    /// The difference between the new result and the old one
+    pub absolute: f64,
+
+    /// The exact difference, if both results are rational numbers
+    pub exact: Option<BigRational>,
+
+    /// The difference relative to the old result in percents, if the old
+    /// result is not zero
+    pub percent: Option<f64>,
+}
+
+/// The history of the calculations of a user.
+#[derive(Debug, Default, Clone)]
+pub struct Session {
+    // The state is reference counted so that snapshots share it with the
+    // session until one of them changes.
+    history: Vec<Rc<Entry>>,
+    // The definitions and options of each calculation in the history.
+    scopes: Vec<Scope>,
+    vars: Rc<HashMap<String, Node>>,
+    units: Rc<HashMap<String, Node>>,
+    operators: Rc<Vec<CustomOperator>>,
+    functions: FunctionRegistry,
+    options: EvalOptions,
+    // The generator of all the random numbers of the session, which is
+    // created from the options when it is first needed.
+    rng: Option<StdRng>,
+}
+
+/// A saved state of a session that can be restored later.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    history: Vec<Rc<Entry>>,
+    scopes: Vec<Scope>,
+    vars: Rc<HashMap<String, Node>>,
+    // The units defined by the user, in terms of the builtin units or the
+    // base units.
+    units: Rc<HashMap<String, Node>>,
+    operators: Rc<Vec<CustomOperator>>,
+    functions: FunctionRegistry,
+    options: EvalOptions,
+    rng: Option<StdRng>,
+}
+
+/// The definitions and options that a calculation was done with.
+#[derive(Debug, Default, Clone)]
+struct Scope {
+    vars: Rc<HashMap<String, Node>>,
+    units: Rc<HashMap<String, Node>>,
+    operators: Rc<Vec<CustomOperator>>,
+    functions: FunctionRegistry,
+    options: EvalOptions,
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session::default()
+    }
+
+    /// Evaluates an expression and adds it to the history.
+    pub fn eval(&mut self, expr: &str) -> Result<&Entry, SessionError> {
+        let entry = evaluate(
+            expr,
+            &self.vars,
+            &self.units,
+            &self.operators,
+            &self.functions,
+            &self.options,
+        )?;
+        self.history.push(Rc::new(entry));
+        let scope = self.scope();
+        self.scopes.push(scope);
+        Ok(self.history.last().unwrap())
+    }
+
+    /// Returns all of the calculations, from the oldest to the newest.
+    pub fn history(&self) -> &[Rc<Entry>] {
+        &self.history
+    }
+
+    /// Defines a variable that will be replaced with `val` in the next
+    /// calculations.
+    pub fn define(&mut self, name: &str, val: Node) {
+        Rc::make_mut(&mut self.vars).insert(name.to_owned(), val);
+    }
+
+    /// Removes the definition of a variable.
+    pub fn undefine(&mut self, name: &str) {
+        if self.vars.contains_key(name) {
+            Rc::make_mut(&mut self.vars).remove(name);
+        }
+    }
+
+    /// Returns the value of a variable, if it is defined.
+    pub fn var(&self, name: &str) -> Option<&Node> {
+        self.vars.get(name)
+    }
+
+    /// Defines a unit that is worth `factor` times the unit `base`, such as a
+    /// furlong which is 201.168 meters. The `base` can be a builtin unit such
+    /// as `m`, so that the new unit can be added to and converted to the
+    /// builtin units of the same dimension. A `base` that is neither a
+    /// builtin unit nor defined is a base unit, which stays as a variable in
+    /// the results.
+    pub fn define_unit(&mut self, name: &str, factor: Node, base: &str) {
+        let base = self.unit_in_base_units(base);
+        Rc::make_mut(&mut self.units).insert(name.to_owned(), factor * base);
+    }
+
+    /// Defines an infix operator that can be used in the next calculations.
+    pub fn define_operator(&mut self, operator: CustomOperator) -> Result<(), OperatorError> {
+        let symbol = &operator.symbol;
+        let is_valid = !symbol.is_empty()
+            && symbol
+                .chars()
+                .all(|c| !c.is_alphanumeric() && !c.is_whitespace() && !"_().,'".contains(c));
+        if !is_valid {
+            return Err(OperatorError::InvalidSymbol);
+        }
+        let is_builtin = symbol == "=~"
+            || (symbol.chars().count() == 1
+                && TokenKind::from_single_char(symbol.chars().next().unwrap()).is_some());
+        if is_builtin || self.operators.iter().any(|x| x.symbol == *symbol) {
+            return Err(OperatorError::Collision {
+                symbol: symbol.clone(),
+            });
+        }
+        Rc::make_mut(&mut self.operators).push(operator);
+        Ok(())
+    }
+
+    /// Removes the definition of an operator.
+    pub fn undefine_operator(&mut self, symbol: &str) {
+        if self.operators.iter().any(|x| x.symbol == symbol) {
+            Rc::make_mut(&mut self.operators).retain(|x| x.symbol != symbol);
+        }
+    }
+
+    /// Defines a function that can be called in the next calculations.
+    pub fn define_function(&mut self, function: UserFunction) -> Result<(), FunctionError> {
+        self.functions.define(function)
+    }
+
+    /// Removes the definition of a function.
+    pub fn undefine_function(&mut self, name: &str) {
+        self.functions.undefine(name);
+    }
+
+    pub fn functions(&self) -> &FunctionRegistry {
+        &self.functions
+    }
+
+    pub fn options(&self) -> &EvalOptions {
+        &self.options
+    }
+
+    /// Changes the options of the session. The random numbers start again
+    /// from the new seed, so that a session can be replayed exactly.
+    pub fn set_options(&mut self, options: EvalOptions) {
+        self.options = options;
+        self.rng = None;
+    }
+
+    /// Returns the random number generator that all the randomized
+    /// algorithms of the session use.
+    pub fn rng(&mut self) -> &mut StdRng {
+        let options = &self.options;
+        self.rng.get_or_insert_with(|| options.rng())
+    }
+
+    /// Runs a Monte Carlo simulation with the random number generator of the
+    /// session.
+    pub fn montecarlo(
+        &mut self,
+        node: &Node,
+        inputs: &[(&str, Distribution)],
+        n: usize,
+    ) -> Result<Summary, MonteCarloError> {
+        montecarlo_with_rng(node, inputs, n, self.rng())
+    }
+
+    /// Returns the value of a unit in builtin or base units, if it is
+    /// defined.
+    pub fn unit(&self, name: &str) -> Option<&Node> {
+        self.units.get(name)
+    }
+
+    fn scope(&self) -> Scope {
+        Scope {
+            vars: self.vars.clone(),
+            units: self.units.clone(),
+            operators: self.operators.clone(),
+            functions: self.functions.clone(),
+            options: self.options.clone(),
+        }
+    }
+
+    fn unit_in_base_units(&self, name: &str) -> Node {
+        match (self.units.get(name), Unit::from_symbol(name)) {
+            (Some(val), _) => val.clone(),
+            (None, Some(unit)) => Node::Quantity(Box::new(common::one()), unit),
+            (None, None) => Node::Var(name.to_owned()),
+        }
+    }
+
+    /// Expresses a quantity as a number of the given unit, such as a result
+    /// in meters as a number of furlongs.
+    /// Returns `None` if the quantity is not a number of this unit.
+    pub fn convert(&self, val: &Node, unit: &str) -> Option<Node> {
+        let mut val = val.clone();
+        for (name, def) in self.units.iter() {
+            val = val.substitute(name, def);
+        }
+        let options = &self.options;
+        // the builtin units cancel out when they have the same dimension
+        let ratio = (val / self.unit_in_base_units(unit))
+            .simplify_with_options(options)
+            .ok()?
+            .result;
+        let base_units = ratio.vars();
+        // the base units cancel out only if the ratio does not depend on
+        // their values
+        let count = value_with(&ratio, &base_units, options, |_| 1)?;
+        if value_with(&ratio, &base_units, options, |i| i as i64 + 2)? != count {
+            return None;
+        }
+        let count = Node::Num {
+            val: count,
+            input_base: None,
+        };
+        let converted = match Unit::from_symbol(unit).filter(|_| !self.units.contains_key(unit)) {
+            Some(builtin) => Node::Quantity(Box::new(count), builtin),
+            None => count * Node::Var(unit.to_owned()),
+        }
+        .simplify_with_options(options);
+        Some(converted.ok()?.result)
+    }
+
+    /// Saves the state of the session. This does not copy the calculations
+    /// nor the variables.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            history: self.history.clone(),
+            scopes: self.scopes.clone(),
+            vars: self.vars.clone(),
+            units: self.units.clone(),
+            operators: self.operators.clone(),
+            functions: self.functions.clone(),
+            options: self.options.clone(),
+            rng: self.rng.clone(),
+        }
+    }
+
+    /// Goes back to a saved state of the session.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.history = snapshot.history.clone();
+        self.scopes = snapshot.scopes.clone();
+        self.vars = snapshot.vars.clone();
+        self.units = snapshot.units.clone();
+        self.operators = snapshot.operators.clone();
+        self.functions = snapshot.functions.clone();
+        self.options = snapshot.options.clone();
+        self.rng = snapshot.rng.clone();
+    }
+
+    /// Dumps the state of the session as text that users can attach to bug
+    /// reports: the version of the library, and the inputs with their results
+    /// as S-expressions, each preceded by the changes of the options and
+    /// definitions since the previous one. Nothing else from the device is
+    /// included.
+    pub fn export_debug_bundle(&self) -> String {
+        let mut out = format!("{} {}\n", BUNDLE_HEADER, env!("CARGO_PKG_VERSION"));
+        // the inputs are replayed in order, so a definition that changed
+        // after an input must come after it
+        let initial = Scope::default();
+        let mut last = &initial;
+        for (entry, scope) in self.history.iter().zip(&self.scopes) {
+            write_changes(&mut out, last, scope);
+            out.push_str(&format!("input {}\n", escape(&entry.input)));
+            out.push_str(&format!("result {}\n", entry.result.to_sexpr()));
+            last = scope;
+        }
+        write_changes(&mut out, last, &self.scope());
+        out
+    }
+
+    /// Creates a session from a debug bundle by going through its changes of
+    /// the options and definitions and evaluating its inputs again in the same
+    /// order, so that maintainers can reproduce a reported state. The results
+    /// of the replay can be compared with `bundle_results`.
+    pub fn import_debug_bundle(bundle: &str) -> Result<Session, BundleError> {
+        let mut lines = bundle.lines().enumerate();
+        match lines.next() {
+            Some((_, header)) if header.starts_with(BUNDLE_HEADER) => {}
+            _ => return Err(BundleError::NotABundle),
+        }
+        let mut session = Session::new();
+        for (i, line) in lines {
+            let malformed = || BundleError::Malformed { line: i + 1 };
+            let sexpr = |text: &str| {
+                Node::from_sexpr(text).map_err(|error| BundleError::Sexpr { line: i + 1, error })
+            };
+            let mut parts = line.splitn(2, ' ');
+            let (key, rest) = (parts.next().unwrap(), parts.next().ok_or_else(malformed)?);
+            match key {
+                "seed" | "budget" | "compensated" => {
+                    let mut options = session.options.clone();
+                    match (key, rest) {
+                        ("seed", "none") => options.seed = None,
+                        ("seed", seed) => {
+                            options.seed = Some(seed.parse().map_err(|_| malformed())?)
+                        }
+                        ("budget", "none") => options.memory_budget = None,
+                        ("budget", budget) => {
+                            options.memory_budget = Some(budget.parse().map_err(|_| malformed())?)
+                        }
+                        (_, compensated) => {
+                            options.compensated = compensated.parse().map_err(|_| malformed())?
+                        }
+                    }
+                    session.set_options(options);
+                }
+                "forget" => {
+                    let mut parts = rest.splitn(2, ' ');
+                    let kind = parts.next().unwrap();
+                    let name = unescape(parts.next().ok_or_else(malformed)?);
+                    match kind {
+                        "var" => session.undefine(&name),
+                        "unit" => {
+                            Rc::make_mut(&mut session.units).remove(&name);
+                        }
+                        "operator" => session.undefine_operator(&name),
+                        "function" => session.undefine_function(&name),
+                        _ => return Err(malformed()),
+                    }
+                }
+                "var" | "unit" => {
+                    let mut parts = rest.splitn(2, ' ');
+                    let name = unescape(parts.next().unwrap());
+                    let val = sexpr(parts.next().ok_or_else(malformed)?)?;
+                    let map = if key == "var" {
+                        &mut session.vars
+                    } else {
+                        &mut session.units
+                    };
+                    Rc::make_mut(map).insert(name, val);
+                }
+                "operator" => {
+                    let parts: Vec<&str> = rest.splitn(6, ' ').collect();
+                    if parts.len() != 6 {
+                        return Err(malformed());
+                    }
+                    let assoc = match parts[4] {
+                        "left" => Assoc::Left,
+                        "right" => Assoc::Right,
+                        _ => return Err(malformed()),
+                    };
+                    let operator = CustomOperator {
+                        symbol: unescape(parts[0]),
+                        params: (unescape(parts[1]), unescape(parts[2])),
+                        body: sexpr(parts[5])?,
+                        precedence: parts[3].parse().map_err(|_| malformed())?,
+                        assoc,
+                    };
+                    session.define_operator(operator).map_err(|_| malformed())?;
+                }
+                "function" => {
+                    let parts: Vec<&str> = rest.splitn(3, ' ').collect();
+                    if parts.len() != 3 {
+                        return Err(malformed());
+                    }
+                    let function = UserFunction {
+                        name: unescape(parts[0]),
+                        params: parts[1]
+                            .split(',')
+                            .filter(|x| !x.is_empty())
+                            .map(unescape)
+                            .collect(),
+                        body: sexpr(parts[2])?,
+                    };
+                    session.define_function(function).map_err(|_| malformed())?;
+                }
+                "input" => {
+                    // the inputs that fail are not in the history, like
+                    // when the user typed them
+                    let _ = session.eval(&unescape(rest));
+                }
+                "result" => {}
+                _ => return Err(malformed()),
+            }
+        }
+        Ok(session)
+    }
+
+    /// Returns the change between the last two results, if they are both
+    /// numbers.
+    pub fn delta(&self) -> Option<Delta> {
+        let n = self.history.len();
+        if n < 2 {
+            return None;
+        }
+        delta_between(&self.history[n - 2], &self.history[n - 1])
+    }
+}
+
+/// The first line of a debug bundle, which is followed by the version.
+const BUNDLE_HEADER: &str = "costau-debug-bundle";
+
+/// Writes the lines of a debug bundle that change the options and definitions
+/// of `old` into the ones of `new`.
+fn write_changes(out: &mut String, old: &Scope, new: &Scope) {
+    if new.options.seed != old.options.seed {
+        match new.options.seed {
+            Some(seed) => out.push_str(&format!("seed {}\n", seed)),
+            None => out.push_str("seed none\n"),
+        }
+    }
+    if new.options.memory_budget != old.options.memory_budget {
+        match new.options.memory_budget {
+            Some(budget) => out.push_str(&format!("budget {}\n", budget)),
+            None => out.push_str("budget none\n"),
+        }
+    }
+    if new.options.compensated != old.options.compensated {
+        out.push_str(&format!("compensated {}\n", new.options.compensated));
+    }
+    write_map_changes(out, "var", &old.vars, &new.vars);
+    write_map_changes(out, "unit", &old.units, &new.units);
+    // an operator or a function is forgotten before it is defined again
+    // because they cannot be redefined
+    for operator in old.operators.iter() {
+        if !new.operators.contains(operator) {
+            out.push_str(&format!(
+                "forget operator {}\n",
+                escape_name(&operator.symbol)
+            ));
+        }
+    }
+    for operator in new.operators.iter() {
+        if old.operators.contains(operator) {
+            continue;
+        }
+        let assoc = match operator.assoc {
+            Assoc::Left => "left",
+            Assoc::Right => "right",
+        };
+        out.push_str(&format!(
+            "operator {} {} {} {} {} {}\n",
+            escape_name(&operator.symbol),
+            escape_name(&operator.params.0),
+            escape_name(&operator.params.1),
+            operator.precedence,
+            assoc,
+            operator.body.to_sexpr()
+        ));
+    }
+    // the definitions are sorted so that bundles can be diffed
+    let mut names: Vec<&str> = old.functions.names().collect();
+    names.sort();
+    for name in names {
+        if new.functions.get(name) != old.functions.get(name) {
+            out.push_str(&format!("forget function {}\n", escape_name(name)));
+        }
+    }
+    let mut names: Vec<&str> = new.functions.names().collect();
+    names.sort();
+    for name in names {
+        let function = new.functions.get(name).unwrap();
+        if old.functions.get(name) != Some(function) {
+            out.push_str(&format!(
+                "function {} {} {}\n",
+                escape_name(name),
+                function
+                    .params
+                    .iter()
+                    .map(|x| escape_name(x))
+                    .collect::<Vec<_>>()
+                    .join(","),
+                function.body.to_sexpr()
+            ));
+        }
+    }
+}
+
+/// Writes the lines of a debug bundle that change the variables or units of
+/// `old` into the ones of `new`.
+fn write_map_changes(
+    out: &mut String,
+    key: &str,
+    old: &HashMap<String, Node>,
+    new: &HashMap<String, Node>,
+) {
+    let mut forgotten: Vec<&String> = old.keys().filter(|x| !new.contains_key(*x)).collect();
+    forgotten.sort();
+    for name in forgotten {
+        out.push_str(&format!("forget {} {}\n", key, escape_name(name)));
+    }
+    let mut changed: Vec<_> = new
+        .iter()
+        .filter(|(name, val)| old.get(*name) != Some(val))
+        .collect();
+    changed.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, val) in changed {
+        out.push_str(&format!(
+            "{} {} {}\n",
+            key,
+            escape_name(name),
+            val.to_sexpr()
+        ));
+    }
+}
+
+/// Escapes the line breaks of an input so that it fits on one line.
+fn escape(input: &str) -> String {
+    input.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// Escapes a name so that it is a single field of a line, without spaces,
+/// and an item of a list of parameters, without commas.
+fn escape_name(name: &str) -> String {
+    escape(name).replace(' ', "\\s").replace(',', "\\c")
+}
+
+fn unescape(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('s') => out.push(' '),
+            Some('c') => out.push(','),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Returns the results that are recorded in a debug bundle, in the order of
+/// the inputs, to compare them with the ones of the replay.
+pub fn bundle_results(bundle: &str) -> Result<Vec<Node>, BundleError> {
+    let mut results = Vec::new();
+    for (i, line) in bundle.lines().enumerate() {
+        let mut parts = line.splitn(2, ' ');
+        if let (Some("result"), Some(text)) = (parts.next(), parts.next()) {
+            let node = Node::from_sexpr(text)
+                .map_err(|error| BundleError::Sexpr { line: i + 1, error })?;
+            results.push(node);
+        }
+    }
+    Ok(results)
+}
+
+/// Returns the exact value of the node when the variable `names[i]` is
+/// `values(i)`.
+fn value_with<F>(
+    node: &Node,
+    names: &[String],
+    options: &EvalOptions,
+    values: F,
+) -> Option<BigRational>
+where
+    F: Fn(usize) -> i64,
+{
+    let mut node = node.clone();
+    for (i, name) in names.iter().enumerate() {
+        let val = Node::Num {
+            val: BigRational::from_integer(values(i).into()),
+            input_base: None,
+        };
+        node = node.substitute(name, &val);
+    }
+    node.eval_exact_with_options(options).ok()
+}
+
+/// Returns the standard operators with the ones defined by the user.
+fn operator_table(operators: &[CustomOperator]) -> OperatorTable {
+    let mut table = OperatorTable::standard();
+    for operator in operators {
+        // the operands are renamed to names that cannot be typed so that
+        // they cannot be confused with the variables in the arguments
+        let (left, right) = &operator.params;
+        let body = operator
+            .body
+            .substitute(left, &Node::Var("$0".to_owned()))
+            .substitute(right, &Node::Var("$1".to_owned()));
+        table.insert(Operator {
+            symbol: TokenKind::Symbol(operator.symbol.clone()),
+            precedence: operator.precedence,
+            assoc: operator.assoc,
+            arity: Arity::Infix(Rc::new(move |a, b| {
+                body.substitute("$0", &a).substitute("$1", &b)
+            })),
+        });
+    }
+    table
+}
+
+/// Lexes, parses and simplifies an expression after replacing the variables
+/// and then the units with their values.
+pub(crate) fn evaluate(
+    expr: &str,
+    vars: &HashMap<String, Node>,
+    units: &HashMap<String, Node>,
+    operators: &[CustomOperator],
+    functions: &FunctionRegistry,
+    options: &EvalOptions,
+) -> Result<Entry, SessionError> {
+    let symbols: Vec<String> = operators.iter().map(|x| x.symbol.clone()).collect();
+    let mut tokens = Vec::new();
+    for r in Lexer::with_symbols(expr, &symbols) {
+        tokens.push(r.map_err(SessionError::Lexer)?);
+    }
+    let names: Vec<String> = vars.keys().cloned().collect();
+    let mut node = Parser::with_operators(&tokens, operator_table(operators))
+        .with_functions(functions)
+        .with_vars(&names)
+        .parse()
+        .map_err(SessionError::Parser)?;
+    // a variable can be defined with other variables, so they are replaced
+    // until none is left, with at most one pass per variable so that cyclic
+    // definitions stop
+    let mut names: Vec<&String> = vars.keys().collect();
+    names.sort();
+    for _ in 0..names.len() {
+        if !node.vars().iter().any(|x| vars.contains_key(x)) {
+            break;
+        }
+        for name in &names {
+            node = node.substitute(name, &vars[*name]);
+        }
+    }
+    for (name, val) in units.iter() {
+        node = node.substitute(name, val);
+    }
+    let result = node
+        .simplify_with_options(options)
+        .map_err(SessionError::Simplify)?
+        .result;
+    let approx = result
+        .eval_with_options(options)
+        .ok()
+        .filter(|x| x.imag == 0.0)
+        .map(|x| x.val);
+    Ok(Entry {
+        input: expr.to_owned(),
+        result,
+        approx,
+    })
+}
+
+/// Returns the change from the old entry to the new entry, if they are both
+/// numbers.
+pub fn delta_between(old: &Entry, new: &Entry) -> Option<Delta> {
+    let (old_val, new_val) = match (old.approx, new.approx) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return None,
+    };
+    let exact = match (&old.result, &new.result) {
+        (Node::Num { val: a, .. }, Node::Num { val: b, .. }) => Some(b - a),
+        _ => None,
+    };
+    let absolute = new_val - old_val;
+    let percent = if old_val != 0.0 {
+        Some(absolute / old_val.abs() * 100.0)
+    } else {
+        None
+    };
+    Some(Delta {
+        absolute,
+        exact,
+        percent,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::{ApproxEq, F64Margin};
+
+    use super::*;
+    use crate::parser::precedence;
+    use crate::test_util::parse;
+
+    #[test]
+    fn it_computes_the_change_since_the_last_result() {
+        let mut session = Session::new();
+        assert_eq!(session.delta(), None);
+        session.eval("40").unwrap();
+        assert_eq!(session.delta(), None);
+        session.eval("50").unwrap();
+
+        let delta = session.delta().unwrap();
+        assert!(delta.absolute.approx_eq(10.0, F64Margin::default()));
+        assert_eq!(delta.exact, Some(BigRational::from_integer(10.into())));
+        assert_eq!(delta.percent, Some(25.0));
+
+        session.eval("-50").unwrap();
+        assert_eq!(session.delta().unwrap().percent, Some(-200.0));
+        assert_eq!(session.history().len(), 3);
+    }
+
+    #[test]
+    fn it_has_no_delta_without_two_numbers() {
+        let mut session = Session::new();
+        session.eval("0").unwrap();
+        session.eval("pi").unwrap();
+        let delta = session.delta().unwrap();
+        assert_eq!(delta.exact, None);
+        assert_eq!(delta.percent, None);
+
+        session.eval("(1, 2)").unwrap();
+        assert_eq!(session.delta(), None);
+        assert!(session.eval("1 +").is_err());
+        assert_eq!(session.history().len(), 3);
+    }
+
+    #[test]
+    fn it_replaces_variables_defined_with_other_variables() {
+        let mut session = Session::new();
+        session.define("a", parse("3"));
+        session.define("b", parse("a"));
+        session.define("c", parse("3 * (1 + b)"));
+        session.define("d", parse("c - a"));
+        assert_eq!(session.eval("d").unwrap().result.to_string(), "9");
+        assert_eq!(session.eval("c").unwrap().result.to_string(), "12");
+
+        // the variables are not units
+        session.define("t", parse("2"));
+        assert_eq!(session.eval("3t").unwrap().result.to_string(), "6");
+
+        // cyclic definitions stop
+        session.define("a", parse("b + 1"));
+        assert!(session.eval("a").is_ok());
+    }
+
+    #[test]
+    fn it_converts_between_custom_units() {
+        let num = |val: i64| Node::Num {
+            val: BigRational::from_integer(val.into()),
+            input_base: None,
+        };
+        let mut session = Session::new();
+        session.define_unit("furlong", num(201), "m");
+        session.define_unit("league", num(15), "furlong");
+        assert_eq!(session.unit("league").map(|x| x.eval().is_ok()), Some(true));
+
+        let simplify_str =
+            |session: &mut Session, expr: &str| session.eval(expr).unwrap().result.to_string();
+        assert_eq!(simplify_str(&mut session, "2 furlong in m"), "402 m");
+        assert_eq!(simplify_str(&mut session, "1 furlong + 1 m"), "202 m");
+        assert_eq!(
+            simplify_str(&mut session, "3 league in km"),
+            "(1809/200) km"
+        );
+
+        let result = session.eval("2 league + 4 furlong").unwrap().result.clone();
+        assert_eq!(result.to_string(), "6834 m");
+        assert_eq!(
+            session.convert(&result, "furlong").unwrap().to_string(),
+            "34furlong"
+        );
+        assert_eq!(
+            session.convert(&result, "league").unwrap().to_string(),
+            "34/15 * league"
+        );
+        assert_eq!(
+            session.convert(&result, "km").unwrap().to_string(),
+            "(3417/500) km"
+        );
+        assert_eq!(session.convert(&result, "s"), None);
+
+        // units that are not builtin units stay as variables
+        session.define_unit("crate", num(12), "bottle");
+        let result = session.eval("2 crate").unwrap().result.clone();
+        assert_eq!(result.to_string(), "24bottle");
+        assert_eq!(
+            session.convert(&result, "crate").unwrap().to_string(),
+            "2crate"
+        );
+    }
+
+    #[test]
+    fn it_uses_custom_operators() {
+        let var = |name: &str| Node::Var(name.to_owned());
+        let operator = |symbol: &str, body: Node| CustomOperator {
+            symbol: symbol.to_owned(),
+            params: ("a".to_owned(), "b".to_owned()),
+            body,
+            precedence: precedence::MUL,
+            assoc: Assoc::Left,
+        };
+        let mut session = Session::new();
+        session
+            .define_operator(operator("//", (var("a") / var("b")).floor()))
+            .unwrap();
+        let hypot = (var("a") * var("a") + var("b") * var("b")).sqrt();
+        session.define_operator(operator("⊕", hypot)).unwrap();
+
+        assert_eq!(session.eval("7 // 2").unwrap().result.to_string(), "3");
+        assert_eq!(session.eval("1 + 3 ⊕ 4").unwrap().result.to_string(), "6");
+        assert_eq!(session.eval("8 / 2 // 3").unwrap().result.to_string(), "1");
+        // the variables in the operands are not the parameters
+        assert_eq!(
+            session.eval("b // a").unwrap().result.to_string(),
+            "floor(b / a)"
+        );
+
+        assert_eq!(
+            session.define_operator(operator("+", var("a"))),
+            Err(OperatorError::Collision {
+                symbol: "+".to_owned()
+            })
+        );
+        assert_eq!(
+            session.define_operator(operator("//", var("a"))),
+            Err(OperatorError::Collision {
+                symbol: "//".to_owned()
+            })
+        );
+        assert_eq!(
+            session.define_operator(operator("x*", var("a"))),
+            Err(OperatorError::InvalidSymbol)
+        );
+
+        session.undefine_operator("//");
+        assert!(session.eval("7 // 2").is_err());
+    }
+
+    #[test]
+    fn it_reproduces_random_results_with_a_seed() {
+        let node = Node::Var("x".to_owned());
+        let inputs = [("x", Distribution::Uniform { min: 0.0, max: 1.0 })];
+        let run = |session: &mut Session| session.montecarlo(&node, &inputs, 100).unwrap();
+
+        let mut session = Session::new();
+        session.set_options(EvalOptions {
+            seed: Some(7),
+            ..EvalOptions::default()
+        });
+        let first = run(&mut session);
+        let second = run(&mut session);
+        assert_ne!(first, second);
+
+        // the same seed replays the same results
+        let mut other = Session::new();
+        other.set_options(EvalOptions {
+            seed: Some(7),
+            ..EvalOptions::default()
+        });
+        let snapshot = other.snapshot();
+        assert_eq!(run(&mut other), first);
+        assert_eq!(run(&mut other), second);
+        other.restore(&snapshot);
+        assert_eq!(run(&mut other), first);
+    }
+
+    #[test]
+    fn it_replays_debug_bundles() {
+        let var = |name: &str| Node::Var(name.to_owned());
+        let mut session = Session::new();
+        session.set_options(EvalOptions {
+            seed: Some(3),
+            memory_budget: Some(1 << 20),
+            compensated: true,
+            ..EvalOptions::default()
+        });
+        session.define("x", var("y") + var("y"));
+        session.define_unit("furlong", var("meter") * var("meter"), "meter");
+        session
+            .define_operator(CustomOperator {
+                symbol: "//".to_owned(),
+                params: ("a".to_owned(), "b".to_owned()),
+                body: (var("a") / var("b")).floor(),
+                precedence: precedence::MUL,
+                assoc: Assoc::Left,
+            })
+            .unwrap();
+        session
+            .define_function(UserFunction {
+                name: "area".to_owned(),
+                params: vec!["w".to_owned(), "h".to_owned()],
+                body: var("w") * var("h"),
+            })
+            .unwrap();
+        session.eval("x * 2").unwrap();
+        session.eval("7 // 2").unwrap();
+        session.eval("area(3, x)").unwrap();
+        session.eval("1 +\n2").unwrap();
+
+        let bundle = session.export_debug_bundle();
+        assert!(bundle.contains("budget 1048576"));
+        assert!(bundle.contains("compensated true"));
+        assert!(bundle.contains("var x (+ y y)"));
+        assert!(bundle.contains("input 1 +\\n2"));
+        assert!(bundle.contains("function area w,h (* w h)"));
+
+        let replayed = Session::import_debug_bundle(&bundle).unwrap();
+        assert_eq!(replayed.options(), session.options());
+        assert_eq!(replayed.var("x"), session.var("x"));
+        let results: Vec<Node> = replayed
+            .history()
+            .iter()
+            .map(|x| x.result.clone())
+            .collect();
+        assert_eq!(bundle_results(&bundle), Ok(results));
+        assert_eq!(replayed.history(), session.history());
+        assert_eq!(replayed.export_debug_bundle(), bundle);
+
+        assert_eq!(
+            Session::import_debug_bundle("1 + 1").unwrap_err(),
+            BundleError::NotABundle
+        );
+        assert_eq!(
+            Session::import_debug_bundle("costau-debug-bundle 0.4.0\nfoo bar").unwrap_err(),
+            BundleError::Malformed { line: 2 }
+        );
+    }
+
+    #[test]
+    fn it_replays_debug_bundles_with_names_that_have_spaces() {
+        let var = |name: &str| Node::Var(name.to_owned());
+        let mut session = Session::new();
+        session.define("my var", var("y"));
+        session.define_unit("board foot", var("foot"), "foot");
+        session
+            .define_function(UserFunction {
+                name: "f".to_owned(),
+                params: vec!["a b".to_owned(), "c,d\\".to_owned()],
+                body: var("a b") * var("c,d\\"),
+            })
+            .unwrap();
+        session.eval("f(2, 3)").unwrap();
+        session.undefine("my var");
+
+        let bundle = session.export_debug_bundle();
+        assert!(bundle.contains("var my\\svar y"));
+        assert!(bundle.contains("function f a\\sb,c\\cd\\\\ "));
+        let replayed = Session::import_debug_bundle(&bundle).unwrap();
+        assert_eq!(replayed.unit("board foot"), session.unit("board foot"));
+        assert_eq!(replayed.functions().get("f"), session.functions().get("f"));
+        assert_eq!(replayed.history(), session.history());
+        assert_eq!(replayed.export_debug_bundle(), bundle);
+    }
+
+    #[test]
+    fn it_replays_debug_bundles_with_the_definitions_of_each_input() {
+        let num = |val: i64| Node::Num {
+            val: BigRational::from_integer(val.into()),
+            input_base: None,
+        };
+        let mut session = Session::new();
+        session.define("x", num(1));
+        session.eval("x + 1").unwrap();
+        session.define("x", num(2));
+        session.eval("x + 1").unwrap();
+        session
+            .define_function(UserFunction {
+                name: "f".to_owned(),
+                params: vec!["a".to_owned()],
+                body: Node::Var("a".to_owned()),
+            })
+            .unwrap();
+        session.eval("f(x)").unwrap();
+        session.undefine_function("f");
+        session
+            .define_function(UserFunction {
+                name: "f".to_owned(),
+                params: vec!["a".to_owned()],
+                body: Node::Var("a".to_owned()) * num(10),
+            })
+            .unwrap();
+        session.eval("f(x)").unwrap();
+        session.undefine("x");
+        session.eval("x + 1").unwrap();
+
+        let bundle = session.export_debug_bundle();
+        assert!(bundle.contains("forget var x"));
+        assert!(bundle.contains("forget function f"));
+        let replayed = Session::import_debug_bundle(&bundle).unwrap();
+        let results: Vec<String> = replayed
+            .history()
+            .iter()
+            .map(|x| x.result.to_string())
+            .collect();
+        assert_eq!(results, ["2", "3", "2", "20", "1 + x"]);
+        assert_eq!(replayed.history(), session.history());
+        assert_eq!(replayed.var("x"), None);
+        assert_eq!(replayed.functions().get("f"), session.functions().get("f"));
+        assert_eq!(replayed.export_debug_bundle(), bundle);
+    }
+
+    #[test]
+    fn it_restores_snapshots() {
+        let two = || Node::Num {
+            val: BigRational::from_integer(2.into()),
+            input_base: None,
+        };
+
+        let mut session = Session::new();
+        session.eval("1").unwrap();
+        let before = session.snapshot();
+
+        session.define("x", two());
+        assert_eq!(session.eval("x * 3").unwrap().result.to_string(), "6");
+        let after = session.snapshot();
+
+        session.restore(&before);
+        assert_eq!(session.history().len(), 1);
+        assert_eq!(session.var("x"), None);
+        assert_eq!(session.eval("x * 3").unwrap().result.to_string(), "3x");
+
+        // redo
+        session.restore(&after);
+        assert_eq!(session.history().len(), 2);
+        assert_eq!(session.var("x"), Some(&two()));
+        // the calculations are shared with the snapshot
+        assert!(Rc::ptr_eq(&session.history()[1], &after.history[1]));
+    }
+}
@@ -0,0 +1,35 @@
+//! Helpers for the tests, which read expressions like the frontends do.
+
+use crate::lexer::{Lexer, Token};
+use crate::node::Node;
+use crate::parser::{ParseError, Parser};
+
+/// Lexes an expression that has no invalid tokens.
+pub fn tokens(expr: &str) -> Vec<Token> {
+    Lexer::new(expr).map(|x| x.unwrap()).collect()
+}
+
+/// Parses an expression that has no invalid tokens.
+pub fn try_parse(expr: &str) -> Result<Node, ParseError> {
+    Parser::new(&tokens(expr)).parse()
+}
+
+/// Parses a valid expression.
+pub fn parse(expr: &str) -> Node {
+    try_parse(expr).unwrap()
+}
+
+/// Parses and simplifies an expression.
+pub fn simplify(expr: &str) -> Node {
+    parse(expr).simplify().unwrap().result
+}
+
+/// Writes the simplified expression.
+pub fn simplify_str(expr: &str) -> String {
+    simplify(expr).to_string()
+}
+
+/// Writes the exact and the approximate results of an expression.
+pub fn calculate_str(expr: &str) -> String {
+    parse(expr).calculate().unwrap().to_string()
+}
@@ -0,0 +1,192 @@
+//! Numbers written in words, such as "one thousand two hundred thirty-four
+//! point five", which screen readers can read out.
+
+/// The words of the numbers in a language.
+pub trait Language {
+    /// Writes a non-negative integer.
+    fn integer(&self, n: u64) -> String;
+
+    /// The word before negative numbers
+    fn minus(&self) -> &str;
+
+    /// The word of the decimal point
+    fn point(&self) -> &str;
+
+    /// Writes a digit after the decimal point.
+    fn digit(&self, d: u64) -> String {
+        self.integer(d)
+    }
+}
+
+pub struct English;
+
+pub(crate) const ENGLISH_UNITS: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+
+pub(crate) const ENGLISH_TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+
+const ENGLISH_SCALES: [&str; 7] = [
+    "",
+    "thousand",
+    "million",
+    "billion",
+    "trillion",
+    "quadrillion",
+    "quintillion",
+];
+
+impl English {
+    /// Writes a number below 1000 that is not zero.
+    fn below_thousand(n: u64, words: &mut Vec<String>) {
+        if n >= 100 {
+            words.push(ENGLISH_UNITS[(n / 100) as usize].to_owned());
+            words.push("hundred".to_owned());
+        }
+        let n = (n % 100) as usize;
+        match n {
+            0 => {}
+            1..=19 => words.push(ENGLISH_UNITS[n].to_owned()),
+            _ => match n % 10 {
+                0 => words.push(ENGLISH_TENS[n / 10].to_owned()),
+                units => words.push(format!("{}-{}", ENGLISH_TENS[n / 10], ENGLISH_UNITS[units])),
+            },
+        }
+    }
+}
+
+impl Language for English {
+    fn integer(&self, n: u64) -> String {
+        if n == 0 {
+            return ENGLISH_UNITS[0].to_owned();
+        }
+        let mut groups = Vec::new();
+        let mut rest = n;
+        while rest > 0 {
+            groups.push(rest % 1000);
+            rest /= 1000;
+        }
+        let mut words = Vec::new();
+        for (scale, &group) in groups.iter().enumerate().rev() {
+            if group == 0 {
+                continue;
+            }
+            English::below_thousand(group, &mut words);
+            if scale > 0 {
+                words.push(ENGLISH_SCALES[scale].to_owned());
+            }
+        }
+        words.join(" ")
+    }
+
+    fn minus(&self) -> &str {
+        "minus"
+    }
+
+    fn point(&self) -> &str {
+        "point"
+    }
+}
+
+/// Returns the language with the given key, such as `en`.
+pub fn language(key: &str) -> Option<&'static dyn Language> {
+    match key {
+        "en" => Some(&English),
+        _ => None,
+    }
+}
+
+/// Writes the number in words in the given language.
+/// Returns `None` if the number is not finite or is too large to have a
+/// name.
+pub fn to_words_with(x: f64, lang: &dyn Language) -> Option<String> {
+    if !x.is_finite() {
+        return None;
+    }
+    // the shortest decimal form that reads back as the same number
+    let text = x.abs().to_string();
+    let mut parts = text.splitn(2, '.');
+    let integer: u64 = parts.next()?.parse().ok()?;
+    let mut words = Vec::new();
+    if x < 0.0 {
+        words.push(lang.minus().to_owned());
+    }
+    words.push(lang.integer(integer));
+    if let Some(fraction) = parts.next() {
+        words.push(lang.point().to_owned());
+        for c in fraction.chars() {
+            words.push(lang.digit(u64::from(c.to_digit(10)?)));
+        }
+    }
+    Some(words.join(" "))
+}
+
+/// Writes the number in words in the language with the given key, such as
+/// `en`.
+pub fn to_words(x: f64, lang_key: &str) -> Option<String> {
+    to_words_with(x, language(lang_key)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_writes_numbers_in_english() {
+        assert_eq!(
+            to_words(1234.5, "en"),
+            Some("one thousand two hundred thirty-four point five".to_owned())
+        );
+        assert_eq!(to_words(0.0, "en"), Some("zero".to_owned()));
+        assert_eq!(
+            to_words(-2_000_017.25, "en"),
+            Some("minus two million seventeen point two five".to_owned())
+        );
+        assert_eq!(to_words(90.0, "en"), Some("ninety".to_owned()));
+        assert_eq!(to_words(1e300, "en"), None);
+        assert_eq!(to_words(f64::NAN, "en"), None);
+    }
+
+    struct Digits;
+
+    impl Language for Digits {
+        fn integer(&self, n: u64) -> String {
+            n.to_string()
+        }
+
+        fn minus(&self) -> &str {
+            "-"
+        }
+
+        fn point(&self) -> &str {
+            "."
+        }
+    }
+
+    #[test]
+    fn it_accepts_other_languages() {
+        assert_eq!(to_words(12.5, "xx"), None);
+        assert_eq!(to_words_with(-12.5, &Digits), Some("- 12 . 5".to_owned()));
+    }
+}
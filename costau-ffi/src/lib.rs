@@ -2,9 +2,7 @@
 #![allow(clippy::missing_safety_doc)]
 
 use costau_core::lexer::{Lexer, Token};
-use costau_core::node::Node;
 use costau_core::parser::Parser;
-use num_traits::One;
 use std::ffi::CStr;
 use std::mem;
 use std::os::raw::c_char;
@@ -97,27 +95,23 @@ pub unsafe extern "C" fn costau_eval(expr: *const c_char) -> *mut EvalResult {
         Err(_) => return Box::into_raw(Box::new(Err(()))),
     };
 
-    let (simplified_expr, did_simplify) = match root_node.simplify() {
-        Ok(x) => (x.result, x.did_something),
+    let calculation = match root_node.calculate() {
+        Ok(x) => x,
         Err(_) => return Box::into_raw(Box::new(Err(()))),
     };
 
-    let needs_approx = if let Node::Num { val, .. } = &simplified_expr {
-        // if integer, already simplified to the maximum
-        !val.denom().is_one() && *val.denom() != (-1).into()
-    } else {
-        true
-    };
-    let approx = if needs_approx {
-        simplified_expr.eval().ok().map(|x| x.val.to_string())
-    } else {
-        None
+    let approx = match calculation.approx {
+        Some(Ok(x)) if x.imag == 0.0 => Some(x.val.to_string()),
+        Some(Ok(x)) => Some(x.to_string()),
+        _ => None,
     };
 
     let r = EvalSuccess {
-        simplified_expr: Some(simplified_expr)
-            .filter(|_| did_simplify)
-            .map(|x| x.to_string()),
+        simplified_expr: if calculation.did_simplify {
+            Some(calculation.exact.to_string())
+        } else {
+            None
+        },
         approx,
     };
     Box::into_raw(Box::new(Ok(r)))